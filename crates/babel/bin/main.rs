@@ -1,58 +1,181 @@
-use babel::{BabelServer, CosmosBabel, EthereumBabel, EthereumBeaconBabel};
-use clap::Parser;
+use babel::{Babel, BabelServer, EthereumPairedBabel, HeadReference, HealthHistory, PeerTrend};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use logging::{LevelFilter, LogFormat};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "babel")]
 #[command(about = "Blockchain node health check server", long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 struct Cli {
-    /// Node type: ethereum, ethereum_beacon, cosmos
-    #[arg(long)]
-    node_type: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Node type: ethereum, ethereum_beacon, ethereum_paired, cosmos
+    #[arg(long, required = true)]
+    node_type: Option<String>,
 
-    /// RPC/API URL for the node
+    /// RPC/API URL for the node, the execution client for ethereum_paired.
+    /// The ethereum clients can be reached on their IPC socket with
+    /// unix:///path/geth.ipc
+    #[arg(long, required = true)]
+    rpc_url: Option<String>,
+
+    /// Beacon API URL of the consensus client paired with the execution
+    /// client (ethereum_paired)
     #[arg(long)]
-    rpc_url: String,
+    cl_url: Option<String>,
+
+    /// Blocks the execution head can be apart from the payload of the
+    /// consensus head (ethereum_paired)
+    #[arg(long, default_value_t = 5)]
+    max_pair_lag: u64,
+
+    /// Public RPC/API URL of the same chain the head of the node is compared
+    /// against (can be repeated)
+    #[arg(long = "reference-url")]
+    reference_urls: Vec<String>,
+
+    /// Blocks (slots for ethereum_beacon) the node can be behind the references
+    #[arg(long, default_value_t = 10)]
+    max_head_lag: u64,
+
+    /// Seconds between two polls of the health of the node
+    #[arg(long, default_value_t = 15, value_parser = clap::value_parser!(u64).range(1..))]
+    poll_interval: u64,
+
+    /// Seconds the head of the node can stay at the same height before the
+    /// polls count it as down
+    #[arg(long, default_value_t = 300)]
+    max_head_stall: u64,
+
+    /// Seconds of a window the uptime of the node is reported over in
+    /// `/history` (can be repeated)
+    #[arg(long = "uptime-window", default_values_t = [3600, 86400])]
+    uptime_windows: Vec<u64>,
+
+    /// Seconds of polls the peer count trend is evaluated over in `/ready`
+    #[arg(long, default_value_t = 600)]
+    peer_trend_window: u64,
+
+    /// Percentage of the highest peer count of the trend window the peers
+    /// can drop before the node is not ready
+    #[arg(long, default_value_t = 50.0)]
+    max_peer_drop: f64,
+
+    /// Polls with fewer peers than the previous one that make the node not
+    /// ready, if the peers never went up in the trend window
+    #[arg(long, default_value_t = 3)]
+    peer_declines: usize,
+
+    /// Percentage of the first peer count of the trend window the declines
+    /// must add up to
+    #[arg(long, default_value_t = 20.0)]
+    min_peer_decline: f64,
+
+    /// Peers the node needs to be ready, whatever their trend
+    #[arg(long, default_value_t = 1)]
+    min_peers: u64,
 
     /// Server bind address
     #[arg(long, default_value = "127.0.0.1:3000")]
     addr: String,
+
+    /// Format of the log output
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Minimum log level (trace, debug, info, warn, error)
+    #[arg(long, default_value_t = LevelFilter::INFO)]
+    log_level: LevelFilter,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the shell completions of babel
+    Completions {
+        /// Shell to generate the completions for
+        shell: Shell,
+    },
+    /// Print the manpage of babel
+    Manpage,
+    /// Exit with 0 if the url replies with a 2xx status, the health check of
+    /// the babel container since its image has no curl
+    Probe {
+        /// Url of a babel endpoint, e.g. http://127.0.0.1:3000/health
+        url: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
-    tracing_subscriber::fmt::init();
-
     let cli = Cli::parse();
 
+    match cli.command {
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "babel", &mut std::io::stdout());
+            return Ok(());
+        }
+        Some(Command::Manpage) => {
+            clap_mangen::Man::new(Cli::command()).render(&mut std::io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::Probe { url }) => {
+            reqwest::get(&url).await?.error_for_status()?;
+            return Ok(());
+        }
+        None => {}
+    }
+
+    // Both are required by clap when no subcommand is given
+    let (Some(node_type), Some(rpc_url)) = (cli.node_type, cli.rpc_url) else {
+        unreachable!("node type and rpc url are required");
+    };
+
+    logging::init(cli.log_format, cli.log_level);
+
     tracing::info!(
         "Starting Babel server for {} node at {}",
-        cli.node_type,
-        cli.rpc_url
+        node_type,
+        rpc_url
     );
 
-    match cli.node_type.as_str() {
-        "ethereum" => {
-            let babel = EthereumBabel::new(cli.rpc_url);
-            let server = BabelServer::new(babel);
-            server.serve(&cli.addr).await?;
-        }
-        "ethereum_beacon" => {
-            let babel = EthereumBeaconBabel::new(cli.rpc_url);
-            let server = BabelServer::new(babel);
-            server.serve(&cli.addr).await?;
-        }
-        "cosmos" => {
-            let babel = CosmosBabel::new(cli.rpc_url);
-            let server = BabelServer::new(babel);
-            server.serve(&cli.addr).await?;
-        }
-        _ => {
-            return Err(eyre::eyre!(
-                "Unknown node type: {}. Supported types: ethereum, ethereum_beacon, cosmos",
-                cli.node_type
-            ));
+    // the head of the paired mode is the one of its execution client, its
+    // references are execution clients as well
+    let (node, node_type): (Arc<dyn Babel>, &str) = match node_type.as_str() {
+        "ethereum_paired" => {
+            let paired = EthereumPairedBabel::from_urls(rpc_url, cli.cl_url, cli.max_pair_lag)?;
+            (Arc::new(paired), "ethereum")
         }
+        node_type => (babel::node(node_type, rpc_url)?, node_type),
+    };
+    let history = Arc::new(
+        HealthHistory::new(cli.uptime_windows)
+            .with_peer_trend(PeerTrend {
+                window: cli.peer_trend_window,
+                max_drop: cli.max_peer_drop,
+                min_declines: cli.peer_declines,
+                min_decline: cli.min_peer_decline,
+                min_peers: cli.min_peers,
+            })
+            .with_max_head_stall(cli.max_head_stall),
+    );
+    history
+        .clone()
+        .spawn_poller(node.clone(), Duration::from_secs(cli.poll_interval));
+
+    let mut server = BabelServer::from_arc(node).with_history(history);
+    if !cli.reference_urls.is_empty() {
+        let references = cli
+            .reference_urls
+            .into_iter()
+            .map(|url| babel::node(node_type, url))
+            .collect::<eyre::Result<Vec<_>>>()?;
+        server = server.with_reference(HeadReference::new(references, cli.max_head_lag));
     }
+    server.serve(&cli.addr).await?;
 
     Ok(())
 }
@@ -1,4 +1,4 @@
-use crate::Babel;
+use crate::{Babel, Head};
 use async_trait::async_trait;
 use serde::Deserialize;
 
@@ -18,6 +18,37 @@ struct NetInfoResult {
     n_peers: String,
 }
 
+#[derive(Deserialize)]
+struct StatusResponse {
+    result: StatusResult,
+}
+
+#[derive(Deserialize)]
+struct StatusResult {
+    sync_info: SyncInfo,
+}
+
+#[derive(Deserialize)]
+struct SyncInfo {
+    latest_block_height: String,
+    latest_block_hash: String,
+}
+
+#[derive(Deserialize)]
+struct BlockResponse {
+    result: BlockResult,
+}
+
+#[derive(Deserialize)]
+struct BlockResult {
+    block_id: BlockId,
+}
+
+#[derive(Deserialize)]
+struct BlockId {
+    hash: String,
+}
+
 impl CosmosBabel {
     pub fn new(rpc_url: String) -> Self {
         Self {
@@ -33,10 +64,7 @@ impl Babel for CosmosBabel {
         // Cosmos/Tendermint uses REST endpoint: /net_info
         let url = format!("{}/net_info", self.rpc_url.trim_end_matches('/'));
 
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
+        let response = self.client.get(&url).send().await?;
 
         let net_info: NetInfoResponse = response.json().await?;
 
@@ -44,4 +72,31 @@ impl Babel for CosmosBabel {
 
         Ok(count)
     }
+
+    async fn head(&self) -> eyre::Result<Head> {
+        let url = format!("{}/status", self.rpc_url.trim_end_matches('/'));
+
+        let response = self.client.get(&url).send().await?;
+
+        let status: StatusResponse = response.json().await?;
+
+        Ok(Head {
+            height: status.result.sync_info.latest_block_height.parse::<u64>()?,
+            hash: status.result.sync_info.latest_block_hash,
+        })
+    }
+
+    async fn block_hash(&self, height: u64) -> eyre::Result<String> {
+        let url = format!(
+            "{}/block?height={}",
+            self.rpc_url.trim_end_matches('/'),
+            height
+        );
+
+        let response = self.client.get(&url).send().await?;
+
+        let block: BlockResponse = response.json().await?;
+
+        Ok(block.result.block_id.hash)
+    }
 }
@@ -1,9 +1,10 @@
-use crate::Babel;
+use crate::{ipc, Babel, Head};
 use async_trait::async_trait;
 use serde_json::json;
 
 /// Ethereum node implementation (supports execution layer clients like Geth, Reth, etc.)
 pub struct EthereumBabel {
+    // http url or `unix://` path of the IPC socket of the node
     rpc_url: String,
     client: reqwest::Client,
 }
@@ -16,19 +17,31 @@ impl EthereumBabel {
         }
     }
 
-    async fn rpc_call(&self, method: &str, params: serde_json::Value) -> eyre::Result<serde_json::Value> {
-        let response = self.client
-            .post(&self.rpc_url)
-            .json(&json!({
-                "jsonrpc": "2.0",
-                "method": method,
-                "params": params,
-                "id": 1
-            }))
-            .send()
-            .await?;
+    async fn rpc_call(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> eyre::Result<serde_json::Value> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 1
+        });
 
-        let json: serde_json::Value = response.json().await?;
+        // operators often disable the http rpc and only expose the IPC socket in the pod
+        let json: serde_json::Value = match ipc::socket_path(&self.rpc_url) {
+            Some(path) => ipc::call(path, &request).await?,
+            None => {
+                self.client
+                    .post(&self.rpc_url)
+                    .json(&request)
+                    .send()
+                    .await?
+                    .json()
+                    .await?
+            }
+        };
 
         if let Some(error) = json.get("error") {
             return Err(eyre::eyre!("RPC error: {}", error));
@@ -45,13 +58,40 @@ impl Babel for EthereumBabel {
     async fn peer_count(&self) -> eyre::Result<u64> {
         let result = self.rpc_call("net_peerCount", json!([])).await?;
 
-        // Result is a hex string like "0x19"
-        let hex_str = result.as_str()
-            .ok_or_else(|| eyre::eyre!("Expected string result"))?;
+        parse_quantity(&result)
+    }
 
-        // Remove "0x" prefix and parse
-        let count = u64::from_str_radix(hex_str.trim_start_matches("0x"), 16)?;
+    async fn head(&self) -> eyre::Result<Head> {
+        let result = self.rpc_call("eth_blockNumber", json!([])).await?;
+        let height = parse_quantity(&result)?;
 
-        Ok(count)
+        Ok(Head {
+            height,
+            hash: self.block_hash(height).await?,
+        })
     }
+
+    async fn block_hash(&self, height: u64) -> eyre::Result<String> {
+        let block = self
+            .rpc_call(
+                "eth_getBlockByNumber",
+                json!([format!("0x{:x}", height), false]),
+            )
+            .await?;
+
+        block
+            .get("hash")
+            .and_then(|hash| hash.as_str())
+            .map(String::from)
+            .ok_or_else(|| eyre::eyre!("Block {} not found", height))
+    }
+}
+
+/// Parses a hex quantity like "0x19"
+fn parse_quantity(value: &serde_json::Value) -> eyre::Result<u64> {
+    let hex_str = value
+        .as_str()
+        .ok_or_else(|| eyre::eyre!("Expected string result"))?;
+
+    Ok(u64::from_str_radix(hex_str.trim_start_matches("0x"), 16)?)
 }
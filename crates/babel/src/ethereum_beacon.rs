@@ -1,4 +1,4 @@
-use crate::Babel;
+use crate::{Babel, Head};
 use async_trait::async_trait;
 use serde::Deserialize;
 
@@ -18,6 +18,53 @@ struct PeerCountData {
     connected: String,
 }
 
+#[derive(Deserialize)]
+struct HeaderResponse {
+    data: HeaderData,
+}
+
+#[derive(Deserialize)]
+struct HeaderData {
+    root: String,
+    header: SignedHeader,
+}
+
+#[derive(Deserialize)]
+struct SignedHeader {
+    message: HeaderMessage,
+}
+
+#[derive(Deserialize)]
+struct HeaderMessage {
+    slot: String,
+}
+
+#[derive(Deserialize)]
+struct BlockResponse {
+    data: SignedBlock,
+}
+
+#[derive(Deserialize)]
+struct SignedBlock {
+    message: BlockMessage,
+}
+
+#[derive(Deserialize)]
+struct BlockMessage {
+    body: BlockBody,
+}
+
+#[derive(Deserialize)]
+struct BlockBody {
+    execution_payload_header: ExecutionPayloadHeader,
+}
+
+#[derive(Deserialize)]
+struct ExecutionPayloadHeader {
+    block_number: String,
+    block_hash: String,
+}
+
 impl EthereumBeaconBabel {
     pub fn new(api_url: String) -> Self {
         Self {
@@ -25,18 +72,54 @@ impl EthereumBeaconBabel {
             client: reqwest::Client::new(),
         }
     }
+
+    /// Beacon API endpoint: /eth/v1/beacon/headers/{block_id}
+    async fn header(&self, block_id: &str) -> eyre::Result<HeaderData> {
+        let url = format!(
+            "{}/eth/v1/beacon/headers/{}",
+            self.api_url.trim_end_matches('/'),
+            block_id
+        );
+
+        let response = self.client.get(&url).send().await?.error_for_status()?;
+
+        let header: HeaderResponse = response.json().await?;
+
+        Ok(header.data)
+    }
+
+    /// Execution block of the payload of the head block, the block the
+    /// consensus client expects its execution client to be at. The blinded
+    /// block only has the header of the payload, not its transactions.
+    /// Beacon API endpoint: /eth/v1/beacon/blinded_blocks/head
+    pub async fn execution_head(&self) -> eyre::Result<Head> {
+        let url = format!(
+            "{}/eth/v1/beacon/blinded_blocks/head",
+            self.api_url.trim_end_matches('/')
+        );
+
+        let response = self.client.get(&url).send().await?.error_for_status()?;
+
+        let block: BlockResponse = response.json().await?;
+        let payload = block.data.message.body.execution_payload_header;
+
+        Ok(Head {
+            height: payload.block_number.parse::<u64>()?,
+            hash: payload.block_hash,
+        })
+    }
 }
 
 #[async_trait]
 impl Babel for EthereumBeaconBabel {
     async fn peer_count(&self) -> eyre::Result<u64> {
         // Beacon API endpoint: /eth/v1/node/peer_count
-        let url = format!("{}/eth/v1/node/peer_count", self.api_url.trim_end_matches('/'));
+        let url = format!(
+            "{}/eth/v1/node/peer_count",
+            self.api_url.trim_end_matches('/')
+        );
 
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
+        let response = self.client.get(&url).send().await?;
 
         let peer_count: PeerCountResponse = response.json().await?;
 
@@ -44,4 +127,17 @@ impl Babel for EthereumBeaconBabel {
 
         Ok(count)
     }
+
+    async fn head(&self) -> eyre::Result<Head> {
+        let header = self.header("head").await?;
+
+        Ok(Head {
+            height: header.header.message.slot.parse::<u64>()?,
+            hash: header.root,
+        })
+    }
+
+    async fn block_hash(&self, height: u64) -> eyre::Result<String> {
+        Ok(self.header(&height.to_string()).await?.root)
+    }
 }
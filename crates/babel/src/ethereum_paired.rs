@@ -0,0 +1,231 @@
+use crate::{ipc, Babel, EthereumBabel, EthereumBeaconBabel, Head, HealthStatus};
+use async_trait::async_trait;
+use serde::Serialize;
+
+/// Execution and consensus clients of the same node checked together. The
+/// consensus client can follow the chain while its execution client is
+/// stuck, each layer on its own looks healthy.
+pub struct EthereumPairedBabel {
+    el: EthereumBabel,
+    cl: EthereumBeaconBabel,
+    // blocks the execution head can be apart from the payload of the
+    // consensus head
+    max_lag: u64,
+}
+
+/// Execution head compared with the execution payload of the consensus head
+#[derive(Debug, Clone, Serialize)]
+pub struct PairComparison {
+    pub execution_head: Head,
+    pub payload_head: Head,
+    pub lag: u64,
+    pub max_lag: u64,
+    // the execution client has another block at the height of the payload
+    pub mismatch: bool,
+    pub healthy: bool,
+}
+
+impl EthereumPairedBabel {
+    pub fn new(el_url: String, cl_url: String, max_lag: u64) -> Self {
+        Self {
+            el: EthereumBabel::new(el_url),
+            cl: EthereumBeaconBabel::new(cl_url),
+            max_lag,
+        }
+    }
+
+    /// Pair of the node type flags, the consensus client is required and
+    /// only served over http
+    pub fn from_urls(el_url: String, cl_url: Option<String>, max_lag: u64) -> eyre::Result<Self> {
+        let cl_url = cl_url
+            .ok_or_else(|| eyre::eyre!("--cl-url is required by the ethereum_paired node type"))?;
+        if ipc::socket_path(&cl_url).is_some() {
+            eyre::bail!("--cl-url must be the http url of the beacon API");
+        }
+        Ok(Self::new(el_url, cl_url, max_lag))
+    }
+
+    pub async fn compare(&self) -> eyre::Result<PairComparison> {
+        let execution_head = self.el.head().await?;
+        let payload_head = self.cl.execution_head().await?;
+
+        let mismatch = if execution_head.height >= payload_head.height {
+            self.el.block_hash(payload_head.height).await? != payload_head.hash
+        } else {
+            false
+        };
+        let lag = execution_head.height.abs_diff(payload_head.height);
+
+        Ok(PairComparison {
+            healthy: !mismatch && lag <= self.max_lag,
+            execution_head,
+            payload_head,
+            lag,
+            max_lag: self.max_lag,
+            mismatch,
+        })
+    }
+}
+
+#[async_trait]
+impl Babel for EthereumPairedBabel {
+    /// Peers of the layer with the fewest of them
+    async fn peer_count(&self) -> eyre::Result<u64> {
+        Ok(self.el.peer_count().await?.min(self.cl.peer_count().await?))
+    }
+
+    async fn head(&self) -> eyre::Result<Head> {
+        self.el.head().await
+    }
+
+    async fn block_hash(&self, height: u64) -> eyre::Result<String> {
+        self.el.block_hash(height).await
+    }
+
+    /// Fails when the layers disagree, so that the pair is unhealthy
+    async fn health_status(&self) -> eyre::Result<HealthStatus> {
+        let comparison = self.compare().await?;
+        if comparison.mismatch {
+            return Err(eyre::eyre!(
+                "Execution block {} is not the payload {} of the consensus head",
+                comparison.payload_head.height,
+                comparison.payload_head.hash
+            ));
+        }
+        if !comparison.healthy {
+            return Err(eyre::eyre!(
+                "Execution head {} is {} blocks apart from the payload {} of the consensus head",
+                comparison.execution_head.height,
+                comparison.lag,
+                comparison.payload_head.height
+            ));
+        }
+
+        Ok(HealthStatus {
+            peers: self.peer_count().await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+
+    /// Result of the JSON-RPC call to an execution client with the blocks
+    /// `(number, hash)`, the last one is the head
+    fn rpc_result(blocks: &[(u64, &str)], request: &Value) -> Value {
+        let head = blocks.last().unwrap().0;
+        let result = match request["method"].as_str().unwrap() {
+            "eth_blockNumber" => json!(format!("0x{:x}", head)),
+            "eth_getBlockByNumber" => {
+                let number = request["params"][0]
+                    .as_str()
+                    .unwrap()
+                    .trim_start_matches("0x");
+                let number = u64::from_str_radix(number, 16).unwrap();
+                blocks
+                    .iter()
+                    .find(|(height, _)| *height == number)
+                    .map_or(Value::Null, |(_, hash)| json!({ "hash": hash }))
+            }
+            "net_peerCount" => json!("0x5"),
+            method => panic!("unexpected call {}", method),
+        };
+        json!({ "jsonrpc": "2.0", "id": 1, "result": result })
+    }
+
+    /// Serves the execution blocks over JSON-RPC and the payload header of
+    /// the consensus head
+    async fn serve_pair(blocks: Vec<(u64, &'static str)>, payload: (u64, &'static str)) -> String {
+        let rpc = move |Json(request): Json<Value>| {
+            let reply = rpc_result(&blocks, &request);
+            async move { Json(reply) }
+        };
+        let blinded_block = move || async move {
+            Json(
+                json!({ "data": { "message": { "body": { "execution_payload_header": {
+                    "block_number": payload.0.to_string(),
+                    "block_hash": payload.1,
+                }}}}}),
+            )
+        };
+        let router = Router::new()
+            .route("/", post(rpc))
+            .route("/eth/v1/beacon/blinded_blocks/head", get(blinded_block));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_pair_comparison() -> eyre::Result<()> {
+        let blocks = vec![(10, "0xa"), (11, "0xb"), (12, "0xc")];
+        // head, payload, lag, mismatch, healthy
+        let cases = [
+            (blocks.clone(), (12, "0xc"), 0, false, true),
+            // the execution client is ahead of the consensus head
+            (blocks.clone(), (11, "0xb"), 1, false, true),
+            (blocks.clone(), (11, "0xf"), 1, true, false),
+            // the execution client is stuck behind the payload
+            (blocks[..1].to_vec(), (12, "0xc"), 2, false, false),
+        ];
+        for (blocks, payload, lag, mismatch, healthy) in cases {
+            let url = serve_pair(blocks, payload).await;
+            let comparison = EthereumPairedBabel::new(url.clone(), url, 1)
+                .compare()
+                .await?;
+            assert_eq!(comparison.payload_head.height, payload.0);
+            assert_eq!(comparison.lag, lag, "{:?}", payload);
+            assert_eq!(comparison.mismatch, mismatch, "{:?}", payload);
+            assert_eq!(comparison.healthy, healthy, "{:?}", payload);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pair_with_the_execution_client_on_ipc() -> eyre::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("reth.ipc");
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        tokio::spawn(async move {
+            let blocks = [(10, "0xa"), (11, "0xb")];
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 1024];
+                let read = stream.read(&mut request).await.unwrap();
+                let request: Value = serde_json::from_slice(&request[..read]).unwrap();
+                let reply = serde_json::to_vec(&rpc_result(&blocks, &request)).unwrap();
+                stream.write_all(&reply).await.unwrap();
+            }
+        });
+
+        let cl_url = serve_pair(vec![], (11, "0xb")).await;
+        let el_url = format!("unix://{}", path.display());
+        let paired = EthereumPairedBabel::from_urls(el_url, Some(cl_url), 1)?;
+        let comparison = paired.compare().await?;
+        assert_eq!(comparison.execution_head.hash, "0xb");
+        assert!(comparison.healthy);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pair_requires_the_http_beacon_api() {
+        let el_url = "http://el:8545".to_string();
+        assert!(EthereumPairedBabel::from_urls(el_url.clone(), None, 5).is_err());
+        assert!(EthereumPairedBabel::from_urls(
+            el_url.clone(),
+            Some("unix:///data/beacon.ipc".to_string()),
+            5
+        )
+        .is_err());
+        assert!(
+            EthereumPairedBabel::from_urls(el_url, Some("http://cl:5052".to_string()), 5).is_ok()
+        );
+    }
+}
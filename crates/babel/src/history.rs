@@ -0,0 +1,455 @@
+use crate::Babel;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Change of the health of the node seen by the poller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Transition {
+    // unix time of the poll that saw the change
+    pub at: u64,
+    pub healthy: bool,
+}
+
+/// Time the node was down during the last `window` seconds
+#[derive(Debug, Clone, Serialize)]
+pub struct Uptime {
+    pub window: u64,
+    // seconds of the window babel was polling, shorter than the window
+    // until babel has run for that long
+    pub observed: u64,
+    pub downtime: u64,
+    // percentage of the observed time the node was healthy
+    pub uptime: f64,
+}
+
+/// Evaluation of the peer count of the node over the trend window
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerTrendReport {
+    pub window: u64,
+    // peer counts of the polls in the window, oldest first
+    pub samples: Vec<u64>,
+    // percentage the last sample is below the highest one of the window
+    pub drop: f64,
+    // the count only went down during the window, by enough to matter
+    pub declining: bool,
+    // the last sample is below the minimum peers
+    pub too_few: bool,
+    pub healthy: bool,
+}
+
+/// Reply of `/ready`
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    // health of the last poll, none before the first one
+    pub healthy: Option<bool>,
+    pub peers: PeerTrendReport,
+}
+
+/// Rules the peer count of the node is evaluated with, a node whose peers
+/// are falling is reported before it runs out of them
+#[derive(Debug, Clone, Copy)]
+pub struct PeerTrend {
+    // seconds of polls the trend is evaluated over
+    pub window: u64,
+    // percentage of the highest count of the window the peers can drop
+    pub max_drop: f64,
+    // decreases of the count that make a decline, if it never went up in
+    // the window
+    pub min_declines: usize,
+    // percentage of the first count of the window the decreases must add up
+    // to, a few peers churning out are not a decline
+    pub min_decline: f64,
+    // peers the node needs whatever the trend, the drop of a node that never
+    // had peers is 0
+    pub min_peers: u64,
+}
+
+impl Default for PeerTrend {
+    fn default() -> Self {
+        Self {
+            window: 600,
+            max_drop: 50.0,
+            min_declines: 3,
+            min_decline: 20.0,
+            min_peers: 1,
+        }
+    }
+}
+
+impl PeerTrend {
+    pub fn evaluate(&self, samples: Vec<u64>) -> PeerTrendReport {
+        let highest = samples.iter().max().copied().unwrap_or_default();
+        let last = samples.last().copied().unwrap_or_default();
+        let drop = if highest == 0 {
+            0.0
+        } else {
+            (highest - last) as f64 * 100.0 / highest as f64
+        };
+
+        let first = samples.first().copied().unwrap_or_default();
+        let decline = if first == 0 {
+            0.0
+        } else {
+            first.saturating_sub(last) as f64 * 100.0 / first as f64
+        };
+        let declines = samples.windows(2).filter(|pair| pair[1] < pair[0]).count();
+        let increases = samples.windows(2).any(|pair| pair[1] > pair[0]);
+        let declining = !increases && declines >= self.min_declines && decline >= self.min_decline;
+        let too_few = last < self.min_peers;
+
+        PeerTrendReport {
+            window: self.window,
+            healthy: drop <= self.max_drop && !declining && !too_few,
+            samples,
+            drop,
+            declining,
+            too_few,
+        }
+    }
+}
+
+/// Reply of `/history`
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryReport {
+    // health of the last poll, none before the first one
+    pub healthy: Option<bool>,
+    pub transitions: Vec<Transition>,
+    pub uptime: Vec<Uptime>,
+}
+
+#[derive(Default)]
+struct Polls {
+    // unix time of the first poll
+    started_at: Option<u64>,
+    transitions: VecDeque<Transition>,
+    // unix time and peer count of the polls in the trend window
+    peers: VecDeque<(u64, u64)>,
+    // last height of the head and unix time it was first seen
+    head: Option<(u64, u64)>,
+}
+
+/// Health transitions of the node over the longest of the uptime windows,
+/// filled by a background poller
+pub struct HealthHistory {
+    // seconds of the windows the uptime is computed over
+    windows: Vec<u64>,
+    trend: PeerTrend,
+    // seconds the head can stay at the same height before the node is down
+    max_head_stall: u64,
+    polls: Mutex<Polls>,
+}
+
+impl HealthHistory {
+    pub fn new(windows: Vec<u64>) -> Self {
+        Self {
+            windows,
+            trend: PeerTrend::default(),
+            max_head_stall: 300,
+            polls: Mutex::new(Polls::default()),
+        }
+    }
+
+    pub fn with_peer_trend(mut self, trend: PeerTrend) -> Self {
+        self.trend = trend;
+        self
+    }
+
+    pub fn with_max_head_stall(mut self, seconds: u64) -> Self {
+        self.max_head_stall = seconds;
+        self
+    }
+
+    fn retention(&self) -> u64 {
+        self.windows.iter().max().copied().unwrap_or_default()
+    }
+
+    /// Records the health seen by a poll, only the changes are kept
+    pub fn record(&self, at: u64, healthy: bool) {
+        let mut polls = self.polls.lock().unwrap();
+        polls.started_at.get_or_insert(at);
+        if polls
+            .transitions
+            .back()
+            .is_none_or(|last| last.healthy != healthy)
+        {
+            polls.transitions.push_back(Transition { at, healthy });
+        }
+
+        // The last transition before the longest window is kept, it is the
+        // health of the node when the window starts
+        let start = at.saturating_sub(self.retention());
+        while polls.transitions.len() > 1 && polls.transitions[1].at <= start {
+            polls.transitions.pop_front();
+        }
+    }
+
+    /// Records a poll that reached the node, it is healthy while it has the
+    /// minimum peers and its head keeps moving
+    pub fn record_poll(&self, at: u64, peers: u64, height: u64) {
+        let stalled = {
+            let mut polls = self.polls.lock().unwrap();
+            let since = match polls.head {
+                Some((last, since)) if last == height => since,
+                _ => at,
+            };
+            polls.head = Some((height, since));
+            at.saturating_sub(since) > self.max_head_stall
+        };
+        self.record(at, peers >= self.trend.min_peers && !stalled);
+        self.record_peers(at, peers);
+    }
+
+    /// Records the peer count seen by a poll
+    pub fn record_peers(&self, at: u64, peers: u64) {
+        let mut polls = self.polls.lock().unwrap();
+        polls.peers.push_back((at, peers));
+        let start = at.saturating_sub(self.trend.window);
+        while polls.peers.front().is_some_and(|(at, _)| *at < start) {
+            polls.peers.pop_front();
+        }
+    }
+
+    /// Ready if the last poll was healthy and the peers are not falling
+    pub fn readiness(&self, now: u64) -> Readiness {
+        let polls = self.polls.lock().unwrap();
+        let start = now.saturating_sub(self.trend.window);
+        let samples = polls
+            .peers
+            .iter()
+            .filter(|(at, _)| *at >= start)
+            .map(|(_, peers)| *peers)
+            .collect();
+        let peers = self.trend.evaluate(samples);
+        let healthy = polls.transitions.back().map(|last| last.healthy);
+        Readiness {
+            ready: healthy == Some(true) && peers.healthy,
+            healthy,
+            peers,
+        }
+    }
+
+    pub fn report(&self, now: u64) -> HistoryReport {
+        let polls = self.polls.lock().unwrap();
+        let start = now.saturating_sub(self.retention());
+        HistoryReport {
+            healthy: polls.transitions.back().map(|last| last.healthy),
+            transitions: polls
+                .transitions
+                .iter()
+                .filter(|transition| transition.at >= start)
+                .copied()
+                .collect(),
+            uptime: self
+                .windows
+                .iter()
+                .map(|window| polls.uptime(*window, now))
+                .collect(),
+        }
+    }
+
+    /// Polls the health and the head of the node every interval, a poll that
+    /// fails is the node being down
+    pub fn spawn_poller(
+        self: Arc<Self>,
+        babel: Arc<dyn Babel>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let at = unix_time();
+                let poll = async {
+                    Ok::<_, eyre::Error>((babel.health_status().await?, babel.head().await?))
+                };
+                match poll.await {
+                    Ok((status, head)) => self.record_poll(at, status.peers, head.height),
+                    Err(err) => {
+                        tracing::warn!("Health poll failed: {}", err);
+                        self.record(at, false);
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl Polls {
+    fn uptime(&self, window: u64, now: u64) -> Uptime {
+        let start = now
+            .saturating_sub(window)
+            .max(self.started_at.unwrap_or(now));
+
+        // every unhealthy transition lasts until the next one, clipped to the window
+        let mut downtime = 0;
+        for (i, transition) in self.transitions.iter().enumerate() {
+            if transition.healthy {
+                continue;
+            }
+            let end = self
+                .transitions
+                .get(i + 1)
+                .map_or(now, |next| next.at.min(now));
+            downtime += end.saturating_sub(transition.at.max(start));
+        }
+
+        let observed = now.saturating_sub(start);
+        let uptime = if observed == 0 {
+            100.0
+        } else {
+            (observed - downtime) as f64 * 100.0 / observed as f64
+        };
+        Uptime {
+            window,
+            observed,
+            downtime,
+            uptime,
+        }
+    }
+}
+
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_trend_evaluation() {
+        let trend = PeerTrend::default();
+        // samples, drop, declining, too few, healthy
+        let cases = [
+            (vec![], 0.0, false, true, false),
+            (vec![0, 0, 0], 0.0, false, true, false),
+            (vec![40, 42, 41, 43], 0.0, false, false, true),
+            (vec![40, 10], 75.0, false, false, false),
+            // one peer churning out at a time is not a decline
+            (vec![50, 49, 48, 47], 6.0, false, false, true),
+            (vec![50, 45, 40, 35], 30.0, true, false, false),
+            // the count went up in the window
+            (vec![50, 45, 40, 45, 35], 30.0, false, false, true),
+            (vec![2, 1], 50.0, false, false, true),
+            (vec![2, 0], 100.0, false, true, false),
+        ];
+        for (samples, drop, declining, too_few, healthy) in cases {
+            let report = trend.evaluate(samples.clone());
+            assert_eq!(report.drop, drop, "{:?}", samples);
+            assert_eq!(report.declining, declining, "{:?}", samples);
+            assert_eq!(report.too_few, too_few, "{:?}", samples);
+            assert_eq!(report.healthy, healthy, "{:?}", samples);
+        }
+    }
+
+    #[test]
+    fn test_uptime_over_the_windows() {
+        let history = HealthHistory::new(vec![100, 1000]);
+        history.record(0, true);
+        history.record(500, false);
+        // only the changes are kept
+        history.record(520, false);
+        history.record(560, true);
+        history.record(900, false);
+        history.record(950, true);
+
+        let report = history.report(1000);
+        assert_eq!(report.healthy, Some(true));
+        let transitions: Vec<_> = report
+            .transitions
+            .iter()
+            .map(|t| (t.at, t.healthy))
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                (0, true),
+                (500, false),
+                (560, true),
+                (900, false),
+                (950, true)
+            ]
+        );
+
+        // window, observed, downtime, uptime
+        let uptime: Vec<_> = report
+            .uptime
+            .iter()
+            .map(|uptime| {
+                (
+                    uptime.window,
+                    uptime.observed,
+                    uptime.downtime,
+                    uptime.uptime,
+                )
+            })
+            .collect();
+        assert_eq!(uptime, vec![(100, 100, 50, 50.0), (1000, 1000, 110, 89.0)]);
+
+        // the downtime that started before the window is clipped to it
+        let report = history.report(1500);
+        assert_eq!(report.uptime[0].downtime, 0);
+        assert_eq!(report.uptime[1].downtime, 110);
+        history.record(1600, false);
+        assert_eq!(history.report(1700).uptime[0].downtime, 100);
+    }
+
+    #[test]
+    fn test_uptime_until_babel_ran_for_the_window() {
+        let history = HealthHistory::new(vec![3600]);
+        history.record(1000, false);
+        history.record(1030, true);
+        let uptime = &history.report(1060).uptime[0];
+        assert_eq!((uptime.observed, uptime.downtime), (60, 30));
+        assert_eq!(uptime.uptime, 50.0);
+        assert_eq!(history.report(1000).uptime[0].uptime, 100.0);
+    }
+
+    #[test]
+    fn test_polls_without_peers_or_progress_are_down() {
+        let history = HealthHistory::new(vec![3600]).with_max_head_stall(60);
+        history.record_poll(0, 10, 100);
+        history.record_poll(30, 10, 100);
+        assert_eq!(history.report(30).healthy, Some(true));
+        history.record_poll(90, 10, 100);
+        assert_eq!(history.report(90).healthy, Some(false));
+        history.record_poll(100, 10, 101);
+        assert_eq!(history.report(100).healthy, Some(true));
+        history.record_poll(110, 0, 102);
+        assert_eq!(history.report(110).healthy, Some(false));
+    }
+
+    #[test]
+    fn test_readiness_from_the_polls() {
+        let history = HealthHistory::new(vec![3600]).with_peer_trend(PeerTrend {
+            window: 60,
+            ..Default::default()
+        });
+        assert!(!history.readiness(0).ready);
+        assert_eq!(history.readiness(0).healthy, None);
+
+        history.record(0, true);
+        history.record_peers(0, 10);
+        assert!(history.readiness(0).ready);
+
+        // the peers drop out of the window once it moves on
+        history.record(30, true);
+        history.record_peers(30, 2);
+        assert!(!history.readiness(30).ready);
+        history.record(100, true);
+        history.record_peers(100, 2);
+        assert_eq!(history.readiness(100).peers.samples, vec![2]);
+        assert!(history.readiness(100).ready);
+
+        history.record(110, false);
+        let readiness = history.readiness(110);
+        assert_eq!(readiness.healthy, Some(false));
+        assert!(!readiness.ready);
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+// scheme of the rpc urls that point to an IPC socket, e.g. unix:///data/geth.ipc
+pub const UNIX_SCHEME: &str = "unix://";
+
+// the poller would hang on a node that accepts the connection and never replies
+const IPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Path of the socket if the url is a `unix://` one
+pub fn socket_path(url: &str) -> Option<&Path> {
+    url.strip_prefix(UNIX_SCHEME).map(Path::new)
+}
+
+/// Sends the JSON-RPC request over a new connection to the socket and reads
+/// its response. The IPC endpoints of the clients stream the JSON values
+/// without framing, the response ends when a whole value has been read.
+pub async fn call(path: &Path, request: &serde_json::Value) -> eyre::Result<serde_json::Value> {
+    tokio::time::timeout(IPC_TIMEOUT, async {
+        let mut stream = UnixStream::connect(path)
+            .await
+            .map_err(|err| eyre::eyre!("Failed to connect to {}: {}", path.display(), err))?;
+        stream.write_all(&serde_json::to_vec(request)?).await?;
+
+        let mut buf = vec![];
+        let mut chunk = [0u8; 8192];
+        loop {
+            let read = stream.read(&mut chunk).await?;
+            if read == 0 {
+                eyre::bail!("{} closed the connection before replying", path.display());
+            }
+            buf.extend_from_slice(&chunk[..read]);
+
+            let mut values = serde_json::Deserializer::from_slice(&buf).into_iter();
+            match values.next() {
+                Some(Ok(value)) => return Ok(value),
+                Some(Err(err)) if err.is_eof() => continue,
+                Some(Err(err)) => return Err(err.into()),
+                None => continue,
+            }
+        }
+    })
+    .await
+    .map_err(|_| eyre::eyre!("No reply from {} in {:?}", path.display(), IPC_TIMEOUT))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::net::UnixListener;
+
+    #[tokio::test]
+    async fn test_call_reads_a_reply_split_in_chunks() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("geth.ipc");
+        let listener = UnixListener::bind(&path)?;
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let read = stream.read(&mut request).await.unwrap();
+            let request: serde_json::Value = serde_json::from_slice(&request[..read]).unwrap();
+            assert_eq!(request["method"], "eth_blockNumber");
+
+            // the clients do not frame the values, the reply can come in pieces
+            let reply = br#"{"jsonrpc":"2.0","id":1,"result":"0x10"}"#;
+            let (first, rest) = reply.split_at(10);
+            stream.write_all(first).await.unwrap();
+            stream.flush().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            stream.write_all(rest).await.unwrap();
+            // the connection stays open, the reply ends with the value
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let request =
+            json!({ "jsonrpc": "2.0", "method": "eth_blockNumber", "params": [], "id": 1 });
+        let reply = call(&path, &request).await?;
+        assert_eq!(reply["result"], "0x10");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_call_fails_without_a_reply() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("geth.ipc");
+        let request = json!({ "jsonrpc": "2.0", "method": "eth_blockNumber", "id": 1 });
+        assert!(call(&path, &request).await.is_err());
+
+        let listener = UnixListener::bind(&path)?;
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).await;
+        });
+        let err = call(&path, &request).await.unwrap_err();
+        assert!(err.to_string().contains("closed the connection"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_socket_path_of_the_url() {
+        assert_eq!(
+            socket_path("unix:///data/geth.ipc"),
+            Some(Path::new("/data/geth.ipc"))
+        );
+        assert_eq!(socket_path("http://el:8545"), None);
+    }
+}
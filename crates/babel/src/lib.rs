@@ -7,6 +7,12 @@ pub trait Babel: Send + Sync {
     /// Get the number of connected peers for this node
     async fn peer_count(&self) -> eyre::Result<u64>;
 
+    /// Get the latest block (or slot) of the node
+    async fn head(&self) -> eyre::Result<Head>;
+
+    /// Get the hash (or root) of the block at the given height
+    async fn block_hash(&self, height: u64) -> eyre::Result<String>;
+
     /// Get comprehensive health status
     async fn health_status(&self) -> eyre::Result<HealthStatus> {
         Ok(HealthStatus {
@@ -15,6 +21,13 @@ pub trait Babel: Send + Sync {
     }
 }
 
+/// Latest block of a node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Head {
+    pub height: u64,
+    pub hash: String,
+}
+
 /// Health status response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealthStatus {
@@ -24,9 +37,37 @@ pub struct HealthStatus {
 pub mod cosmos;
 pub mod ethereum;
 pub mod ethereum_beacon;
+pub mod ethereum_paired;
+pub mod history;
+pub mod ipc;
+pub mod reference;
 pub mod server;
 
 pub use cosmos::CosmosBabel;
 pub use ethereum::EthereumBabel;
 pub use ethereum_beacon::EthereumBeaconBabel;
+pub use ethereum_paired::{EthereumPairedBabel, PairComparison};
+pub use history::{HealthHistory, HistoryReport, PeerTrend, PeerTrendReport, Readiness};
+pub use reference::{HeadComparison, HeadReference};
 pub use server::BabelServer;
+
+use std::sync::Arc;
+
+/// Creates the babel of the node type for the node at the url
+pub fn node(node_type: &str, rpc_url: String) -> eyre::Result<Arc<dyn Babel>> {
+    if ipc::socket_path(&rpc_url).is_some() && node_type != "ethereum" {
+        return Err(eyre::eyre!(
+            "The {} node type does not support IPC sockets, only ethereum does",
+            node_type
+        ));
+    }
+    match node_type {
+        "ethereum" => Ok(Arc::new(EthereumBabel::new(rpc_url))),
+        "ethereum_beacon" => Ok(Arc::new(EthereumBeaconBabel::new(rpc_url))),
+        "cosmos" => Ok(Arc::new(CosmosBabel::new(rpc_url))),
+        _ => Err(eyre::eyre!(
+            "Unknown node type: {}. Supported types: ethereum, ethereum_beacon, cosmos",
+            node_type
+        )),
+    }
+}
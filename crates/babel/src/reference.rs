@@ -0,0 +1,174 @@
+use crate::{Babel, Head};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Public endpoints of the same chain the head of the node is compared
+/// against, local checks can not tell that a node with peers has stalled or
+/// follows a minority fork
+pub struct HeadReference {
+    references: Vec<Arc<dyn Babel>>,
+    // blocks (or slots) the node can be behind the references
+    max_lag: u64,
+}
+
+/// Head of the node compared with the highest head of the references
+#[derive(Debug, Clone, Serialize)]
+pub struct HeadComparison {
+    pub head: Head,
+    pub reference_head: Head,
+    pub lag: u64,
+    pub max_lag: u64,
+    // the reference has another block at the height of the node, unknown if
+    // the reference could not tell its block
+    pub fork: Option<bool>,
+    pub healthy: bool,
+}
+
+impl HeadReference {
+    pub fn new(references: Vec<Arc<dyn Babel>>, max_lag: u64) -> Self {
+        Self {
+            references,
+            max_lag,
+        }
+    }
+
+    pub async fn compare(&self, node: &dyn Babel) -> eyre::Result<HeadComparison> {
+        let head = node.head().await?;
+
+        // The references that fail are skipped, one public endpoint being
+        // down does not make the node unhealthy
+        let mut best: Option<(&Arc<dyn Babel>, Head)> = None;
+        for reference in &self.references {
+            match reference.head().await {
+                Ok(reference_head) => {
+                    if best
+                        .as_ref()
+                        .is_none_or(|(_, best)| reference_head.height > best.height)
+                    {
+                        best = Some((reference, reference_head));
+                    }
+                }
+                Err(err) => tracing::warn!("Reference endpoint failed: {}", err),
+            }
+        }
+        let (reference, reference_head) =
+            best.ok_or_else(|| eyre::eyre!("No reference endpoint is available"))?;
+
+        // a reference that pruned the block (or fails to serve it) can still
+        // tell the lag of the node
+        let fork = if reference_head.height >= head.height {
+            match reference.block_hash(head.height).await {
+                Ok(hash) => Some(hash != head.hash),
+                Err(err) => {
+                    tracing::warn!("Reference endpoint failed to get the block: {}", err);
+                    None
+                }
+            }
+        } else {
+            Some(false)
+        };
+        let lag = reference_head.height.saturating_sub(head.height);
+
+        Ok(HeadComparison {
+            healthy: fork != Some(true) && lag <= self.max_lag,
+            head,
+            reference_head,
+            lag,
+            max_lag: self.max_lag,
+            fork,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+
+    struct MockBabel {
+        head: Option<u64>,
+        // hashes of the blocks the node serves by height
+        hashes: HashMap<u64, String>,
+    }
+
+    fn node(head: Option<u64>, hashes: &[(u64, &str)]) -> Arc<dyn Babel> {
+        Arc::new(MockBabel {
+            head,
+            hashes: hashes
+                .iter()
+                .map(|(height, hash)| (*height, hash.to_string()))
+                .collect(),
+        })
+    }
+
+    #[async_trait]
+    impl Babel for MockBabel {
+        async fn peer_count(&self) -> eyre::Result<u64> {
+            Ok(1)
+        }
+
+        async fn head(&self) -> eyre::Result<Head> {
+            let height = self.head.ok_or_else(|| eyre::eyre!("node is down"))?;
+            Ok(Head {
+                height,
+                hash: self.block_hash(height).await?,
+            })
+        }
+
+        async fn block_hash(&self, height: u64) -> eyre::Result<String> {
+            self.hashes
+                .get(&height)
+                .cloned()
+                .ok_or_else(|| eyre::eyre!("block {} is pruned", height))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_head_is_compared_with_the_highest_reference() -> eyre::Result<()> {
+        let local = node(Some(100), &[(100, "a")]);
+        let references = vec![
+            node(None, &[]),
+            node(Some(105), &[(100, "a"), (105, "b")]),
+            node(Some(120), &[(100, "a"), (120, "c")]),
+        ];
+
+        let comparison = HeadReference::new(references.clone(), 10)
+            .compare(local.as_ref())
+            .await?;
+        assert_eq!(comparison.reference_head.height, 120);
+        assert_eq!(comparison.lag, 20);
+        assert_eq!(comparison.fork, Some(false));
+        assert!(!comparison.healthy);
+
+        let comparison = HeadReference::new(references, 20)
+            .compare(local.as_ref())
+            .await?;
+        assert!(comparison.healthy);
+
+        let down = HeadReference::new(vec![node(None, &[])], 10);
+        assert!(down.compare(local.as_ref()).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fork_of_the_node() -> eyre::Result<()> {
+        let local = node(Some(100), &[(100, "a")]);
+        let forked = HeadReference::new(vec![node(Some(101), &[(100, "x"), (101, "y")])], 10);
+        let comparison = forked.compare(local.as_ref()).await?;
+        assert_eq!(comparison.fork, Some(true));
+        assert!(!comparison.healthy);
+
+        // the reference pruned the block, the fork is unknown
+        let pruned = HeadReference::new(vec![node(Some(101), &[(101, "y")])], 10);
+        let comparison = pruned.compare(local.as_ref()).await?;
+        assert_eq!(comparison.fork, None);
+        assert!(comparison.healthy);
+
+        // a node ahead of the reference is not compared
+        let behind = HeadReference::new(vec![node(Some(90), &[(90, "z")])], 10);
+        let comparison = behind.compare(local.as_ref()).await?;
+        assert_eq!((comparison.fork, comparison.lag), (Some(false), 0));
+        Ok(())
+    }
+}
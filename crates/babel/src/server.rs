@@ -1,56 +1,122 @@
-use crate::{Babel, HealthStatus};
-use axum::{
-    extract::State,
-    http::StatusCode,
-    response::IntoResponse,
-    routing::get,
-    Json, Router,
-};
+use crate::history::unix_time;
+use crate::{Babel, HeadReference, HealthHistory, HealthStatus};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
 use std::sync::Arc;
 
 pub struct BabelServer {
     babel: Arc<dyn Babel>,
+    reference: Option<Arc<HeadReference>>,
+    history: Option<Arc<HealthHistory>>,
+}
+
+#[derive(Clone)]
+struct AppState {
+    babel: Arc<dyn Babel>,
+    reference: Option<Arc<HeadReference>>,
+    history: Option<Arc<HealthHistory>>,
 }
 
 impl BabelServer {
     pub fn new(babel: impl Babel + 'static) -> Self {
+        Self::from_arc(Arc::new(babel))
+    }
+
+    pub fn from_arc(babel: Arc<dyn Babel>) -> Self {
         Self {
-            babel: Arc::new(babel),
+            babel,
+            reference: None,
+            history: None,
         }
     }
 
+    /// Compares the head of the node against the reference endpoints in `/head`
+    pub fn with_reference(mut self, reference: HeadReference) -> Self {
+        self.reference = Some(Arc::new(reference));
+        self
+    }
+
+    /// Serves the health transitions recorded by the poller in `/history`
+    /// and the evaluation of its peer trend in `/ready`
+    pub fn with_history(mut self, history: Arc<HealthHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
     pub fn router(self) -> Router {
         Router::new()
             .route("/health", get(health_handler))
             .route("/peers", get(peers_handler))
-            .with_state(self.babel)
+            .route("/head", get(head_handler))
+            .route("/history", get(history_handler))
+            .route("/ready", get(ready_handler))
+            .with_state(AppState {
+                babel: self.babel,
+                reference: self.reference,
+                history: self.history,
+            })
     }
 
     pub async fn serve(self, addr: &str) -> eyre::Result<()> {
         let listener = tokio::net::TcpListener::bind(addr).await?;
         tracing::info!("Babel server listening on {}", addr);
 
-        axum::serve(listener, self.router())
-            .await?;
+        axum::serve(listener, self.router()).await?;
 
         Ok(())
     }
 }
 
-async fn health_handler(
-    State(babel): State<Arc<dyn Babel>>,
-) -> Result<Json<HealthStatus>, AppError> {
-    let status = babel.health_status().await?;
+async fn health_handler(State(state): State<AppState>) -> Result<Json<HealthStatus>, AppError> {
+    let status = state.babel.health_status().await?;
     Ok(Json(status))
 }
 
-async fn peers_handler(
-    State(babel): State<Arc<dyn Babel>>,
-) -> Result<Json<PeersResponse>, AppError> {
-    let count = babel.peer_count().await?;
+async fn peers_handler(State(state): State<AppState>) -> Result<Json<PeersResponse>, AppError> {
+    let count = state.babel.peer_count().await?;
     Ok(Json(PeersResponse { peers: count }))
 }
 
+/// Head of the node, compared against the references if any. A node that is
+/// behind them or on another fork replies with 503.
+async fn head_handler(State(state): State<AppState>) -> Result<axum::response::Response, AppError> {
+    let Some(reference) = state.reference else {
+        let head = state.babel.head().await?;
+        return Ok(Json(head).into_response());
+    };
+
+    let comparison = reference.compare(state.babel.as_ref()).await?;
+    let status = if comparison.healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok((status, Json(comparison)).into_response())
+}
+
+/// Health transitions and uptime of the node, 404 if its health is not
+/// polled
+async fn history_handler(State(state): State<AppState>) -> axum::response::Response {
+    match state.history {
+        Some(history) => Json(history.report(unix_time())).into_response(),
+        None => (StatusCode::NOT_FOUND, "Health history is not enabled").into_response(),
+    }
+}
+
+/// Readiness of the node from the polls, 503 if its last poll failed or its
+/// peers are falling
+async fn ready_handler(State(state): State<AppState>) -> axum::response::Response {
+    let Some(history) = state.history else {
+        return (StatusCode::NOT_FOUND, "Health history is not enabled").into_response();
+    };
+    let readiness = history.readiness(unix_time());
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness)).into_response()
+}
+
 #[derive(serde::Serialize)]
 struct PeersResponse {
     peers: u64,
@@ -1,37 +1,630 @@
-use runtime_docker_compose::DockerRuntime;
-use runtime_trait::Runtime;
-use spec::{Dep, Manifest};
-use std::{env, fs};
+use bbuilder::Client;
+use bbuilder::config::Config;
+use bbuilder::error::{Error, ErrorKind, ResultExt};
+use bbuilder::keys::PASSPHRASE_SECRET;
+use bbuilder::telemetry::{self, LogFormat};
+use catalog::{DeploymentRegistry, Migration};
+use clap::{
+    Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+    builder::PossibleValuesParser,
+};
+use clap_complete::Shell;
+use serde::Serialize;
+use spec::{Arch, Dep, EnvSecretProvider, SecretProvider};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+use tracing_subscriber::filter::LevelFilter;
+
+#[derive(Parser)]
+#[command(name = "bbuilder")]
+#[command(about = "Deploy blockchain infrastructure from declarative configurations", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Format of the log output
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Minimum log level (trace, debug, info, warn, error)
+    #[arg(long, global = true, default_value_t = LevelFilter::INFO)]
+    log_level: LevelFilter,
+
+    /// Format of the command output
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+}
+
+/// Format of the results printed to stdout by the commands
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Deploy the given deployment input file
+    Deploy {
+        /// Path to the deployment input file
+        input: PathBuf,
+
+        /// Wait until all the specs of the deployment are healthy, then run
+        /// the smoke tests of the deployment
+        #[arg(long)]
+        wait: bool,
+
+        /// Maximum time to wait for the deployment to be healthy (e.g. 10m, 90s)
+        #[arg(long, default_value = "10m", value_parser = humantime::parse_duration, requires = "wait")]
+        timeout: Duration,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Deploy the given deployment input file with the chain data of another
+    /// client imported into its execution node
+    Migrate {
+        /// Path to the deployment input file
+        input: PathBuf,
+
+        /// Client the chain data is exported from
+        #[arg(long, value_parser = PossibleValuesParser::new(["geth"]))]
+        from: String,
+
+        /// Data directory of the client the chain data is exported from
+        #[arg(long)]
+        from_datadir: PathBuf,
+
+        /// Pod whose node imports the chain data
+        #[arg(long, default_value = "el")]
+        pod: String,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Print the manifest generated for the given deployment input file
+    Plan {
+        /// Path to the deployment input file
+        input: PathBuf,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Print the nftables rules that keep the internal host ports of the given
+    /// deployment input file off the network
+    Firewall {
+        /// Path to the deployment input file
+        input: PathBuf,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Adopt a docker-compose project that was not deployed by bbuilder
+    Adopt {
+        /// Path to the compose file of the project
+        compose_file: PathBuf,
+
+        /// Name of the deployment [default: the name of the compose project]
+        #[arg(long)]
+        name: Option<String>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Print the status and resource usage of the specs of a deployment
+    Status {
+        /// Name of the deployment
+        deployment: String,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Stop the specs of a deployment for maintenance, they keep their ports and data
+    Pause {
+        /// Name of the deployment
+        deployment: String,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Start again the specs of a paused deployment
+    Resume {
+        /// Name of the deployment
+        deployment: String,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Remove the specs of a deployment and release its host ports, its data is kept
+    Destroy {
+        /// Name of the deployment
+        deployment: String,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Export the EIP-3076 slashing protection of the validator client of a deployment
+    SlashingProtection {
+        /// Name of the deployment
+        deployment: String,
+
+        /// File the interchange is written to, stdout if not set
+        #[arg(long)]
+        output_file: Option<PathBuf>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Replace the node identity keys (p2p key, tendermint node key) of a
+    /// component of a deployment. The old keys are archived encrypted with the
+    /// passphrase of the BBUILDER_SECRET_KEYS_ARCHIVE environment variable.
+    RotateKeys {
+        /// Name of the deployment
+        deployment: String,
+
+        /// Pod of the component whose keys are rotated
+        component: String,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Generate the genesis files of a devnet from its parameters
+    Genesis {
+        /// Chain family of the devnet
+        #[arg(value_enum)]
+        chain: GenesisChain,
+
+        /// Path to the genesis parameters file (json)
+        params: PathBuf,
+
+        /// Directory where the genesis files are written
+        #[arg(long, default_value = ".")]
+        output_dir: PathBuf,
+    },
+    /// List the modules, chains and components available in the catalog
+    Catalog {
+        /// Only list the given module
+        module: Option<String>,
+
+        /// Print the JSON Schema of the deployment input args of the modules
+        #[arg(long)]
+        schema: bool,
+    },
+    /// Print the shell completions of bbuilder
+    Completions {
+        /// Shell to generate the completions for
+        shell: Shell,
+    },
+    /// Print the manpage of bbuilder
+    Manpage,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum GenesisChain {
+    Ethereum,
+    Cosmos,
+}
+
+/// Builds the clap command with the module names of the catalog and the
+/// plugins of the config as the possible values of the module args, so they
+/// are part of the completions
+fn cli_command() -> clap::Command {
+    // an invalid config is reported by the command that loads it
+    let catalog = Config::load()
+        .and_then(|config| config.catalog())
+        .unwrap_or_default();
+    let modules: Vec<String> = catalog.modules().map(str::to_string).collect();
+
+    Cli::command().mut_subcommand("catalog", |cmd| {
+        cmd.mut_arg("module", |arg| {
+            arg.value_parser(PossibleValuesParser::new(modules))
+        })
+    })
+}
+
+/// Flags that override the values of the bbuilder.toml config files
+#[derive(Args)]
+struct ConfigArgs {
+    /// Runtime that deploys the manifest (docker-compose, podman, nomad) [default: docker-compose]
+    #[arg(long)]
+    runtime: Option<String>,
+
+    /// Directory where the deployment files are written [default: composer]
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Docker daemon address (e.g. unix:///var/run/docker.sock)
+    #[arg(long)]
+    docker_host: Option<String>,
+
+    /// Docker Hub mirror used to pull the images (can be repeated)
+    #[arg(long = "registry-mirror")]
+    registry_mirrors: Option<Vec<String>>,
+
+    /// Chain of the deployment when the input does not set one
+    #[arg(long)]
+    chain: Option<String>,
+
+    /// Architecture the images are selected for (amd64, arm64) [default: the one of the docker daemon]
+    #[arg(long)]
+    arch: Option<Arch>,
+}
+
+impl From<ConfigArgs> for Config {
+    fn from(args: ConfigArgs) -> Self {
+        Config {
+            runtime: args.runtime,
+            output_dir: args.output_dir,
+            docker_host: args.docker_host,
+            registry_mirrors: args.registry_mirrors,
+            registry_auths: None,
+            plugins: None,
+            backup_location: None,
+            babel: None,
+            chain: args.chain,
+            arch: args.arch,
+        }
+    }
+}
 
 #[tokio::main]
-async fn main() -> eyre::Result<()> {
-    // Get filename from command-line arguments
-    let args: Vec<String> = env::args().collect();
-    let filename = &args[1];
+async fn main() -> ExitCode {
+    let cli = Cli::from_arg_matches(&cli_command().get_matches()).unwrap_or_else(|err| err.exit());
+    let output = cli.output;
 
-    let contents = fs::read_to_string(filename)?;
-    let input: Dep = serde_json::from_str(contents.as_str())?;
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            let summary = err.summary();
+            match output {
+                OutputFormat::Text => eprintln!("{}", summary),
+                OutputFormat::Json => {
+                    if let Err(err) = print_json(&serde_json::json!({ "error": summary })) {
+                        eprintln!("{}", err);
+                    }
+                }
+            }
+            ExitCode::from(summary.exit_code)
+        }
+    }
+}
 
-    println!("input {:?}", input);
+async fn run(cli: Cli) -> Result<(), Error> {
+    let _telemetry = telemetry::init(cli.log_format, cli.log_level)?;
 
-    let manifest = catalog::apply(input)?;
+    match cli.command {
+        Command::Deploy {
+            input,
+            wait,
+            timeout,
+            config,
+        } => {
+            let config = load_config(config)?;
+            deploy(&input, config, wait.then_some(timeout)).await
+        }
+        Command::Migrate {
+            input,
+            from,
+            from_datadir,
+            pod,
+            config,
+        } => {
+            let config = load_config(config)?;
+            let migration = Migration {
+                from: from.parse().kind(ErrorKind::InvalidInput)?,
+                datadir: fs::canonicalize(&from_datadir)
+                    .map_err(|err| {
+                        eyre::eyre!("Failed to read {}: {}", from_datadir.display(), err)
+                    })
+                    .kind(ErrorKind::InvalidInput)?
+                    .to_string_lossy()
+                    .to_string(),
+                pod,
+            };
+            Client::new(&config)?
+                .migrate(load_input(&input)?, &migration)
+                .await?;
+            Ok(())
+        }
+        Command::Plan { input, config } => {
+            let config = load_config(config)?;
+            plan(&input, config, cli.output).await
+        }
+        Command::Firewall { input, config } => {
+            let config = load_config(config)?;
+            let manifest = Client::new(&config)?.plan(load_input(&input)?).await?;
+            print!("{}", manifest.nftables_rules());
+            Ok(())
+        }
+        Command::Adopt {
+            compose_file,
+            name,
+            config,
+        } => {
+            let config = load_config(config)?;
+            let manifest = Client::new(&config)?.adopt(&compose_file, name).await?;
+            tracing::info!(deployment = %manifest.name, module = %manifest.module, "Adopted deployment");
+            Ok(())
+        }
+        Command::Status { deployment, config } => {
+            let config = load_config(config)?;
+            status(&deployment, config, cli.output).await
+        }
+        Command::Pause { deployment, config } => {
+            let config = load_config(config)?;
+            Client::new(&config)?.pause(&deployment).await
+        }
+        Command::Resume { deployment, config } => {
+            let config = load_config(config)?;
+            Client::new(&config)?.resume(&deployment).await
+        }
+        Command::Destroy { deployment, config } => {
+            let config = load_config(config)?;
+            Client::new(&config)?.destroy(&deployment).await
+        }
+        Command::SlashingProtection {
+            deployment,
+            output_file,
+            config,
+        } => {
+            let config = load_config(config)?;
+            let interchange = Client::new(&config)?
+                .export_slashing_protection(&deployment)
+                .await?;
+            match output_file {
+                Some(path) => fs::write(path, interchange).map_err(eyre::Report::from)?,
+                None => print!("{}", interchange),
+            }
+            Ok(())
+        }
+        Command::RotateKeys {
+            deployment,
+            component,
+            config,
+        } => {
+            let config = load_config(config)?;
+            let passphrase = EnvSecretProvider
+                .secret(PASSPHRASE_SECRET)
+                .and_then(|passphrase| {
+                    passphrase.ok_or_else(|| {
+                        eyre::eyre!(
+                            "{} is required to archive the old keys",
+                            EnvSecretProvider::var_name(PASSPHRASE_SECRET)
+                        )
+                    })
+                })
+                .kind(ErrorKind::InvalidInput)?;
+            let archive = Client::new(&config)?
+                .rotate_keys(&deployment, &component, &passphrase)
+                .await?;
+            tracing::info!(archive = %archive.display(), "Rotated the identity keys");
+            Ok(())
+        }
+        Command::Genesis {
+            chain,
+            params,
+            output_dir,
+        } => write_genesis(chain, &params, &output_dir),
+        Command::Catalog { module, schema } => {
+            let catalog = Config::load()
+                .and_then(|config| config.catalog())
+                .kind(ErrorKind::InvalidInput)?;
+            if schema {
+                return Ok(print_schemas(&catalog, module.as_deref())?);
+            }
+            Ok(print_catalog(&catalog, module.as_deref(), cli.output)?)
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut cli_command(),
+                "bbuilder",
+                &mut std::io::stdout(),
+            );
+            Ok(())
+        }
+        Command::Manpage => clap_mangen::Man::new(cli_command())
+            .render(&mut std::io::stdout())
+            .kind(ErrorKind::Internal),
+    }
+}
 
-    let svc = Service::new(DockerRuntime::new("composer".to_string()));
-    svc.deploy(manifest).await?;
+/// Merges the flags over the bbuilder.toml config files
+fn load_config(args: ConfigArgs) -> Result<Config, Error> {
+    let config = Config::load().kind(ErrorKind::InvalidInput)?;
+    Ok(config.merge(args.into()))
+}
+
+/// Reads the deployment input file
+fn load_input(input: &Path) -> Result<Dep, Error> {
+    let contents = fs::read_to_string(input)
+        .map_err(|err| eyre::eyre!("Failed to read {}: {}", input.display(), err))
+        .kind(ErrorKind::InvalidInput)?;
+    serde_json::from_str(contents.as_str()).kind(ErrorKind::InvalidInput)
+}
 
+/// Generates the genesis files of the params into the output directory
+fn write_genesis(chain: GenesisChain, params: &Path, output_dir: &Path) -> Result<(), Error> {
+    let contents = fs::read_to_string(params)
+        .map_err(|err| eyre::eyre!("Failed to read {}: {}", params.display(), err))
+        .kind(ErrorKind::InvalidInput)?;
+    let files = match chain {
+        GenesisChain::Ethereum => {
+            let params = serde_json::from_str(&contents).kind(ErrorKind::InvalidInput)?;
+            genesis::EthereumGenesis::generate(&params)
+                .kind(ErrorKind::InvalidInput)?
+                .files()?
+        }
+        GenesisChain::Cosmos => {
+            let params = serde_json::from_str(&contents).kind(ErrorKind::InvalidInput)?;
+            genesis::CosmosGenesis::generate(&params)
+                .kind(ErrorKind::InvalidInput)?
+                .files()?
+        }
+    };
+
+    fs::create_dir_all(output_dir).map_err(eyre::Report::from)?;
+    for file in files {
+        let path = output_dir.join(&file.name);
+        fs::write(&path, file.contents).map_err(eyre::Report::from)?;
+        tracing::info!(path = %path.display(), "Wrote genesis file");
+    }
     Ok(())
 }
 
-struct Service {
-    runtime: DockerRuntime,
+/// Deploys the input, if `wait` is set it blocks until the deployment is
+/// healthy or the timeout expires
+async fn deploy(input: &Path, config: Config, wait: Option<Duration>) -> Result<(), Error> {
+    let client = Client::new(&config)?;
+    let manifest = client.apply(load_input(input)?).await?;
+
+    // the smoke tests need the nodes to be serving
+    if let Some(timeout) = wait {
+        client.wait(&manifest.name, timeout).await?;
+        client.smoke_test(&manifest).await?;
+    }
+    Ok(())
 }
 
-impl Service {
-    fn new(runtime: DockerRuntime) -> Self {
-        Self { runtime }
+async fn status(deployment: &str, config: Config, output: OutputFormat) -> Result<(), Error> {
+    let statuses = Client::new(&config)?.status(deployment, true).await?;
+
+    if let OutputFormat::Json = output {
+        return Ok(print_json(&statuses)?);
     }
 
-    async fn deploy(&self, manifest: Manifest) -> eyre::Result<()> {
-        self.runtime.run(manifest).await
+    println!(
+        "{:<24} {:<10} {:<10} {:>7} {:>21} {:>21} {:>21}",
+        "SPEC", "STATE", "HEALTH", "CPU", "MEM", "NET RX/TX", "DISK R/W"
+    );
+    for status in statuses {
+        let (cpu, memory, network, disk) = match &status.stats {
+            Some(stats) => (
+                format!("{:.1}%", stats.cpu_percent),
+                format!(
+                    "{} / {}",
+                    format_bytes(stats.memory_bytes),
+                    format_bytes(stats.memory_limit_bytes)
+                ),
+                format!(
+                    "{} / {}",
+                    format_bytes(stats.network_rx_bytes),
+                    format_bytes(stats.network_tx_bytes)
+                ),
+                format!(
+                    "{} / {}",
+                    format_bytes(stats.disk_read_bytes),
+                    format_bytes(stats.disk_write_bytes)
+                ),
+            ),
+            None => Default::default(),
+        };
+        println!(
+            "{:<24} {:<10} {:<10} {:>7} {:>21} {:>21} {:>21}",
+            format!("{}/{}", status.pod, status.spec),
+            status.state,
+            format!("{:?}", status.health).to_lowercase(),
+            cpu,
+            memory,
+            network,
+            disk
+        );
     }
+    Ok(())
+}
+
+/// Formats a byte count with binary units (e.g. 1.5GiB)
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[0])
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
+
+async fn plan(input: &Path, config: Config, output: OutputFormat) -> Result<(), Error> {
+    let manifest = Client::new(&config)?.plan(load_input(input)?).await?;
+
+    match output {
+        OutputFormat::Text => print!("{}", manifest.to_yaml()?),
+        OutputFormat::Json => print_json(&manifest)?,
+    }
+    Ok(())
+}
+
+/// Prints the value as pretty json. It goes through `serde_json::Value` so
+/// that the keys of the maps are sorted and the output is stable.
+fn print_json<T: Serialize>(value: &T) -> eyre::Result<()> {
+    let value = serde_json::to_value(value)?;
+    println!("{}", serde_json::to_string_pretty(&value)?);
+    Ok(())
+}
+
+/// Prints the JSON Schema of the input of the modules, by module name
+fn print_schemas(catalog: &DeploymentRegistry, module: Option<&str>) -> eyre::Result<()> {
+    let mut schemas = catalog.schemas();
+    schemas.retain(|name, _| module.is_none_or(|module| name == module));
+    print_json(&schemas)
+}
+
+fn print_catalog(
+    catalog: &DeploymentRegistry,
+    module: Option<&str>,
+    output: OutputFormat,
+) -> eyre::Result<()> {
+    let modules: Vec<_> = catalog
+        .capabilities()
+        .into_iter()
+        .filter(|capabilities| module.is_none_or(|module| capabilities.module == module))
+        .collect();
+
+    if let OutputFormat::Json = output {
+        return print_json(&modules);
+    }
+
+    let format_chains = |chains: &[catalog::ChainCapability]| {
+        chains
+            .iter()
+            .map(|chain| {
+                match (
+                    chain.min_version.as_str(),
+                    chain.recommended_version.as_str(),
+                ) {
+                    ("", "") => chain.chain.clone(),
+                    (min_version, "") => format!("{} (>= {})", chain.chain, min_version),
+                    (min_version, recommended) => format!(
+                        "{} (>= {}, recommended {})",
+                        chain.chain, min_version, recommended
+                    ),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    for module in modules {
+        println!("{}", module.module);
+        println!("  chains: {}", format_chains(&module.chains));
+        println!("  components:");
+        for component in module.components {
+            println!(
+                "    {}: {}",
+                component.name,
+                format_chains(&component.chains)
+            );
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,1252 @@
+use crate::config::{Config, DEFAULT_RUNTIME, NOMAD_RUNTIME, PODMAN_RUNTIME};
+use crate::error::{Error, ErrorKind, ResultExt};
+use crate::keys::{KEYS_DIR, KeyArchive};
+use crate::ports::{self, PortRegistry};
+use catalog::{DeploymentRegistry, Migration};
+use runtime_docker_compose::{CRASH_LOOP, DockerRuntime, Engine, PAUSED};
+use runtime_nomad::NomadRuntime;
+use runtime_trait::{IdentityKey, Runtime, SpecStatus};
+use spec::{Arch, Artifacts, Context, Dep, HostPort, Manifest, SmokeTest};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::Instant;
+
+// pod of the validator client in the ethereum deployments
+const VALIDATOR_POD: &str = "vc";
+
+// manifest of the last deploy inside the folder of the deployment
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+// the host ports are published on the docker daemon, the local one unless
+// docker_host points to a remote one
+const SMOKE_TEST_HOST: &str = "127.0.0.1";
+const SMOKE_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// High level API to plan, deploy and manage deployments, the cli is a thin
+/// wrapper over it
+pub struct Client {
+    runtime: Box<dyn Runtime + Send + Sync>,
+    // directory where the runtime writes the deployment files
+    output_dir: PathBuf,
+    // chain of the deployment inputs that do not set one
+    chain: Option<String>,
+    // architecture of the images, detected from the docker daemon the first
+    // time a manifest needs it if not set
+    arch: tokio::sync::OnceCell<Arch>,
+    // port registry shared by the deployments of the host, ports are not
+    // tracked when it is not set
+    registry_path: Option<PathBuf>,
+    // modules the deployment inputs can name
+    catalog: DeploymentRegistry,
+    // host the smoke tests reach the published ports on
+    smoke_test_host: String,
+    // storage the key archives are uploaded to, they are only kept in the
+    // output dir when it is not set
+    backup_location: Option<String>,
+}
+
+impl Client {
+    /// Client for the runtime of the config, the host ports are tracked in
+    /// the default port registry
+    pub fn new(config: &Config) -> Result<Self, Error> {
+        // podman runs the same compose file through its docker compatible API
+        let engine = match config.runtime() {
+            DEFAULT_RUNTIME => Engine::Docker,
+            PODMAN_RUNTIME => Engine::podman(),
+            NOMAD_RUNTIME => {
+                // the ports are published on the Nomad clients, reached
+                // through the host of the agent
+                let addr = std::env::var("NOMAD_ADDR").ok();
+                return Self::from_runtime(
+                    config,
+                    Box::new(NomadRuntime::from_env()),
+                    daemon_host(addr.as_deref()),
+                );
+            }
+            runtime => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    eyre::eyre!("Unknown runtime: {}", runtime),
+                ));
+            }
+        };
+        let runtime = DockerRuntime::with_docker_host(
+            config.output_dir().to_string_lossy().to_string(),
+            config.docker_host.clone().or_else(|| engine.socket()),
+        )
+        .with_registry(config.registry())
+        .with_engine(engine)
+        .with_babel(config.babel.unwrap_or_default());
+        Self::from_runtime(
+            config,
+            Box::new(runtime),
+            daemon_host(config.docker_host.as_deref()),
+        )
+    }
+
+    fn from_runtime(
+        config: &Config,
+        runtime: Box<dyn Runtime + Send + Sync>,
+        smoke_test_host: String,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            runtime,
+            output_dir: config.output_dir(),
+            chain: config.chain.clone(),
+            arch: tokio::sync::OnceCell::new_with(config.arch),
+            registry_path: ports::registry_path(),
+            catalog: config.catalog().kind(ErrorKind::InvalidInput)?,
+            smoke_test_host,
+            backup_location: config.backup_location.clone(),
+        })
+    }
+
+    pub fn with_registry_path(mut self, path: Option<PathBuf>) -> Self {
+        self.registry_path = path;
+        self
+    }
+
+    /// Deploys the modules of the registry, the modules of other crates are
+    /// registered in it on top of the ones of the catalog
+    pub fn with_catalog(mut self, catalog: DeploymentRegistry) -> Self {
+        self.catalog = catalog;
+        self
+    }
+
+    /// Deploys with the runtime instead of the one of the config, it writes
+    /// the deployment files to the output dir of the config
+    pub fn with_runtime(mut self, runtime: impl Runtime + Send + Sync + 'static) -> Self {
+        self.runtime = Box::new(runtime);
+        self
+    }
+
+    pub fn runtime(&self) -> &dyn Runtime {
+        self.runtime.as_ref()
+    }
+
+    /// Builds the manifest of the deployment without deploying it
+    pub async fn plan(&self, dep: Dep) -> Result<Manifest, Error> {
+        let (manifest, _) = self.build(dep).await?;
+        Ok(manifest)
+    }
+
+    /// Deploys the deployment and records the host ports it claims
+    #[tracing::instrument(skip_all, fields(module = %dep.module))]
+    pub async fn apply(&self, dep: Dep) -> Result<Manifest, Error> {
+        let (manifest, registry) = self.build(dep).await?;
+        self.deploy(manifest, registry).await
+    }
+
+    /// Deploys the deployment with the chain data of another client migrated
+    /// into it, the migration runs before the node of the pod starts
+    #[tracing::instrument(skip_all, fields(module = %dep.module, from = migration.from.name()))]
+    pub async fn migrate(&self, dep: Dep, migration: &Migration) -> Result<Manifest, Error> {
+        let ctx = Context {
+            versions: dep.versions.clone(),
+            ..Default::default()
+        };
+        let (mut manifest, registry) = self.build(dep).await?;
+        catalog::migrate(&mut manifest, migration, &ctx).kind(ErrorKind::InvalidInput)?;
+        manifest.validate().kind(ErrorKind::Catalog)?;
+        self.deploy(manifest, registry).await
+    }
+
+    /// Runs the manifest and records the host ports it claims
+    async fn deploy(
+        &self,
+        mut manifest: Manifest,
+        registry: Option<PortRegistry>,
+    ) -> Result<Manifest, Error> {
+        // the generated contents are saved with the manifest, the runtime
+        // only resolves the url ones
+        resolve_generators(&mut manifest)
+            .await
+            .kind(ErrorKind::Catalog)?;
+        self.check_data_dirs(&manifest)?;
+        self.runtime
+            .run(manifest.clone())
+            .await
+            .kind(ErrorKind::Runtime)?;
+
+        if let (Some(mut registry), Some(path)) = (registry, &self.registry_path) {
+            registry.claim(&manifest);
+            registry.save(path)?;
+        }
+        // the Nomad runtime writes no deployment files, the folder is
+        // created for the manifest
+        let deployment_dir = self.output_dir.join(&manifest.name);
+        std::fs::create_dir_all(&deployment_dir).kind(ErrorKind::Internal)?;
+        std::fs::write(
+            deployment_dir.join(MANIFEST_FILE),
+            serde_json::to_string_pretty(&manifest).kind(ErrorKind::Internal)?,
+        )
+        .kind(ErrorKind::Internal)?;
+        Ok(manifest)
+    }
+
+    /// Manifest of the last deploy of the deployment, saved manifests of
+    /// older versions are migrated
+    pub fn manifest(&self, deployment: &str) -> Result<Manifest, Error> {
+        let path = self.output_dir.join(deployment).join(MANIFEST_FILE);
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|err| eyre::eyre!("Failed to read {}: {}", path.display(), err))
+            .kind(ErrorKind::InvalidInput)?;
+        let value = serde_json::from_str(&contents).kind(ErrorKind::InvalidInput)?;
+        Manifest::from_json(value).kind(ErrorKind::InvalidInput)
+    }
+
+    /// Fails if the data dir of a spec moved since the last deploy while the
+    /// previous one has data, the node would start over from an empty one
+    fn check_data_dirs(&self, manifest: &Manifest) -> Result<(), Error> {
+        let deployment_dir = self.output_dir.join(&manifest.name);
+        if !deployment_dir.join(MANIFEST_FILE).exists() {
+            return Ok(());
+        }
+        let previous = self.manifest(&manifest.name)?;
+        let current = manifest.data_dirs();
+
+        let moved: Vec<String> = previous
+            .data_dirs()
+            .into_iter()
+            .filter_map(|(key, old)| {
+                let new = current.get(&key).filter(|new| **new != old)?;
+                let old = deployment_dir.join(old);
+                let has_data = std::fs::read_dir(&old).is_ok_and(|mut dir| dir.next().is_some());
+                has_data.then(|| {
+                    format!(
+                        "{}/{} ({} -> {})",
+                        key.0,
+                        key.1,
+                        old.display(),
+                        deployment_dir.join(new).display()
+                    )
+                })
+            })
+            .collect();
+        if !moved.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                eyre::eyre!(
+                    "The data dirs of {} moved while the previous ones have data, move the data first: {}",
+                    manifest.name,
+                    moved.join(", ")
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Adopts a compose project that was not deployed by bbuilder. Its services
+    /// are mapped onto the pods of the catalog components they run and the
+    /// deployment is written and registered like the applied ones, so that
+    /// the next deploys and status work on it. The deployment is named after
+    /// the compose project unless `name` is set.
+    #[tracing::instrument(skip_all, fields(compose_file = %compose_file.display()))]
+    pub async fn adopt(
+        &self,
+        compose_file: &Path,
+        name: Option<String>,
+    ) -> Result<Manifest, Error> {
+        let contents = std::fs::read_to_string(compose_file)
+            .map_err(|err| eyre::eyre!("Failed to read {}: {}", compose_file.display(), err))
+            .kind(ErrorKind::InvalidInput)?;
+        let compose_dir = std::path::absolute(compose_file)
+            .map_err(eyre::Report::from)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let project = runtime_docker_compose::parse_compose(&contents, &compose_dir)
+            .kind(ErrorKind::InvalidInput)?;
+
+        // compose names the project after the directory of the file by default
+        let name = name
+            .or(project.name)
+            .or_else(|| {
+                compose_dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .ok_or_else(|| eyre::eyre!("The deployment of the compose file needs a name"))
+            .kind(ErrorKind::InvalidInput)?;
+
+        let mut manifest = Manifest::new(name);
+        manifest.chain = self.chain.clone().unwrap_or_default();
+        let mut modules = BTreeSet::new();
+        for (service, adopted) in project.services {
+            let (pod, spec) = match (&adopted.metadata, catalog::identify(&adopted.spec)) {
+                (Some(metadata), _) => {
+                    if !metadata.module.is_empty() {
+                        modules.insert(metadata.module.clone());
+                    }
+                    if !metadata.chain.is_empty() {
+                        manifest.chain = metadata.chain.clone();
+                    }
+                    (metadata.pod.clone(), metadata.spec.clone())
+                }
+                (None, Some(component)) => {
+                    tracing::info!(%service, component = component.name, "Adopted service");
+                    modules.insert(component.module.to_string());
+                    (component.pod.to_string(), component.spec.to_string())
+                }
+                (None, None) => {
+                    tracing::warn!(%service, "Service is not a catalog component, adopted as is");
+                    (service.clone(), "node".to_string())
+                }
+            };
+
+            let specs = &mut manifest.pods.entry(pod.clone()).or_default().specs;
+            if specs.contains_key(&spec) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    eyre::eyre!("Service {} is a second {}/{}", service, pod, spec),
+                ));
+            }
+            // the published ports keep their host ports
+            for port in &adopted.spec.ports {
+                let Some(host_port) = adopted.host_ports.get(&port.name) else {
+                    continue;
+                };
+                manifest.allocated_ports.push(HostPort {
+                    pod: pod.clone(),
+                    spec: spec.clone(),
+                    name: port.name.clone(),
+                    port: port.port,
+                    host_port: *host_port,
+                    protocol: port.protocol,
+                    public: port.expose_publicly,
+                });
+            }
+            specs.insert(spec, adopted.spec);
+        }
+
+        if modules.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                eyre::eyre!(
+                    "No service of {} runs a catalog component",
+                    compose_file.display()
+                ),
+            ));
+        }
+        if modules.len() > 1 {
+            let modules: Vec<String> = modules.into_iter().collect();
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                eyre::eyre!(
+                    "The services belong to several modules: {}",
+                    modules.join(", ")
+                ),
+            ));
+        }
+        manifest.module = modules.into_iter().next().unwrap_or_default();
+        manifest.validate().kind(ErrorKind::InvalidInput)?;
+
+        let registry = match &self.registry_path {
+            Some(path) => Some(PortRegistry::load(path)?),
+            None => None,
+        };
+        if let Some(registry) = &registry {
+            for conflict in registry.conflicts(&manifest) {
+                tracing::warn!("Host port conflict: {}", conflict);
+            }
+        }
+        self.deploy(manifest, registry).await
+    }
+
+    /// Status of the specs of the deployment, with their resource usage if
+    /// `with_stats` is set
+    pub async fn status(
+        &self,
+        deployment: &str,
+        with_stats: bool,
+    ) -> Result<Vec<SpecStatus>, Error> {
+        let status = if with_stats {
+            self.runtime.status_with_stats(deployment).await
+        } else {
+            self.runtime.status(deployment).await
+        };
+        status.kind(ErrorKind::Runtime)
+    }
+
+    /// Removes the specs of the deployment and releases its host ports, the
+    /// data of the deployment is kept
+    #[tracing::instrument(skip(self))]
+    pub async fn destroy(&self, deployment: &str) -> Result<(), Error> {
+        self.runtime
+            .destroy(deployment)
+            .await
+            .kind(ErrorKind::Runtime)?;
+
+        if let Some(path) = &self.registry_path {
+            let mut registry = PortRegistry::load(path)?;
+            registry.release(deployment);
+            registry.save(path)?;
+        }
+        Ok(())
+    }
+
+    /// Stops the specs of the deployment without removing them, its host ports
+    /// stay claimed so that it resumes with the same ports and volumes
+    #[tracing::instrument(skip(self))]
+    pub async fn pause(&self, deployment: &str) -> Result<(), Error> {
+        self.runtime
+            .pause(deployment)
+            .await
+            .kind(ErrorKind::Runtime)
+    }
+
+    /// Starts again the specs of a paused deployment
+    #[tracing::instrument(skip(self))]
+    pub async fn resume(&self, deployment: &str) -> Result<(), Error> {
+        self.runtime
+            .resume(deployment)
+            .await
+            .kind(ErrorKind::Runtime)
+    }
+
+    /// Exports the EIP-3076 slashing protection of the validator client of the
+    /// deployment. The duties signed after the export are not part of it, the
+    /// deployment must be destroyed right after when migrating the validators.
+    pub async fn export_slashing_protection(&self, deployment: &str) -> Result<String, Error> {
+        let script = format!(
+            "lighthouse account validator slashing-protection export {path} \
+             --network \"$NETWORK\" --datadir /data/validators >/dev/null && cat {path}",
+            path = "/tmp/slashing-protection.json"
+        );
+        self.runtime
+            .exec(
+                deployment,
+                VALIDATOR_POD,
+                "node",
+                vec!["sh".to_string(), "-c".to_string(), script],
+            )
+            .await
+            .kind(ErrorKind::Runtime)
+    }
+
+    /// Replaces the identity keys of the specs of the pod with new ones, the
+    /// old keys are sealed with the passphrase into an archive in the folder
+    /// of the deployment before any of them is removed. The keys written as
+    /// artifacts are generated again by the catalog and deployed, the others
+    /// are removed so that the nodes generate new ones. Returns the path of
+    /// the archive.
+    #[tracing::instrument(skip(self, passphrase))]
+    pub async fn rotate_keys(
+        &self,
+        deployment: &str,
+        pod: &str,
+        passphrase: &str,
+    ) -> Result<PathBuf, Error> {
+        let mut manifest = self.manifest(deployment)?;
+        let keys = self
+            .runtime
+            .identity_keys(deployment, pod)
+            .await
+            .kind(ErrorKind::Runtime)?;
+
+        let dir = self.output_dir.join(deployment).join(KEYS_DIR);
+        std::fs::create_dir_all(&dir).kind(ErrorKind::Internal)?;
+        let archive = KeyArchive::new(deployment, pod, keys.clone())
+            .save(&dir, passphrase)
+            .kind(ErrorKind::Internal)?;
+        if let Some(location) = &self.backup_location {
+            backup(location, deployment, &archive)
+                .await
+                .kind(ErrorKind::Internal)?;
+        }
+
+        let (artifact_keys, node_keys): (Vec<_>, Vec<_>) = keys
+            .into_iter()
+            .partition(|key| key_artifact(&mut manifest, pod, key).is_some());
+        if !artifact_keys.is_empty() {
+            let dep = Dep {
+                name: Some(manifest.name.clone()),
+                module: manifest.module.clone(),
+                chain: manifest.chain.clone(),
+                args: manifest.input.clone(),
+                external_ip: None,
+                versions: Default::default(),
+                patch: Default::default(),
+                data_dirs: Default::default(),
+            };
+            let mut generated = self.catalog.apply(dep).map_err(Error::catalog)?;
+            for key in &artifact_keys {
+                let content = key_artifact(&mut generated, pod, key)
+                    .map(|file| file.content.clone())
+                    .ok_or_else(|| eyre::eyre!("The catalog does not generate {}", key.path))
+                    .kind(ErrorKind::Catalog)?;
+                if let Some(file) = key_artifact(&mut manifest, pod, key) {
+                    file.content = content;
+                }
+            }
+            self.deploy(manifest, None).await?;
+        }
+
+        self.runtime
+            .rotate_keys(deployment, pod, &node_keys)
+            .await
+            .kind(ErrorKind::Runtime)?;
+        Ok(archive)
+    }
+
+    /// Polls the status of the deployment until all its specs are ready
+    #[tracing::instrument(skip(self))]
+    pub async fn wait(&self, deployment: &str, timeout: Duration) -> Result<(), Error> {
+        const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status = self.status(deployment, false).await?;
+
+            let pending: Vec<String> = status
+                .iter()
+                .filter(|spec| !spec.is_ready())
+                .map(|spec| {
+                    format!(
+                        "{}/{} ({}, {:?})",
+                        spec.pod, spec.spec, spec.state, spec.health
+                    )
+                })
+                .collect();
+
+            if status.iter().any(|spec| spec.state == PAUSED) {
+                return Err(Error::new(
+                    ErrorKind::Runtime,
+                    eyre::eyre!("Deployment {} is paused", deployment),
+                ));
+            }
+
+            let crash_looping: Vec<String> = status
+                .iter()
+                .filter(|spec| spec.state == CRASH_LOOP)
+                .map(|spec| format!("{}/{}", spec.pod, spec.spec))
+                .collect();
+            if !crash_looping.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::Runtime,
+                    eyre::eyre!(
+                        "Deployment {} is degraded, crash looping: {}",
+                        deployment,
+                        crash_looping.join(", ")
+                    ),
+                ));
+            }
+
+            if !status.is_empty() && pending.is_empty() {
+                tracing::info!("Deployment is healthy");
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                let pending = if status.is_empty() {
+                    "no specs are running".to_string()
+                } else {
+                    format!("not ready: {}", pending.join(", "))
+                };
+                return Err(Error::new(
+                    ErrorKind::HealthTimeout,
+                    eyre::eyre!(
+                        "Deployment {} not healthy after {}, {}",
+                        deployment,
+                        humantime::format_duration(timeout),
+                        pending
+                    ),
+                ));
+            }
+
+            tracing::debug!(pending = pending.len(), "Waiting for the deployment");
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Runs the smoke tests of the manifest through the host ports of its specs,
+    /// the deployment should be healthy first. Every test runs and the
+    /// failures are reported together.
+    #[tracing::instrument(skip_all, fields(deployment = %manifest.name))]
+    pub async fn smoke_test(&self, manifest: &Manifest) -> Result<(), Error> {
+        let http = reqwest::Client::builder()
+            .timeout(SMOKE_TEST_TIMEOUT)
+            .build()
+            .kind(ErrorKind::Internal)?;
+        let host_ports = manifest.host_ports();
+
+        let mut failures = vec![];
+        for test in &manifest.smoke_tests {
+            match run_smoke_test(&http, &self.smoke_test_host, &host_ports, test).await {
+                Ok(()) => tracing::info!(test = %test.name, "Smoke test passed"),
+                Err(err) => failures.push(format!("{} ({}): {}", test.name, test.method, err)),
+            }
+        }
+        if !failures.is_empty() {
+            return Err(Error::new(
+                ErrorKind::SmokeTest,
+                eyre::eyre!(
+                    "Smoke tests of {} failed: {}",
+                    manifest.name,
+                    failures.join(", ")
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Builds and validates the manifest of the deployment for the architecture
+    /// of the docker daemon, the registry is returned to record the claims of
+    /// the manifest once deployed
+    async fn build(&self, mut dep: Dep) -> Result<(Manifest, Option<PortRegistry>), Error> {
+        if dep.chain.is_empty() {
+            dep.chain = self
+                .chain
+                .clone()
+                .ok_or_else(|| eyre::eyre!("The deployment input does not set a chain"))
+                .kind(ErrorKind::InvalidInput)?;
+        }
+
+        tracing::info!(module = %dep.module, chain = %dep.chain, "Loaded deployment input");
+
+        let mut manifest = self.catalog.apply(dep).map_err(Error::catalog)?;
+        manifest.validate().kind(ErrorKind::Catalog)?;
+
+        // the daemon is only queried for the images that do not run everywhere
+        if catalog::needs_platform(&manifest) {
+            let arch = self
+                .arch
+                .get_or_try_init(|| self.runtime.arch())
+                .await
+                .kind(ErrorKind::Runtime)?;
+            catalog::select_platform(&mut manifest, *arch).kind(ErrorKind::Catalog)?;
+        }
+
+        let registry = self.allocate_host_ports(&mut manifest)?;
+        Ok((manifest, registry))
+    }
+
+    /// Moves the host ports of the manifest that are already claimed by another
+    /// deployment or spec to free ones
+    fn allocate_host_ports(&self, manifest: &mut Manifest) -> Result<Option<PortRegistry>, Error> {
+        let registry = match &self.registry_path {
+            Some(path) => Some(PortRegistry::load(path)?),
+            None => None,
+        };
+        let used = registry
+            .as_ref()
+            .map(|registry| registry.used_ports(&manifest.name))
+            .unwrap_or_default();
+
+        for port in manifest.allocate_host_ports(&used) {
+            tracing::info!(
+                pod = %port.pod,
+                spec = %port.spec,
+                name = %port.name,
+                preferred = port.port,
+                host_port = port.host_port,
+                "Preferred host port in use, allocated another one"
+            );
+        }
+        if let Some(registry) = &registry {
+            for conflict in registry.conflicts(manifest) {
+                tracing::warn!("Host port conflict: {}", conflict);
+            }
+        }
+        Ok(registry)
+    }
+}
+
+/// Fetches the urls read by the generators of the manifest and runs them
+async fn resolve_generators(manifest: &mut Manifest) -> eyre::Result<()> {
+    let mut fetched = HashMap::new();
+    for url in manifest.generator_urls() {
+        tracing::info!(url = %url, "Fetching artifact content");
+        match fetch(&url).await {
+            Ok(content) => {
+                fetched.insert(url, content);
+            }
+            // the generator decides whether it has a fallback
+            Err(e) => tracing::warn!(url = %url, error = %e, "Failed to fetch artifact content"),
+        }
+    }
+    manifest.resolve_generators(&fetched)
+}
+
+async fn fetch(url: &str) -> eyre::Result<String> {
+    Ok(reqwest::get(url).await?.error_for_status()?.text().await?)
+}
+
+/// Uploads the key archive to `<location>/<deployment>/<file name>` through
+/// the storage providers of the fetcher
+async fn backup(location: &str, deployment: &str, archive: &Path) -> eyre::Result<()> {
+    let file_name = archive
+        .file_name()
+        .ok_or_else(|| eyre::eyre!("Invalid key archive path {}", archive.display()))?;
+    let location = format!(
+        "{}/{}/{}",
+        location.trim_end_matches('/'),
+        deployment,
+        file_name.to_string_lossy()
+    );
+    let archive = archive.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let (provider, key) = fetcher::storage::provider_for(&location)?;
+        provider.upload(&archive, &key)
+    })
+    .await?
+    .map_err(|err| eyre::eyre!("Failed to back up the key archive: {:#}", err))
+}
+
+/// File artifact of the manifest the identity key is materialized from
+fn key_artifact<'a>(
+    manifest: &'a mut Manifest,
+    pod: &str,
+    key: &IdentityKey,
+) -> Option<&'a mut spec::File> {
+    let spec = manifest.pods.get_mut(pod)?.specs.get_mut(&key.spec)?;
+    spec.artifacts
+        .iter_mut()
+        .find_map(|artifact| match artifact {
+            Artifacts::File(file) if file.target_path == key.path => Some(file),
+            _ => None,
+        })
+}
+
+/// Host of the docker daemon the ports are published on, the local one for
+/// unix sockets (e.g. `tcp://10.0.0.2:2376` and `ssh://user@node1` are
+/// reached on `10.0.0.2` and `node1`)
+fn daemon_host(docker_host: Option<&str>) -> String {
+    let authority = docker_host
+        .and_then(|host| host.split_once("://"))
+        .filter(|(scheme, _)| *scheme != "unix" && *scheme != "npipe")
+        .and_then(|(_, rest)| rest.split('/').next())
+        .map(|authority| authority.rsplit('@').next().unwrap_or(authority))
+        .filter(|authority| !authority.is_empty());
+    let Some(authority) = authority else {
+        return SMOKE_TEST_HOST.to_string();
+    };
+
+    // ipv6 hosts are bracketed, the brackets are kept for the url
+    let host = match authority.strip_prefix('[') {
+        Some(rest) => rest
+            .split_once(']')
+            .map(|(host, _)| format!("[{}]", host))
+            .unwrap_or_else(|| authority.to_string()),
+        None => authority
+            .split_once(':')
+            .map(|(host, _)| host)
+            .unwrap_or(authority)
+            .to_string(),
+    };
+    match host.as_str() {
+        "0.0.0.0" | "localhost" => SMOKE_TEST_HOST.to_string(),
+        _ => host,
+    }
+}
+
+/// Sends the JSON-RPC call of the test and checks its result
+async fn run_smoke_test(
+    http: &reqwest::Client,
+    host: &str,
+    host_ports: &[HostPort],
+    test: &SmokeTest,
+) -> eyre::Result<()> {
+    let port = host_ports
+        .iter()
+        .find(|port| port.pod == test.pod && port.spec == test.spec && port.name == test.port)
+        .ok_or_else(|| eyre::eyre!("{}/{} has no port {}", test.pod, test.spec, test.port))?;
+
+    let response: serde_json::Value = http
+        .post(format!("http://{}:{}", host, port.host_port))
+        .json(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": test.method,
+            "params": test.params,
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if let Some(error) = response.get("error") {
+        eyre::bail!("{}", error);
+    }
+    let result = response
+        .get("result")
+        .ok_or_else(|| eyre::eyre!("the response has no result"))?;
+    test.expect.check(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runtime_trait::Health;
+    use spec::{Expect, Pod, Spec};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_plan_uses_the_default_chain() -> Result<(), Error> {
+        let config = Config {
+            chain: Some("ethereum.sepolia".to_string()),
+            ..Default::default()
+        };
+        let client = Client::new(&config)?.with_registry_path(None);
+
+        let dep: Dep = serde_json::from_value(serde_json::json!({
+            "module": "ethereum",
+            "args": {}
+        }))
+        .unwrap();
+        let manifest = client.plan(dep).await?;
+        assert_eq!(manifest.chain, "ethereum.sepolia");
+
+        // args that are not an input of the module are not a catalog error
+        let dep: Dep = serde_json::from_value(serde_json::json!({
+            "module": "ethereum",
+            "args": { "el_node": "reth" }
+        }))
+        .unwrap();
+        let err = client.plan(dep).await.err().unwrap();
+        assert_eq!(err.kind, ErrorKind::InvalidInput);
+
+        let config = Config {
+            runtime: Some("kubernetes".to_string()),
+            ..Default::default()
+        };
+        let err = Client::new(&config).err().unwrap();
+        assert_eq!(err.kind, ErrorKind::InvalidInput);
+
+        let config = Config {
+            runtime: Some(NOMAD_RUNTIME.to_string()),
+            ..Default::default()
+        };
+        assert!(Client::new(&config).is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_adopt_maps_services_onto_the_catalog() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join("test-client-adopt");
+        let project_dir = dir.join("mainnet-node");
+        std::fs::create_dir_all(&project_dir).unwrap();
+
+        let compose_file = project_dir.join("docker-compose.yml");
+        std::fs::write(
+            &compose_file,
+            r#"
+services:
+  execution:
+    image: ghcr.io/paradigmxyz/reth:v1.4.8
+    command: ["node", "--chain", "mainnet"]
+    ports:
+      - "30304:30303"
+    volumes:
+      - ./reth:/data
+  consensus:
+    image: sigp/lighthouse:v7.0.0
+    command: lighthouse bn --network mainnet
+"#,
+        )
+        .unwrap();
+
+        let config = Config {
+            output_dir: Some(dir.join("output")),
+            ..Default::default()
+        };
+        // the compose file is written without starting its services
+        let runtime = DockerRuntime::new(dir.join("output").to_string_lossy().to_string())
+            .with_compose_command(vec!["true".to_string()]);
+        let client = Client::new(&config)?
+            .with_registry_path(None)
+            .with_runtime(runtime);
+        let manifest = client.adopt(&compose_file, None).await?;
+        assert_eq!(manifest.name, "mainnet-node");
+        assert_eq!(manifest.module, "ethereum");
+        assert!(manifest.pods["el"].specs.contains_key("node"));
+        assert!(manifest.pods["cl"].specs.contains_key("node"));
+
+        let p2p = manifest
+            .host_ports()
+            .into_iter()
+            .find(|port| port.pod == "el")
+            .unwrap();
+        assert_eq!((p2p.port, p2p.host_port), (30303, 30304));
+        assert!(dir.join("output/mainnet-node/docker-compose.yaml").exists());
+        Ok(())
+    }
+    #[test]
+    fn test_smoke_tests_reach_the_host_of_the_docker_daemon() {
+        let cases = [
+            (None, "127.0.0.1"),
+            (Some("unix:///var/run/docker.sock"), "127.0.0.1"),
+            (Some("tcp://localhost:2375"), "127.0.0.1"),
+            (Some("tcp://10.0.0.2:2376"), "10.0.0.2"),
+            (Some("ssh://user@node1"), "node1"),
+            (Some("ssh://user@node1:2222/path"), "node1"),
+            (Some("tcp://[fd00::2]:2376"), "[fd00::2]"),
+        ];
+        for (docker_host, expected) in cases {
+            assert_eq!(daemon_host(docker_host), expected, "{:?}", docker_host);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_smoke_tests_check_the_rpc_result() -> Result<(), Error> {
+        // node that answers every call with chain id 1
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host_port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).await;
+                let body = r#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                     content-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let mut manifest = Manifest::new("eth".to_string());
+        let node = Spec::builder()
+            .image("reth")
+            .arg2("--http.port", spec::port!("http", 8545));
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", node));
+        manifest.allocated_ports = manifest.host_ports();
+        manifest.allocated_ports[0].host_port = host_port;
+
+        let chain_id = |expected: &str| {
+            SmokeTest::rpc(
+                "chain-id",
+                "el",
+                "http",
+                "eth_chainId",
+                Expect::Equals(expected.into()),
+            )
+        };
+        let client = Client::new(&Config::default())?.with_registry_path(None);
+        manifest.add_smoke_test(chain_id("0x1"));
+        client.smoke_test(&manifest).await?;
+
+        manifest.add_smoke_test(chain_id("0xaa36a7"));
+        let err = client.smoke_test(&manifest).await.err().unwrap();
+        assert_eq!(err.kind, ErrorKind::SmokeTest);
+        Ok(())
+    }
+
+    #[test]
+    fn test_artifact_identity_keys_are_generated_again() -> eyre::Result<()> {
+        let dep = || -> eyre::Result<Dep> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "module": "polygon",
+                "chain": "amoy",
+                "args": { "heimdall": {}, "bor": {} },
+            }))?)
+        };
+        let mut manifest = catalog::apply(dep()?)?;
+        let mut generated = catalog::apply(dep()?)?;
+
+        let key = IdentityKey {
+            spec: "node".to_string(),
+            path: "/data/heimdall/config/node_key.json".to_string(),
+            content: String::new(),
+        };
+        assert!(
+            manifest.pods["heimdall"].specs["node"]
+                .identity_keys
+                .contains(&key.path)
+        );
+        let old = key_artifact(&mut manifest, "heimdall", &key).unwrap();
+        let new = key_artifact(&mut generated, "heimdall", &key).unwrap();
+        assert_ne!(
+            serde_json::to_string(&old.content)?,
+            serde_json::to_string(&new.content)?
+        );
+
+        // the p2p key of bor is generated by the node itself
+        let bor_key = IdentityKey {
+            spec: "bor".to_string(),
+            ..key
+        };
+        assert!(key_artifact(&mut manifest, "bor", &bor_key).is_none());
+        Ok(())
+    }
+
+    // runtime whose status replies with the next of its statuses, the last
+    // one is repeated
+    struct MockRuntime {
+        statuses: std::sync::Mutex<Vec<Vec<SpecStatus>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Runtime for MockRuntime {
+        async fn run(&self, _manifest: Manifest) -> eyre::Result<()> {
+            Ok(())
+        }
+
+        async fn status(&self, _deployment: &str) -> eyre::Result<Vec<SpecStatus>> {
+            let mut statuses = self.statuses.lock().unwrap();
+            if statuses.len() > 1 {
+                Ok(statuses.remove(0))
+            } else {
+                Ok(statuses[0].clone())
+            }
+        }
+
+        async fn status_with_stats(&self, deployment: &str) -> eyre::Result<Vec<SpecStatus>> {
+            self.status(deployment).await
+        }
+
+        async fn exec(
+            &self,
+            _deployment: &str,
+            _pod: &str,
+            _spec: &str,
+            _command: Vec<String>,
+        ) -> eyre::Result<String> {
+            eyre::bail!("exec is not supported by the mock runtime")
+        }
+
+        async fn pause(&self, _deployment: &str) -> eyre::Result<()> {
+            Ok(())
+        }
+
+        async fn resume(&self, _deployment: &str) -> eyre::Result<()> {
+            Ok(())
+        }
+
+        async fn identity_keys(
+            &self,
+            _deployment: &str,
+            _pod: &str,
+        ) -> eyre::Result<Vec<IdentityKey>> {
+            Ok(vec![])
+        }
+
+        async fn rotate_keys(
+            &self,
+            _deployment: &str,
+            _pod: &str,
+            _keys: &[IdentityKey],
+        ) -> eyre::Result<()> {
+            Ok(())
+        }
+
+        async fn destroy(&self, _deployment: &str) -> eyre::Result<()> {
+            Ok(())
+        }
+
+        fn subscribe(&self, deployment: &str) -> runtime_trait::Subscription {
+            runtime_trait::Subscription::new(deployment, tokio::sync::broadcast::channel(1).1)
+        }
+
+        async fn arch(&self) -> eyre::Result<Arch> {
+            eyre::bail!("the mock runtime has no daemon to query")
+        }
+    }
+
+    fn spec_status(spec: &str, state: &str, health: Health) -> SpecStatus {
+        SpecStatus {
+            pod: "el".to_string(),
+            spec: spec.to_string(),
+            state: state.to_string(),
+            health,
+            stats: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wait_polls_until_the_specs_are_ready() -> Result<(), Error> {
+        let starting = vec![
+            spec_status("node", "running", Health::Starting),
+            spec_status("babel", "running", Health::Unknown),
+        ];
+        let healthy = vec![
+            spec_status("node", "running", Health::Healthy),
+            spec_status("babel", "running", Health::Unknown),
+        ];
+        let cases = vec![
+            (vec![vec![], starting.clone(), healthy.clone()], None, ""),
+            (
+                vec![starting.clone()],
+                Some(ErrorKind::HealthTimeout),
+                "not ready: el/node (running, Starting)",
+            ),
+            (
+                vec![vec![]],
+                Some(ErrorKind::HealthTimeout),
+                "no specs are running",
+            ),
+            (
+                vec![
+                    starting.clone(),
+                    vec![spec_status("node", CRASH_LOOP, Health::Unknown)],
+                ],
+                Some(ErrorKind::Runtime),
+                "crash looping: el/node",
+            ),
+            (
+                vec![vec![spec_status("node", PAUSED, Health::Unknown)]],
+                Some(ErrorKind::Runtime),
+                "is paused",
+            ),
+        ];
+
+        for (statuses, kind, message) in cases {
+            let runtime = MockRuntime {
+                statuses: std::sync::Mutex::new(statuses),
+            };
+            let client = Client::new(&Config::default())?
+                .with_registry_path(None)
+                .with_runtime(runtime);
+            let result = client.wait("test", Duration::from_secs(60)).await;
+            match kind {
+                None => assert!(result.is_ok()),
+                Some(kind) => {
+                    let err = result.err().unwrap();
+                    assert_eq!(err.kind, kind);
+                    let report = err.report.to_string();
+                    assert!(report.contains(message), "{}", report);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_the_arch_is_only_queried_for_single_arch_images() -> Result<(), Error> {
+        let client = Client::new(&Config::default())?
+            .with_registry_path(None)
+            .with_runtime(MockRuntime {
+                statuses: std::sync::Mutex::new(vec![vec![]]),
+            });
+        let dep = |module: &str, chain: &str, args: serde_json::Value| -> Dep {
+            serde_json::from_value(serde_json::json!({
+                "module": module,
+                "chain": chain,
+                "args": args,
+            }))
+            .unwrap()
+        };
+        client
+            .plan(dep("ethereum", "sepolia", serde_json::json!({})))
+            .await?;
+
+        // heimdall only publishes amd64 images
+        let polygon = serde_json::json!({"heimdall": {}, "bor": {}});
+        let err = client
+            .plan(dep("polygon", "amoy", polygon))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_moving_a_data_dir_with_data_fails() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join("test-client-data-dirs");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = Config {
+            output_dir: Some(dir.join("output")),
+            ..Default::default()
+        };
+        let client = Client::new(&config)?
+            .with_registry_path(None)
+            .with_runtime(MockRuntime {
+                statuses: std::sync::Mutex::new(vec![vec![]]),
+            });
+        let dep = |data_dirs: serde_json::Value| -> Dep {
+            serde_json::from_value(serde_json::json!({
+                "name": "eth",
+                "module": "ethereum",
+                "chain": "sepolia",
+                "args": {},
+                "data_dirs": data_dirs,
+            }))
+            .unwrap()
+        };
+        std::fs::create_dir_all(dir.join("output/eth")).unwrap();
+        client.apply(dep(serde_json::json!({}))).await?;
+
+        // the data dirs move freely until the node writes to them
+        let moved = serde_json::json!({"root": dir.join("hdd")});
+        client.apply(dep(moved.clone())).await?;
+        client.apply(dep(serde_json::json!({}))).await?;
+
+        std::fs::create_dir_all(dir.join("output/eth/data/el/node/db")).unwrap();
+        let err = client.apply(dep(moved)).await.err().unwrap();
+        assert_eq!(err.kind, ErrorKind::InvalidInput);
+        assert!(err.report.to_string().contains("el/node"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_key_archives_are_uploaded_to_the_backup_location() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join("test-client-key-backups");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = Config {
+            output_dir: Some(dir.join("output")),
+            backup_location: Some(format!("file://{}/backups/", dir.display())),
+            ..Default::default()
+        };
+        let client = Client::new(&config)?
+            .with_registry_path(None)
+            .with_runtime(MockRuntime {
+                statuses: std::sync::Mutex::new(vec![vec![]]),
+            });
+        let dep: Dep = serde_json::from_value(serde_json::json!({
+            "name": "eth",
+            "module": "ethereum",
+            "chain": "sepolia",
+            "args": {},
+        }))
+        .unwrap();
+        std::fs::create_dir_all(dir.join("output/eth")).unwrap();
+        client.apply(dep).await?;
+
+        let archive = client.rotate_keys("eth", "el", "passphrase").await?;
+        let backup = dir.join("backups/eth").join(archive.file_name().unwrap());
+        assert_eq!(
+            std::fs::read(backup).unwrap(),
+            std::fs::read(&archive).unwrap()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_generated_contents_are_saved_with_the_manifest() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join("test-client-generated-contents");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = Config {
+            output_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        let client = Client::new(&config)?
+            .with_registry_path(None)
+            .with_runtime(MockRuntime {
+                statuses: std::sync::Mutex::new(vec![vec![]]),
+            });
+        let node = spec::Spec::builder()
+            .image("beacon-kit")
+            .artifact(Artifacts::File(spec::file(
+                "config",
+                "/data/config.toml",
+                spec::ArtifactSource::generator(vec![], |_| Ok("seeds = \"a\"".to_string())),
+            )));
+        let mut manifest = Manifest::new("bera".to_string());
+        manifest.add_spec(
+            "cl".to_string(),
+            spec::Pod::default().with_spec("node", node),
+        );
+        std::fs::create_dir_all(dir.join("bera")).unwrap();
+        client.deploy(manifest, None).await?;
+
+        let saved = client.manifest("bera")?;
+        let Artifacts::File(config) = &saved.pods["cl"].specs["node"].artifacts[0] else {
+            panic!("expected a file artifact");
+        };
+        assert_eq!(config.content.inline(), Some("seeds = \"a\""));
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use catalog::DeploymentRegistry;
+use runtime_docker_compose::{RegistryAuth, RegistryConfig};
+use serde::Deserialize;
+use spec::Arch;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+// name of the config file, both at the user and the project level
+pub const CONFIG_FILE: &str = "bbuilder.toml";
+
+pub const DEFAULT_RUNTIME: &str = "docker-compose";
+// compose runtime against the podman socket, rootless unless run as root
+pub const PODMAN_RUNTIME: &str = "podman";
+// Nomad job through the agent of the `NOMAD_ADDR` var
+pub const NOMAD_RUNTIME: &str = "nomad";
+pub const DEFAULT_OUTPUT_DIR: &str = "composer";
+
+/// Defaults for the cli loaded from `bbuilder.toml`. The project file (in the
+/// working directory) overrides the user file and cli flags override both.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // runtime that deploys the manifests
+    pub runtime: Option<String>,
+    // directory where the runtime writes the deployment files
+    pub output_dir: Option<PathBuf>,
+    // docker daemon used by the runtime, the local one if not set
+    pub docker_host: Option<String>,
+    // mirrors of docker hub, tried in order before it
+    pub registry_mirrors: Option<Vec<String>>,
+    // credentials of the private registries by host
+    pub registry_auths: Option<HashMap<String, RegistryAuth>>,
+    // chain of the deployment inputs that do not set one
+    pub chain: Option<String>,
+    // architecture the images are selected for, the one of the docker daemon if not set
+    pub arch: Option<Arch>,
+    // WASM deployment modules by module name, on top of the ones of the catalog
+    pub plugins: Option<BTreeMap<String, PathBuf>>,
+    // storage the key archives are uploaded to (e.g. `s3://<bucket>/keys`),
+    // see `fetcher::storage`
+    pub backup_location: Option<String>,
+    // runs the babel sidecars of the nodes, their image is built locally
+    pub babel: Option<bool>,
+}
+
+impl Config {
+    /// Loads and merges the user and project config files, missing files are skipped
+    pub fn load() -> eyre::Result<Self> {
+        let paths = [user_config_path(), Some(PathBuf::from(CONFIG_FILE))];
+
+        let mut config = Config::default();
+        for path in paths.into_iter().flatten() {
+            if path.exists() {
+                config = config.merge(Self::from_file(&path)?);
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> eyre::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents)
+            .map_err(|err| eyre::eyre!("Invalid config file {}: {}", path.display(), err))
+    }
+
+    /// Returns the config with the values set in `other` taking precedence
+    pub fn merge(self, other: Config) -> Config {
+        Config {
+            runtime: other.runtime.or(self.runtime),
+            output_dir: other.output_dir.or(self.output_dir),
+            docker_host: other.docker_host.or(self.docker_host),
+            registry_mirrors: other.registry_mirrors.or(self.registry_mirrors),
+            registry_auths: other.registry_auths.or(self.registry_auths),
+            chain: other.chain.or(self.chain),
+            arch: other.arch.or(self.arch),
+            plugins: other.plugins.or(self.plugins),
+            backup_location: other.backup_location.or(self.backup_location),
+            babel: other.babel.or(self.babel),
+        }
+    }
+
+    pub fn runtime(&self) -> &str {
+        self.runtime.as_deref().unwrap_or(DEFAULT_RUNTIME)
+    }
+
+    pub fn registry(&self) -> RegistryConfig {
+        RegistryConfig {
+            mirrors: self.registry_mirrors.clone().unwrap_or_default(),
+            auths: self.registry_auths.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Modules of the catalog with the WASM plugins of the config
+    pub fn catalog(&self) -> eyre::Result<DeploymentRegistry> {
+        #[cfg_attr(not(feature = "wasm"), allow(unused_mut))]
+        let mut catalog = DeploymentRegistry::default();
+        for (name, path) in self.plugins.iter().flatten() {
+            #[cfg(feature = "wasm")]
+            catalog.register(name.clone(), Box::new(catalog::WasmDeployment::load(path)))?;
+            #[cfg(not(feature = "wasm"))]
+            eyre::bail!(
+                "Plugin {} ({}) requires bbuilder built with the wasm feature",
+                name,
+                path.display()
+            );
+        }
+        Ok(catalog)
+    }
+
+    pub fn output_dir(&self) -> PathBuf {
+        self.output_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_OUTPUT_DIR))
+    }
+}
+
+/// `$XDG_CONFIG_HOME/bbuilder/bbuilder.toml`, falling back to `~/.config`
+fn user_config_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("bbuilder").join(CONFIG_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_config_overrides_user_config() {
+        let user: Config = toml::from_str(
+            r#"
+            output_dir = "/var/lib/bbuilder"
+            docker_host = "unix:///var/run/docker.sock"
+            chain = "mainnet"
+
+            [registry_auths."ghcr.io"]
+            username = "bbuilder"
+            password_secret = "ghcr_token"
+            "#,
+        )
+        .unwrap();
+        let project: Config = toml::from_str(r#"chain = "sepolia""#).unwrap();
+
+        let config = user.merge(project);
+        assert_eq!(config.chain.as_deref(), Some("sepolia"));
+        assert_eq!(config.output_dir(), PathBuf::from("/var/lib/bbuilder"));
+        assert_eq!(config.runtime(), DEFAULT_RUNTIME);
+        assert_eq!(config.registry().auths["ghcr.io"].username, "bbuilder");
+    }
+}
@@ -0,0 +1,178 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Class of failure of a cli command, each one maps to its own exit code so
+/// that scripts can branch on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    // unexpected failure that does not fit any other class
+    Internal,
+    // the deployment input, the config files or the flags are not valid
+    InvalidInput,
+    // the catalog could not build the manifest of the deployment
+    Catalog,
+    // the runtime failed to deploy the manifest
+    Runtime,
+    // the deployment did not become healthy in time
+    HealthTimeout,
+    // a smoke test of the deployment did not get the expected result
+    SmokeTest,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::Internal => 1,
+            ErrorKind::InvalidInput => 2,
+            ErrorKind::Catalog => 3,
+            ErrorKind::Runtime => 4,
+            ErrorKind::HealthTimeout => 5,
+            ErrorKind::SmokeTest => 6,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorKind::Internal => "internal error",
+            ErrorKind::InvalidInput => "invalid input",
+            ErrorKind::Catalog => "catalog error",
+            ErrorKind::Runtime => "runtime error",
+            ErrorKind::HealthTimeout => "health timeout",
+            ErrorKind::SmokeTest => "smoke test failed",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub report: eyre::Report,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, report: impl Into<eyre::Report>) -> Self {
+        Self {
+            kind,
+            report: report.into(),
+        }
+    }
+
+    /// Error of the catalog building a manifest, the args of the Dep that do
+    /// not parse into the input of the module are invalid input
+    pub fn catalog(report: eyre::Report) -> Self {
+        let input = report.chain().any(|cause| cause.is::<serde_json::Error>());
+        let kind = if input {
+            ErrorKind::InvalidInput
+        } else {
+            ErrorKind::Catalog
+        };
+        Self::new(kind, report)
+    }
+
+    /// Structured summary of the error printed when a command fails
+    pub fn summary(&self) -> ErrorSummary {
+        ErrorSummary {
+            kind: self.kind,
+            exit_code: self.kind.exit_code(),
+            message: self.report.to_string(),
+            causes: self
+                .report
+                .chain()
+                .skip(1)
+                .map(|cause| cause.to_string())
+                .collect(),
+        }
+    }
+}
+
+// errors without an explicit class are internal
+impl From<eyre::Report> for Error {
+    fn from(report: eyre::Report) -> Self {
+        Self::new(ErrorKind::Internal, report)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorSummary {
+    pub kind: ErrorKind,
+    pub exit_code: u8,
+    pub message: String,
+    pub causes: Vec<String>,
+}
+
+impl fmt::Display for ErrorSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error ({}): {}", self.kind, self.message)?;
+        for cause in &self.causes {
+            write!(f, "\n  caused by: {}", cause)?;
+        }
+        Ok(())
+    }
+}
+
+/// Tags the error of a result with its class
+pub trait ResultExt<T> {
+    fn kind(self, kind: ErrorKind) -> Result<T, Error>;
+}
+
+impl<T, E: Into<eyre::Report>> ResultExt<T> for Result<T, E> {
+    fn kind(self, kind: ErrorKind) -> Result<T, Error> {
+        self.map_err(|err| Error::new(kind, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_kind_has_its_own_exit_code() {
+        let kinds = [
+            ErrorKind::Internal,
+            ErrorKind::InvalidInput,
+            ErrorKind::Catalog,
+            ErrorKind::Runtime,
+            ErrorKind::HealthTimeout,
+            ErrorKind::SmokeTest,
+        ];
+        let codes: std::collections::BTreeSet<u8> =
+            kinds.iter().map(|kind| kind.exit_code()).collect();
+        assert_eq!(codes.len(), kinds.len());
+        // 0 is success
+        assert!(!codes.contains(&0));
+
+        let err: Error = eyre::eyre!("unexpected").into();
+        assert_eq!(err.kind, ErrorKind::Internal);
+    }
+
+    #[test]
+    fn test_summary_lists_the_causes() {
+        let err = Result::<(), _>::Err(eyre::eyre!("connection refused"))
+            .map_err(|err| err.wrap_err("Failed to deploy eth"))
+            .kind(ErrorKind::Runtime)
+            .unwrap_err();
+        let summary = err.summary();
+        assert_eq!(summary.exit_code, 4);
+        assert_eq!(summary.message, "Failed to deploy eth");
+        assert_eq!(summary.causes, vec!["connection refused"]);
+        assert_eq!(
+            summary.to_string(),
+            "Error (runtime error): Failed to deploy eth\n  caused by: connection refused"
+        );
+        assert_eq!(serde_json::to_value(&summary).unwrap()["kind"], "runtime");
+    }
+
+    #[test]
+    fn test_input_that_does_not_parse_is_invalid_input() {
+        let parse = serde_json::from_str::<u64>("\"eth\"").unwrap_err();
+        let err = Error::catalog(eyre::Report::new(parse).wrap_err("Invalid input of ethereum"));
+        assert_eq!(err.kind, ErrorKind::InvalidInput);
+
+        let err = Error::catalog(eyre::eyre!("The catalog does not generate the key"));
+        assert_eq!(err.kind, ErrorKind::Catalog);
+    }
+}
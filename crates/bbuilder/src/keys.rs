@@ -0,0 +1,109 @@
+//! Archive of the identity keys replaced by `rotate-keys`. The keys are kept
+//! as JSON sealed with ChaCha20-Poly1305 under a key derived with Argon2 from
+//! a passphrase, the file is `salt | nonce | ciphertext`.
+
+use argon2::Argon2;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use runtime_trait::IdentityKey;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// secret with the passphrase of the archives, see `spec::SecretProvider`
+pub const PASSPHRASE_SECRET: &str = "keys-archive";
+
+// directory of the archives inside the folder of the deployment
+pub const KEYS_DIR: &str = "keys";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyArchive {
+    pub deployment: String,
+    pub pod: String,
+    // unix time of the rotation
+    pub rotated_at: u64,
+    pub keys: Vec<IdentityKey>,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> eyre::Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| eyre::eyre!("Failed to derive the archive key: {}", err))?;
+    Ok(key)
+}
+
+impl KeyArchive {
+    pub fn new(deployment: &str, pod: &str, keys: Vec<IdentityKey>) -> Self {
+        Self {
+            deployment: deployment.to_string(),
+            pod: pod.to_string(),
+            rotated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            keys,
+        }
+    }
+
+    pub fn seal(&self, passphrase: &str) -> eyre::Result<Vec<u8>> {
+        let salt: [u8; SALT_LEN] = rand::random();
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                serde_json::to_vec(self)?.as_slice(),
+            )
+            .map_err(|_| eyre::eyre!("Failed to encrypt the key archive"))?;
+
+        Ok([&salt[..], &nonce[..], &ciphertext].concat())
+    }
+
+    pub fn open(sealed: &[u8], passphrase: &str) -> eyre::Result<Self> {
+        if sealed.len() < SALT_LEN + NONCE_LEN {
+            eyre::bail!("Key archive is truncated");
+        }
+        let (salt, rest) = sealed.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| eyre::eyre!("Wrong passphrase or corrupted key archive"))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    /// Writes the sealed archive to `<dir>/<pod>-<rotated_at>.enc`
+    pub fn save(&self, dir: &Path, passphrase: &str) -> eyre::Result<PathBuf> {
+        let path = dir.join(format!("{}-{}.enc", self.pod, self.rotated_at));
+        std::fs::write(&path, self.seal(passphrase)?)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_archive_is_sealed_with_the_passphrase() -> eyre::Result<()> {
+        let archive = KeyArchive::new(
+            "cosmos-1",
+            "node",
+            vec![IdentityKey {
+                spec: "node".to_string(),
+                path: "/data/config/node_key.json".to_string(),
+                content: "eyJwcml2X2tleSI6e319".to_string(),
+            }],
+        );
+        let sealed = archive.seal("passphrase")?;
+        assert!(!String::from_utf8_lossy(&sealed).contains("node_key"));
+
+        assert_eq!(KeyArchive::open(&sealed, "passphrase")?, archive);
+        assert!(KeyArchive::open(&sealed, "other").is_err());
+        Ok(())
+    }
+}
@@ -1 +1,8 @@
+pub mod client;
+pub mod config;
+pub mod error;
+pub mod keys;
+pub mod ports;
+pub mod telemetry;
 
+pub use client::Client;
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use spec::{HostPort, Manifest, Protocol};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+// name of the registry file inside the state directory
+pub const PORTS_FILE: &str = "ports.json";
+
+/// Host ports claimed by every deployment of the host, it is updated on
+/// deploy and consulted on plan and deploy to find conflicts early
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortRegistry {
+    // deployment name to the ports it publishes
+    deployments: BTreeMap<String, Vec<HostPort>>,
+}
+
+/// Host port published more than once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortConflict {
+    pub port: HostPort,
+    // deployment that claimed the port first
+    pub deployment: String,
+    // spec that claimed the port first as `<pod>/<spec>`
+    pub claimed_by: String,
+}
+
+impl std::fmt::Display for PortConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "port {}/{:?} of {}/{} is already claimed by {} in deployment {}",
+            self.port.host_port,
+            self.port.protocol,
+            self.port.pod,
+            self.port.spec,
+            self.claimed_by,
+            self.deployment
+        )
+    }
+}
+
+impl PortRegistry {
+    /// Loads the registry, a missing file is an empty registry
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)?;
+        serde_json::from_str(&contents)
+            .map_err(|err| eyre::eyre!("Invalid port registry {}: {}", path.display(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Returns the host ports of the manifest claimed by other deployments or
+    /// by another spec of the same manifest. Redeploying a manifest does not
+    /// conflict with its own claims.
+    pub fn conflicts(&self, manifest: &Manifest) -> Vec<PortConflict> {
+        let mut conflicts = vec![];
+        let mut claimed: Vec<(&str, &HostPort)> = self
+            .deployments
+            .iter()
+            .filter(|(deployment, _)| *deployment != &manifest.name)
+            .flat_map(|(deployment, ports)| ports.iter().map(|port| (deployment.as_str(), port)))
+            .collect();
+
+        let ports = manifest.host_ports();
+        for port in &ports {
+            let existing = claimed.iter().find(|(_, claim)| {
+                claim.host_port == port.host_port && claim.protocol == port.protocol
+            });
+            if let Some((deployment, claim)) = existing {
+                conflicts.push(PortConflict {
+                    port: port.clone(),
+                    deployment: deployment.to_string(),
+                    claimed_by: format!("{}/{}", claim.pod, claim.spec),
+                });
+            } else {
+                claimed.push((manifest.name.as_str(), port));
+            }
+        }
+        conflicts
+    }
+
+    /// Host ports claimed by the deployments other than the given one
+    pub fn used_ports(&self, deployment: &str) -> HashSet<(u16, Protocol)> {
+        self.deployments
+            .iter()
+            .filter(|(name, _)| *name != deployment)
+            .flat_map(|(_, ports)| ports.iter().map(|port| (port.host_port, port.protocol)))
+            .collect()
+    }
+
+    /// Drops the claims of a deployment once it is destroyed
+    pub fn release(&mut self, deployment: &str) {
+        self.deployments.remove(deployment);
+    }
+
+    /// Records the host ports of the manifest, replacing its previous claims
+    pub fn claim(&mut self, manifest: &Manifest) {
+        self.deployments
+            .insert(manifest.name.clone(), manifest.host_ports());
+    }
+}
+
+/// `$XDG_STATE_HOME/bbuilder/ports.json`, falling back to `~/.local/state`
+pub fn registry_path() -> Option<PathBuf> {
+    let state_dir = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(state_dir.join("bbuilder").join(PORTS_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::{Arg, Pod, Spec};
+
+    fn manifest(name: &str, ports: &[u16]) -> Manifest {
+        let mut manifest = Manifest::new(name.to_string());
+        for (i, port) in ports.iter().enumerate() {
+            let spec = Spec::builder().image("test-image").arg(Arg::Port {
+                name: "http".to_string(),
+                preferred: *port,
+            });
+            manifest.add_spec(format!("pod{}", i), Pod::default().with_spec("node", spec));
+        }
+        manifest
+    }
+
+    #[test]
+    fn test_conflicts_with_other_deployments() {
+        let mut registry = PortRegistry::default();
+        registry.claim(&manifest("eth-1", &[8545, 5052]));
+
+        // redeploying the same deployment does not conflict with itself
+        assert!(registry.conflicts(&manifest("eth-1", &[8545])).is_empty());
+
+        let conflicts = registry.conflicts(&manifest("eth-2", &[8545, 9000]));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].port.host_port, 8545);
+        assert_eq!(conflicts[0].deployment, "eth-1");
+
+        // two specs of the same manifest on the same port
+        let conflicts = registry.conflicts(&manifest("eth-3", &[30303, 30303]));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].deployment, "eth-3");
+    }
+
+    #[test]
+    fn test_allocated_ports_do_not_conflict() {
+        let mut registry = PortRegistry::default();
+        registry.claim(&manifest("eth-1", &[8551]));
+
+        let mut second = manifest("eth-2", &[8551]);
+        second.allocate_host_ports(&registry.used_ports(&second.name));
+        assert!(registry.conflicts(&second).is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+pub use logging::LogFormat;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+// standard OpenTelemetry variable, when set spans are exported over OTLP to it
+const OTLP_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Keeps the OTLP exporter alive and flushes the pending spans when dropped
+pub struct TelemetryGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take()
+            && let Err(err) = provider.shutdown()
+        {
+            eprintln!("Failed to shutdown the tracer provider: {}", err);
+        }
+    }
+}
+
+/// Installs the global tracing subscriber. Spans are always logged to stderr
+/// and additionally exported over OTLP if `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+/// `RUST_LOG` takes precedence over the given log level.
+pub fn init(format: LogFormat, level: LevelFilter) -> eyre::Result<TelemetryGuard> {
+    let provider = match std::env::var(OTLP_ENDPOINT_ENV) {
+        Ok(_) => {
+            // The exporter reads the endpoint from the environment itself
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .build()?;
+
+            Some(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(Resource::builder().with_service_name("bbuilder").build())
+                    .build(),
+            )
+        }
+        Err(_) => None,
+    };
+
+    let otel_layer = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("bbuilder")));
+
+    // Logs go to stderr so that the output of the commands can be piped
+    let (text_layer, json_layer) = match format {
+        LogFormat::Text => (
+            Some(tracing_subscriber::fmt::layer().with_writer(std::io::stderr)),
+            None,
+        ),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_writer(std::io::stderr),
+            ),
+        ),
+    };
+
+    tracing_subscriber::registry()
+        .with(logging::env_filter(level))
+        .with(text_layer)
+        .with(json_layer)
+        .with(otel_layer)
+        .try_init()?;
+
+    Ok(TelemetryGuard { provider })
+}
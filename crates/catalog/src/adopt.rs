@@ -0,0 +1,128 @@
+//! Recognition of the catalog components in the specs of deployments that
+//! were not created by bbuilder, so they can be adopted as one of its modules.
+
+use spec::{Arg, Spec};
+
+/// Catalog component a spec runs and where the module places it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Component {
+    pub module: &'static str,
+    pub name: &'static str,
+    pub pod: &'static str,
+    pub spec: &'static str,
+}
+
+const fn component(
+    module: &'static str,
+    name: &'static str,
+    pod: &'static str,
+    spec: &'static str,
+) -> Component {
+    Component {
+        module,
+        name,
+        pod,
+        spec,
+    }
+}
+
+// default image repository of each component, lighthouse and nimbus are
+// listed once since their nodes share the image
+const COMPONENTS: &[(&str, Component)] = &[
+    (
+        "ghcr.io/paradigmxyz/reth",
+        component("ethereum", "reth", "el", "node"),
+    ),
+    (
+        "sigp/lighthouse",
+        component("ethereum", "lighthouse", "cl", "node"),
+    ),
+    (
+        "gcr.io/prysmaticlabs/prysm/beacon-chain",
+        component("ethereum", "prysm", "cl", "node"),
+    ),
+    (
+        "consensys/web3signer",
+        component("ethereum", "web3signer", "signer", "node"),
+    ),
+    (
+        "ghcr.io/base/blob-archiver",
+        component("ethereum", "blob_archiver", "blobs", "archiver"),
+    ),
+    (
+        "ssvlabs/ssv-node",
+        component("ethereum", "ssv", "dv", "node"),
+    ),
+    (
+        "obolnetwork/charon",
+        component("ethereum", "charon", "dv", "node"),
+    ),
+    (
+        "statusim/nimbus-eth2",
+        component("ethereum_light", "nimbus", "light", "node"),
+    ),
+    (
+        "portalnetwork/trin",
+        component("ethereum_light", "trin", "light", "node"),
+    ),
+    (
+        "0xpolygon/heimdall-v2",
+        component("polygon", "heimdall", "heimdall", "node"),
+    ),
+    ("0xpolygon/bor", component("polygon", "bor", "bor", "bor")),
+    (
+        "erigontech/erigon",
+        component("polygon", "erigon", "erigon", "erigon"),
+    ),
+    (
+        "ghcr.io/berachain/beacon-kit",
+        component("berachain", "beacon_kit", "beaconkit", "node"),
+    ),
+    (
+        "ghcr.io/berachain/bera-reth",
+        component("berachain", "bera_reth", "berareth", "reth"),
+    ),
+];
+
+const LIGHTHOUSE_VALIDATOR: Component = component("ethereum", "lighthouse_validator", "vc", "node");
+
+const NIMBUS_BEACON: Component = component("ethereum", "nimbus", "cl", "node");
+
+/// Catalog component of the spec, recognized by the repository of its image.
+/// The images of docker hub match with and without the `docker.io/` prefix.
+pub fn identify(spec: &Spec) -> Option<Component> {
+    let repo = spec
+        .image
+        .trim_start_matches("docker.io/")
+        .trim_start_matches("library/");
+    let component = COMPONENTS
+        .iter()
+        .find(|(image, _)| *image == repo)
+        .map(|(_, component)| *component)?;
+
+    let command: Vec<&String> = spec
+        .entrypoint
+        .iter()
+        .chain(spec.args.iter().filter_map(|arg| match arg {
+            Arg::Value(value) => Some(value),
+            _ => None,
+        }))
+        .collect();
+    // the lighthouse validator client runs the `vc` subcommand
+    if component.name == "lighthouse"
+        && command
+            .iter()
+            .any(|arg| *arg == "vc" || *arg == "validator_client")
+    {
+        return Some(LIGHTHOUSE_VALIDATOR);
+    }
+    // the image runs the nimbus beacon node unless the light client is its entrypoint
+    if component.name == "nimbus"
+        && !command
+            .iter()
+            .any(|arg| arg.ends_with("nimbus_light_client"))
+    {
+        return Some(NIMBUS_BEACON);
+    }
+    Some(component)
+}
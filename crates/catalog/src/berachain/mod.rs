@@ -1,7 +1,16 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use spec::{Arg, Artifacts, Babel, Capabilities, ChainSpec, ComputeResource, Deployment, Manifest, Pod, Spec};
+use spec::{
+    ArtifactSource, Artifacts, Babel, Capabilities, ChainSpec, ClientVersion, ComputeResource,
+    Context, Deployment, Manifest, Overrides, Pod, Port, Spec, Versions, Volume,
+};
 use template::Template;
-use tokio::task;
+
+use crate::cosmos::{P2P_PORT, P2PConfig, Peers, StateSync, StateSyncConfig, parse_peers};
+use crate::{
+    ChainCapability, ComponentCapabilities, EL_STOP_GRACE_PERIOD, ModuleCapabilities, NOFILE_LIMIT,
+    validate_sha256,
+};
 
 fn bera_chain_file(chain_id: u64, path: &str) -> String {
     format!(
@@ -10,7 +19,16 @@ fn bera_chain_file(chain_id: u64, path: &str) -> String {
     )
 }
 
-#[derive(Default, Clone)]
+// seeds shipped for mainnet, used when the ones of the beacon-kit repo can
+// not be fetched
+const MAINNET_SEEDS: &[&str] = &[
+    "9aa463497679e18a8a6ddc5e8503071255ce3844@34.47.83.64:26656",
+    "52b8bfb58c1d60774dd55310b24740569587ef5e@34.64.49.76:26656",
+    "1c8aa4af80684c29904a09044ffafba2e1814a30@34.64.211.6:26656",
+    "9989f4bb3e3b6975f7d056aaf150e7f10f046344@34.64.76.252:26656",
+];
+
+#[derive(Default, Clone, PartialEq)]
 pub enum Chains {
     #[default]
     Mainnet,
@@ -24,9 +42,55 @@ impl Chains {
             Chains::Bepolia => 80069,
         }
     }
+
+    fn name(&self) -> &str {
+        match self {
+            Chains::Mainnet => "mainnet",
+            Chains::Bepolia => "bepolia",
+        }
+    }
+
+    fn fallback_seeds(&self) -> &'static [&'static str] {
+        match self {
+            Chains::Mainnet => MAINNET_SEEDS,
+            Chains::Bepolia => &[],
+        }
+    }
 }
 
-#[derive(Default, Deserialize)]
+impl std::str::FromStr for Chains {
+    type Err = eyre::Report;
+
+    /// Parses the name of the chain, with or without the `berachain.` prefix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("berachain.").unwrap_or(s) {
+            "mainnet" => Ok(Chains::Mainnet),
+            "bepolia" => Ok(Chains::Bepolia),
+            _ => Err(eyre::eyre!("Unknown berachain chain: {}", s)),
+        }
+    }
+}
+
+const VERSIONS: Versions<Chains> = Versions(&[
+    ClientVersion::new("beacon_kit", Chains::Mainnet, "v1.3.4-rc1", "v1.3.4-rc1"),
+    ClientVersion::new("beacon_kit", Chains::Bepolia, "v1.3.4-rc1", "v1.3.4-rc1"),
+    ClientVersion::new("bera_reth", Chains::Mainnet, "v1.3.0", "v1.3.0"),
+    ClientVersion::new("bera_reth", Chains::Bepolia, "v1.3.0", "v1.3.0"),
+]);
+
+pub(crate) fn capabilities() -> ModuleCapabilities {
+    ModuleCapabilities {
+        module: "berachain".to_string(),
+        network: "berachain".to_string(),
+        chains: ChainCapability::from_deployment(&BerachainDeployment::default(), Chains::name),
+        components: vec![
+            ComponentCapabilities::new("beacon_kit", &BeaconKit::default(), Chains::name),
+            ComponentCapabilities::new("bera_reth", &BeraReth::default(), Chains::name),
+        ],
+    }
+}
+
+#[derive(Default, Deserialize, JsonSchema)]
 pub struct BerachainDeploymentInput {
     pub beacon_kit: BeaconKit,
     pub bera_reth: BeraReth,
@@ -39,56 +103,55 @@ impl Deployment for BerachainDeployment {
     type Input = BerachainDeploymentInput;
     type Chains = Chains;
 
+    fn defaults(&self, _chain: &Chains) -> serde_json::Value {
+        serde_json::json!({ "beacon_kit": {}, "bera_reth": {} })
+    }
+
     fn capabilities(&self) -> Vec<ChainSpec<Chains>> {
-        vec![ChainSpec {
-            chain: Chains::Mainnet,
-            min_version: "".to_string(),
-        }]
+        vec![
+            ChainSpec {
+                chain: Chains::Mainnet,
+                ..Default::default()
+            },
+            ChainSpec {
+                chain: Chains::Bepolia,
+                ..Default::default()
+            },
+        ]
     }
 
-    fn manifest(&self, chain: Chains, input: BerachainDeploymentInput) -> eyre::Result<Manifest> {
+    fn manifest(
+        &self,
+        chain: Chains,
+        input: BerachainDeploymentInput,
+        ctx: &Context,
+    ) -> eyre::Result<Manifest> {
         let mut manifest = Manifest::new("berachain".to_string());
 
-        let mut beaconkit_pod = input.beacon_kit.spec(chain.clone())?;
+        let mut beaconkit_pod = input.beacon_kit.spec(chain.clone(), ctx)?;
         // Add Babel sidecar to BeaconKit pod
-        let babel_cosmos = Babel::new(
-            "cosmos",
-            Arg::Ref {
-                name: "beaconkit".to_string(),
-                port: "http".to_string(),
-            },
-        );
-        beaconkit_pod = beaconkit_pod.with_spec("babel", babel_cosmos.spec());
+        let babel_cosmos = Babel::new("cosmos", spec::ref_!("beaconkit", "http"));
+        beaconkit_pod = beaconkit_pod.with_babel(babel_cosmos);
         manifest.add_spec("beaconkit".to_string(), beaconkit_pod);
 
-        let mut berareth_pod = input.bera_reth.spec(chain)?;
+        let mut berareth_pod = input.bera_reth.spec(chain, ctx)?;
         // Add Babel sidecar to BeraReth pod
-        let babel_ethereum = Babel::new(
-            "ethereum",
-            Arg::Ref {
-                name: "berareth".to_string(),
-                port: "http".to_string(),
-            },
-        );
-        berareth_pod = berareth_pod.with_spec("babel", babel_ethereum.spec());
+        let babel_ethereum = Babel::new("ethereum", spec::ref_!("berareth", "http"));
+        berareth_pod = berareth_pod.with_babel(babel_ethereum);
         manifest.add_spec("berareth".to_string(), berareth_pod);
+        // beacon kit drives the execution client through the engine api
+        manifest.add_dependency("beaconkit", "berareth");
 
         Ok(manifest)
     }
 }
 
-fn fetch_data(url: String) -> String {
-    let url = url.to_string();
-
-    let handle = task::spawn_blocking(move || reqwest::blocking::get(&url)?.text());
-
-    // Block on the handle from sync context
-    task::block_in_place(|| tokio::runtime::Handle::current().block_on(handle).unwrap()).unwrap()
-}
-
 #[derive(Template, Serialize)]
 #[template(path = "config/config.toml")]
-struct BeaconKitConfigFile {}
+struct BeaconKitConfigFile {
+    statesync: StateSyncConfig,
+    p2p: P2PConfig,
+}
 
 #[derive(Template, Serialize)]
 #[template(path = "config/app.toml")]
@@ -96,104 +159,235 @@ struct BeaconKitAppFile {
     rpc_dial_url: String,
 }
 
-#[derive(Default, Deserialize)]
-pub struct BeaconKit {}
+#[derive(Default, Deserialize, JsonSchema)]
+pub struct BeaconKit {
+    #[serde(default)]
+    pub state_sync: Option<StateSync>,
+    #[serde(flatten)]
+    pub peers: Peers,
+    // image run instead of ghcr.io/berachain/beacon-kit (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    // sha256 of the genesis, it is verified before the node starts
+    #[serde(default)]
+    pub genesis_sha256: Option<String>,
+    // sha256 of the kzg trusted setup, it is verified before the node starts
+    #[serde(default)]
+    pub kzg_trusted_setup_sha256: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
 
 impl ComputeResource for BeaconKit {
     type Chains = Chains;
 
     fn capabilities(&self) -> Capabilities<Chains> {
         Capabilities {
-            chains: vec![],
-            volumes: vec![],
+            chains: VERSIONS.chains("beacon_kit"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
         }
     }
 
-    fn spec(&self, chain: Chains) -> eyre::Result<Pod> {
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
         let chain_id = chain.chain_id();
+        validate_sha256("beacon_kit genesis_sha256", self.genesis_sha256.as_deref())?;
+        validate_sha256(
+            "beacon_kit kzg_trusted_setup_sha256",
+            self.kzg_trusted_setup_sha256.as_deref(),
+        )?;
 
-        let config_file = BeaconKitConfigFile {};
+        // the default seeds are fetched by the runtime when it deploys the
+        // manifest, planning does not depend on github. The shipped ones are
+        // used when github is not reachable.
+        let fallback_seeds = chain.fallback_seeds();
+        let statesync = StateSyncConfig::resolve(self.state_sync.as_ref())?;
+        let peers = self.peers.clone();
+        let p2p_ctx = ctx.clone();
+        let seeds_url = bera_chain_file(chain_id, "cl-seeds.txt");
+        let urls = match peers.seeds {
+            Some(_) => vec![],
+            None => vec![seeds_url.clone()],
+        };
+        let config_file = ArtifactSource::generator(urls, move |fetched| {
+            let config_file = BeaconKitConfigFile {
+                statesync: statesync.clone(),
+                p2p: P2PConfig::resolve(&peers, &p2p_ctx, || match fetched.get(&seeds_url) {
+                    Some(content) => Ok(parse_peers(content)),
+                    None if !fallback_seeds.is_empty() => {
+                        Ok(fallback_seeds.iter().map(|seed| seed.to_string()).collect())
+                    }
+                    None => Err(eyre::eyre!("Failed to fetch the seeds of {}", seeds_url)),
+                })?,
+            };
+            Ok(config_file.render().to_string())
+        });
         let app_file = BeaconKitAppFile {
-            rpc_dial_url: "http://localhost:8551".to_string(),
+            // resolved by the runtime to the engine api of bera-reth
+            rpc_dial_url: "{{ref:berareth:authrpc}}".to_string(),
         };
 
-        let bootnodes = fetch_data(bera_chain_file(chain_id, "el-bootnodes.txt"));
-        let peers = fetch_data(bera_chain_file(chain_id, "el-peers.txt"));
-
         let node = Spec::builder()
-            .image("ghcr.io/berachain/beacon-kit")
-            .tag("v1.3.4-rc1")
+            .image(
+                self.image
+                    .as_deref()
+                    .unwrap_or("ghcr.io/berachain/beacon-kit"),
+            )
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("beacon_kit", &chain, ctx, self.tag.as_deref())?)
+            .port(Port::tcp("p2p", P2P_PORT).public())
             .arg("start")
             .arg2("--home", "/data")
-            .arg2(
-                "--api.address",
-                Arg::Port {
-                    name: "http".to_string(),
-                    preferred: 1317,
-                },
+            .identity_key("/data/config/node_key.json")
+            .arg2("--api.address", spec::port!("http", 1317))
+            .env_file(
+                "EL_BOOTNODES",
+                ArtifactSource::url(bera_chain_file(chain_id, "el-bootnodes.txt")),
             )
-            .env("EL_BOOTNODES", bootnodes.trim())
-            .env("EL_PEERS", peers.trim())
-            .artifact(Artifacts::File(spec::File {
-                name: "genesis".to_string(),
-                target_path: "/data/genesis.json".to_string(),
-                content: bera_chain_file(chain_id, "genesis.json"),
-            }))
-            .artifact(Artifacts::File(spec::File {
-                name: "kzg-trusted-setup".to_string(),
-                target_path: "/data/kzg-trusted-setup.json".to_string(),
-                content: bera_chain_file(chain_id, "kzg-trusted-setup.json"),
-            }))
-            .artifact(Artifacts::File(spec::File {
-                name: "config".to_string(),
-                target_path: "/data/config.toml".to_string(),
-                content: config_file.render().to_string(),
-            }))
-            .artifact(Artifacts::File(spec::File {
-                name: "app".to_string(),
-                target_path: "/data/app.toml".to_string(),
-                content: app_file.render().to_string(),
-            }));
-
-        Ok(Pod::default().with_spec("node", node))
+            .env_file(
+                "EL_PEERS",
+                ArtifactSource::url(bera_chain_file(chain_id, "el-peers.txt")),
+            )
+            .artifact(Artifacts::File(spec::file(
+                "config",
+                "/data/config.toml",
+                config_file,
+            )))
+            .artifact(Artifacts::File(spec::file(
+                "app",
+                "/data/app.toml",
+                app_file.render(),
+            )))
+            .overrides(&self.overrides);
+
+        Ok(Pod::default()
+            .with_init_spec(
+                "genesis",
+                Spec::download(
+                    &bera_chain_file(chain_id, "genesis.json"),
+                    "/data/genesis.json",
+                    self.genesis_sha256.as_deref(),
+                ),
+            )
+            .with_init_spec(
+                "kzg-trusted-setup",
+                Spec::download(
+                    &bera_chain_file(chain_id, "kzg-trusted-setup.json"),
+                    "/data/kzg-trusted-setup.json",
+                    self.kzg_trusted_setup_sha256.as_deref(),
+                ),
+            )
+            .with_spec("node", node))
     }
 }
 
-#[derive(Default, Deserialize)]
-pub struct BeraReth {}
+#[derive(Default, Deserialize, JsonSchema)]
+pub struct BeraReth {
+    // overrides the default bootnodes of the chain (enode urls)
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+    // image run instead of ghcr.io/berachain/bera-reth (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    // sha256 of the execution genesis, it is verified before the node starts
+    #[serde(default)]
+    pub genesis_sha256: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
 
 impl ComputeResource for BeraReth {
     type Chains = Chains;
 
     fn capabilities(&self) -> Capabilities<Chains> {
         Capabilities {
-            chains: vec![],
-            volumes: vec![],
+            chains: VERSIONS.chains("bera_reth"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
         }
     }
 
-    fn spec(&self, chain: Chains) -> eyre::Result<Pod> {
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
         let chain_id = chain.chain_id();
+        validate_sha256("bera_reth genesis_sha256", self.genesis_sha256.as_deref())?;
 
-        let node = Spec::builder()
-            .image("ghcr.io/berachain/bera-reth")
-            .tag("v1.3.0")
-            .arg2("--chain", "/data/genesis.json")
-            .arg2(
-                "--http.port",
-                Arg::Port {
-                    name: "http".to_string(),
-                    preferred: 8545,
-                },
+        let mut node = Spec::builder()
+            .image(
+                self.image
+                    .as_deref()
+                    .unwrap_or("ghcr.io/berachain/bera-reth"),
             )
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("bera_reth", &chain, ctx, self.tag.as_deref())?)
+            .ulimit("nofile", NOFILE_LIMIT, NOFILE_LIMIT)
+            .stop_signal("SIGINT")
+            .stop_grace_period(EL_STOP_GRACE_PERIOD)
+            .port(Port::tcp("p2p", 30303).public())
+            .port(Port::udp("discovery", 30303).public())
+            .arg2("--chain", "/data/genesis.json")
+            .arg2("--http.port", spec::port!("http", 8545))
             .arg2("--http.addr", "0.0.0.0")
             .arg("--http")
-            .artifact(Artifacts::File(spec::File {
-                name: "eth-genesis".to_string(),
-                target_path: "/data/eth-genesis.json".to_string(),
-                content: bera_chain_file(chain_id, "eth-genesis.json"),
-            }));
+            .arg2("--authrpc.port", spec::port!("authrpc", 8551))
+            .arg2("--authrpc.addr", "0.0.0.0");
+
+        if let Some(nat) = ctx.nat_extip() {
+            node = node.arg2("--nat", nat);
+        }
+        if !self.bootnodes.is_empty() {
+            node = node.arg2("--bootnodes", self.bootnodes.join(","));
+        }
+
+        node = node.overrides(&self.overrides);
+        Ok(Pod::default()
+            .with_init_spec(
+                "eth-genesis",
+                Spec::download(
+                    &bera_chain_file(chain_id, "eth-genesis.json"),
+                    "/data/eth-genesis.json",
+                    self.genesis_sha256.as_deref(),
+                ),
+            )
+            .with_spec("reth", node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(chain: Chains, fetched: &HashMap<String, String>) -> eyre::Result<String> {
+        let pod = BeaconKit::default().spec(chain, &Context::default())?;
+        let config = pod.specs["node"]
+            .artifacts
+            .iter()
+            .find_map(|artifact| match artifact {
+                Artifacts::File(file) if file.name == "config" => Some(file.content.clone()),
+                _ => None,
+            });
+        let mut config = config.expect("config file");
+        config.resolve(fetched)?;
+        Ok(config.inline().unwrap_or_default().to_string())
+    }
+
+    #[test]
+    fn test_shipped_seeds_are_used_when_they_can_not_be_fetched() -> eyre::Result<()> {
+        let seeds_url = bera_chain_file(Chains::Mainnet.chain_id(), "cl-seeds.txt");
+        let fetched = HashMap::from([(seeds_url, "abc@1.2.3.4:26656\n".to_string())]);
+        assert!(config(Chains::Mainnet, &fetched)?.contains("abc@1.2.3.4:26656"));
+
+        let shipped = config(Chains::Mainnet, &HashMap::new())?;
+        assert!(shipped.contains(MAINNET_SEEDS[0]));
 
-        Ok(Pod::default().with_spec("reth", node))
+        // no seeds are shipped for the testnet
+        assert!(config(Chains::Bepolia, &HashMap::new()).is_err());
+        Ok(())
     }
 }
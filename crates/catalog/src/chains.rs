@@ -0,0 +1,136 @@
+//! Registry of the chains of the catalog by their full name, `<network>.<chain>`
+//! (e.g. `polygon.amoy`), and of the modules and components that run them.
+
+use serde::Serialize;
+
+use crate::{ChainCapability, capabilities};
+
+// short names accepted for the networks
+const NETWORK_ALIASES: &[(&str, &str)] = &[("eth", "ethereum"), ("bera", "berachain")];
+
+/// Modules and components of the catalog that can run a chain
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainProviders {
+    pub chain: String,
+    pub modules: Vec<ModuleProvider>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleProvider {
+    pub module: String,
+    pub chain: ChainCapability,
+    pub components: Vec<ComponentProvider>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentProvider {
+    pub name: String,
+    pub chain: ChainCapability,
+}
+
+/// Full name of the chain with the alias of its network resolved (e.g.
+/// `eth.mainnet` is `ethereum.mainnet`)
+pub fn chain_name(name: &str) -> eyre::Result<String> {
+    let (network, chain) = name
+        .split_once('.')
+        .ok_or_else(|| eyre::eyre!("Chain {} is not <network>.<chain>", name))?;
+    let network = NETWORK_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == network)
+        .map_or(network, |(_, network)| network);
+    Ok(format!("{}.{}", network, chain))
+}
+
+/// Full names of the chains supported by any module of the catalog
+pub fn chains() -> Vec<String> {
+    let mut chains: Vec<String> = capabilities()
+        .into_iter()
+        .flat_map(|module| {
+            module
+                .chains
+                .into_iter()
+                .map(move |chain| format!("{}.{}", module.network, chain.chain))
+        })
+        .collect();
+    chains.sort();
+    chains.dedup();
+    chains
+}
+
+/// Modules that can deploy the chain and the components of each one that
+/// support it, with their versions for the chain
+pub fn providers(chain: &str) -> eyre::Result<ChainProviders> {
+    let name = chain_name(chain)?;
+    let (network, chain) = name.split_once('.').unwrap_or_default();
+
+    let supports = |chains: &[ChainCapability]| {
+        chains
+            .iter()
+            .find(|capability| capability.chain == chain)
+            .cloned()
+    };
+    let mut modules = vec![];
+    for module in capabilities() {
+        if module.network != network {
+            continue;
+        }
+        let Some(capability) = supports(&module.chains) else {
+            continue;
+        };
+        let components = module
+            .components
+            .iter()
+            .filter_map(|component| {
+                Some(ComponentProvider {
+                    name: component.name.clone(),
+                    chain: supports(&component.chains)?,
+                })
+            })
+            .collect();
+        modules.push(ModuleProvider {
+            module: module.module,
+            chain: capability,
+            components,
+        });
+    }
+
+    if modules.is_empty() {
+        eyre::bail!("No module of the catalog runs the chain {}", name);
+    }
+    Ok(ChainProviders {
+        chain: name,
+        modules,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_providers_of_a_chain() -> eyre::Result<()> {
+        assert!(chains().contains(&"berachain.bepolia".to_string()));
+
+        let bepolia = providers("bera.bepolia")?;
+        assert_eq!(bepolia.chain, "berachain.bepolia");
+        assert_eq!(bepolia.modules.len(), 1);
+        let components: Vec<&str> = bepolia.modules[0]
+            .components
+            .iter()
+            .map(|component| component.name.as_str())
+            .collect();
+        assert_eq!(components, vec!["beacon_kit", "bera_reth"]);
+
+        // the light clients run the ethereum chains too
+        let modules: Vec<String> = providers("eth.mainnet")?
+            .modules
+            .into_iter()
+            .map(|module| module.module)
+            .collect();
+        assert_eq!(modules, vec!["ethereum", "ethereum_light"]);
+
+        assert!(providers("polygon.sepolia").is_err());
+        assert!(providers("mainnet").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+//! Configuration shared by the Tendermint/CometBFT based modules.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use spec::Context;
+use std::net::SocketAddr;
+
+use crate::fetch_data;
+
+// p2p port the nodes listen on (p2p.laddr in config.toml)
+pub(crate) const P2P_PORT: u16 = 26656;
+
+// distance from the latest block used as trust height in auto mode
+const AUTO_TRUST_HEIGHT_OFFSET: u64 = 2000;
+
+/// State sync input of a Cosmos node. When `trust_height` and `trust_hash` are
+/// omitted ("auto" mode) they are resolved from the first rpc server at apply time.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+pub struct StateSync {
+    pub rpc_servers: Vec<String>,
+    #[serde(default)]
+    pub trust_height: Option<u64>,
+    #[serde(default)]
+    pub trust_hash: Option<String>,
+}
+
+/// Values rendered in the `[statesync]` section of config.toml
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StateSyncConfig {
+    pub enable: bool,
+    pub rpc_servers: String,
+    pub trust_height: u64,
+    pub trust_hash: String,
+}
+
+/// P2P peers of a Cosmos node, the chain defaults are used for the unset fields
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+pub struct Peers {
+    #[serde(default)]
+    pub seeds: Option<Vec<String>>,
+    #[serde(default)]
+    pub persistent_peers: Option<Vec<String>>,
+}
+
+/// Values rendered in the `[p2p]` section of config.toml
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct P2PConfig {
+    pub seeds: String,
+    pub persistent_peers: String,
+    pub external_address: String,
+}
+
+impl P2PConfig {
+    pub fn resolve(
+        peers: &Peers,
+        ctx: &Context,
+        default_seeds: impl FnOnce() -> eyre::Result<Vec<String>>,
+    ) -> eyre::Result<Self> {
+        let seeds = match &peers.seeds {
+            Some(seeds) => seeds.clone(),
+            None => default_seeds()?,
+        };
+
+        let external_address = ctx
+            .external_ip
+            .map(|ip| SocketAddr::new(ip, P2P_PORT).to_string())
+            .unwrap_or_default();
+
+        Ok(Self {
+            seeds: seeds.join(","),
+            persistent_peers: peers.persistent_peers.clone().unwrap_or_default().join(","),
+            external_address,
+        })
+    }
+}
+
+/// Parses a list of peers separated by new lines or commas, skipping comments
+pub fn parse_peers(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split(','))
+        .map(str::trim)
+        .filter(|peer| !peer.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct BlockResponse {
+    result: BlockResult,
+}
+
+#[derive(Deserialize)]
+struct BlockResult {
+    block_id: BlockId,
+    block: Block,
+}
+
+#[derive(Deserialize)]
+struct BlockId {
+    hash: String,
+}
+
+#[derive(Deserialize)]
+struct Block {
+    header: BlockHeader,
+}
+
+#[derive(Deserialize)]
+struct BlockHeader {
+    height: String,
+}
+
+impl StateSyncConfig {
+    pub fn resolve(state_sync: Option<&StateSync>) -> eyre::Result<Self> {
+        let Some(state_sync) = state_sync else {
+            return Ok(Self::default());
+        };
+
+        let rpc_server = state_sync
+            .rpc_servers
+            .first()
+            .ok_or_else(|| eyre::eyre!("state sync requires at least one rpc server"))?;
+
+        let (trust_height, trust_hash) = match (state_sync.trust_height, &state_sync.trust_hash) {
+            (Some(height), Some(hash)) => (height, hash.clone()),
+            (None, None) => trust_point(rpc_server)?,
+            _ => {
+                return Err(eyre::eyre!(
+                    "state sync trust_height and trust_hash must be set together"
+                ));
+            }
+        };
+
+        Ok(Self {
+            enable: true,
+            rpc_servers: state_sync.rpc_servers.join(","),
+            trust_height,
+            trust_hash,
+        })
+    }
+}
+
+/// Queries the rpc server for a recent block to use as the trusted state sync point
+fn trust_point(rpc_server: &str) -> eyre::Result<(u64, String)> {
+    let rpc_server = rpc_server.trim_end_matches('/');
+
+    let latest: BlockResponse =
+        serde_json::from_str(&fetch_data(format!("{}/block", rpc_server))?)?;
+    let latest_height = latest.result.block.header.height.parse::<u64>()?;
+    let trust_height = latest_height
+        .saturating_sub(AUTO_TRUST_HEIGHT_OFFSET)
+        .max(1);
+
+    let trusted: BlockResponse = serde_json::from_str(&fetch_data(format!(
+        "{}/block?height={}",
+        rpc_server, trust_height
+    ))?)?;
+
+    Ok((trust_height, trusted.result.block_id.hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_peers() {
+        let content = "# seeds\nid1@1.1.1.1:26656, id2@2.2.2.2:26656\n\nid3@3.3.3.3:26656\n";
+        assert_eq!(
+            parse_peers(content),
+            [
+                "id1@1.1.1.1:26656",
+                "id2@2.2.2.2:26656",
+                "id3@3.3.3.3:26656"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_p2p_config_prefers_input_over_defaults() -> eyre::Result<()> {
+        let peers = Peers {
+            seeds: Some(vec!["a@1.1.1.1:26656".to_string()]),
+            persistent_peers: None,
+        };
+        let config = P2PConfig::resolve(&peers, &Context::default(), || {
+            panic!("defaults should not be fetched")
+        })?;
+        assert_eq!(config.seeds, "a@1.1.1.1:26656");
+        assert_eq!(config.persistent_peers, "");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+//! EIP-2335 validator keystores imported into the validator clients.
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+
+// secret with the password that decrypts the keystores
+pub(crate) const KEYSTORE_PASSWORD_SECRET: &str = "keystore_password";
+
+// directory where the keystores are mounted in the import init spec
+pub(crate) const KEYSTORES_DIR: &str = "/keystores";
+
+/// Source of the keystores, either a directory with the `keystore*.json`
+/// files (as written by the staking deposit cli) or a bundle with the
+/// keystores inline
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KeystoreSource {
+    Dir(String),
+    Bundle(Vec<Value>),
+}
+
+/// Keystore validated from the source
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Keystore {
+    pub pubkey: String,
+    pub content: String,
+}
+
+impl Keystore {
+    /// Name of the keystore file, unique per validator
+    pub(crate) fn file_name(&self) -> String {
+        format!("keystore-{}.json", self.pubkey)
+    }
+}
+
+/// Loads and validates the keystores of the source, a source without
+/// keystores or with the same validator twice is not valid
+pub(crate) fn load(source: &KeystoreSource) -> eyre::Result<Vec<Keystore>> {
+    let values = match source {
+        KeystoreSource::Dir(dir) => read_dir(Path::new(dir))?,
+        KeystoreSource::Bundle(values) => values.clone(),
+    };
+    if values.is_empty() {
+        eyre::bail!("No keystores found");
+    }
+
+    let mut pubkeys = HashSet::new();
+    let mut keystores = vec![];
+    for value in values {
+        let pubkey = validate(&value)?;
+        if !pubkeys.insert(pubkey.clone()) {
+            eyre::bail!("Keystore for validator {} is duplicated", pubkey);
+        }
+        keystores.push(Keystore {
+            pubkey,
+            content: serde_json::to_string(&value)?,
+        });
+    }
+    Ok(keystores)
+}
+
+fn read_dir(dir: &Path) -> eyre::Result<Vec<Value>> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| eyre::eyre!("Failed to read keystores dir {}: {}", dir.display(), err))?;
+
+    let mut paths = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        let is_keystore = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("keystore") && name.ends_with(".json"));
+        if is_keystore {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let contents = std::fs::read_to_string(path)?;
+            serde_json::from_str(&contents)
+                .map_err(|err| eyre::eyre!("Invalid keystore {}: {}", path.display(), err))
+        })
+        .collect()
+}
+
+/// Checks the EIP-2335 structure of the keystore, returns its public key
+fn validate(keystore: &Value) -> eyre::Result<String> {
+    let pubkey = keystore["pubkey"]
+        .as_str()
+        .map(|pubkey| pubkey.trim_start_matches("0x").to_lowercase())
+        .filter(|pubkey| pubkey.len() == 96 && pubkey.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| eyre::eyre!("Keystore without a valid pubkey"))?;
+
+    if keystore["version"] != 4 {
+        eyre::bail!(
+            "Keystore {} is not an EIP-2335 (version 4) keystore",
+            pubkey
+        );
+    }
+    let crypto = &keystore["crypto"];
+    if !matches!(
+        crypto["kdf"]["function"].as_str(),
+        Some("scrypt" | "pbkdf2")
+    ) {
+        eyre::bail!("Keystore {} uses an unknown kdf", pubkey);
+    }
+    if crypto["checksum"]["function"] != "sha256" {
+        eyre::bail!("Keystore {} uses an unknown checksum", pubkey);
+    }
+    if crypto["cipher"]["function"] != "aes-128-ctr" {
+        eyre::bail!("Keystore {} uses an unknown cipher", pubkey);
+    }
+    Ok(pubkey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn keystore(pubkey: &str) -> Value {
+        json!({
+            "crypto": {
+                "kdf": { "function": "scrypt", "params": {}, "message": "" },
+                "checksum": { "function": "sha256", "params": {}, "message": "" },
+                "cipher": { "function": "aes-128-ctr", "params": {}, "message": "" }
+            },
+            "pubkey": pubkey,
+            "path": "m/12381/3600/0/0/0",
+            "uuid": "1d85ae20-35c5-4611-98e8-aa14a633906f",
+            "version": 4
+        })
+    }
+
+    #[test]
+    fn test_keystores_are_validated() -> eyre::Result<()> {
+        let pubkey = "a".repeat(96);
+        let dir = std::env::temp_dir().join("test-catalog-keystores");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("keystore-m_12381_3600_0_0_0.json"),
+            keystore(&pubkey).to_string(),
+        )?;
+        // the deposit data written next to the keystores is skipped
+        std::fs::write(dir.join("deposit_data.json"), "[]")?;
+
+        let keystores = load(&KeystoreSource::Dir(dir.to_string_lossy().to_string()))?;
+        assert_eq!(keystores.len(), 1);
+        assert_eq!(
+            keystores[0].file_name(),
+            format!("keystore-{}.json", pubkey)
+        );
+
+        let duplicated = KeystoreSource::Bundle(vec![keystore(&pubkey), keystore(&pubkey)]);
+        assert!(load(&duplicated).is_err());
+
+        let mut invalid = keystore(&pubkey);
+        invalid["version"] = json!(3);
+        assert!(load(&KeystoreSource::Bundle(vec![invalid])).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+        Ok(())
+    }
+}
@@ -1,276 +1,1799 @@
-use serde::Deserialize;
+use crate::{
+    ChainCapability, ComponentCapabilities, EL_STOP_GRACE_PERIOD, FETCHER_IMAGE, FETCHER_TAG,
+    ModuleCapabilities, NODE_UID, NOFILE_LIMIT,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use spec::{
-    Arg, Artifacts, Babel, Capabilities, ChainSpec, ComputeResource, DEFAULT_JWT_TOKEN, Deployment,
-    Manifest, Pod, Spec, Volume,
+    Arg, Artifacts, Babel, Capabilities, ChainSpec, ClientVersion, ComputeResource, Context,
+    Dashboard, Deployment, Expect, JWT_SECRET, LogConfig, METRICS_PORT, Manifest, Overrides, Pod,
+    Port, Profile, SmokeTest, Spec, SpecBuilder, Versions, Volume,
 };
+use template::Template;
+
+mod keystore;
+mod slashing_protection;
+
+pub use keystore::KeystoreSource;
+use keystore::{KEYSTORE_PASSWORD_SECRET, KEYSTORES_DIR};
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, PartialEq)]
 pub enum Chains {
     #[default]
     Mainnet,
     Sepolia,
 }
 
+impl Chains {
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Chains::Mainnet => "mainnet",
+            Chains::Sepolia => "sepolia",
+        }
+    }
+
+    pub(crate) fn chain_id(&self) -> u64 {
+        match self {
+            Chains::Mainnet => 1,
+            Chains::Sepolia => 11155111,
+        }
+    }
+}
+
+impl std::str::FromStr for Chains {
+    type Err = eyre::Report;
+
+    /// Parses the name of the chain, with or without the `ethereum.` prefix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("ethereum.").unwrap_or(s) {
+            "mainnet" => Ok(Chains::Mainnet),
+            "sepolia" => Ok(Chains::Sepolia),
+            _ => Err(eyre::eyre!("Unknown ethereum chain: {}", s)),
+        }
+    }
+}
+
+pub(crate) const VERSIONS: Versions<Chains> = Versions(&[
+    ClientVersion::new("reth", Chains::Mainnet, "v1.4.8", "v1.4.8"),
+    ClientVersion::new("reth", Chains::Sepolia, "v1.4.8", "v1.4.8"),
+    ClientVersion::new("lighthouse", Chains::Mainnet, "v7.0.0", "v8.0.0-rc.2"),
+    ClientVersion::new("lighthouse", Chains::Sepolia, "v7.0.0", "v8.0.0-rc.2"),
+    ClientVersion::new("prysm", Chains::Mainnet, "v6.0.0", "v6.0.0"),
+    ClientVersion::new(
+        "nimbus",
+        Chains::Mainnet,
+        "multiarch-v25.3.0",
+        "multiarch-v25.9.2",
+    ),
+    ClientVersion::new(
+        "nimbus",
+        Chains::Sepolia,
+        "multiarch-v25.3.0",
+        "multiarch-v25.9.2",
+    ),
+    ClientVersion::new("prysm", Chains::Sepolia, "v6.0.0", "v6.0.0"),
+    ClientVersion::new(
+        "lighthouse_validator",
+        Chains::Mainnet,
+        "v7.0.0",
+        "v8.0.0-rc.2",
+    ),
+    ClientVersion::new(
+        "lighthouse_validator",
+        Chains::Sepolia,
+        "v7.0.0",
+        "v8.0.0-rc.2",
+    ),
+    ClientVersion::new("blob_archiver", Chains::Mainnet, "v0.1.0", "v0.1.0"),
+    // exports the history of a geth data dir as era1 files, see `migrate`
+    ClientVersion::new("geth", Chains::Mainnet, "v1.14.0", "v1.16.1"),
+    ClientVersion::new("geth", Chains::Sepolia, "v1.14.0", "v1.16.1"),
+    ClientVersion::new("blob_archiver", Chains::Sepolia, "v0.1.0", "v0.1.0"),
+    ClientVersion::new("ssv", Chains::Mainnet, "v2.3.0", "v2.3.4"),
+    ClientVersion::new("charon", Chains::Mainnet, "v1.5.0", "v1.6.0"),
+    ClientVersion::new("charon", Chains::Sepolia, "v1.5.0", "v1.6.0"),
+    ClientVersion::new("web3signer", Chains::Mainnet, "25.4.1", "25.6.0"),
+    ClientVersion::new("web3signer", Chains::Sepolia, "25.4.1", "25.6.0"),
+]);
+
+pub(crate) fn capabilities() -> ModuleCapabilities {
+    ModuleCapabilities {
+        module: "ethereum".to_string(),
+        network: "ethereum".to_string(),
+        chains: ChainCapability::from_deployment(&EthereumDeployment::default(), Chains::name),
+        components: vec![
+            ComponentCapabilities::new("reth", &Reth::default(), Chains::name),
+            ComponentCapabilities::new("lighthouse", &Lighthouse::default(), Chains::name),
+            ComponentCapabilities::new("prysm", &Prysm::default(), Chains::name),
+            ComponentCapabilities::new("nimbus", &Nimbus::default(), Chains::name),
+            ComponentCapabilities::new(
+                "lighthouse_validator",
+                &LighthouseValidator::default(),
+                Chains::name,
+            ),
+            ComponentCapabilities::new("blob_archiver", &BlobArchiver::default(), Chains::name),
+            ComponentCapabilities::new("ssv", &SsvNode::default(), Chains::name),
+            ComponentCapabilities::new("charon", &Charon::default(), Chains::name),
+            ComponentCapabilities::new("web3signer", &Web3Signer::default(), Chains::name),
+        ],
+    }
+}
+
 #[derive(Default, Deserialize)]
 pub struct EthereumDeployment {}
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct EthDeploymentInput {
     pub el_node: ELNode,
-    pub cl_node: CLNode,
+    // beacon nodes in priority order, the first one runs in the "cl" pod and
+    // the fallbacks in "cl-2", "cl-3", ...
+    pub cl_node: CLNodes,
+    #[serde(default)]
+    pub vc_node: Option<VCNode>,
+    // archives the blob sidecars before the beacon node prunes them
+    #[serde(default)]
+    pub blob_archiver: Option<BlobArchiver>,
+    // distributed validator node, it runs in the "dv" pod
+    #[serde(default)]
+    pub dv_node: Option<DVNode>,
+    // remote signer that holds the keystores of the validator client, it
+    // runs in the "signer" pod
+    #[serde(default)]
+    pub remote_signer: Option<RemoteSigner>,
 }
 
 impl Deployment for EthereumDeployment {
     type Input = EthDeploymentInput;
     type Chains = Chains;
 
+    fn defaults(&self, _chain: &Chains) -> serde_json::Value {
+        serde_json::json!({
+            "el_node": { "reth": {} },
+            "cl_node": { "lighthouse": {} },
+        })
+    }
+
     fn capabilities(&self) -> Vec<ChainSpec<Chains>> {
         vec![
             ChainSpec {
                 chain: Chains::Mainnet,
-                min_version: "".to_string(),
+                ..Default::default()
             },
             ChainSpec {
                 chain: Chains::Sepolia,
-                min_version: "".to_string(),
+                ..Default::default()
             },
         ]
     }
 
-    fn manifest(&self, chain: Chains, input: EthDeploymentInput) -> eyre::Result<Manifest> {
+    fn manifest(
+        &self,
+        chain: Chains,
+        input: EthDeploymentInput,
+        ctx: &Context,
+    ) -> eyre::Result<Manifest> {
         let mut manifest = Manifest::new("eth".to_string());
 
-        let mut el_node = match input.el_node {
-            ELNode::Reth(reth) => reth.spec(chain.clone()),
-        }?;
+        let (mut el_node, el_capabilities) = match input.el_node {
+            ELNode::Reth(reth) => (reth.spec(chain.clone(), ctx)?, reth.capabilities()),
+        };
+        manifest.add_dashboards(el_capabilities.dashboards);
 
         // Add Babel sidecar to EL pod
-        let babel_el = Babel::new(
-            "ethereum",
-            Arg::Ref {
-                name: "el".to_string(),
-                port: "http".to_string(),
-            },
-        );
-        el_node = el_node.with_spec("babel", babel_el.spec());
+        let babel_el = Babel::new("ethereum", spec::ref_!("el", "http")).with_ipc(RETH_IPC_SOCKET);
+        el_node = el_node.with_babel(babel_el);
         manifest.add_spec("el".to_string(), el_node);
+        manifest.add_smoke_test(SmokeTest::rpc(
+            "chain-id",
+            "el",
+            "http",
+            "eth_chainId",
+            Expect::Equals(format!("{:#x}", chain.chain_id()).into()),
+        ));
 
-        let mut cl_node = match input.cl_node {
-            CLNode::Lighthouse(lighthouse) => lighthouse.spec(chain.clone()),
-            CLNode::Prysm(prysm) => prysm.spec(chain.clone()),
-        }?;
-
-        // Add Babel sidecar to CL pod
-        let babel_beacon = Babel::new(
-            "ethereum_beacon",
-            Arg::Ref {
-                name: "cl".to_string(),
-                port: "http".to_string(),
-            },
-        );
-        cl_node = cl_node.with_spec("babel", babel_beacon.spec());
-        manifest.add_spec("cl".to_string(), cl_node);
+        let mut cl_pods = vec![];
+        for (i, cl_node) in input.cl_node.into_vec().into_iter().enumerate() {
+            let cl_pod = match i {
+                0 => "cl".to_string(),
+                i => format!("cl-{}", i + 1),
+            };
+            let (mut cl_node, cl_capabilities) = match cl_node {
+                CLNode::Lighthouse(lighthouse) => (
+                    lighthouse.spec(chain.clone(), ctx)?,
+                    lighthouse.capabilities(),
+                ),
+                CLNode::Prysm(prysm) => (prysm.spec(chain.clone(), ctx)?, prysm.capabilities()),
+                CLNode::Nimbus(nimbus) => (nimbus.spec(chain.clone(), ctx)?, nimbus.capabilities()),
+            };
+            manifest.add_dashboards(cl_capabilities.dashboards);
+
+            // Add Babel sidecar to CL pod
+            let babel_beacon = Babel::new("ethereum_beacon", spec::ref_!(cl_pod, "http"));
+            cl_node = cl_node.with_babel(babel_beacon);
+            manifest.add_spec(cl_pod.clone(), cl_node);
+            manifest.add_dependency(&cl_pod, "el");
+            cl_pods.push(cl_pod);
+        }
+
+        // charon sits between the validator client and the beacon node
+        let distributed = matches!(input.dv_node, Some(DVNode::Obol(_)));
+
+        // The clients of the beacon nodes wait for the cl pod only without
+        // fallbacks, otherwise they would not start while the first one is
+        // down. They retry the beacon nodes until one is up.
+        let beacon_dependency = (cl_pods.len() == 1).then_some("cl");
+
+        if input.remote_signer.is_some() && input.vc_node.is_none() {
+            eyre::bail!("The remote signer requires a validator client");
+        }
+
+        if let Some(vc_node) = input.vc_node {
+            let vc_node = match vc_node {
+                VCNode::Lighthouse(mut lighthouse) => {
+                    lighthouse.beacon_pods = cl_pods.clone();
+                    if distributed {
+                        lighthouse.distributed = true;
+                        lighthouse
+                            .beacon_nodes
+                            .get_or_insert_with(|| CHARON_VALIDATOR_API.to_string());
+                    }
+                    // the keystores are loaded by the signer, the validator
+                    // client only registers their public keys
+                    if let Some(RemoteSigner::Web3signer(mut web3signer)) = input.remote_signer {
+                        web3signer.keystores = lighthouse.keystores.clone();
+                        lighthouse.remote_signer = true;
+                        manifest
+                            .add_spec("signer".to_string(), web3signer.spec(chain.clone(), ctx)?);
+                        manifest.add_dependency("vc", "signer");
+                    }
+                    lighthouse.spec(chain.clone(), ctx)?
+                }
+            };
+            manifest.add_spec("vc".to_string(), vc_node);
+            if distributed {
+                manifest.add_dependency("vc", "dv");
+            } else if let Some(cl) = beacon_dependency {
+                manifest.add_dependency("vc", cl);
+            }
+        }
+
+        if let Some(dv_node) = input.dv_node {
+            let dv_node = match dv_node {
+                DVNode::Ssv(mut ssv) => {
+                    ssv.beacon_pods = cl_pods.clone();
+                    ssv.spec(chain.clone(), ctx)?
+                }
+                DVNode::Obol(mut charon) => {
+                    charon.beacon_pods = cl_pods.clone();
+                    charon.spec(chain.clone(), ctx)?
+                }
+            };
+            manifest.add_spec("dv".to_string(), dv_node);
+            if let Some(cl) = beacon_dependency {
+                manifest.add_dependency("dv", cl);
+            }
+        }
+
+        if let Some(blob_archiver) = input.blob_archiver {
+            manifest.add_spec("blobs".to_string(), blob_archiver.spec(chain.clone(), ctx)?);
+            if let Some(cl) = beacon_dependency {
+                manifest.add_dependency("blobs", cl);
+            }
+        }
 
         Ok(manifest)
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ELNode {
     Reth(Reth),
 }
 
-#[derive(Debug, Default, Deserialize)]
-pub struct Reth {}
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Reth {
+    // full (the default) or archive
+    #[serde(default)]
+    pub profile: Option<Profile>,
+    // overrides the default bootnodes of the chain (enode urls)
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+    // custom reth based image (e.g. a node with ExEx enabled) run instead of upstream reth
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    // binary of the custom image, it must accept the reth `node` cli
+    #[serde(default)]
+    pub entrypoint: Vec<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+    // era1 archive files imported before the node starts syncing
+    #[serde(default)]
+    pub era: Option<EraImport>,
+}
+
+/// Era archive files of the history, era1 files of the execution blocks for
+/// the execution clients and era files of the beacon chain for the consensus
+/// clients. Reading them is faster than syncing the history from the peers.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct EraImport {
+    // url of the sha256sum file that lists the era files, they are fetched
+    // from its directory and verified against it
+    pub checksums_url: String,
+}
+
+// directory of the data dir where the era files are fetched
+const ERA_DIR: &str = "/data/era";
+
+/// Init spec that fetches the era files into the era dir unless its `done`
+/// marker exists, `mark_done` sets the marker once they are all verified for
+/// the clients that read them in place instead of importing them
+fn era_fetch(era: &EraImport, mark_done: bool) -> SpecBuilder {
+    let done = format!("{}/done", ERA_DIR);
+    let mut fetch = format!("fetcher --checksums {} {}", era.checksums_url, ERA_DIR);
+    if mark_done {
+        fetch = format!("({} && touch {})", fetch, done);
+    }
+    Spec::builder()
+        .image(FETCHER_IMAGE)
+        .tag(FETCHER_TAG)
+        .entrypoint(["sh", "-c"])
+        .arg(format!("test -f {} || {}", done, fetch))
+}
+
+// IPC socket of reth in the ipc volume of its pod
+const RETH_IPC_SOCKET: &str = "/ipc/reth.ipc";
 
 impl ComputeResource for Reth {
     type Chains = Chains;
 
     fn capabilities(&self) -> Capabilities<Chains> {
         Capabilities {
-            chains: vec![
-                ChainSpec {
-                    chain: Chains::Mainnet,
-                    min_version: "v1.4.8".to_string(),
-                },
-                ChainSpec {
-                    chain: Chains::Sepolia,
-                    min_version: "v1.4.8".to_string(),
-                },
-            ],
-            volumes: vec![Volume {
-                name: "data".to_string(),
+            chains: VERSIONS.chains("reth"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![Dashboard {
+                name: "reth".to_string(),
+                content: include_str!("dashboards/reth.json").to_string(),
             }],
         }
     }
 
-    fn spec(&self, chain: Chains) -> eyre::Result<Pod> {
-        let chain_arg = match chain {
-            Chains::Mainnet => "mainnet",
-            Chains::Sepolia => "sepolia",
-        };
+    fn profiles(&self) -> Vec<Profile> {
+        vec![Profile::Full, Profile::Archive]
+    }
 
-        let node = Spec::builder()
-            .image("ghcr.io/paradigmxyz/reth")
-            .tag("v1.4.8")
-            .arg("node")
-            .arg2("--chain", chain_arg)
-            .arg("--full")
-            .arg2("--color", "never")
-            .arg2(
-                "--authrpc.port",
-                Arg::Port {
-                    name: "authrpc".to_string(),
-                    preferred: 8551,
-                },
-            )
-            .arg2("--authrpc.addr", "0.0.0.0")
-            .arg2("--authrpc.jwtsecret", "/data/jwt_secret")
-            .arg2(
-                "--http.port",
-                Arg::Port {
-                    name: "http".to_string(),
-                    preferred: 8545,
-                },
-            )
-            .arg2("--http.addr", "0.0.0.0")
-            .arg("--http")
-            .arg2("--datadir", "/data")
-            .artifact(Artifacts::File(spec::File {
-                name: "jwt".to_string(),
-                target_path: "/data/jwt_secret".to_string(),
-                content: DEFAULT_JWT_TOKEN.to_string(),
-            }));
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let image = self.image.as_deref().unwrap_or("ghcr.io/paradigmxyz/reth");
+        let tag = VERSIONS.resolve_tag("reth", &chain, ctx, self.tag.as_deref())?;
+        let profile = self.profile(self.profile)?;
 
-        Ok(Pod::default().with_spec("node", node))
+        let mut node = reth_node(image, &tag).arg2("--chain", chain.name());
+
+        // reth keeps the archive by default
+        if profile == Profile::Full {
+            node = node.arg("--full");
+        }
+        if let Some(nat) = ctx.nat_extip() {
+            node = node.arg2("--nat", nat);
+        }
+        if !self.bootnodes.is_empty() {
+            node = node.arg2("--bootnodes", self.bootnodes.join(","));
+        }
+        if !self.entrypoint.is_empty() {
+            node = node.entrypoint(&self.entrypoint);
+        }
+        node = node.overrides(&self.overrides);
+
+        let mut pod = Pod::default();
+        if let Some(era) = &self.era {
+            // the era files are removed once imported, the marker skips both
+            // steps on the next deployments
+            let done = format!("{}/done", ERA_DIR);
+            let fetch = era_fetch(era, false);
+            let binary = reth_binary(&self.entrypoint);
+            let import = Spec::builder()
+                .image(image)
+                .tag(&tag)
+                .entrypoint(["sh", "-c"])
+                .arg(format!(
+                    "test -f {done} || ({binary} import-era --chain {chain} --datadir /data \
+                     --path {dir} && rm -f {dir}/*.era1 && touch {done})",
+                    done = done,
+                    binary = binary,
+                    chain = chain.name(),
+                    dir = ERA_DIR
+                ));
+            pod = pod
+                .with_init_spec("era-fetch", fetch)
+                .with_init_spec("era-import", import);
+        }
+
+        Ok(pod.with_spec("node", node))
+    }
+}
+
+/// Binary of the reth cli in the image of the node, the custom reth based
+/// images set it as their entrypoint
+pub(crate) fn reth_binary(entrypoint: &[String]) -> String {
+    if entrypoint.is_empty() {
+        "reth".to_string()
+    } else {
+        entrypoint.join(" ")
+    }
+}
+
+/// Whether the spec is a node built by `reth_node`, upstream reth or a custom
+/// reth based image
+pub(crate) fn is_reth_node(spec: &Spec) -> bool {
+    matches!(spec.args.first(), Some(Arg::Value(arg)) if arg == "node")
+}
+
+/// Reth node with the flags shared by the modules that run it, the caller
+/// sets the `--chain` it runs
+pub(crate) fn reth_node(image: &str, tag: &str) -> SpecBuilder {
+    Spec::builder()
+        .image(image)
+        .volume(Volume::data())
+        .tag(tag)
+        .ulimit("nofile", NOFILE_LIMIT, NOFILE_LIMIT)
+        .stop_signal("SIGINT")
+        .stop_grace_period(EL_STOP_GRACE_PERIOD)
+        // the debug logs of reth fill the disk without rotation
+        .logging(LogConfig::rotate("100m", 5))
+        .port(Port::tcp("p2p", 30303).public())
+        .port(Port::udp("discovery", 30303).public())
+        .arg("node")
+        .arg2("--color", "never")
+        .arg2("--authrpc.port", spec::port!("authrpc", 8551))
+        .arg2("--authrpc.addr", "0.0.0.0")
+        .arg2("--authrpc.jwtsecret", Arg::secret(JWT_SECRET))
+        .arg2("--http.port", spec::port!("http", 8545))
+        .arg2("--http.addr", "0.0.0.0")
+        .arg("--http")
+        .arg2("--ws.port", spec::port!("ws", 8546))
+        .arg2("--ws.addr", "0.0.0.0")
+        .arg("--ws")
+        .arg2("--metrics", spec::port!(METRICS_PORT, 9001))
+        .arg2("--datadir", "/data")
+        // the babel of the pod reaches the node on it
+        .volume(Volume::ipc())
+        .arg2("--ipcpath", RETH_IPC_SOCKET)
+        .identity_key("/data/discovery-secret")
+}
+
+/// Lighthouse beacon node with the flags shared by the modules that run it,
+/// the caller sets the network it runs
+pub(crate) fn beacon_node(
+    image: &str,
+    tag: &str,
+    bootnodes: &[String],
+    ctx: &Context,
+) -> SpecBuilder {
+    let mut node = Spec::builder()
+        .image(image)
+        .volume(Volume::data())
+        .tag(tag)
+        // the beacon node only writes to its data dir
+        .unprivileged(NODE_UID)
+        .port(Port::tcp("p2p", 9000).public())
+        .port(Port::udp("discovery", 9000).public())
+        .port(Port::udp("quic", 9001).public())
+        .entrypoint(["lighthouse"])
+        .arg("bn")
+        .arg2("--execution-endpoint", spec::ref_!("el", "authrpc"))
+        .arg2("--execution-jwt", Arg::secret(JWT_SECRET))
+        .arg2("--http-port", spec::port!("http", 5052))
+        .arg2("--http-address", "0.0.0.0")
+        .arg("--http")
+        .arg("--metrics")
+        .arg2("--metrics-address", "0.0.0.0")
+        .arg2("--metrics-port", spec::port!(METRICS_PORT, 5054))
+        .arg2("--datadir", "/data")
+        .identity_key("/data/beacon/network/key");
+
+    if let Some(ip) = ctx.external_ip {
+        node = node.arg2("--enr-address", ip.to_string());
+    }
+    if !bootnodes.is_empty() {
+        node = node.arg2("--boot-nodes", bootnodes.join(","));
     }
+    node
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum CLNode {
     Prysm(Prysm),
     Lighthouse(Lighthouse),
+    Nimbus(Nimbus),
 }
 
-#[derive(Debug, Default, Deserialize)]
-pub struct Lighthouse {}
+/// A single beacon node or a list of them in priority order
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum CLNodes {
+    One(CLNode),
+    Many(Vec<CLNode>),
+}
+
+impl CLNodes {
+    fn into_vec(self) -> Vec<CLNode> {
+        match self {
+            CLNodes::One(node) => vec![node],
+            CLNodes::Many(nodes) => nodes,
+        }
+    }
+}
+
+/// Beacon api urls of the beacon pods in priority order, the cl pod if none
+fn beacon_nodes(beacon_pods: &[String]) -> Arg {
+    if beacon_pods.is_empty() {
+        return spec::ref_!("cl", "http");
+    }
+    Arg::Refs {
+        names: beacon_pods.to_vec(),
+        port: "http".to_string(),
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Lighthouse {
+    // overrides the default bootnodes of the chain (enr records)
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+    // execution address that receives the priority fees of proposed blocks
+    #[serde(default)]
+    pub suggested_fee_recipient: Option<String>,
+    // url of the external block builder (mev-boost or a relay)
+    #[serde(default)]
+    pub builder: Option<String>,
+    // epochs the blobs are kept on top of the minimum retention window
+    #[serde(default)]
+    pub blob_retention_epochs: Option<u64>,
+    // image run instead of sigp/lighthouse (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
 
 impl ComputeResource for Lighthouse {
     type Chains = Chains;
 
     fn capabilities(&self) -> Capabilities<Chains> {
         Capabilities {
-            chains: vec![
-                ChainSpec {
-                    chain: Chains::Mainnet,
-                    min_version: "v1.4.8".to_string(),
-                },
-                ChainSpec {
-                    chain: Chains::Sepolia,
-                    min_version: "v1.4.8".to_string(),
-                },
-            ],
-            volumes: vec![Volume {
-                name: "data".to_string(),
+            chains: VERSIONS.chains("lighthouse"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![Dashboard {
+                name: "lighthouse".to_string(),
+                content: include_str!("dashboards/lighthouse.json").to_string(),
             }],
         }
     }
 
-    fn spec(&self, chain: Chains) -> eyre::Result<Pod> {
-        let chain_arg = match chain {
-            Chains::Mainnet => "mainnet",
-            Chains::Sepolia => "sepolia",
-        };
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let tag = VERSIONS.resolve_tag("lighthouse", &chain, ctx, self.tag.as_deref())?;
+        let mut node = beacon_node(
+            self.image.as_deref().unwrap_or("sigp/lighthouse"),
+            &tag,
+            &self.bootnodes,
+            ctx,
+        )
+        .arg2("--network", chain.name());
 
-        let node = Spec::builder()
-            .image("sigp/lighthouse")
-            .tag("v8.0.0-rc.2")
-            .entrypoint(["lighthouse"])
-            .arg("bn")
-            .arg2("--network", chain_arg)
-            .arg2(
-                "--execution-endpoint",
-                Arg::Ref {
-                    name: "el".to_string(),
-                    port: "authrpc".to_string(),
-                },
-            )
-            .arg2("--execution-jwt", "/data/jwt_secret")
-            .arg2(
-                "--http-port",
-                Arg::Port {
-                    name: "http".to_string(),
-                    preferred: 5052,
-                },
-            )
-            .arg2("--http-address", "0.0.0.0")
-            .arg("--http")
-            .arg2("--datadir", "/data")
-            .artifact(Artifacts::File(spec::File {
-                name: "jwt".to_string(),
-                target_path: "/data/jwt_secret".to_string(),
-                content: DEFAULT_JWT_TOKEN.to_string(),
-            }));
+        if let Some(fee_recipient) = &self.suggested_fee_recipient {
+            validate_address(fee_recipient)?;
+            node = node.arg2("--suggested-fee-recipient", fee_recipient);
+        }
+        if let Some(builder) = &self.builder {
+            node = node.arg2("--builder", builder);
+        }
+        if let Some(epochs) = self.blob_retention_epochs {
+            node = node.arg2("--blob-prune-margin-epochs", epochs.to_string());
+        }
 
+        node = node.overrides(&self.overrides);
         Ok(Pod::default().with_spec("node", node))
     }
 }
 
-#[derive(Debug, Default, Deserialize)]
-pub struct Prysm {}
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Prysm {
+    // overrides the default bootnodes of the chain (enr records)
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+    // execution address that receives the priority fees of proposed blocks
+    #[serde(default)]
+    pub suggested_fee_recipient: Option<String>,
+    // url of the external block builder (mev-boost or a relay)
+    #[serde(default)]
+    pub builder: Option<String>,
+    // epochs the blobs are kept on top of the minimum retention window
+    #[serde(default)]
+    pub blob_retention_epochs: Option<u64>,
+    // image run instead of gcr.io/prysmaticlabs/prysm/beacon-chain (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
 
 impl ComputeResource for Prysm {
     type Chains = Chains;
 
     fn capabilities(&self) -> Capabilities<Chains> {
         Capabilities {
-            chains: vec![],
-            volumes: vec![],
+            chains: VERSIONS.chains("prysm"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
         }
     }
 
-    fn spec(&self, chain: Chains) -> eyre::Result<Pod> {
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
         let chain_arg = match chain {
             Chains::Mainnet => "--mainnet",
             Chains::Sepolia => "--sepolia",
         };
 
-        let node = Spec::builder()
-            .image("gcr.io/prysmaticlabs/prysm/beacon-chain")
-            .tag("v6.0.0")
+        let mut node = Spec::builder()
+            .image(
+                self.image
+                    .as_deref()
+                    .unwrap_or("gcr.io/prysmaticlabs/prysm/beacon-chain"),
+            )
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("prysm", &chain, ctx, self.tag.as_deref())?)
+            .port(Port::tcp("p2p", 13000).public())
+            .port(Port::udp("discovery", 12000).public())
             .arg(chain_arg)
+            .arg2("--datadir", spec::dir!("prysm_data", "/data"))
+            .arg2("--execution-endpoint", spec::ref_!("el", "authrpc"))
+            .arg2("--jwt-secret", Arg::secret(JWT_SECRET))
+            .arg2("--grpc-gateway-host", "0.0.0.0")
+            .arg2("--grpc-gateway-port", spec::port!("http", 5052))
+            .arg2("--monitoring-host", "0.0.0.0")
+            .arg2("--monitoring-port", spec::port!(METRICS_PORT, 8080))
+            .arg("--accept-terms-of-use");
+
+        if let Some(ip) = ctx.external_ip {
+            node = node.arg2("--p2p-host-ip", ip.to_string());
+        }
+        for bootnode in &self.bootnodes {
+            node = node.arg2("--bootstrap-node", bootnode);
+        }
+        if let Some(fee_recipient) = &self.suggested_fee_recipient {
+            validate_address(fee_recipient)?;
+            node = node.arg2("--suggested-fee-recipient", fee_recipient);
+        }
+        if let Some(builder) = &self.builder {
+            node = node.arg2("--http-mev-relay", builder);
+        }
+        if let Some(epochs) = self.blob_retention_epochs {
+            // prysm takes the full retention window instead of a margin
+            node = node.arg2(
+                "--blob-retention-epochs",
+                (MIN_EPOCHS_FOR_BLOB_SIDECARS_REQUESTS + epochs).to_string(),
+            );
+        }
+
+        node = node.overrides(&self.overrides);
+        Ok(Pod::default().with_spec("node", node))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Nimbus {
+    // overrides the default bootnodes of the chain (enr records)
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+    // execution address that receives the priority fees of proposed blocks
+    #[serde(default)]
+    pub suggested_fee_recipient: Option<String>,
+    // url of the external block builder (mev-boost or a relay)
+    #[serde(default)]
+    pub builder: Option<String>,
+    // era files of the beacon chain read by the node instead of backfilling
+    // the history from the peers
+    #[serde(default)]
+    pub era: Option<EraImport>,
+    // image run instead of statusim/nimbus-eth2 (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+// nimbus only takes `--flag=value` options, the engine api url and the jwt
+// secret resolved by the runtime are passed as the first two args of the shell
+const NIMBUS_COMMAND: &str = "el=$1 jwt=$2; shift 2; \
+    exec /home/user/nimbus-eth2/build/nimbus_beacon_node --el=$el --jwt-secret=$jwt \"$@\"";
+
+impl ComputeResource for Nimbus {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("nimbus"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let mut node = Spec::builder()
+            .image(self.image.as_deref().unwrap_or("statusim/nimbus-eth2"))
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("nimbus", &chain, ctx, self.tag.as_deref())?)
+            // the beacon node only writes to its data dir
+            .unprivileged(NODE_UID)
+            .port(Port::tcp("p2p", 9000).public())
+            .port(Port::udp("discovery", 9000).public())
+            .port(Port::tcp("http", 5052))
+            .port(Port::tcp(METRICS_PORT, 8008))
+            .entrypoint(["sh", "-c", NIMBUS_COMMAND, "nimbus"])
+            .arg(spec::ref_!("el", "authrpc"))
+            .arg(Arg::secret(JWT_SECRET))
+            .arg(format!("--network={}", chain.name()))
+            .arg("--data-dir=/data")
+            .arg("--non-interactive")
+            .arg("--tcp-port=9000")
+            .arg("--udp-port=9000")
+            .arg("--rest")
+            .arg("--rest-address=0.0.0.0")
+            .arg("--rest-port=5052")
+            .arg("--metrics")
+            .arg("--metrics-address=0.0.0.0")
+            .arg("--metrics-port=8008");
+
+        if let Some(nat) = ctx.nat_extip() {
+            node = node.arg(format!("--nat={}", nat));
+        }
+        for bootnode in &self.bootnodes {
+            node = node.arg(format!("--bootstrap-node={}", bootnode));
+        }
+        if let Some(fee_recipient) = &self.suggested_fee_recipient {
+            validate_address(fee_recipient)?;
+            node = node.arg(format!("--suggested-fee-recipient={}", fee_recipient));
+        }
+        if let Some(builder) = &self.builder {
+            node = node
+                .arg("--payload-builder=true")
+                .arg(format!("--payload-builder-url={}", builder));
+        }
+
+        let mut pod = Pod::default();
+        if let Some(era) = &self.era {
+            // the era files are kept, the node reads the history from them
+            node = node.arg(format!("--era-dir={}", ERA_DIR));
+            pod = pod.with_init_spec("era-fetch", era_fetch(era, true));
+        }
+
+        node = node.overrides(&self.overrides);
+        Ok(pod.with_spec("node", node))
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum VCNode {
+    Lighthouse(LighthouseValidator),
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct LighthouseValidator {
+    // execution address that receives the priority fees of proposed blocks
+    #[serde(default)]
+    pub suggested_fee_recipient: Option<String>,
+    // propose blocks through the builder configured in the beacon node
+    #[serde(default)]
+    pub builder_proposals: bool,
+    // beacon api the validator connects to, defaults to the cl pod
+    #[serde(default)]
+    pub beacon_nodes: Option<String>,
+    // run as part of a distributed validator cluster
+    #[serde(default)]
+    pub distributed: bool,
+    // EIP-2335 keystores imported before the validator client starts, the
+    // password is read from the keystore_password secret
+    #[serde(default)]
+    pub keystores: Option<KeystoreSource>,
+    // EIP-3076 interchange imported with the keystores, required for them
+    // unless the validators are new and never signed
+    #[serde(default)]
+    pub slashing_protection: Option<serde_json::Value>,
+    #[serde(default)]
+    pub allow_missing_slashing_protection: bool,
+    // the keystores are held by the remote signer of the deployment
+    #[serde(skip)]
+    pub(crate) remote_signer: bool,
+    // pods of the beacon nodes of the deployment in priority order
+    #[serde(skip)]
+    pub(crate) beacon_pods: Vec<String>,
+    // image run instead of sigp/lighthouse (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+impl ComputeResource for LighthouseValidator {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("lighthouse_validator"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        // the init specs run the same lighthouse as the validator client
+        let image = self.image.as_deref().unwrap_or("sigp/lighthouse");
+        let tag = VERSIONS.resolve_tag("lighthouse_validator", &chain, ctx, self.tag.as_deref())?;
+
+        let mut node = Spec::builder()
+            .image(image)
+            .volume(Volume::data())
+            .tag(&tag)
+            .entrypoint(["lighthouse"])
+            // read by the slashing protection export
+            .env("NETWORK", chain.name())
+            .arg("vc")
+            .arg2("--network", chain.name())
+            .arg2("--datadir", "/data/validators")
             .arg2(
-                "--datadir",
-                Arg::Dir {
-                    name: "prysm_data".to_string(),
-                    path: "/data".to_string(),
+                "--beacon-nodes",
+                match &self.beacon_nodes {
+                    Some(beacon_nodes) => Arg::Value(beacon_nodes.clone()),
+                    None => beacon_nodes(&self.beacon_pods),
                 },
             )
-            .arg2(
-                "--execution-endpoint",
-                Arg::Ref {
-                    name: "el".to_string(),
-                    port: "authrpc".to_string(),
-                },
+            .arg("--init-slashing-protection")
+            .arg("--metrics")
+            .arg2("--metrics-address", "0.0.0.0")
+            .arg2("--metrics-port", spec::port!(METRICS_PORT, 5064));
+
+        if let Some(fee_recipient) = &self.suggested_fee_recipient {
+            validate_address(fee_recipient)?;
+            node = node.arg2("--suggested-fee-recipient", fee_recipient);
+        }
+        if self.builder_proposals {
+            node = node.arg("--builder-proposals");
+        }
+        if self.distributed {
+            node = node.arg("--distributed");
+        }
+
+        if self.slashing_protection.is_some() && self.keystores.is_none() {
+            eyre::bail!("Slashing protection is only imported with keystores");
+        }
+
+        let mut pod = Pod::default();
+        if let Some(source) = &self.keystores {
+            let keystores = keystore::load(source)?;
+            let pubkeys: Vec<String> = keystores.iter().map(|k| k.pubkey.clone()).collect();
+
+            if self.remote_signer {
+                pod = pod.with_init_spec(
+                    "register-remote-signer",
+                    remote_signer_definitions(image, &tag, &pubkeys),
+                );
+            } else {
+                let mut import = Spec::builder()
+                    .image(image)
+                    .tag(&tag)
+                    .entrypoint(["lighthouse"])
+                    .arg("account")
+                    .arg("validator")
+                    .arg("import")
+                    .arg2("--network", chain.name())
+                    .arg2("--datadir", "/data/validators")
+                    .arg2("--directory", KEYSTORES_DIR)
+                    .arg("--reuse-password")
+                    .arg2("--password-file", Arg::secret(KEYSTORE_PASSWORD_SECRET));
+                for keystore in keystores {
+                    import = import.artifact(Artifacts::File(spec::File {
+                        name: format!("keystores/{}", keystore.file_name()),
+                        target_path: format!("{}/{}", KEYSTORES_DIR, keystore.file_name()),
+                        content: keystore.content.into(),
+                        mode: Some(0o600),
+                        read_only: true,
+                        ..Default::default()
+                    }));
+                }
+                pod = pod.with_init_spec("import-keystores", import);
+            }
+
+            // validators that signed elsewhere must not start without their history
+            match &self.slashing_protection {
+                Some(interchange) => {
+                    slashing_protection::validate(interchange, &pubkeys)?;
+                    let import = Spec::builder()
+                        .image(image)
+                        .tag(&tag)
+                        .entrypoint(["lighthouse"])
+                        .arg("account")
+                        .arg("validator")
+                        .arg("slashing-protection")
+                        .arg("import")
+                        .arg(slashing_protection::INTERCHANGE_PATH)
+                        .arg2("--network", chain.name())
+                        .arg2("--datadir", "/data/validators")
+                        .artifact(Artifacts::File(spec::File {
+                            name: "slashing-protection.json".to_string(),
+                            target_path: slashing_protection::INTERCHANGE_PATH.to_string(),
+                            content: serde_json::to_string(interchange)?.into(),
+                            read_only: true,
+                            ..Default::default()
+                        }));
+                    pod = pod.with_init_spec("import-slashing-protection", import);
+                }
+                None if !self.allow_missing_slashing_protection => eyre::bail!(
+                    "Importing keystores requires their slashing protection, set \
+                     allow_missing_slashing_protection only for new validators"
+                ),
+                None => {}
+            }
+        }
+
+        node = node.overrides(&self.overrides);
+        Ok(pod.with_spec("node", node))
+    }
+}
+
+// where the validator definitions of the remote signer are mounted in the
+// init spec that registers them
+const REMOTE_SIGNER_DEFINITIONS: &str = "/remote-signer/validator_definitions.yml";
+
+/// Init spec that registers the validators of the remote signer in the
+/// validator definitions of lighthouse, the url of the signer is resolved
+/// from its ref and replaces the placeholder of the definitions
+fn remote_signer_definitions(image: &str, tag: &str, pubkeys: &[String]) -> Spec {
+    let definitions: String = pubkeys
+        .iter()
+        .map(|pubkey| {
+            format!(
+                "- enabled: true\n  voting_public_key: \"0x{}\"\n  type: web3signer\n  url: \"SIGNER_URL\"\n",
+                pubkey
             )
-            .arg2("--jwt-secret", "/data/jwt_secret".to_string())
-            .arg2("--grpc-gateway-host", "0.0.0.0")
-            .arg2(
-                "--grpc-gateway-port",
-                Arg::Port {
-                    name: "http".to_string(),
-                    preferred: 5052,
-                },
+        })
+        .collect();
+
+    let script = format!(
+        "mkdir -p /data/validators/validators && sed \"s|SIGNER_URL|$1|\" {} > \
+         /data/validators/validators/validator_definitions.yml",
+        REMOTE_SIGNER_DEFINITIONS
+    );
+    Spec::builder()
+        .image(image)
+        .tag(tag)
+        .entrypoint(["sh", "-c"])
+        .arg(script)
+        .arg("sh")
+        .arg(spec::ref_!("signer", "http"))
+        .artifact(Artifacts::File(spec::File {
+            name: "remote-signer/validator_definitions.yml".to_string(),
+            target_path: REMOTE_SIGNER_DEFINITIONS.to_string(),
+            content: definitions.into(),
+            read_only: true,
+            ..Default::default()
+        }))
+        .build()
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteSigner {
+    Web3signer(Web3Signer),
+}
+
+/// Web3Signer remote signer, it signs the duties of the validator client
+/// with the keystores of the deployment so they are not kept on the
+/// validator host. The slashing protection stays in the validator client.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Web3Signer {
+    // keystores of the validator client routed to the signer
+    #[serde(skip)]
+    pub(crate) keystores: Option<KeystoreSource>,
+    // image run instead of consensys/web3signer (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+impl ComputeResource for Web3Signer {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("web3signer"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let mut node = Spec::builder()
+            .image(self.image.as_deref().unwrap_or("consensys/web3signer"))
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("web3signer", &chain, ctx, self.tag.as_deref())?)
+            .arg2("--data-path", "/data")
+            .arg2("--http-listen-host", "0.0.0.0")
+            .arg2("--http-listen-port", spec::port!("http", 9000))
+            .arg2("--http-host-allowlist", "*")
+            .arg("--metrics-enabled")
+            .arg2("--metrics-host", "0.0.0.0")
+            .arg2("--metrics-host-allowlist", "*")
+            .arg2("--metrics-port", spec::port!(METRICS_PORT, 9001))
+            .arg("eth2")
+            .arg2("--network", chain.name())
+            // lighthouse keeps the slashing protection of the validators
+            .arg2("--slashing-protection-enabled", "false");
+
+        if let Some(source) = &self.keystores {
+            node = node.arg2("--keystores-path", KEYSTORES_DIR).arg2(
+                "--keystores-password-file",
+                Arg::secret(KEYSTORE_PASSWORD_SECRET),
+            );
+            for keystore in keystore::load(source)? {
+                node = node.artifact(Artifacts::File(spec::File {
+                    name: format!("keystores/{}", keystore.file_name()),
+                    target_path: format!("{}/{}", KEYSTORES_DIR, keystore.file_name()),
+                    content: keystore.content.into(),
+                    mode: Some(0o600),
+                    read_only: true,
+                    ..Default::default()
+                }));
+            }
+        }
+
+        node = node.overrides(&self.overrides);
+        Ok(Pod::default().with_spec("node", node))
+    }
+}
+
+// epochs (~18 days) the consensus clients must serve the blob sidecars for
+const MIN_EPOCHS_FOR_BLOB_SIDECARS_REQUESTS: u64 = 4096;
+
+/// Stores the blob sidecars served by the beacon node so they remain
+/// available after the beacon node prunes them
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct BlobArchiver {
+    // hash of the oldest block whose blobs are archived
+    pub origin_block: String,
+    // image run instead of ghcr.io/base/blob-archiver (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+impl ComputeResource for BlobArchiver {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("blob_archiver"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let node = Spec::builder()
+            .image(
+                self.image
+                    .as_deref()
+                    .unwrap_or("ghcr.io/base/blob-archiver"),
             )
-            .arg("--accept-terms-of-use")
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("blob_archiver", &chain, ctx, self.tag.as_deref())?)
+            .entrypoint(["blob-archiver"])
+            .arg2("--l1-beacon-http", spec::ref_!("cl", "http"))
+            .arg2("--origin-block", &self.origin_block)
+            .arg2("--data-store", "disk")
+            .arg2("--file-storage-path", "/data/blobs")
+            .arg("--metrics.enabled")
+            .arg2("--metrics.addr", "0.0.0.0")
+            .arg2("--metrics.port", spec::port!(METRICS_PORT, 7300))
+            .overrides(&self.overrides);
+
+        Ok(Pod::default().with_spec("archiver", node))
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DVNode {
+    Ssv(SsvNode),
+    Obol(Charon),
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "ssv/config.yaml")]
+struct SsvConfigFile {
+    network: String,
+    beacon_node_addr: String,
+    eth1_addr: String,
+    host_address: String,
+    metrics_port: u16,
+    password_file: String,
+}
+
+// password that decrypts the operator keystore of the ssv node
+pub(crate) const SSV_OPERATOR_PASSWORD_SECRET: &str = "ssv_operator_password";
+
+/// SSV operator node, it runs the validator duties of the shares assigned
+/// to the operator on the SSV network
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct SsvNode {
+    // encrypted operator private key (keystore json), it is decrypted with
+    // the ssv_operator_password secret
+    pub operator_keystore: String,
+    // image run instead of ssvlabs/ssv-node (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+    // pods of the beacon nodes of the deployment in priority order
+    #[serde(skip)]
+    pub(crate) beacon_pods: Vec<String>,
+}
+
+impl ComputeResource for SsvNode {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("ssv"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let tag = VERSIONS.resolve_tag("ssv", &chain, ctx, self.tag.as_deref())?;
+
+        // ssv fails over between the beacon nodes separated by ';'
+        let beacon_pods = match self.beacon_pods.as_slice() {
+            [] => &["cl".to_string()][..],
+            pods => pods,
+        };
+        let beacon_node_addr = beacon_pods
+            .iter()
+            .map(|pod| format!("{{{{ref:{}:http}}}}", pod))
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let config = SsvConfigFile {
+            network: chain.name().to_string(),
+            // resolved by the runtime, ssv only takes a websocket for the el
+            beacon_node_addr,
+            eth1_addr: "ws://{{addr:el:ws}}".to_string(),
+            host_address: ctx.external_ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            metrics_port: 15000,
+            password_file: format!("{{{{secret:{}}}}}", SSV_OPERATOR_PASSWORD_SECRET),
+        };
+
+        let node = Spec::builder()
+            .image(self.image.as_deref().unwrap_or("ssvlabs/ssv-node"))
+            .volume(Volume::data())
+            .tag(tag)
+            .port(Port::tcp("p2p", 13001).public())
+            .port(Port::udp("discovery", 12001).public())
+            .entrypoint(["/go/bin/ssvnode"])
+            .arg("start-node")
+            .env("CONFIG_PATH", "/data/config.yaml")
+            .artifact(Artifacts::File(spec::File {
+                name: "config".to_string(),
+                target_path: "/data/config.yaml".to_string(),
+                content: config.render().into(),
+                read_only: true,
+                ..Default::default()
+            }))
             .artifact(Artifacts::File(spec::File {
-                name: "jwt".to_string(),
-                target_path: "/data/jwt_secret".to_string(),
-                content: DEFAULT_JWT_TOKEN.to_string(),
+                name: "operator_keystore".to_string(),
+                target_path: "/data/operator_keystore.json".to_string(),
+                content: self.operator_keystore.clone().into(),
+                mode: Some(0o600),
+                read_only: true,
+                ..Default::default()
+            }))
+            .overrides(&self.overrides);
+
+        Ok(Pod::default().with_spec("node", node))
+    }
+}
+
+// validator api of charon that the validator client connects to
+const CHARON_VALIDATOR_API: &str = "http://dv-node:3600";
+
+/// Obol charon middleware, it coordinates the validator client of this node
+/// with the other operators of the distributed validator cluster
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Charon {
+    // cluster-lock.json produced by the distributed key generation ceremony
+    pub cluster_lock: String,
+    // private key of the charon enr of this operator
+    pub enr_private_key: String,
+    // overrides the default obol relays
+    #[serde(default)]
+    pub relays: Vec<String>,
+    // image run instead of obolnetwork/charon (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+    // pods of the beacon nodes of the deployment in priority order
+    #[serde(skip)]
+    pub(crate) beacon_pods: Vec<String>,
+}
+
+impl ComputeResource for Charon {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("charon"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let mut node = Spec::builder()
+            .image(self.image.as_deref().unwrap_or("obolnetwork/charon"))
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("charon", &chain, ctx, self.tag.as_deref())?)
+            .port(Port::tcp("p2p", 3610).public())
+            .arg("run")
+            .arg2("--beacon-node-endpoints", beacon_nodes(&self.beacon_pods))
+            .arg2("--validator-api-address", "0.0.0.0:3600")
+            .arg2("--p2p-tcp-address", "0.0.0.0:3610")
+            .arg2("--monitoring-address", "0.0.0.0:3620")
+            .arg2("--lock-file", "/data/cluster-lock.json")
+            .arg2("--private-key-file", "/data/charon-enr-private-key")
+            .artifact(Artifacts::File(spec::File {
+                name: "cluster_lock".to_string(),
+                target_path: "/data/cluster-lock.json".to_string(),
+                content: self.cluster_lock.clone().into(),
+                read_only: true,
+                ..Default::default()
+            }))
+            .artifact(Artifacts::File(spec::File {
+                name: "enr_private_key".to_string(),
+                target_path: "/data/charon-enr-private-key".to_string(),
+                content: self.enr_private_key.clone().into(),
+                mode: Some(0o600),
+                read_only: true,
+                ..Default::default()
             }));
 
+        if let Some(ip) = ctx.external_ip {
+            node = node.arg2("--p2p-external-ip", ip.to_string());
+        }
+        if !self.relays.is_empty() {
+            node = node.arg2("--p2p-relays", self.relays.join(","));
+        }
+
+        node = node.overrides(&self.overrides);
         Ok(Pod::default().with_spec("node", node))
     }
 }
+
+/// Checks that the value is a 0x prefixed 20 bytes hex execution address
+fn validate_address(address: &str) -> eyre::Result<()> {
+    let valid = address
+        .strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+
+    if !valid {
+        return Err(eyre::eyre!("Invalid execution address: {}", address));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_chain_of_the_dep_is_deployed() -> eyre::Result<()> {
+        let dep: spec::Dep = serde_json::from_value(json!({
+            "module": "ethereum",
+            "chain": "ethereum.sepolia",
+            "args": {}
+        }))?;
+        let manifest = crate::apply(dep)?;
+        let reth = &manifest.pods["el"].specs["node"];
+        assert!(reth.args.windows(2).any(|args| matches!(
+            args,
+            [Arg::Value(flag), Arg::Value(chain)] if flag == "--chain" && chain == "sepolia"
+        )));
+
+        let dep: spec::Dep = serde_json::from_value(json!({
+            "module": "ethereum",
+            "chain": "holesky",
+            "args": {}
+        }))?;
+        assert!(crate::apply(dep).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_schema_lists_the_clients() {
+        let schema = crate::schemas().remove("ethereum").unwrap();
+        assert_eq!(schema["title"], "EthDeploymentInput");
+        assert_eq!(schema["required"], json!(["el_node", "cl_node"]));
+
+        let lighthouse = &schema["$defs"]["Lighthouse"]["properties"];
+        assert_eq!(lighthouse["extra_args"]["type"], "array");
+        assert!(schema["$defs"]["CLNode"]["oneOf"].is_array());
+    }
+
+    #[test]
+    fn test_era_files_are_imported_before_the_node() -> eyre::Result<()> {
+        let reth: Reth = serde_json::from_value(json!({
+            "era": { "checksums_url": "https://era.example.com/checksums.txt" }
+        }))?;
+        let pod = reth.spec(Chains::Mainnet, &Context::default())?;
+
+        let names: Vec<&str> = pod
+            .init_specs
+            .iter()
+            .map(|init| init.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["era-fetch", "era-import"]);
+        let Arg::Value(import) = &pod.init_specs[1].spec.args[0] else {
+            panic!("the import is a script");
+        };
+        assert!(import.contains("reth import-era --chain mainnet --datadir /data"));
+        assert_eq!(pod.init_specs[0].spec.tag.as_deref(), Some(FETCHER_TAG));
+        Ok(())
+    }
+
+    #[test]
+    fn test_beacon_era_files_are_read_by_nimbus() -> eyre::Result<()> {
+        let nimbus: Nimbus = serde_json::from_value(json!({
+            "era": { "checksums_url": "https://era.example.com/checksums.txt" }
+        }))?;
+        let pod = nimbus.spec(Chains::Mainnet, &Context::default())?;
+
+        let names: Vec<&str> = pod
+            .init_specs
+            .iter()
+            .map(|init| init.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["era-fetch"]);
+        let Arg::Value(fetch) = &pod.init_specs[0].spec.args[0] else {
+            panic!("the fetch is a script");
+        };
+        assert!(fetch.contains("fetcher --checksums https://era.example.com/checksums.txt"));
+        assert!(fetch.contains("touch /data/era/done"));
+
+        // the engine api and the jwt secret are the first args of the wrapper
+        let node = &pod.specs["node"];
+        assert!(
+            matches!(&node.args[0], Arg::Ref { name, port } if name == "el" && port == "authrpc")
+        );
+        assert!(matches!(&node.args[1], Arg::Secret { name } if name == JWT_SECRET));
+        assert!(
+            node.args
+                .iter()
+                .any(|arg| matches!(arg, Arg::Value(arg) if arg == "--era-dir=/data/era"))
+        );
+        Ok(())
+    }
+
+    // values of the flag in the args of the spec, in order
+    fn flag_values<'a>(spec: &'a Spec, flag: &str) -> Vec<&'a str> {
+        spec.args
+            .windows(2)
+            .filter_map(|args| match args {
+                [Arg::Value(name), Arg::Value(value)] if name == flag => Some(value.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bootnodes_override_the_chain_defaults() -> eyre::Result<()> {
+        let pod = Reth::default().spec(Chains::Mainnet, &Context::default())?;
+        assert!(flag_values(&pod.specs["node"], "--bootnodes").is_empty());
+
+        let reth: Reth = serde_json::from_value(json!({
+            "bootnodes": ["enode://a@203.0.113.7:30303", "enode://b@203.0.113.8:30303"]
+        }))?;
+        let pod = reth.spec(Chains::Mainnet, &Context::default())?;
+        assert_eq!(
+            flag_values(&pod.specs["node"], "--bootnodes"),
+            vec!["enode://a@203.0.113.7:30303,enode://b@203.0.113.8:30303"]
+        );
+
+        // prysm takes one flag per node
+        let prysm: Prysm = serde_json::from_value(json!({ "bootnodes": ["enr:-a", "enr:-b"] }))?;
+        let pod = prysm.spec(Chains::Mainnet, &Context::default())?;
+        assert_eq!(
+            flag_values(&pod.specs["node"], "--bootstrap-node"),
+            vec!["enr:-a", "enr:-b"]
+        );
+
+        let lighthouse: Lighthouse =
+            serde_json::from_value(json!({ "bootnodes": ["enr:-a", "enr:-b"] }))?;
+        let pod = lighthouse.spec(Chains::Mainnet, &Context::default())?;
+        assert_eq!(
+            flag_values(&pod.specs["node"], "--boot-nodes"),
+            vec!["enr:-a,enr:-b"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee_recipient_and_builder_of_the_proposers() -> eyre::Result<()> {
+        let fee_recipient = "0x00000000219ab540356cbb839cbe05303d7705fa";
+        let input = json!({
+            "suggested_fee_recipient": fee_recipient,
+            "builder": "http://mev-boost:18550"
+        });
+
+        let lighthouse: Lighthouse = serde_json::from_value(input.clone())?;
+        let node = &lighthouse.spec(Chains::Mainnet, &Context::default())?.specs["node"];
+        assert_eq!(
+            flag_values(node, "--suggested-fee-recipient"),
+            vec![fee_recipient]
+        );
+        assert_eq!(
+            flag_values(node, "--builder"),
+            vec!["http://mev-boost:18550"]
+        );
+
+        let prysm: Prysm = serde_json::from_value(input.clone())?;
+        let node = &prysm.spec(Chains::Mainnet, &Context::default())?.specs["node"];
+        assert_eq!(
+            flag_values(node, "--http-mev-relay"),
+            vec!["http://mev-boost:18550"]
+        );
+
+        let nimbus: Nimbus = serde_json::from_value(input)?;
+        let node = &nimbus.spec(Chains::Mainnet, &Context::default())?.specs["node"];
+        assert!(node.args.iter().any(
+            |arg| matches!(arg, Arg::Value(arg) if arg == "--payload-builder-url=http://mev-boost:18550")
+        ));
+
+        let validator: LighthouseValidator = serde_json::from_value(json!({
+            "suggested_fee_recipient": fee_recipient,
+            "builder_proposals": true
+        }))?;
+        let node = &validator.spec(Chains::Mainnet, &Context::default())?.specs["node"];
+        assert_eq!(
+            flag_values(node, "--suggested-fee-recipient"),
+            vec![fee_recipient]
+        );
+        assert!(
+            node.args
+                .iter()
+                .any(|arg| matches!(arg, Arg::Value(arg) if arg == "--builder-proposals"))
+        );
+
+        let lighthouse: Lighthouse =
+            serde_json::from_value(json!({ "suggested_fee_recipient": "0x1234" }))?;
+        let err = lighthouse
+            .spec(Chains::Mainnet, &Context::default())
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Invalid execution address: 0x1234")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_of_the_node() -> eyre::Result<()> {
+        let full = |pod: &Pod| {
+            pod.specs["node"]
+                .args
+                .iter()
+                .any(|arg| matches!(arg, Arg::Value(arg) if arg == "--full"))
+        };
+        let pod = Reth::default().spec(Chains::Mainnet, &Context::default())?;
+        assert!(full(&pod));
+
+        let reth: Reth = serde_json::from_value(json!({ "profile": "archive" }))?;
+        let pod = reth.spec(Chains::Mainnet, &Context::default())?;
+        assert!(!full(&pod));
+
+        let reth: Reth = serde_json::from_value(json!({ "profile": "pruned" }))?;
+        assert!(reth.spec(Chains::Mainnet, &Context::default()).is_err());
+
+        let capabilities = crate::capabilities();
+        let reth = capabilities[0]
+            .components
+            .iter()
+            .find(|component| component.name == "reth")
+            .unwrap();
+        assert_eq!(reth.profiles, vec![Profile::Full, Profile::Archive]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extra_args_and_env_are_appended() -> eyre::Result<()> {
+        let lighthouse: Lighthouse = serde_json::from_value(json!({
+            "extra_args": ["--subscribe-all-subnets"],
+            "extra_env": { "RUST_LOG": "debug" }
+        }))?;
+        let pod = lighthouse.spec(Chains::Mainnet, &Context::default())?;
+
+        let node = &pod.specs["node"];
+        assert!(
+            matches!(node.args.last(), Some(Arg::Value(arg)) if arg == "--subscribe-all-subnets")
+        );
+        assert_eq!(node.env["RUST_LOG"], "debug");
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_reth_image_runs_its_binary() -> eyre::Result<()> {
+        let reth: Reth = serde_json::from_value(json!({
+            "image": "registry.example.com/reth-exex",
+            "tag": "v0.1.0",
+            "entrypoint": ["/usr/local/bin/reth-exex"],
+            "extra_args": ["--exex.enabled"],
+            "era": { "checksums_url": "https://era.example.com/checksums.txt" }
+        }))?;
+        let pod = reth.spec(Chains::Mainnet, &Context::default())?;
+
+        let node = &pod.specs["node"];
+        assert_eq!(node.image_ref(), "registry.example.com/reth-exex:v0.1.0");
+        assert_eq!(node.entrypoint, vec!["/usr/local/bin/reth-exex"]);
+        // the reth cli of the catalog comes before the flags of the image
+        assert!(matches!(&node.args[0], Arg::Value(arg) if arg == "node"));
+        assert!(matches!(node.args.last(), Some(Arg::Value(arg)) if arg == "--exex.enabled"));
+
+        // the era files are imported with the binary of the image
+        let import = &pod.init_specs[1].spec;
+        assert_eq!(import.image_ref(), "registry.example.com/reth-exex:v0.1.0");
+        assert!(matches!(
+            &import.args[0],
+            Arg::Value(script) if script.contains("/usr/local/bin/reth-exex import-era")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_beacon_node_runs_unprivileged() -> eyre::Result<()> {
+        let pod = Lighthouse::default().spec(Chains::Mainnet, &Context::default())?;
+
+        let node = &pod.specs["node"];
+        assert_eq!(node.uid, Some(NODE_UID));
+        assert_eq!(node.cap_drop, vec!["ALL".to_string()]);
+        assert!(node.read_only_rootfs);
+        assert!(node.volumes.contains_key("tmp"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_and_tag_override_the_catalog() -> eyre::Result<()> {
+        let prysm: Prysm = serde_json::from_value(json!({
+            "image": "registry.example.com/prysm/beacon-chain",
+            "tag": "v6.1.0-rc.0"
+        }))?;
+        let pod = prysm.spec(Chains::Mainnet, &Context::default())?;
+        assert_eq!(
+            pod.specs["node"].image_ref(),
+            "registry.example.com/prysm/beacon-chain:v6.1.0-rc.0"
+        );
+
+        let pod = Prysm::default().spec(Chains::Mainnet, &Context::default())?;
+        assert_eq!(
+            pod.specs["node"].image_ref(),
+            "gcr.io/prysmaticlabs/prysm/beacon-chain:v6.0.0"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_keystores_are_routed_to_the_remote_signer() -> eyre::Result<()> {
+        let keystore = json!({
+            "crypto": {
+                "kdf": { "function": "pbkdf2", "params": {}, "message": "" },
+                "checksum": { "function": "sha256", "params": {}, "message": "" },
+                "cipher": { "function": "aes-128-ctr", "params": {}, "message": "" }
+            },
+            "pubkey": "a".repeat(96),
+            "version": 4
+        });
+        let input: EthDeploymentInput = serde_json::from_value(json!({
+            "el_node": { "reth": {} },
+            "cl_node": { "lighthouse": {} },
+            "vc_node": {
+                "lighthouse": {
+                    "keystores": { "bundle": [keystore] },
+                    "allow_missing_slashing_protection": true
+                }
+            },
+            "remote_signer": { "web3signer": {} }
+        }))?;
+        let manifest =
+            EthereumDeployment::default().manifest(Chains::Mainnet, input, &Context::default())?;
+        manifest.validate()?;
+
+        let signer = &manifest.pods["signer"].specs["node"];
+        assert_eq!(signer.artifacts.len(), 1);
+
+        let vc = &manifest.pods["vc"];
+        let init_specs: Vec<&str> = vc
+            .init_specs
+            .iter()
+            .map(|init| init.name.as_str())
+            .collect();
+        assert_eq!(init_specs, vec!["register-remote-signer"]);
+        assert!(vc.init_specs[0].spec.args.iter().any(
+            |arg| matches!(arg, Arg::Ref { name, port } if name == "signer" && port == "http")
+        ));
+        let order = manifest.startup_order()?;
+        let position = |pod: &str| order.iter().position(|name| name == pod);
+        assert!(position("signer") < position("vc"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validator_client_falls_back_to_the_next_beacon_node() -> eyre::Result<()> {
+        let input: EthDeploymentInput = serde_json::from_value(json!({
+            "el_node": { "reth": {} },
+            "cl_node": [{ "lighthouse": {} }, { "prysm": {} }],
+            "vc_node": { "lighthouse": {} }
+        }))?;
+        let manifest =
+            EthereumDeployment::default().manifest(Chains::Mainnet, input, &Context::default())?;
+        manifest.validate()?;
+
+        assert!(manifest.pods["cl-2"].specs.contains_key("babel"));
+        assert_eq!(manifest.dependencies["cl-2"], vec!["el"]);
+
+        let vc = &manifest.pods["vc"].specs["node"];
+        assert!(vc.args.iter().any(|arg| matches!(
+            arg,
+            Arg::Refs { names, port } if names == &["cl", "cl-2"] && port == "http"
+        )));
+        // the validator client starts while the first beacon node is down
+        assert!(!manifest.dependencies.contains_key("vc"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ssv_node_falls_back_to_the_next_beacon_node() -> eyre::Result<()> {
+        let input: EthDeploymentInput = serde_json::from_value(json!({
+            "el_node": { "reth": {} },
+            "cl_node": [{ "lighthouse": {} }, { "prysm": {} }],
+            "dv_node": { "ssv": { "operator_keystore": "{}" } },
+            "blob_archiver": { "origin_block": "0x0" }
+        }))?;
+        let manifest =
+            EthereumDeployment::default().manifest(Chains::Mainnet, input, &Context::default())?;
+        manifest.validate()?;
+
+        let ssv = &manifest.pods["dv"].specs["node"];
+        let Some(Artifacts::File(config)) = ssv.artifacts.first() else {
+            panic!("expected the config file");
+        };
+        let config = config.content.inline().unwrap();
+        assert!(config.contains("BeaconNodeAddr: {{ref:cl:http}};{{ref:cl-2:http}}"));
+        assert!(!manifest.dependencies.contains_key("dv"));
+        assert!(!manifest.dependencies.contains_key("blobs"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_blobs_are_archived_from_the_beacon_node() -> eyre::Result<()> {
+        let input: EthDeploymentInput = serde_json::from_value(json!({
+            "el_node": { "reth": {} },
+            "cl_node": { "lighthouse": { "blob_retention_epochs": 100 } },
+            "blob_archiver": { "origin_block": "0xabcd" }
+        }))?;
+        let manifest =
+            EthereumDeployment::default().manifest(Chains::Mainnet, input, &Context::default())?;
+        manifest.validate()?;
+
+        let archiver = &manifest.pods["blobs"].specs["archiver"];
+        assert_eq!(flag_values(archiver, "--origin-block"), vec!["0xabcd"]);
+        assert!(
+            archiver.args.iter().any(
+                |arg| matches!(arg, Arg::Ref { name, port } if name == "cl" && port == "http")
+            )
+        );
+        assert_eq!(manifest.dependencies["blobs"], vec!["cl"]);
+
+        // lighthouse takes the epochs on top of the minimum window
+        let lighthouse = &manifest.pods["cl"].specs["node"];
+        assert_eq!(
+            flag_values(lighthouse, "--blob-prune-margin-epochs"),
+            vec!["100"]
+        );
+        // and prysm the whole window
+        let prysm: Prysm = serde_json::from_value(json!({ "blob_retention_epochs": 100 }))?;
+        let pod = prysm.spec(Chains::Mainnet, &Context::default())?;
+        assert_eq!(
+            flag_values(&pod.specs["node"], "--blob-retention-epochs"),
+            vec!["4196"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_babel_reaches_reth_on_its_ipc_socket() -> eyre::Result<()> {
+        let input: EthDeploymentInput = serde_json::from_value(json!({
+            "el_node": { "reth": {} },
+            "cl_node": { "lighthouse": {} }
+        }))?;
+        let manifest =
+            EthereumDeployment::default().manifest(Chains::Mainnet, input, &Context::default())?;
+        manifest.validate()?;
+
+        let el = &manifest.pods["el"];
+        let babel = &el.specs["babel"];
+        assert!(babel.args.windows(2).any(|args| matches!(
+            args,
+            [Arg::Value(flag), Arg::Value(url)] if flag == "--rpc-url" && url == "unix:///ipc/reth.ipc"
+        )));
+        // the socket is in the volume of the pod both mount
+        assert_eq!(babel.volumes["ipc"], Volume::ipc());
+        assert_eq!(el.specs["node"].volumes["ipc"], Volume::ipc());
+        assert!(el.specs["node"].readiness.is_some());
+        // reth ships no curl, the health check of the pod is run by babel
+        assert!(babel.healthcheck.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ssv_node_refs_the_nodes_of_the_deployment() -> eyre::Result<()> {
+        let input: EthDeploymentInput = serde_json::from_value(json!({
+            "el_node": { "reth": {} },
+            "cl_node": { "lighthouse": {} },
+            "dv_node": { "ssv": { "operator_keystore": "{}" } }
+        }))?;
+        let manifest =
+            EthereumDeployment::default().manifest(Chains::Mainnet, input, &Context::default())?;
+        manifest.validate()?;
+
+        let ssv = &manifest.pods["dv"].specs["node"];
+        let Some(Artifacts::File(config)) = ssv.artifacts.first() else {
+            panic!("expected the config file");
+        };
+        let config = config.content.inline().unwrap();
+        assert!(config.contains("BeaconNodeAddr: {{ref:cl:http}}"));
+        assert!(config.contains("ETH1Addr: ws://{{addr:el:ws}}"));
+        // the password is a secret, not part of the input or the manifest
+        assert!(config.contains("PasswordFile: {{secret:ssv_operator_password}}"));
+        assert_eq!(ssv.artifacts.len(), 2);
+        Ok(())
+    }
+}
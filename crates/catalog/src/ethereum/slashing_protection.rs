@@ -0,0 +1,85 @@
+//! EIP-3076 slashing protection interchange of the validator clients.
+
+use serde_json::Value;
+use std::collections::HashSet;
+
+// interchange format version supported by the clients
+const INTERCHANGE_FORMAT_VERSION: &str = "5";
+
+// path where the interchange is mounted in the import init spec
+pub(crate) const INTERCHANGE_PATH: &str = "/slashing-protection/interchange.json";
+
+/// Validates the interchange and checks that it covers every imported
+/// validator, so that a migrated validator never starts signing without the
+/// history of what it signed before
+pub(crate) fn validate(interchange: &Value, pubkeys: &[String]) -> eyre::Result<()> {
+    let metadata = &interchange["metadata"];
+    if metadata["interchange_format_version"] != INTERCHANGE_FORMAT_VERSION {
+        eyre::bail!(
+            "Slashing protection is not an EIP-3076 interchange (format version {})",
+            INTERCHANGE_FORMAT_VERSION
+        );
+    }
+    let root = metadata["genesis_validators_root"]
+        .as_str()
+        .unwrap_or_default();
+    let valid_root = root
+        .strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+    if !valid_root {
+        eyre::bail!("Slashing protection has an invalid genesis_validators_root");
+    }
+
+    let data = interchange["data"]
+        .as_array()
+        .ok_or_else(|| eyre::eyre!("Slashing protection without data"))?;
+    let mut covered = HashSet::new();
+    for record in data {
+        let pubkey = record["pubkey"]
+            .as_str()
+            .ok_or_else(|| eyre::eyre!("Slashing protection record without pubkey"))?;
+        covered.insert(pubkey.trim_start_matches("0x").to_lowercase());
+    }
+
+    let missing: Vec<&str> = pubkeys
+        .iter()
+        .filter(|pubkey| !covered.contains(*pubkey))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        eyre::bail!(
+            "Slashing protection does not cover the validators: {}",
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_interchange_must_cover_the_validators() {
+        let pubkey = "b".repeat(96);
+        let interchange = json!({
+            "metadata": {
+                "interchange_format_version": "5",
+                "genesis_validators_root": format!("0x{}", "4b".repeat(32))
+            },
+            "data": [{
+                "pubkey": format!("0x{}", pubkey),
+                "signed_blocks": [],
+                "signed_attestations": []
+            }]
+        });
+
+        assert!(validate(&interchange, std::slice::from_ref(&pubkey)).is_ok());
+        assert!(validate(&interchange, &["c".repeat(96)]).is_err());
+
+        let mut old_format = interchange;
+        old_format["metadata"]["interchange_format_version"] = json!("4");
+        assert!(validate(&old_format, &[pubkey]).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use spec::{
+    Capabilities, ChainSpec, ClientVersion, ComputeResource, Context, Deployment, Manifest,
+    Overrides, Pod, Port, Spec, Versions, Volume,
+};
+
+use crate::ethereum::Chains;
+use crate::{ChainCapability, ComponentCapabilities, ModuleCapabilities};
+
+const VERSIONS: Versions<Chains> = Versions(&[
+    ClientVersion::new(
+        "nimbus",
+        Chains::Mainnet,
+        "multiarch-v25.3.0",
+        "multiarch-v25.9.2",
+    ),
+    ClientVersion::new(
+        "nimbus",
+        Chains::Sepolia,
+        "multiarch-v25.3.0",
+        "multiarch-v25.9.2",
+    ),
+    ClientVersion::new("trin", Chains::Mainnet, "v0.3.0", "v0.3.0"),
+]);
+
+pub(crate) fn capabilities() -> ModuleCapabilities {
+    ModuleCapabilities {
+        module: "ethereum_light".to_string(),
+        network: "ethereum".to_string(),
+        chains: ChainCapability::from_deployment(&EthereumLightDeployment::default(), Chains::name),
+        components: vec![
+            ComponentCapabilities::new("nimbus", &NimbusLightClient::default(), Chains::name),
+            ComponentCapabilities::new("trin", &Trin::default(), Chains::name),
+        ],
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct EthLightDeploymentInput {
+    pub node: LightNode,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LightNode {
+    Nimbus(NimbusLightClient),
+    Trin(Trin),
+}
+
+/// Ethereum deployment without a full node, it only follows the chain through
+/// the beacon light client protocol or the Portal Network
+#[derive(Default, Deserialize)]
+pub struct EthereumLightDeployment {}
+
+impl Deployment for EthereumLightDeployment {
+    type Input = EthLightDeploymentInput;
+    type Chains = Chains;
+
+    fn defaults(&self, _chain: &Chains) -> serde_json::Value {
+        serde_json::json!({ "node": { "trin": {} } })
+    }
+
+    fn capabilities(&self) -> Vec<ChainSpec<Chains>> {
+        vec![
+            ChainSpec {
+                chain: Chains::Mainnet,
+                ..Default::default()
+            },
+            ChainSpec {
+                chain: Chains::Sepolia,
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn manifest(
+        &self,
+        chain: Chains,
+        input: EthLightDeploymentInput,
+        ctx: &Context,
+    ) -> eyre::Result<Manifest> {
+        let mut manifest = Manifest::new("eth-light".to_string());
+
+        let node = match input.node {
+            LightNode::Nimbus(nimbus) => nimbus.spec(chain, ctx)?,
+            LightNode::Trin(trin) => trin.spec(chain, ctx)?,
+        };
+        manifest.add_spec("light".to_string(), node);
+
+        Ok(manifest)
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct NimbusLightClient {
+    // block root of a recent finalized checkpoint the light client syncs from
+    pub trusted_block_root: String,
+    // engine api url of an execution client driven by the light client, if any
+    #[serde(default)]
+    pub web3_url: Option<String>,
+    // image run instead of statusim/nimbus-eth2 (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+impl ComputeResource for NimbusLightClient {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("nimbus"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let mut node = Spec::builder()
+            .image(self.image.as_deref().unwrap_or("statusim/nimbus-eth2"))
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("nimbus", &chain, ctx, self.tag.as_deref())?)
+            .port(Port::tcp("p2p", 9000).public())
+            .port(Port::udp("discovery", 9000).public())
+            .entrypoint(["/home/user/nimbus-eth2/build/nimbus_light_client"])
+            .arg(format!("--network={}", chain.name()))
+            .arg("--data-dir=/data")
+            .arg(format!("--trusted-block-root={}", self.trusted_block_root))
+            .arg("--tcp-port=9000")
+            .arg("--udp-port=9000");
+
+        if let Some(nat) = ctx.nat_extip() {
+            node = node.arg(format!("--nat={}", nat));
+        }
+        if let Some(web3_url) = &self.web3_url {
+            node = node.arg(format!("--web3-url={}", web3_url));
+        }
+
+        node = node.overrides(&self.overrides);
+        Ok(Pod::default().with_spec("node", node))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Trin {
+    // portal subnetworks the node participates in
+    #[serde(default = "default_subnetworks")]
+    pub subnetworks: Vec<String>,
+    // disk space in MB the node allocates to store content of the network
+    #[serde(default)]
+    pub storage_mb: Option<u32>,
+    // image run instead of portalnetwork/trin (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+fn default_subnetworks() -> Vec<String> {
+    vec!["history".to_string(), "beacon".to_string()]
+}
+
+impl ComputeResource for Trin {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("trin"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let mut node = Spec::builder()
+            .image(self.image.as_deref().unwrap_or("portalnetwork/trin"))
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("trin", &chain, ctx, self.tag.as_deref())?)
+            .port(Port::udp("p2p", 9009).public())
+            .arg2("--network", chain.name())
+            .arg2("--data-dir", "/data")
+            .arg2("--portal-subnetworks", self.subnetworks.join(","))
+            .arg2("--web3-transport", "http")
+            // both addresses are urls, the ports can not be expressed as port args
+            .arg2("--web3-http-address", "http://0.0.0.0:8545/")
+            .arg2("--enable-metrics-with-url", "0.0.0.0:9100")
+            .arg2("--discovery-port", "9009");
+
+        if let Some(ip) = ctx.external_ip {
+            node = node.arg2("--external-address", format!("{}:9009", ip));
+        }
+        if let Some(storage_mb) = self.storage_mb {
+            node = node.arg2("--mb", storage_mb.to_string());
+        }
+
+        node = node.overrides(&self.overrides);
+        Ok(Pod::default().with_spec("node", node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::Arg;
+
+    #[test]
+    fn test_trin_is_the_default_light_node() -> eyre::Result<()> {
+        let dep: spec::Dep = serde_json::from_value(serde_json::json!({
+            "module": "ethereum_light",
+            "external_ip": "203.0.113.7",
+            "args": {}
+        }))?;
+        let manifest = crate::apply(dep)?;
+        manifest.validate()?;
+
+        let node = &manifest.pods["light"].specs["node"];
+        assert_eq!(node.image_ref(), "portalnetwork/trin:v0.3.0");
+        assert!(node.args.windows(2).any(|args| matches!(
+            args,
+            [Arg::Value(flag), Arg::Value(address)]
+                if flag == "--external-address" && address == "203.0.113.7:9009"
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nimbus_light_client_syncs_from_the_trusted_block_root() -> eyre::Result<()> {
+        let nimbus: NimbusLightClient = serde_json::from_value(serde_json::json!({
+            "trusted_block_root": "0xabcd",
+            "web3_url": "http://el:8551"
+        }))?;
+        let pod = nimbus.spec(Chains::Sepolia, &Context::default())?;
+
+        let node = &pod.specs["node"];
+        assert_eq!(
+            node.entrypoint,
+            vec!["/home/user/nimbus-eth2/build/nimbus_light_client"]
+        );
+        let args: Vec<&str> = node
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                Arg::Value(arg) => Some(arg.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert!(args.contains(&"--network=sepolia"));
+        assert!(args.contains(&"--trusted-block-root=0xabcd"));
+        assert!(args.contains(&"--web3-url=http://el:8551"));
+
+        // the light client has no default checkpoint
+        let input = serde_json::json!({ "node": { "nimbus": {} } });
+        assert!(serde_json::from_value::<EthLightDeploymentInput>(input).is_err());
+        Ok(())
+    }
+}
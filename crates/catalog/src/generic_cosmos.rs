@@ -0,0 +1,331 @@
+//! Cosmos SDK chains deployed from their chain registry metadata
+//! (https://github.com/cosmos/chain-registry), so that a chain without a
+//! module of its own only needs its `chain.json` and the image of its daemon.
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use spec::{
+    Babel, Capabilities, ChainSpec, Compression, ComputeResource, Context, Deployment, Download,
+    Expect, Manifest, Overrides, Pod, Port, SmokeTest, Spec, Volume,
+};
+
+use crate::cosmos::{P2P_PORT, P2PConfig, Peers};
+use crate::{ChainCapability, ComponentCapabilities, ModuleCapabilities, validate_sha256};
+
+// rpc port of cometbft, babel follows the node through it
+const RPC_PORT: u16 = 26657;
+
+/// The chain is given by the registry metadata, `custom` is the only name of
+/// the module
+#[derive(Default, Clone, PartialEq)]
+pub enum Chains {
+    #[default]
+    Custom,
+}
+
+impl Chains {
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Chains::Custom => "custom",
+        }
+    }
+}
+
+impl std::str::FromStr for Chains {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("cosmos.").unwrap_or(s) {
+            "custom" => Ok(Chains::Custom),
+            _ => Err(eyre::eyre!("Unknown generic cosmos chain: {}", s)),
+        }
+    }
+}
+
+pub(crate) fn capabilities() -> ModuleCapabilities {
+    ModuleCapabilities {
+        module: "generic-cosmos".to_string(),
+        network: "cosmos".to_string(),
+        chains: ChainCapability::from_deployment(&GenericCosmosDeployment::default(), Chains::name),
+        components: vec![ComponentCapabilities::new(
+            "node",
+            &CosmosNode::default(),
+            Chains::name,
+        )],
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct GenericCosmosDeployment {}
+
+impl Deployment for GenericCosmosDeployment {
+    type Input = CosmosNode;
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Vec<ChainSpec<Chains>> {
+        vec![ChainSpec {
+            chain: Chains::Custom,
+            ..Default::default()
+        }]
+    }
+
+    fn manifest(&self, chain: Chains, input: CosmosNode, ctx: &Context) -> eyre::Result<Manifest> {
+        let mut manifest = Manifest::new("cosmos".to_string());
+
+        let babel = Babel::new("cosmos", spec::ref_!("node", "rpc"));
+        let node = input.spec(chain, ctx)?.with_babel(babel);
+        manifest.add_spec("node".to_string(), node);
+        manifest.add_smoke_test(SmokeTest::rpc(
+            "network",
+            "node",
+            "rpc",
+            "status",
+            Expect::Contains(serde_json::json!({
+                "node_info": {"network": input.registry.chain_id},
+            })),
+        ));
+
+        Ok(manifest)
+    }
+}
+
+/// Fields of the chain registry `chain.json` the node is built from, the
+/// rest of the file is ignored
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ChainRegistry {
+    pub chain_id: String,
+    // binary of the chain in the image (e.g. `osmosisd`)
+    pub daemon_name: String,
+    pub codebase: Codebase,
+    #[serde(default)]
+    pub peers: RegistryPeers,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Codebase {
+    // version run if the input does not set a tag
+    #[serde(default)]
+    pub recommended_version: Option<String>,
+    pub genesis: RegistryGenesis,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct RegistryGenesis {
+    pub genesis_url: String,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct RegistryPeers {
+    #[serde(default)]
+    pub seeds: Vec<RegistryPeer>,
+    #[serde(default)]
+    pub persistent_peers: Vec<RegistryPeer>,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct RegistryPeer {
+    pub id: String,
+    // host:port of the peer
+    pub address: String,
+}
+
+impl RegistryPeer {
+    fn to_peer(&self) -> String {
+        format!("{}@{}", self.id, self.address)
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct CosmosNode {
+    // chain.json of the chain in the chain registry
+    pub registry: ChainRegistry,
+    // image with the daemon of the chain
+    pub image: String,
+    // image tag run instead of the recommended version of the registry
+    #[serde(default)]
+    pub tag: Option<String>,
+    // sha256 of the genesis, it is verified before the node starts
+    #[serde(default)]
+    pub genesis_sha256: Option<String>,
+    // peers used instead of the ones of the registry
+    #[serde(flatten)]
+    pub peers: Peers,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+impl ComputeResource for CosmosNode {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: vec![ChainSpec {
+                chain: Chains::Custom,
+                ..Default::default()
+            }],
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
+        }
+    }
+
+    fn spec(&self, _chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        validate_sha256("genesis_sha256", self.genesis_sha256.as_deref())?;
+        let registry = &self.registry;
+        let daemon = &registry.daemon_name;
+        let tag = self
+            .tag
+            .as_ref()
+            .or_else(|| ctx.versions.get(daemon))
+            .or(registry.codebase.recommended_version.as_ref())
+            .ok_or_else(|| eyre::eyre!("No version of {} available for the chain", daemon))?;
+
+        let mut peers = self.peers.clone();
+        if peers.persistent_peers.is_none() {
+            peers.persistent_peers = Some(
+                registry
+                    .peers
+                    .persistent_peers
+                    .iter()
+                    .map(RegistryPeer::to_peer)
+                    .collect(),
+            );
+        }
+        let p2p = P2PConfig::resolve(&peers, ctx, || {
+            Ok(registry
+                .peers
+                .seeds
+                .iter()
+                .map(RegistryPeer::to_peer)
+                .collect())
+        })?;
+
+        let mut node = Spec::builder()
+            .image(&self.image)
+            .tag(tag)
+            .volume(Volume::data())
+            .port(Port::tcp("p2p", P2P_PORT).public())
+            .port(Port::tcp("rpc", RPC_PORT))
+            .entrypoint([daemon.as_str()])
+            .arg("start")
+            .arg2("--home", "/data")
+            .identity_key("/data/config/node_key.json")
+            .arg2("--p2p.laddr", format!("tcp://0.0.0.0:{}", P2P_PORT))
+            .arg2("--rpc.laddr", format!("tcp://0.0.0.0:{}", RPC_PORT));
+        if !p2p.seeds.is_empty() {
+            node = node.arg2("--p2p.seeds", p2p.seeds);
+        }
+        if !p2p.persistent_peers.is_empty() {
+            node = node.arg2("--p2p.persistent_peers", p2p.persistent_peers);
+        }
+        if !p2p.external_address.is_empty() {
+            node = node.arg2("--p2p.external-address", p2p.external_address);
+        }
+        node = node.overrides(&self.overrides);
+
+        // the daemon writes its config and keys once, the genesis of the
+        // registry replaces the one it generates
+        let init = Spec::builder()
+            .image(&self.image)
+            .tag(tag)
+            .entrypoint(["sh", "-c"])
+            .arg(format!(
+                "test -f /data/config/config.toml || {} init bbuilder --chain-id {} --home /data",
+                shell_words::quote(daemon),
+                shell_words::quote(&registry.chain_id)
+            ));
+        // the registry links to a gzipped genesis for the chains with a large one
+        let genesis_url = &registry.codebase.genesis.genesis_url;
+        let mut genesis = Download::new(genesis_url, "/data/config/genesis.json");
+        if let Some(compression) = Compression::from_url(genesis_url) {
+            genesis = genesis.decompress(compression);
+        }
+        if let Some(sha256) = &self.genesis_sha256 {
+            genesis = genesis.sha256(sha256);
+        }
+
+        Ok(Pod::default()
+            .with_init_spec("init", init)
+            .with_init_spec("genesis", genesis.build())
+            .with_spec("node", node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use spec::Arg;
+
+    #[test]
+    fn test_node_of_the_registry_chain() -> eyre::Result<()> {
+        let dep: spec::Dep = serde_json::from_value(json!({
+            "module": "generic-cosmos",
+            "chain": "custom",
+            "args": {
+                "image": "ghcr.io/osmosis-labs/osmosis",
+                "registry": {
+                    "chain_name": "osmosis",
+                    "chain_id": "osmosis-1",
+                    "daemon_name": "osmosisd",
+                    "codebase": {
+                        "recommended_version": "v25.0.0",
+                        "genesis": {"genesis_url": "https://example.com/genesis.json"},
+                    },
+                    "peers": {
+                        "seeds": [{"id": "abc", "address": "seed.example.com:26656"}],
+                    },
+                },
+            },
+        }))?;
+        let manifest = crate::apply(dep)?;
+
+        let pod = &manifest.pods["node"];
+        let names: Vec<&str> = pod
+            .init_specs
+            .iter()
+            .map(|init| init.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["init", "genesis"]);
+
+        let node = &pod.specs["node"];
+        assert_eq!(node.image_ref(), "ghcr.io/osmosis-labs/osmosis:v25.0.0");
+        assert_eq!(node.entrypoint, vec!["osmosisd"]);
+        assert!(node.args.windows(2).any(|args| matches!(
+            args,
+            [Arg::Value(flag), Arg::Value(seeds)]
+                if flag == "--p2p.seeds" && seeds == "abc@seed.example.com:26656"
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_init_of_the_registry_chain_is_quoted() -> eyre::Result<()> {
+        let dep: spec::Dep = serde_json::from_value(json!({
+            "module": "generic-cosmos",
+            "chain": "custom",
+            "args": {
+                "image": "ghcr.io/example/chain",
+                "tag": "v1.0.0",
+                "registry": {
+                    "chain_name": "example",
+                    "chain_id": "example-1; rm -rf /data",
+                    "daemon_name": "exampled",
+                    "codebase": {
+                        "genesis": {"genesis_url": "https://example.com/genesis.tar.gz"},
+                    },
+                },
+            },
+        }))?;
+        let manifest = crate::apply(dep)?;
+
+        let pod = &manifest.pods["node"];
+        assert!(matches!(
+            &pod.init_specs[0].spec.args[0],
+            Arg::Value(script) if script.contains("--chain-id 'example-1; rm -rf /data'")
+        ));
+        assert!(matches!(
+            &pod.init_specs[1].spec.args[0],
+            Arg::Value(script) if script.contains("tar -xzOf /data/config/genesis.json.tar.gz")
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,602 @@
+//! EVM chains that are not in the catalog (e.g. private consortium chains),
+//! deployed from the genesis.json and bootnodes of the chain with one of the
+//! supported execution clients and optionally a lighthouse beacon node.
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use spec::{
+    Arg, Artifacts, Babel, Capabilities, ChainSpec, ClientVersion, ComputeResource, Context,
+    Deployment, JWT_SECRET, METRICS_PORT, Manifest, Overrides, Pod, Port, Spec, SpecBuilder,
+    Versions, Volume,
+};
+
+use crate::{
+    ChainCapability, ComponentCapabilities, EL_STOP_GRACE_PERIOD, ModuleCapabilities, NOFILE_LIMIT,
+    ethereum,
+};
+
+// the genesis is mounted outside of the data dir so a new one replaces it
+const GENESIS_PATH: &str = "/genesis/genesis.json";
+
+// testnet dir of the beacon node, downloaded by its init spec
+const TESTNET_DIR: &str = "/data/testnet";
+
+/// The chain is given by the input, `custom` is the only name of the module
+#[derive(Default, Clone, PartialEq)]
+pub enum Chains {
+    #[default]
+    Custom,
+}
+
+impl Chains {
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Chains::Custom => "custom",
+        }
+    }
+}
+
+impl std::str::FromStr for Chains {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("evm.").unwrap_or(s) {
+            "custom" => Ok(Chains::Custom),
+            _ => Err(eyre::eyre!("Unknown generic EVM chain: {}", s)),
+        }
+    }
+}
+
+const VERSIONS: Versions<Chains> = Versions(&[
+    ClientVersion::new("geth", Chains::Custom, "v1.15.0", "v1.16.1"),
+    ClientVersion::new("reth", Chains::Custom, "v1.4.8", "v1.4.8"),
+    ClientVersion::new("besu", Chains::Custom, "25.4.1", "25.6.0"),
+    ClientVersion::new("lighthouse", Chains::Custom, "v7.0.0", "v8.0.0-rc.2"),
+]);
+
+pub(crate) fn capabilities() -> ModuleCapabilities {
+    ModuleCapabilities {
+        module: "generic-evm".to_string(),
+        network: "evm".to_string(),
+        chains: ChainCapability::from_deployment(&GenericEvmDeployment::default(), Chains::name),
+        components: vec![
+            ComponentCapabilities::new("geth", &Geth::default(), Chains::name),
+            ComponentCapabilities::new("reth", &Reth::default(), Chains::name),
+            ComponentCapabilities::new("besu", &Besu::default(), Chains::name),
+            ComponentCapabilities::new("lighthouse", &Lighthouse::default(), Chains::name),
+        ],
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct GenericEvmDeployment {}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GenericEvmInput {
+    pub el_node: ELNode,
+    // beacon node of a proof of stake chain, it runs in the "cl" pod
+    #[serde(default)]
+    pub cl_node: Option<CLNode>,
+    // parameters of a devnet, its genesis and the config of its beacon node
+    // are generated from them instead of being set on the nodes
+    #[serde(default)]
+    pub devnet: Option<genesis::EthereumParams>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ELNode {
+    Geth(Geth),
+    Reth(Reth),
+    Besu(Besu),
+}
+
+impl ELNode {
+    fn network_mut(&mut self) -> &mut Network {
+        match self {
+            ELNode::Geth(geth) => &mut geth.network,
+            ELNode::Reth(reth) => &mut reth.network,
+            ELNode::Besu(besu) => &mut besu.network,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CLNode {
+    Lighthouse(Lighthouse),
+}
+
+impl Deployment for GenericEvmDeployment {
+    type Input = GenericEvmInput;
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Vec<ChainSpec<Chains>> {
+        vec![ChainSpec {
+            chain: Chains::Custom,
+            ..Default::default()
+        }]
+    }
+
+    fn manifest(
+        &self,
+        chain: Chains,
+        mut input: GenericEvmInput,
+        ctx: &Context,
+    ) -> eyre::Result<Manifest> {
+        let mut manifest = Manifest::new("evm".to_string());
+
+        if let Some(params) = &input.devnet {
+            // the genesis is generated on every deploy, a genesis time of
+            // now would start a new chain each time
+            if params.genesis_time.is_none() {
+                eyre::bail!("The devnet requires a genesis_time");
+            }
+            let generated = genesis::EthereumGenesis::generate(params)?;
+
+            let genesis = &mut input.el_node.network_mut().genesis;
+            if genesis.url.is_some() || genesis.content.is_some() {
+                eyre::bail!("The genesis of a devnet is generated, the el node can not set it");
+            }
+            genesis.content = Some(generated.el_genesis);
+            if let Some(CLNode::Lighthouse(lighthouse)) = &mut input.cl_node {
+                if lighthouse.config_url.is_some() {
+                    eyre::bail!("The config of a devnet is generated, the cl node can not set it");
+                }
+                lighthouse.config = Some(generated.cl_config);
+            }
+        }
+
+        let el_node = match input.el_node {
+            ELNode::Geth(geth) => geth.spec(chain.clone(), ctx)?,
+            ELNode::Reth(reth) => reth.spec(chain.clone(), ctx)?,
+            ELNode::Besu(besu) => besu.spec(chain.clone(), ctx)?,
+        };
+        let babel_el = Babel::new("ethereum", spec::ref_!("el", "http"));
+        manifest.add_spec("el".to_string(), el_node.with_babel(babel_el));
+
+        if let Some(CLNode::Lighthouse(lighthouse)) = input.cl_node {
+            let babel_beacon = Babel::new("ethereum_beacon", spec::ref_!("cl", "http"));
+            let cl_node = lighthouse.spec(chain, ctx)?.with_babel(babel_beacon);
+            manifest.add_spec("cl".to_string(), cl_node);
+            manifest.add_dependency("cl", "el");
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// Genesis and peers of the chain, shared by the execution clients
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Network {
+    // generated for a devnet
+    #[serde(default)]
+    pub genesis: Genesis,
+    // network id of the p2p handshake, the chain id of the genesis if not set
+    #[serde(default)]
+    pub network_id: Option<u64>,
+    // enode urls of the nodes of the chain
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+}
+
+/// genesis.json of the chain, either inline or downloaded when deploying
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Genesis {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub content: Option<serde_json::Value>,
+    // expected sha256 (hex) of the genesis file
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+impl Network {
+    /// Mounts the genesis at `GENESIS_PATH` and adds the network id and peers
+    /// of the chain with the flags of the client
+    fn apply(
+        &self,
+        mut node: SpecBuilder,
+        network_id_flag: Option<&str>,
+        bootnodes_flag: &str,
+    ) -> eyre::Result<SpecBuilder> {
+        let content = match (&self.genesis.url, &self.genesis.content) {
+            (Some(url), None) => spec::ArtifactSource::url(url),
+            (None, Some(content)) => serde_json::to_string_pretty(content)?.into(),
+            _ => eyre::bail!("Set either the url or the content of the genesis"),
+        };
+        node = node.artifact(Artifacts::File(spec::File {
+            name: "genesis.json".to_string(),
+            target_path: GENESIS_PATH.to_string(),
+            content,
+            read_only: true,
+            sha256: self.genesis.sha256.clone(),
+            ..Default::default()
+        }));
+
+        match (self.network_id, network_id_flag) {
+            (Some(network_id), Some(flag)) => node = node.arg2(flag, network_id.to_string()),
+            (Some(_), None) => {
+                eyre::bail!("The client uses the chain id of the genesis as network id")
+            }
+            (None, _) => {}
+        }
+        if !self.bootnodes.is_empty() {
+            node = node.arg2(bootnodes_flag, self.bootnodes.join(","));
+        }
+        Ok(node)
+    }
+}
+
+/// Data dir, open files limit and p2p ports of the execution clients
+fn execution_defaults(node: SpecBuilder) -> SpecBuilder {
+    node.volume(Volume::data())
+        .ulimit("nofile", NOFILE_LIMIT, NOFILE_LIMIT)
+        .stop_signal("SIGINT")
+        .stop_grace_period(EL_STOP_GRACE_PERIOD)
+        .port(Port::tcp("p2p", 30303).public())
+        .port(Port::udp("discovery", 30303).public())
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Geth {
+    #[serde(flatten)]
+    pub network: Network,
+    // image run instead of ethereum/client-go (e.g. a fork of geth)
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+impl ComputeResource for Geth {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("geth"),
+            volumes: vec![Volume::data()],
+            ..Default::default()
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let node = Spec::builder()
+            .image(self.image.as_deref().unwrap_or("ethereum/client-go"))
+            .tag(VERSIONS.resolve_tag("geth", &chain, ctx, self.tag.as_deref())?)
+            // the genesis is written to the data dir before the node starts,
+            // geth keeps the one already written if it is the same
+            .pre_start(format!("geth init --datadir /data {}", GENESIS_PATH))
+            .arg2("--datadir", "/data")
+            .identity_key("/data/geth/nodekey")
+            .arg("--http")
+            .arg2("--http.addr", "0.0.0.0")
+            .arg2("--http.port", spec::port!("http", 8545))
+            .arg2("--http.vhosts", "*")
+            .arg("--ws")
+            .arg2("--ws.addr", "0.0.0.0")
+            .arg2("--ws.port", spec::port!("ws", 8546))
+            .arg2("--authrpc.addr", "0.0.0.0")
+            .arg2("--authrpc.port", spec::port!("authrpc", 8551))
+            .arg2("--authrpc.vhosts", "*")
+            .arg2("--authrpc.jwtsecret", Arg::secret(JWT_SECRET))
+            .arg("--metrics")
+            .arg2("--metrics.addr", "0.0.0.0")
+            .arg2("--metrics.port", spec::port!(METRICS_PORT, 6060));
+        let mut node =
+            self.network
+                .apply(execution_defaults(node), Some("--networkid"), "--bootnodes")?;
+
+        if let Some(nat) = ctx.nat_extip() {
+            node = node.arg2("--nat", nat);
+        }
+        node = node.overrides(&self.overrides);
+        Ok(Pod::default().with_spec("node", node))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Reth {
+    #[serde(flatten)]
+    pub network: Network,
+    // image run instead of ghcr.io/paradigmxyz/reth (e.g. a reth based node)
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+impl ComputeResource for Reth {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("reth"),
+            volumes: vec![Volume::data()],
+            ..Default::default()
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let tag = VERSIONS.resolve_tag("reth", &chain, ctx, self.tag.as_deref())?;
+        let node = ethereum::reth_node(
+            self.image.as_deref().unwrap_or("ghcr.io/paradigmxyz/reth"),
+            &tag,
+        )
+        .arg2("--chain", GENESIS_PATH);
+        let mut node = self.network.apply(node, None, "--bootnodes")?;
+
+        if let Some(nat) = ctx.nat_extip() {
+            node = node.arg2("--nat", nat);
+        }
+        node = node.overrides(&self.overrides);
+        Ok(Pod::default().with_spec("node", node))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Besu {
+    #[serde(flatten)]
+    pub network: Network,
+    // image run instead of hyperledger/besu
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+impl ComputeResource for Besu {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("besu"),
+            volumes: vec![Volume::data()],
+            ..Default::default()
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let node = Spec::builder()
+            .image(self.image.as_deref().unwrap_or("hyperledger/besu"))
+            .tag(VERSIONS.resolve_tag("besu", &chain, ctx, self.tag.as_deref())?)
+            .arg2("--data-path", "/data")
+            .arg2("--genesis-file", GENESIS_PATH)
+            .arg("--rpc-http-enabled")
+            .arg2("--rpc-http-host", "0.0.0.0")
+            .arg2("--rpc-http-port", spec::port!("http", 8545))
+            .arg2("--host-allowlist", "*")
+            .arg("--rpc-ws-enabled")
+            .arg2("--rpc-ws-host", "0.0.0.0")
+            .arg2("--rpc-ws-port", spec::port!("ws", 8546))
+            .arg2("--engine-rpc-port", spec::port!("authrpc", 8551))
+            .arg2("--engine-host-allowlist", "*")
+            .arg2("--engine-jwt-secret", Arg::secret(JWT_SECRET))
+            .arg("--metrics-enabled")
+            .arg2("--metrics-host", "0.0.0.0")
+            .arg2("--metrics-port", spec::port!(METRICS_PORT, 9545));
+        let mut node = self.network.apply(
+            execution_defaults(node),
+            Some("--network-id"),
+            "--bootnodes",
+        )?;
+
+        if let Some(ip) = ctx.external_ip {
+            node = node.arg2("--p2p-host", ip.to_string());
+        }
+        node = node.overrides(&self.overrides);
+        Ok(Pod::default().with_spec("node", node))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct Lighthouse {
+    // url of the config.yaml of the consensus chain, required unless the
+    // deployment is a devnet
+    #[serde(default)]
+    pub config_url: Option<String>,
+    // expected sha256 (hex) of the config.yaml
+    #[serde(default)]
+    pub config_sha256: Option<String>,
+    // url of the genesis.ssz state of the consensus chain
+    pub genesis_state_url: String,
+    // expected sha256 (hex) of the genesis.ssz
+    #[serde(default)]
+    pub genesis_state_sha256: Option<String>,
+    // block the deposit contract was deployed at
+    #[serde(default)]
+    pub deposit_contract_block: u64,
+    // config.yaml generated for the devnet
+    #[serde(skip)]
+    pub(crate) config: Option<String>,
+    // enr records of the beacon nodes of the chain
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+    // image run instead of sigp/lighthouse
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
+
+impl ComputeResource for Lighthouse {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("lighthouse"),
+            volumes: vec![Volume::data()],
+            ..Default::default()
+        }
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let tag = VERSIONS.resolve_tag("lighthouse", &chain, ctx, self.tag.as_deref())?;
+        let mut node = ethereum::beacon_node(
+            self.image.as_deref().unwrap_or("sigp/lighthouse"),
+            &tag,
+            &self.bootnodes,
+            ctx,
+        )
+        .arg2("--testnet-dir", TESTNET_DIR)
+        .artifact(Artifacts::File(spec::file(
+            "deposit_contract_block",
+            format!("{}/deposit_contract_block.txt", TESTNET_DIR),
+            self.deposit_contract_block.to_string(),
+        )))
+        .overrides(&self.overrides);
+
+        // without a checksum the files are downloaded again on every deploy,
+        // a new config of the chain replaces the previous one
+        let config_path = format!("{}/config.yaml", TESTNET_DIR);
+        let mut pod = Pod::default();
+        match (&self.config, &self.config_url) {
+            (Some(config), _) => {
+                node = node.artifact(Artifacts::File(spec::file(
+                    "config",
+                    config_path,
+                    config.clone(),
+                )));
+            }
+            (None, Some(url)) => {
+                pod = pod.with_init_spec(
+                    "testnet-config",
+                    Spec::download(url, &config_path, self.config_sha256.as_deref()),
+                );
+            }
+            (None, None) => eyre::bail!("The lighthouse node requires a config_url"),
+        }
+        Ok(pod
+            .with_init_spec(
+                "testnet-genesis",
+                Spec::download(
+                    &self.genesis_state_url,
+                    &format!("{}/genesis.ssz", TESTNET_DIR),
+                    self.genesis_state_sha256.as_deref(),
+                ),
+            )
+            .with_spec("node", node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_generic_evm_manifest() -> eyre::Result<()> {
+        let dep: spec::Dep = serde_json::from_value(json!({
+            "module": "generic-evm",
+            "chain": "custom",
+            "args": {
+                "el_node": {"geth": {
+                    "genesis": {"content": {"config": {"chainId": 1337}}},
+                    "network_id": 1337,
+                    "bootnodes": ["enode://a@10.0.0.1:30303", "enode://b@10.0.0.2:30303"],
+                }},
+                "cl_node": {"lighthouse": {
+                    "config_url": "https://example.com/config.yaml",
+                    "genesis_state_url": "https://example.com/genesis.ssz",
+                }},
+            },
+        }))?;
+        let manifest = crate::apply(dep)?;
+
+        let node = &manifest.pods["el"].specs["node"];
+        assert_eq!(node.image, "ethereum/client-go");
+        assert_eq!(
+            node.pre_start,
+            vec!["geth init --datadir /data /genesis/genesis.json"]
+        );
+        let Artifacts::File(genesis) = &node.artifacts[0] else {
+            panic!("genesis is not a file");
+        };
+        assert_eq!(genesis.target_path, GENESIS_PATH);
+        assert!(genesis.content.inline().unwrap().contains("1337"));
+        let args: Vec<String> = node.args.iter().map(|arg| format!("{:?}", arg)).collect();
+        assert!(
+            args.iter()
+                .any(|arg| arg.contains("enode://a@10.0.0.1:30303,enode://b"))
+        );
+
+        let cl = &manifest.pods["cl"];
+        assert_eq!(cl.init_specs[0].name, "testnet-config");
+        assert_eq!(cl.init_specs[1].name, "testnet-genesis");
+        // the beacon node is the one of the ethereum module
+        assert_eq!(cl.specs["node"].uid, Some(crate::NODE_UID));
+        assert!(manifest.dependencies["cl"].contains(&"el".to_string()));
+
+        // the genesis is required
+        let dep: spec::Dep = serde_json::from_value(json!({
+            "module": "generic-evm",
+            "args": {"el_node": {"besu": {"genesis": {}}}},
+        }))?;
+        assert!(crate::apply(dep).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_devnet_genesis_is_generated() -> eyre::Result<()> {
+        let dep = |genesis_time: serde_json::Value| -> eyre::Result<spec::Dep> {
+            Ok(serde_json::from_value(json!({
+                "module": "generic-evm",
+                "args": {
+                    "el_node": {"reth": {}},
+                    "cl_node": {"lighthouse": {
+                        "genesis_state_url": "https://example.com/genesis.ssz",
+                    }},
+                    "devnet": {
+                        "chain_id": 1337,
+                        "genesis_time": genesis_time,
+                        "validators": 64,
+                    },
+                },
+            }))?)
+        };
+        let manifest = crate::apply(dep(json!(1700000000))?)?;
+
+        let Artifacts::File(genesis) = &manifest.pods["el"].specs["node"].artifacts[0] else {
+            panic!("genesis is not a file");
+        };
+        assert!(
+            genesis
+                .content
+                .inline()
+                .unwrap()
+                .contains("\"chainId\": 1337")
+        );
+
+        // the config is generated with the same fork schedule, only the
+        // genesis state is downloaded
+        let cl = &manifest.pods["cl"];
+        let names: Vec<&str> = cl
+            .init_specs
+            .iter()
+            .map(|init| init.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["testnet-genesis"]);
+        let config = cl.specs["node"]
+            .artifacts
+            .iter()
+            .find_map(|artifact| match artifact {
+                Artifacts::File(file) if file.name == "config" => file.content.inline(),
+                _ => None,
+            })
+            .unwrap();
+        assert!(config.contains("MIN_GENESIS_TIME: 1700000000\n"));
+
+        // a genesis time of now would change on every deploy
+        assert!(crate::apply(dep(json!(null))?).is_err());
+        Ok(())
+    }
+}
@@ -1,18 +1,384 @@
-use spec::{Dep, Deployment, Manifest};
+use serde::{Deserialize, Serialize};
+use spec::{ChainSpec, Dep, Deployment, Manifest, Profile, Volume};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
 
+mod adopt;
 mod berachain;
+mod chains;
+mod cosmos;
 mod ethereum;
+mod ethereum_light;
+mod generic_cosmos;
+mod generic_evm;
+mod migrate;
+mod platform;
 mod polygon;
+mod registry;
+#[cfg(feature = "wasm")]
+mod wasm;
 
+pub use adopt::{Component, identify};
 pub use berachain::BerachainDeployment;
+pub use chains::{
+    ChainProviders, ComponentProvider, ModuleProvider, chain_name, chains, providers,
+};
+pub use cosmos::StateSync;
 pub use ethereum::EthereumDeployment;
+pub use ethereum_light::EthereumLightDeployment;
+pub use generic_cosmos::GenericCosmosDeployment;
+pub use generic_evm::GenericEvmDeployment;
+pub use migrate::{Migration, MigrationSource, migrate};
+pub use platform::{image_archs, needs_platform, select_platform};
 pub use polygon::PolygonDeployment;
+pub use registry::{DeploymentRegistry, ErasedDeployment};
+#[cfg(feature = "wasm")]
+pub use wasm::WasmDeployment;
 
+/// Builds the manifest of the Dep with the modules of the catalog, see
+/// `DeploymentRegistry` to deploy modules of other crates
 pub fn apply(dep: Dep) -> eyre::Result<Manifest> {
-    match dep.module.as_str() {
-        "ethereum" => EthereumDeployment::default().apply(&dep),
-        "polygon" => PolygonDeployment::default().apply(&dep),
-        "berachain" => BerachainDeployment::default().apply(&dep),
-        _ => Err(eyre::eyre!("Unknown module: {}", dep.module)),
+    DeploymentRegistry::default().apply(dep)
+}
+
+// image of the fetcher, it downloads and verifies the files of the init specs
+pub(crate) const FETCHER_IMAGE: &str = "ghcr.io/umbracle/bbuilder/fetcher";
+
+// release of the fetcher run by the catalog, the init specs rely on its
+// `--checksums` flag
+pub(crate) const FETCHER_TAG: &str = "0.2.0";
+
+// open files limit of the execution clients, they keep many database files
+// and peer connections open
+pub(crate) const NOFILE_LIMIT: u64 = 1_048_576;
+
+// seconds the execution clients get to flush their database after SIGINT,
+// a kill in the middle of it forces a long recovery on the next start
+pub(crate) const EL_STOP_GRACE_PERIOD: u64 = 300;
+
+// uid of the nodes that run unprivileged, their data dirs are chowned to it
+pub(crate) const NODE_UID: u32 = 1000;
+
+/// Downloads the url as text from the (sync) catalog code running inside a tokio runtime
+#[tracing::instrument]
+pub(crate) fn fetch_data(url: String) -> eyre::Result<String> {
+    let handle = tokio::task::spawn_blocking(move || reqwest::blocking::get(&url)?.text());
+
+    // Block on the handle from sync context
+    let text = tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(handle))??;
+    Ok(text)
+}
+
+/// Checks that the deployment name can be used as a directory and compose project name
+fn validate_name(name: &str) -> eyre::Result<()> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+
+    if !valid {
+        return Err(eyre::eyre!(
+            "Invalid deployment name '{}': only lowercase letters, digits, '-' and '_' are allowed",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that a sha256 input of a download is a hex digest
+pub(crate) fn validate_sha256(field: &str, sha256: Option<&str>) -> eyre::Result<()> {
+    if let Some(sha256) = sha256
+        && (sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        eyre::bail!("Invalid {}: {}", field, sha256);
+    }
+    Ok(())
+}
+
+// service that replies with the public ip of the caller
+const EXTERNAL_IP_LOOKUP_URL: &str = "https://ifconfig.me/ip";
+
+/// Parses the external ip of the Dep, "auto" looks up the public ip of the host
+fn resolve_external_ip(value: &str) -> eyre::Result<IpAddr> {
+    let ip = match value {
+        "auto" => fetch_data(EXTERNAL_IP_LOOKUP_URL.to_string())?,
+        ip => ip.to_string(),
+    };
+
+    ip.trim()
+        .parse()
+        .map_err(|err| eyre::eyre!("Invalid external ip '{}': {}", ip.trim(), err))
+}
+
+/// Machine-readable description of what a catalog module can deploy
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModuleCapabilities {
+    #[serde(default)]
+    pub module: String,
+    // network of the chains, the prefix of their full name
+    pub network: String,
+    pub chains: Vec<ChainCapability>,
+    pub components: Vec<ComponentCapabilities>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainCapability {
+    pub chain: String,
+    pub min_version: String,
+    pub recommended_version: String,
+    // default input of the module for the chain
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub defaults: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentCapabilities {
+    pub name: String,
+    pub chains: Vec<ChainCapability>,
+    pub volumes: Vec<Volume>,
+    // profiles the component runs with, the first one is the default
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+}
+
+impl ChainCapability {
+    fn from_specs<Chains: Default>(
+        specs: Vec<ChainSpec<Chains>>,
+        name: impl Fn(&Chains) -> &str,
+    ) -> Vec<Self> {
+        specs
+            .iter()
+            .map(|spec| ChainCapability {
+                chain: name(&spec.chain).to_string(),
+                min_version: spec.min_version.clone(),
+                recommended_version: spec.recommended_version.clone(),
+                defaults: serde_json::Value::Null,
+            })
+            .collect()
+    }
+
+    /// Chains of a module with its default input for each one
+    pub fn from_deployment<D: Deployment>(
+        deployment: &D,
+        name: impl Fn(&D::Chains) -> &str,
+    ) -> Vec<Self> {
+        deployment
+            .capabilities()
+            .iter()
+            .map(|spec| ChainCapability {
+                chain: name(&spec.chain).to_string(),
+                min_version: spec.min_version.clone(),
+                recommended_version: spec.recommended_version.clone(),
+                defaults: deployment.defaults(&spec.chain),
+            })
+            .collect()
+    }
+}
+
+impl ComponentCapabilities {
+    pub fn new<R: spec::ComputeResource>(
+        name: &str,
+        resource: &R,
+        chain_name: impl Fn(&R::Chains) -> &str,
+    ) -> Self {
+        let capabilities = resource.capabilities();
+        Self {
+            name: name.to_string(),
+            chains: ChainCapability::from_specs(capabilities.chains, chain_name),
+            volumes: capabilities.volumes,
+            profiles: resource.profiles(),
+        }
+    }
+}
+
+/// Summary of a module of the catalog
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleInfo {
+    pub name: String,
+    // full names of the chains the module deploys (e.g. `ethereum.mainnet`)
+    pub chains: Vec<String>,
+    pub components: Vec<ComponentInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentInfo {
+    pub name: String,
+    // minimum version of the component by the full name of the chain
+    pub min_versions: BTreeMap<String, String>,
+}
+
+/// Lists the modules of the catalog with their chains and components, see
+/// `capabilities` for the defaults and recommended versions as well
+pub fn list() -> Vec<ModuleInfo> {
+    DeploymentRegistry::default().list()
+}
+
+impl ModuleInfo {
+    fn new(module: &ModuleCapabilities) -> Self {
+        let full_name = |chain: &ChainCapability| format!("{}.{}", module.network, chain.chain);
+        Self {
+            name: module.module.clone(),
+            chains: module.chains.iter().map(full_name).collect(),
+            components: module
+                .components
+                .iter()
+                .map(|component| ComponentInfo {
+                    name: component.name.clone(),
+                    min_versions: component
+                        .chains
+                        .iter()
+                        .map(|chain| (full_name(chain), chain.min_version.clone()))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// JSON Schema of the input of every module registered in the catalog
+pub fn schemas() -> BTreeMap<String, serde_json::Value> {
+    DeploymentRegistry::default().schemas()
+}
+
+/// Returns the capabilities of every module registered in the catalog
+pub fn capabilities() -> Vec<ModuleCapabilities> {
+    DeploymentRegistry::default().capabilities()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_modules() {
+        let modules = list();
+        let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "ethereum",
+                "ethereum_light",
+                "polygon",
+                "berachain",
+                "generic-evm",
+                "generic-cosmos"
+            ]
+        );
+
+        let polygon = &modules[2];
+        assert_eq!(polygon.chains, vec!["polygon.mainnet", "polygon.amoy"]);
+        let bor = polygon
+            .components
+            .iter()
+            .find(|component| component.name == "bor")
+            .unwrap();
+        assert_eq!(bor.min_versions["polygon.amoy"], "1.1.0");
+    }
+
+    #[test]
+    fn test_every_client_takes_the_overrides() -> eyre::Result<()> {
+        let overrides = serde_json::json!({
+            "extra_args": ["--override"],
+            "extra_env": {"OVERRIDE": "1"},
+        });
+        let deps = [
+            (
+                serde_json::json!({
+                    "module": "ethereum",
+                    "args": {
+                        "el_node": {"reth": overrides},
+                        "cl_node": [{"lighthouse": overrides}, {"nimbus": overrides}],
+                    },
+                }),
+                vec![("el", "node"), ("cl", "node"), ("cl-2", "node")],
+            ),
+            (
+                serde_json::json!({
+                    "module": "ethereum_light",
+                    "args": {"node": {"trin": overrides}},
+                }),
+                vec![("light", "node")],
+            ),
+            (
+                serde_json::json!({
+                    "module": "polygon",
+                    "chain": "amoy",
+                    "args": {"heimdall": overrides, "bor": overrides},
+                }),
+                vec![("heimdall", "node"), ("bor", "bor")],
+            ),
+            (
+                serde_json::json!({
+                    "module": "berachain",
+                    "args": {"beacon_kit": overrides, "bera_reth": overrides},
+                }),
+                vec![("beaconkit", "node"), ("berareth", "reth")],
+            ),
+            (
+                serde_json::json!({
+                    "module": "generic-evm",
+                    "args": {"el_node": {"besu": {
+                        "genesis": {"content": {"config": {"chainId": 1337}}},
+                        "extra_args": ["--override"],
+                        "extra_env": {"OVERRIDE": "1"},
+                    }}},
+                }),
+                vec![("el", "node")],
+            ),
+        ];
+        for (dep, pods) in deps {
+            let module = dep["module"].clone();
+            let manifest = apply(serde_json::from_value(dep)?)?;
+            for (pod, spec) in pods {
+                let node = &manifest.pods[pod].specs[spec];
+                assert!(
+                    matches!(node.args.last(), Some(spec::Arg::Value(arg)) if arg == "--override"),
+                    "{} {}",
+                    module,
+                    pod
+                );
+                assert_eq!(node.env["OVERRIDE"], "1", "{} {}", module, pod);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_ip_is_advertised_to_the_p2p_networks() -> eyre::Result<()> {
+        let dep = |external_ip: &str| -> eyre::Result<Dep> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "module": "ethereum",
+                "external_ip": external_ip,
+                "args": {
+                    "el_node": {"reth": {}},
+                    "cl_node": [{"lighthouse": {}}, {"prysm": {}}],
+                },
+            }))?)
+        };
+        let manifest = apply(dep(" 203.0.113.7\n")?)?;
+        let flags = [
+            ("el", "--nat", "extip:203.0.113.7"),
+            ("cl", "--enr-address", "203.0.113.7"),
+            ("cl-2", "--p2p-host-ip", "203.0.113.7"),
+        ];
+        for (pod, flag, value) in flags {
+            let args = &manifest.pods[pod].specs["node"].args;
+            assert!(
+                args.windows(2).any(|args| matches!(
+                    args,
+                    [spec::Arg::Value(arg), spec::Arg::Value(ip)] if arg == flag && ip == value
+                )),
+                "{} {}",
+                pod,
+                flag
+            );
+        }
+
+        let err = apply(dep("203.0.113")?).unwrap_err();
+        assert!(err.to_string().contains("Invalid external ip '203.0.113'"));
+        Ok(())
     }
 }
@@ -0,0 +1,198 @@
+//! Migration of the chain data of another client into a deployment, the
+//! data is exported and imported by one-shot init specs of the target pod.
+
+use crate::ethereum::{Chains, VERSIONS, is_reth_node, reth_binary};
+use serde::Deserialize;
+use spec::{Arg, Context, Manifest, Spec, Volume};
+
+// files of the migration in the data dir of the node, its `done` marker
+// skips the migration on the next deployments
+const MIGRATION_DIR: &str = "/data/migration";
+
+// where the era1 files of the exported history are written
+const ERA_DIR: &str = "/data/migration/era";
+
+// where the data dir of the source client is mounted in the export spec
+const SOURCE_DIR: &str = "/source";
+
+/// Client the chain data is migrated from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrationSource {
+    Geth,
+}
+
+impl MigrationSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MigrationSource::Geth => "geth",
+        }
+    }
+}
+
+impl std::str::FromStr for MigrationSource {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "geth" => Ok(MigrationSource::Geth),
+            _ => Err(eyre::eyre!("Unknown migration source: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub from: MigrationSource,
+    // host path of the data dir of the source client
+    pub datadir: String,
+    // pod whose node receives the chain data
+    pub pod: String,
+}
+
+/// Adds the init specs that export the chain of the source client and import
+/// it into the node of the pod before it starts. The migration runs once, the
+/// init specs are skipped on the next deployments of the pod. The image tag
+/// of the source client is the recommended one unless the versions of the
+/// context override it.
+pub fn migrate(manifest: &mut Manifest, migration: &Migration, ctx: &Context) -> eyre::Result<()> {
+    let pod = manifest
+        .pods
+        .get_mut(&migration.pod)
+        .ok_or_else(|| eyre::eyre!("Pod {} does not exist", migration.pod))?;
+    let node = pod
+        .specs
+        .get("node")
+        .ok_or_else(|| eyre::eyre!("Pod {} has no node to migrate to", migration.pod))?;
+
+    // the history is exported as era1 files, the format of `reth import-era`
+    if !is_reth_node(node) {
+        eyre::bail!(
+            "Migrating from {} to {} is not supported",
+            migration.from.name(),
+            node.image
+        );
+    }
+    let chain = node
+        .args
+        .windows(2)
+        .find_map(|args| match args {
+            [Arg::Value(flag), Arg::Value(chain)] if flag == "--chain" => Some(chain.clone()),
+            _ => None,
+        })
+        .ok_or_else(|| eyre::eyre!("The node of pod {} does not set a chain", migration.pod))?;
+
+    let done = format!("{}/done", MIGRATION_DIR);
+
+    // the source is only read, its history up to the head block is exported
+    let export = match migration.from {
+        MigrationSource::Geth => Spec::builder()
+            .image("ethereum/client-go")
+            .tag(VERSIONS.tag("geth", &chain.parse::<Chains>()?, ctx)?)
+            .volume(Volume::bind("source", &migration.datadir, SOURCE_DIR).read_only())
+            .entrypoint(["sh", "-c"])
+            .arg(format!(
+                "test -f {done} || (mkdir -p {dir} && \
+                 head=$(geth --datadir {source} db metadata | \
+                 awk -F'|' '$2 ~ /headBlock.Number/ {{ print $3 + 0 }}') && \
+                 geth --datadir {source} export-history {dir} 0 $head)",
+                done = done,
+                dir = ERA_DIR,
+                source = SOURCE_DIR
+            )),
+    };
+    let mut import = Spec::builder()
+        .image(node.image.clone())
+        .entrypoint(["sh", "-c"])
+        .arg(format!(
+            "test -f {done} || ({binary} import-era --chain {chain} --datadir /data --path {dir} && \
+             rm -rf {dir} && touch {done})",
+            done = done,
+            binary = reth_binary(&node.entrypoint),
+            chain = chain,
+            dir = ERA_DIR
+        ));
+    if let Some(tag) = &node.tag {
+        import = import.tag(tag);
+    }
+    if let Some(digest) = &node.digest {
+        import = import.digest(digest);
+    }
+
+    pod.init_specs.push(spec::InitSpec {
+        name: "migrate-export".to_string(),
+        spec: export.build(),
+    });
+    pod.init_specs.push(spec::InitSpec {
+        name: "migrate-import".to_string(),
+        spec: import.build(),
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::{Dep, VolumeKind};
+
+    #[test]
+    fn test_migration_runs_before_the_node() -> eyre::Result<()> {
+        let dep: Dep = serde_json::from_value(serde_json::json!({
+            "module": "ethereum",
+            "args": {}
+        }))?;
+        let mut manifest = crate::apply(dep)?;
+
+        let migration = Migration {
+            from: MigrationSource::Geth,
+            datadir: "/var/lib/geth".to_string(),
+            pod: "el".to_string(),
+        };
+        migrate(&mut manifest, &migration, &Context::default())?;
+        manifest.validate()?;
+
+        let init_specs = &manifest.pods["el"].init_specs;
+        let names: Vec<&str> = init_specs.iter().map(|init| init.name.as_str()).collect();
+        assert_eq!(names, vec!["migrate-export", "migrate-import"]);
+        assert_eq!(
+            init_specs[0].spec.volumes["source"].kind,
+            VolumeKind::Bind {
+                host_path: "/var/lib/geth".to_string()
+            }
+        );
+        assert!(init_specs[0].spec.volumes["source"].read_only);
+        assert_eq!(init_specs[0].spec.tag.as_deref(), Some("v1.16.1"));
+        assert_eq!(
+            init_specs[1].spec.image_ref(),
+            manifest.pods["el"].specs["node"].image_ref()
+        );
+
+        let cl = Migration {
+            pod: "cl".to_string(),
+            ..migration.clone()
+        };
+        assert!(migrate(&mut manifest, &cl, &Context::default()).is_err());
+
+        // a custom reth based image imports with its own binary
+        let dep: Dep = serde_json::from_value(serde_json::json!({
+            "module": "ethereum",
+            "args": { "el_node": { "reth": {
+                "image": "registry.example.com/reth-exex",
+                "entrypoint": ["/usr/local/bin/reth-exex"]
+            } } }
+        }))?;
+        let mut manifest = crate::apply(dep)?;
+        migrate(&mut manifest, &migration, &Context::default())?;
+        let import = &manifest.pods["el"].init_specs[1].spec;
+        assert!(
+            import
+                .image_ref()
+                .starts_with("registry.example.com/reth-exex:")
+        );
+        assert!(matches!(
+            &import.args[0],
+            Arg::Value(script) if script.contains("(/usr/local/bin/reth-exex import-era --chain mainnet")
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+//! Architectures of the images of the catalog and the selection of the one
+//! that runs on the host of the deployment.
+
+use spec::{Arch, Manifest, Spec};
+
+const ALL_ARCHS: &[Arch] = &[Arch::Amd64, Arch::Arm64];
+
+/// Tags published by the image of a client and the architectures they run on
+enum Tags {
+    // every tag is a manifest of the architectures
+    Archs(&'static [Arch]),
+    // the tags are built per architecture with its prefix (e.g. `amd64-v25.9.2`),
+    // the `multiarch-` tags are a manifest of all of them
+    Prefixed(&'static [(Arch, &'static str)]),
+}
+
+// prefix of the tags of `Tags::Prefixed` that run on every architecture
+const MULTIARCH_PREFIX: &str = "multiarch-";
+
+// tags of the images of the catalog, the images not listed (e.g. the image
+// overrides of the inputs) are left to the runtime
+const IMAGES: &[(&str, Tags)] = &[
+    ("ghcr.io/paradigmxyz/reth", Tags::Archs(ALL_ARCHS)),
+    ("sigp/lighthouse", Tags::Archs(ALL_ARCHS)),
+    (
+        "gcr.io/prysmaticlabs/prysm/beacon-chain",
+        Tags::Archs(ALL_ARCHS),
+    ),
+    (
+        "statusim/nimbus-eth2",
+        Tags::Prefixed(&[(Arch::Amd64, "amd64-"), (Arch::Arm64, "arm64v8-")]),
+    ),
+    ("portalnetwork/trin", Tags::Archs(ALL_ARCHS)),
+    ("consensys/web3signer", Tags::Archs(ALL_ARCHS)),
+    ("obolnetwork/charon", Tags::Archs(ALL_ARCHS)),
+    ("ethereum/client-go", Tags::Archs(ALL_ARCHS)),
+    ("hyperledger/besu", Tags::Archs(ALL_ARCHS)),
+    ("0xpolygon/bor", Tags::Archs(ALL_ARCHS)),
+    ("erigontech/erigon", Tags::Archs(ALL_ARCHS)),
+    ("ghcr.io/berachain/beacon-kit", Tags::Archs(ALL_ARCHS)),
+    ("ghcr.io/berachain/bera-reth", Tags::Archs(ALL_ARCHS)),
+    ("0xpolygon/heimdall-v2", Tags::Archs(&[Arch::Amd64])),
+    ("ghcr.io/base/blob-archiver", Tags::Archs(&[Arch::Amd64])),
+    ("ssvlabs/ssv-node", Tags::Archs(&[Arch::Amd64])),
+];
+
+fn image_tags(image: &str) -> Option<&'static Tags> {
+    IMAGES
+        .iter()
+        .find(|(name, _)| *name == image)
+        .map(|(_, tags)| tags)
+}
+
+/// Architectures the tag of the image is published for, every one for the
+/// images without a record
+pub fn image_archs(image: &str, tag: &str) -> Vec<Arch> {
+    match image_tags(image) {
+        None => ALL_ARCHS.to_vec(),
+        Some(Tags::Archs(archs)) => archs.to_vec(),
+        Some(Tags::Prefixed(prefixes)) => {
+            if tag.starts_with(MULTIARCH_PREFIX) {
+                return prefixes.iter().map(|(arch, _)| *arch).collect();
+            }
+            prefixes
+                .iter()
+                .filter(|(_, prefix)| tag.starts_with(prefix))
+                .map(|(arch, _)| *arch)
+                .collect()
+        }
+    }
+}
+
+/// Whether a spec of the manifest runs an image that is not published for
+/// every architecture, only then `select_platform` needs the one of the host
+pub fn needs_platform(manifest: &Manifest) -> bool {
+    manifest.pods.values().any(|pod| {
+        pod.specs
+            .values()
+            .chain(pod.init_specs.iter().map(|init| &init.spec))
+            .any(|spec| image_archs(&spec.image, spec.tag.as_deref().unwrap_or("")) != ALL_ARCHS)
+    })
+}
+
+/// Pins the specs of the manifest whose image is not published for every
+/// architecture to the one of the host, selecting the tag built for it when
+/// the image has one per architecture. It fails with the specs whose image is
+/// not published for it instead of leaving the runtime to pull an image that
+/// does not run.
+pub fn select_platform(manifest: &mut Manifest, arch: Arch) -> eyre::Result<()> {
+    let mut unsupported = vec![];
+    for (pod_name, pod) in manifest.pods.iter_mut() {
+        let specs = pod.specs.iter_mut().chain(
+            pod.init_specs
+                .iter_mut()
+                .map(|init| (&init.name, &mut init.spec)),
+        );
+        for (name, spec) in specs {
+            if pin(spec, arch) {
+                continue;
+            }
+            unsupported.push(format!("{}/{} ({})", pod_name, name, spec.image));
+        }
+    }
+    if !unsupported.is_empty() {
+        unsupported.sort();
+        eyre::bail!(
+            "No {} image published for: {}",
+            arch.name(),
+            unsupported.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn pin(spec: &mut Spec, arch: Arch) -> bool {
+    let tag = spec.tag.clone().unwrap_or_default();
+    let archs = image_archs(&spec.image, &tag);
+    if archs == ALL_ARCHS {
+        return true;
+    }
+    if !archs.contains(&arch) {
+        // the variant of the tag built for the host, unless the spec pins a digest
+        let variant = match image_tags(&spec.image) {
+            Some(Tags::Prefixed(prefixes)) if spec.digest.is_none() => {
+                let from = prefixes.iter().find(|(_, prefix)| tag.starts_with(prefix));
+                let to = prefixes.iter().find(|(other, _)| *other == arch);
+                from.zip(to)
+                    .map(|((_, from), (_, to))| format!("{}{}", to, &tag[from.len()..]))
+            }
+            _ => None,
+        };
+        match variant {
+            Some(variant) => spec.tag = Some(variant),
+            None => return false,
+        }
+    }
+    spec.platform = Some(arch);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::Dep;
+
+    #[test]
+    fn test_specs_are_pinned_to_the_host_arch() -> eyre::Result<()> {
+        let dep: Dep = serde_json::from_value(serde_json::json!({
+            "module": "polygon",
+            "args": { "heimdall": {}, "bor": {} }
+        }))?;
+        let mut manifest = crate::apply(dep)?;
+        assert!(needs_platform(&manifest));
+
+        select_platform(&mut manifest, Arch::Amd64)?;
+        assert_eq!(
+            manifest.pods["heimdall"].specs["node"].platform,
+            Some(Arch::Amd64)
+        );
+        // bor publishes both, the runtime pulls the one of the host
+        assert_eq!(manifest.pods["bor"].specs["bor"].platform, None);
+
+        let err = select_platform(&mut manifest, Arch::Arm64).unwrap_err();
+        assert!(err.to_string().contains("(0xpolygon/heimdall-v2)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_the_tag_built_for_the_host_arch_is_selected() -> eyre::Result<()> {
+        assert_eq!(
+            image_archs("statusim/nimbus-eth2", "multiarch-v25.9.2"),
+            vec![Arch::Amd64, Arch::Arm64]
+        );
+        assert_eq!(
+            image_archs("statusim/nimbus-eth2", "amd64-v25.9.2"),
+            vec![Arch::Amd64]
+        );
+
+        let mut spec = Spec::builder()
+            .image("statusim/nimbus-eth2")
+            .tag("amd64-v25.9.2")
+            .build();
+        assert!(pin(&mut spec, Arch::Arm64));
+        assert_eq!(spec.tag.as_deref(), Some("arm64v8-v25.9.2"));
+        assert_eq!(spec.platform, Some(Arch::Arm64));
+
+        // the multi-arch default of the catalog needs no platform
+        let dep: Dep = serde_json::from_value(serde_json::json!({
+            "module": "ethereum",
+            "args": { "cl_node": { "nimbus": {} } }
+        }))?;
+        assert!(!needs_platform(&crate::apply(dep)?));
+        Ok(())
+    }
+}
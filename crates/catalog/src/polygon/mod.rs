@@ -2,14 +2,22 @@ use base64::{Engine as _, engine::general_purpose};
 use ed25519_dalek::SigningKey;
 use k256::ecdsa::SigningKey as kSigningKey;
 use rand::rngs::OsRng;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
 use spec::{
-    Arg, Artifacts, Babel, Capabilities, ChainSpec, ComputeResource, Deployment, Manifest, Pod, Spec, Volume,
+    Artifacts, Babel, Capabilities, ChainSpec, ClientVersion, ComputeResource, Context, Deployment,
+    HealthCheck, Manifest, Overrides, Pod, Port, Profile, Spec, Versions, Volume,
 };
 use template::Template;
 
-#[derive(Default, Clone)]
+use crate::cosmos::{P2P_PORT, P2PConfig, Peers, StateSync, StateSyncConfig};
+use crate::{
+    ChainCapability, ComponentCapabilities, EL_STOP_GRACE_PERIOD, ModuleCapabilities, NOFILE_LIMIT,
+    validate_sha256,
+};
+
+#[derive(Default, Clone, PartialEq)]
 pub enum Chains {
     #[default]
     Mainnet,
@@ -30,10 +38,99 @@ impl Chains {
             Chains::Amoy => "amoy",
         }
     }
+
+    fn heimdall_genesis(&self) -> &str {
+        match self {
+            Chains::Mainnet => {
+                "https://storage.googleapis.com/mainnet-heimdallv2-genesis/migrated_dump-genesis.json"
+            }
+            Chains::Amoy => {
+                "https://storage.googleapis.com/amoy-heimdallv2-genesis/migrated_dump-genesis.json"
+            }
+        }
+    }
+
+    fn heimdall_seeds(&self) -> Vec<String> {
+        // No public seeds are published for heimdall-v2 yet, the node
+        // relies on the persistent peers provided in the input.
+        vec![]
+    }
+
+    fn bor_bootnodes(&self) -> Vec<String> {
+        let bootnodes: &[&str] = match self {
+            Chains::Mainnet => &[
+                "enode://0cb82b395094ee4a2915e9714894627de9ed8498fb881cec6db7c65e8b9a5bd7f2f25cc84e71e89d0947e51c76e85d0847de848c7782b13c0255247a6758178c@44.232.55.71:30303",
+                "enode://88116f4295f5a31538ae409e4d44ad40d22e44ee9342869e7d68bdec55b0f83c1530355ce8b41fbec0928a7d75a5745d528450d30aec92066ab6ba1ee351d710@159.203.9.164:30303",
+            ],
+            Chains::Amoy => &[
+                "enode://bce861be777e91b0a5a49d58a51e14f32f201b4c6c2d1fbea6c7a1f14756cbb3f931f3188d6b65de8b07b53ff28d03b6e366d09e56360d2124a9fc5a15a0913d@54.217.171.196:30303",
+                "enode://4a3dc0081a346d26a73d79dd88216a9402d2292318e2db9947dbc97ea9c4afb2498dc519c0af04420dc13a238c279062da0320181e7c1461216ce4513bfd40bf@13.251.184.185:30303",
+            ],
+        };
+        bootnodes.iter().map(|node| node.to_string()).collect()
+    }
+
+    fn erigon_chain(&self) -> &str {
+        match self {
+            Chains::Mainnet => "bor-mainnet",
+            Chains::Amoy => "amoy",
+        }
+    }
 }
 
-#[derive(Default, Deserialize)]
-pub struct Heimdall {}
+impl std::str::FromStr for Chains {
+    type Err = eyre::Report;
+
+    /// Parses the name of the chain, with or without the `polygon.` prefix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("polygon.").unwrap_or(s) {
+            "mainnet" => Ok(Chains::Mainnet),
+            "amoy" => Ok(Chains::Amoy),
+            _ => Err(eyre::eyre!("Unknown polygon chain: {}", s)),
+        }
+    }
+}
+
+const VERSIONS: Versions<Chains> = Versions(&[
+    ClientVersion::new("heimdall", Chains::Mainnet, "0.2.16", "0.2.16"),
+    ClientVersion::new("heimdall", Chains::Amoy, "0.2.16", "0.2.16"),
+    ClientVersion::new("bor", Chains::Mainnet, "1.1.0", "1.1.0"),
+    ClientVersion::new("bor", Chains::Amoy, "1.1.0", "1.1.0"),
+    ClientVersion::new("erigon", Chains::Mainnet, "v3.0.0", "v3.0.0"),
+    ClientVersion::new("erigon", Chains::Amoy, "v3.0.0", "v3.0.0"),
+]);
+
+pub(crate) fn capabilities() -> ModuleCapabilities {
+    ModuleCapabilities {
+        module: "polygon".to_string(),
+        network: "polygon".to_string(),
+        chains: ChainCapability::from_deployment(&PolygonDeployment::default(), Chains::name),
+        components: vec![
+            ComponentCapabilities::new("heimdall", &Heimdall::default(), Chains::name),
+            ComponentCapabilities::new("bor", &Bor::default(), Chains::name),
+            ComponentCapabilities::new("erigon", &Erigon::default(), Chains::name),
+        ],
+    }
+}
+
+#[derive(Default, Deserialize, JsonSchema)]
+pub struct Heimdall {
+    #[serde(default)]
+    pub state_sync: Option<StateSync>,
+    // sha256 of the migrated genesis dump, it is verified before the node starts
+    #[serde(default)]
+    pub genesis_sha256: Option<String>,
+    #[serde(flatten)]
+    pub peers: Peers,
+    // image run instead of 0xpolygon/heimdall-v2 (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
 
 #[derive(Template, Serialize)]
 #[template(path = "heimdall/client.toml")]
@@ -41,30 +138,31 @@ struct HeimdallClientConfigFile {
     chain: String,
 }
 
+#[derive(Template, Serialize)]
+#[template(path = "heimdall/config.toml")]
+struct HeimdallConfigFile {
+    statesync: StateSyncConfig,
+    p2p: P2PConfig,
+}
+
 impl ComputeResource for Heimdall {
     type Chains = Chains;
 
     fn capabilities(&self) -> Capabilities<Chains> {
         Capabilities {
-            chains: vec![
-                ChainSpec {
-                    chain: Chains::Mainnet,
-                    min_version: "v1.4.8".to_string(),
-                },
-                ChainSpec {
-                    chain: Chains::Amoy,
-                    min_version: "v1.4.8".to_string(),
-                },
-            ],
-            volumes: vec![Volume {
-                name: "data".to_string(),
-            }],
+            chains: VERSIONS.chains("heimdall"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
         }
     }
 
-    fn spec(&self, chain: Chains) -> eyre::Result<Pod> {
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        validate_sha256("heimdall genesis_sha256", self.genesis_sha256.as_deref())?;
         let app_config = include_str!("heimdall/app.toml");
-        let config_config = include_str!("heimdall/config.toml");
+        let config_config = HeimdallConfigFile {
+            statesync: StateSyncConfig::resolve(self.state_sync.as_ref())?,
+            p2p: P2PConfig::resolve(&self.peers, ctx, || Ok(chain.heimdall_seeds()))?,
+        };
         let client_config = HeimdallClientConfigFile {
             chain: chain.cosmos_chain_id().to_string(),
         };
@@ -79,55 +177,77 @@ impl ComputeResource for Heimdall {
 }";
 
         let node = Spec::builder()
-            .image("0xpolygon/heimdall-v2")
+            .image(self.image.as_deref().unwrap_or("0xpolygon/heimdall-v2"))
+            .volume(Volume::data())
             .entrypoint(["/usr/bin/heimdalld"])
-            .tag("0.2.16")
+            .tag(VERSIONS.resolve_tag("heimdall", &chain, ctx, self.tag.as_deref())?)
+            .port(Port::tcp("p2p", P2P_PORT).public())
             .arg("start")
             .arg2("--home", "/data/heimdall")
-            .arg2(
-                "--api.address",
-                Arg::Port {
-                    name: "http".to_string(),
-                    preferred: 1317,
-                },
-            )
-            .artifact(Artifacts::File(spec::File{
-                name: "genesis".to_string(),
-                target_path: "/data/heimdall/config/genesis.json".to_string(),
-                content: "https://storage.googleapis.com/amoy-heimdallv2-genesis/migrated_dump-genesis.json".to_string(),
-            }))
-            .artifact(Artifacts::File(spec::File{
+            .arg2("--api.address", spec::port!("http", 1317))
+            // written as artifacts, rotate-keys generates them again
+            .identity_key("/data/heimdall/config/node_key.json")
+            .identity_key("/data/heimdall/config/priv_validator_key.json")
+            .artifact(Artifacts::File(spec::File {
                 name: "client.toml".to_string(),
                 target_path: "/data/heimdall/config/client.toml".to_string(),
-                content: client_config.render().to_string(),
+                content: client_config.render().into(),
+                read_only: true,
+                ..Default::default()
             }))
-            .artifact(Artifacts::File(spec::File{
+            .artifact(Artifacts::File(spec::File {
                 name: "app.toml".to_string(),
                 target_path: "/data/heimdall/config/app.toml".to_string(),
-                content: app_config.to_string(),
+                content: app_config.into(),
+                read_only: true,
+                ..Default::default()
             }))
-            .artifact(Artifacts::File(spec::File{
+            .artifact(Artifacts::File(spec::File {
                 name: "config.toml".to_string(),
                 target_path: "/data/heimdall/config/config.toml".to_string(),
-                content: config_config.to_string(),
+                content: config_config.render().into(),
+                read_only: true,
+                ..Default::default()
             }))
-            .artifact(Artifacts::File(spec::File{
+            .artifact(Artifacts::File(spec::File {
                 name: "node_key.json".to_string(),
                 target_path: "/data/heimdall/config/node_key.json".to_string(),
-                content: keys,
+                content: keys.into(),
+                mode: Some(0o600),
+                read_only: true,
+                ..Default::default()
             }))
-            .artifact(Artifacts::File(spec::File{
+            .artifact(Artifacts::File(spec::File {
                 name: "priv_validator_key.json".to_string(),
                 target_path: "/data/heimdall/config/priv_validator_key.json".to_string(),
-                content: val_keys,
+                content: val_keys.into(),
+                mode: Some(0o600),
+                read_only: true,
+                ..Default::default()
             }))
-            .artifact(Artifacts::File(spec::File{
+            .artifact(Artifacts::File(spec::File {
                 name: "priv_validator_state.json".to_string(),
                 target_path: "/data/heimdall/data/priv_validator_state.json".to_string(),
-                content: val_keys_state.to_string(),
-            }));
-
-        Ok(Pod::default().with_spec("node", node))
+                content: val_keys_state.into(),
+                ..Default::default()
+            }))
+            // the execution client needs the rest api to follow the chain
+            .healthcheck(
+                HealthCheck::http("http", "/cosmos/base/tendermint/v1beta1/node_info")
+                    .start_period(60),
+            )
+            .overrides(&self.overrides);
+
+        Ok(Pod::default()
+            .with_init_spec(
+                "genesis",
+                Spec::download(
+                    chain.heimdall_genesis(),
+                    "/data/heimdall/config/genesis.json",
+                    self.genesis_sha256.as_deref(),
+                ),
+            )
+            .with_spec("node", node))
     }
 }
 
@@ -192,7 +312,7 @@ fn generate_cometbft_key() -> String {
 
     // Get private key bytes (32 bytes)
     let private_bytes = signing_key.to_bytes();
-    let priv_base64 = general_purpose::STANDARD.encode(&private_bytes);
+    let priv_base64 = general_purpose::STANDARD.encode(private_bytes);
 
     // Get public key bytes (uncompressed, 65 bytes)
     let public_bytes = verifying_key.to_encoded_point(false);
@@ -223,15 +343,36 @@ fn generate_cometbft_key() -> String {
 pub struct BorConfig {
     chain: String,
     data_dir: String,
+    bootnodes: Vec<String>,
+    nat: String,
+    gcmode: String,
 }
 
-#[derive(Default, Deserialize)]
-pub struct Bor {}
+#[derive(Default, Deserialize, JsonSchema)]
+pub struct Bor {
+    // full (the default) or archive
+    #[serde(default)]
+    pub profile: Option<Profile>,
+    // overrides the default bootnodes of the chain (enode urls)
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+    // image run instead of 0xpolygon/bor (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    // sha256 of the genesis, it is verified before the node starts
+    #[serde(default)]
+    pub genesis_sha256: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
 
 fn bor_genesis(chain: Chains) -> String {
     let filename = match chain {
         Chains::Mainnet => "genesis-mainnet-v1",
-        Chains::Amoy => "genesis-testnet-v4.json",
+        Chains::Amoy => "genesis-amoy",
     };
 
     format!(
@@ -245,41 +386,163 @@ impl ComputeResource for Bor {
 
     fn capabilities(&self) -> Capabilities<Chains> {
         Capabilities {
-            chains: vec![],
-            volumes: vec![],
+            chains: VERSIONS.chains("bor"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
         }
     }
 
-    fn spec(&self, chain: Chains) -> eyre::Result<Pod> {
+    fn profiles(&self) -> Vec<Profile> {
+        vec![Profile::Full, Profile::Archive]
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        validate_sha256("bor genesis_sha256", self.genesis_sha256.as_deref())?;
         let config = BorConfig {
             chain: chain.name().to_string(),
             data_dir: "/data".to_string(),
+            bootnodes: if self.bootnodes.is_empty() {
+                chain.bor_bootnodes()
+            } else {
+                self.bootnodes.clone()
+            },
+            nat: ctx.nat_extip().unwrap_or_else(|| "any".to_string()),
+            gcmode: self.profile(self.profile)?.name().to_string(),
         };
 
         let node = Spec::builder()
-            .image("0xpolygon/bor")
-            .tag("1.1.0")
+            .image(self.image.as_deref().unwrap_or("0xpolygon/bor"))
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("bor", &chain, ctx, self.tag.as_deref())?)
+            .ulimit("nofile", NOFILE_LIMIT, NOFILE_LIMIT)
+            .stop_signal("SIGINT")
+            .stop_grace_period(EL_STOP_GRACE_PERIOD)
+            .port(Port::tcp("p2p", 30303).public())
+            .port(Port::udp("discovery", 30303).public())
             .arg("server")
             .arg2("--config", "/data/config.toml")
-            .artifact(Artifacts::File(spec::File {
-                name: "config".to_string(),
-                target_path: "/data/config.toml".to_string(),
-                content: config.render(),
-            }))
-            .artifact(Artifacts::File(spec::File {
-                name: "genesis.json".to_string(),
-                target_path: "/data/genesis.json".to_string(),
-                content: bor_genesis(chain),
-            }));
+            // the flag overrides the config, the ref is resolved by the runtime
+            .arg2("--bor.heimdall", spec::ref_!("heimdall", "http"))
+            .artifact(Artifacts::File(spec::file(
+                "config",
+                "/data/config.toml",
+                config.render(),
+            )))
+            .overrides(&self.overrides);
+
+        Ok(Pod::default()
+            .with_init_spec(
+                "genesis",
+                Spec::download(
+                    &bor_genesis(chain),
+                    "/data/genesis.json",
+                    self.genesis_sha256.as_deref(),
+                ),
+            )
+            .with_spec("bor", node))
+    }
+}
+
+#[derive(Default, Deserialize, JsonSchema)]
+pub struct Erigon {
+    // full (the default), archive or pruned
+    #[serde(default)]
+    pub profile: Option<Profile>,
+    // same as the archive profile, kept for the inputs written before profiles
+    #[serde(default)]
+    pub archive: bool,
+    // overrides the default bootnodes of the chain (enode urls)
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+    // image run instead of erigontech/erigon (e.g. a release candidate or a mirror of it)
+    #[serde(default)]
+    pub image: Option<String>,
+    // image tag run instead of the recommended version
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Overrides,
+}
 
-        Ok(Pod::default().with_spec("bor", node))
+impl ComputeResource for Erigon {
+    type Chains = Chains;
+
+    fn capabilities(&self) -> Capabilities<Chains> {
+        Capabilities {
+            chains: VERSIONS.chains("erigon"),
+            volumes: vec![Volume::data()],
+            dashboards: vec![],
+        }
+    }
+
+    fn profiles(&self) -> Vec<Profile> {
+        vec![Profile::Full, Profile::Archive, Profile::Pruned]
+    }
+
+    fn spec(&self, chain: Chains, ctx: &Context) -> eyre::Result<Pod> {
+        let profile = match (self.profile, self.archive) {
+            (None, true) => Profile::Archive,
+            (profile, _) => self.profile(profile)?,
+        };
+        let prune_mode = match profile {
+            Profile::Full => "full",
+            Profile::Archive => "archive",
+            Profile::Pruned => "minimal",
+        };
+
+        // Erigon keeps its state under its own datadir and downloads the
+        // historical snapshots over bittorrent before it starts syncing.
+        let mut node = Spec::builder()
+            .image(self.image.as_deref().unwrap_or("erigontech/erigon"))
+            .volume(Volume::data())
+            .tag(VERSIONS.resolve_tag("erigon", &chain, ctx, self.tag.as_deref())?)
+            .ulimit("nofile", NOFILE_LIMIT, NOFILE_LIMIT)
+            .stop_signal("SIGINT")
+            .stop_grace_period(EL_STOP_GRACE_PERIOD)
+            .port(Port::tcp("p2p", 30303).public())
+            .port(Port::udp("discovery", 30303).public())
+            .port(Port::tcp("torrent", 42069).public())
+            .port(Port::udp("torrent-udp", 42069).public())
+            .arg(format!("--chain={}", chain.erigon_chain()))
+            .arg2("--datadir", "/data/erigon")
+            .arg2("--bor.heimdall", spec::ref_!("heimdall", "http"))
+            .arg(format!("--prune.mode={}", prune_mode))
+            .arg2("--torrent.port", spec::port!("torrent", 42069))
+            .arg("--http")
+            .arg2("--http.addr", "0.0.0.0")
+            .arg2("--http.port", spec::port!("http", 8545))
+            .arg2("--http.api", "eth,net,web3,bor");
+
+        if let Some(nat) = ctx.nat_extip() {
+            node = node.arg2("--nat", nat);
+        }
+        if !self.bootnodes.is_empty() {
+            node = node.arg2("--bootnodes", self.bootnodes.join(","));
+        }
+
+        node = node.overrides(&self.overrides);
+        Ok(Pod::default().with_spec("erigon", node))
     }
 }
 
-#[derive(Default, Deserialize)]
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ELNode {
+    Bor(Bor),
+    Erigon(Erigon),
+}
+
+impl Default for ELNode {
+    fn default() -> Self {
+        ELNode::Bor(Bor::default())
+    }
+}
+
+#[derive(Default, Deserialize, JsonSchema)]
 pub struct PolygonDeploymentInput {
     pub heimdall: Heimdall,
-    pub bor: Bor,
+    #[serde(flatten)]
+    pub el_node: ELNode,
 }
 
 #[derive(Default, Deserialize)]
@@ -289,36 +552,150 @@ impl Deployment for PolygonDeployment {
     type Input = PolygonDeploymentInput;
     type Chains = Chains;
 
+    // the execution client is flattened in the input so it has no default
+    fn defaults(&self, _chain: &Chains) -> serde_json::Value {
+        serde_json::json!({ "heimdall": {} })
+    }
+
     fn capabilities(&self) -> Vec<ChainSpec<Chains>> {
         vec![
             ChainSpec {
                 chain: Chains::Mainnet,
-                min_version: "".to_string(),
+                ..Default::default()
             },
             ChainSpec {
                 chain: Chains::Amoy,
-                min_version: "".to_string(),
+                ..Default::default()
             },
         ]
     }
 
-    fn manifest(&self, chain: Chains, input: PolygonDeploymentInput) -> eyre::Result<Manifest> {
+    fn manifest(
+        &self,
+        chain: Chains,
+        input: PolygonDeploymentInput,
+        ctx: &Context,
+    ) -> eyre::Result<Manifest> {
         let mut manifest = Manifest::new("polygon".to_string());
 
-        let mut heimdall_pod = input.heimdall.spec(chain.clone())?;
+        let mut heimdall_pod = input.heimdall.spec(chain.clone(), ctx)?;
         // Add Babel sidecar to Heimdall pod
-        let babel_cosmos = Babel::new(
-            "cosmos",
-            Arg::Ref {
-                name: "heimdall".to_string(),
-                port: "http".to_string(),
-            },
-        );
-        heimdall_pod = heimdall_pod.with_spec("babel", babel_cosmos.spec());
+        let babel_cosmos = Babel::new("cosmos", spec::ref_!("heimdall", "http"));
+        heimdall_pod = heimdall_pod.with_babel(babel_cosmos);
         manifest.add_spec("heimdall".to_string(), heimdall_pod);
 
-        manifest.add_spec("bor".to_string(), input.bor.spec(chain)?);
+        // the execution client starts once the heimdall rest api is healthy
+        let el_pod = match input.el_node {
+            ELNode::Bor(bor) => {
+                manifest.add_spec("bor".to_string(), bor.spec(chain, ctx)?);
+                "bor"
+            }
+            ELNode::Erigon(erigon) => {
+                manifest.add_spec("erigon".to_string(), erigon.spec(chain, ctx)?);
+                "erigon"
+            }
+        };
+        manifest.add_dependency(el_pod, "heimdall");
 
         Ok(manifest)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::Arg;
+
+    #[test]
+    fn test_el_node_waits_for_heimdall() -> eyre::Result<()> {
+        let manifest = PolygonDeployment::default().manifest(
+            Chains::Amoy,
+            PolygonDeploymentInput::default(),
+            &Context::default(),
+        )?;
+
+        assert!(
+            manifest.pods["heimdall"].specs["node"]
+                .healthcheck
+                .is_some()
+        );
+        assert_eq!(manifest.startup_order()?, vec!["heimdall", "bor"]);
+
+        // the heimdall url is a ref, not the service name of the compose runtime
+        let bor = &manifest.pods["bor"].specs["bor"];
+        let heimdall = bor
+            .args
+            .iter()
+            .position(|arg| matches!(arg, Arg::Value(flag) if flag == "--bor.heimdall"));
+        assert!(matches!(
+            heimdall.map(|i| &bor.args[i + 1]),
+            Some(Arg::Ref { name, port }) if name == "heimdall" && port == "http"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bootnodes_override_the_chain_defaults() -> eyre::Result<()> {
+        let bootnodes = |bor: Bor| -> eyre::Result<String> {
+            let pod = bor.spec(Chains::Amoy, &Context::default())?;
+            let Some(Artifacts::File(config)) = pod.specs["bor"].artifacts.first() else {
+                panic!("expected the config file");
+            };
+            let config = config.content.inline().unwrap();
+            let line = config.lines().find(|line| line.contains("bootnodes = "));
+            Ok(line.unwrap().trim().to_string())
+        };
+        let defaults = bootnodes(Bor::default())?;
+        assert!(defaults.contains(&Chains::Amoy.bor_bootnodes()[0]));
+
+        let enode = "enode://abcd@203.0.113.7:30303";
+        let bor = Bor {
+            bootnodes: vec![enode.to_string()],
+            ..Default::default()
+        };
+        assert_eq!(bootnodes(bor)?, format!("bootnodes = [\"{}\"]", enode));
+
+        let erigon = Erigon {
+            bootnodes: vec![enode.to_string(), enode.to_string()],
+            ..Default::default()
+        };
+        let pod = erigon.spec(Chains::Amoy, &Context::default())?;
+        assert!(pod.specs["erigon"].args.windows(2).any(|args| matches!(
+            args,
+            [Arg::Value(flag), Arg::Value(nodes)]
+                if flag == "--bootnodes" && *nodes == format!("{},{}", enode, enode)
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_heimdall_genesis_is_verified() -> eyre::Result<()> {
+        let sha256 = "ab".repeat(32);
+        let input = |genesis_sha256: &str| PolygonDeploymentInput {
+            heimdall: Heimdall {
+                genesis_sha256: Some(genesis_sha256.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let manifest = PolygonDeployment::default().manifest(
+            Chains::Amoy,
+            input(&sha256),
+            &Context::default(),
+        )?;
+        let genesis = &manifest.pods["heimdall"].init_specs[0];
+        assert_eq!(genesis.name, "genesis");
+        assert!(matches!(
+            &genesis.spec.args[0],
+            Arg::Value(script) if script.contains(&format!("{}  /data/heimdall/config/genesis.json", sha256))
+        ));
+
+        assert!(
+            PolygonDeployment::default()
+                .manifest(Chains::Amoy, input("abc"), &Context::default())
+                .is_err()
+        );
+        Ok(())
+    }
+}
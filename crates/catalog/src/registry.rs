@@ -0,0 +1,268 @@
+//! Registry of the deployment modules by name. The modules of the catalog are
+//! registered by default and downstream crates register their own chains on
+//! top of them.
+
+use spec::{Context, Dep, Deployment, Manifest};
+use std::collections::BTreeMap;
+
+use crate::{
+    BerachainDeployment, EthereumDeployment, EthereumLightDeployment, GenericCosmosDeployment,
+    GenericEvmDeployment, ModuleCapabilities, ModuleInfo, PolygonDeployment, berachain, ethereum,
+    ethereum_light, generic_cosmos, generic_evm, polygon, resolve_external_ip, validate_name,
+};
+
+/// Object safe view of a `Deployment`, its input and chains are only known
+/// by the module itself
+pub trait ErasedDeployment: Send + Sync {
+    /// Builds the manifest of the Dep, usually `Deployment::apply`
+    fn apply(&self, dep: &Dep, ctx: &Context) -> eyre::Result<Manifest>;
+
+    fn input_schema(&self) -> serde_json::Value;
+
+    /// Chains and components of the module, see `ChainCapability::from_deployment`
+    /// and `ComponentCapabilities::new` to build them from the deployment
+    fn capabilities(&self) -> ModuleCapabilities;
+}
+
+macro_rules! erased_deployment {
+    ($deployment:ty, $module:ident) => {
+        impl ErasedDeployment for $deployment {
+            fn apply(&self, dep: &Dep, ctx: &Context) -> eyre::Result<Manifest> {
+                Deployment::apply(self, dep, ctx)
+            }
+
+            fn input_schema(&self) -> serde_json::Value {
+                Deployment::input_schema(self)
+            }
+
+            fn capabilities(&self) -> ModuleCapabilities {
+                $module::capabilities()
+            }
+        }
+    };
+}
+
+erased_deployment!(EthereumDeployment, ethereum);
+erased_deployment!(EthereumLightDeployment, ethereum_light);
+erased_deployment!(PolygonDeployment, polygon);
+erased_deployment!(BerachainDeployment, berachain);
+erased_deployment!(GenericEvmDeployment, generic_evm);
+erased_deployment!(GenericCosmosDeployment, generic_cosmos);
+
+/// Deployment modules by name, in the order they were registered
+pub struct DeploymentRegistry {
+    modules: Vec<(String, Box<dyn ErasedDeployment>)>,
+}
+
+impl Default for DeploymentRegistry {
+    /// Registry with the modules of the catalog
+    fn default() -> Self {
+        let modules: [(&str, Box<dyn ErasedDeployment>); 6] = [
+            ("ethereum", Box::new(EthereumDeployment::default())),
+            (
+                "ethereum_light",
+                Box::new(EthereumLightDeployment::default()),
+            ),
+            ("polygon", Box::new(PolygonDeployment::default())),
+            ("berachain", Box::new(BerachainDeployment::default())),
+            ("generic-evm", Box::new(GenericEvmDeployment::default())),
+            (
+                "generic-cosmos",
+                Box::new(GenericCosmosDeployment::default()),
+            ),
+        ];
+        Self {
+            modules: modules
+                .into_iter()
+                .map(|(name, deployment)| (name.to_string(), deployment))
+                .collect(),
+        }
+    }
+}
+
+impl DeploymentRegistry {
+    /// Registry without any module, not even the ones of the catalog
+    pub fn empty() -> Self {
+        Self { modules: vec![] }
+    }
+
+    /// Registers the deployment as the module `name`, the name of a Dep
+    /// selects it. A name can only be registered once, the modules of the
+    /// catalog included.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        deployment: Box<dyn ErasedDeployment>,
+    ) -> eyre::Result<()> {
+        let name = name.into();
+        if self.get(&name).is_some() {
+            eyre::bail!("Module {} is already registered", name);
+        }
+        self.modules.push((name, deployment));
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn ErasedDeployment> {
+        self.modules
+            .iter()
+            .find(|(module, _)| module == name)
+            .map(|(_, deployment)| deployment.as_ref())
+    }
+
+    /// Names of the registered modules
+    pub fn modules(&self) -> impl Iterator<Item = &str> {
+        self.modules.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Builds the manifest of the Dep with the module it names
+    #[tracing::instrument(skip_all, fields(module = %dep.module, chain = %dep.chain))]
+    pub fn apply(&self, dep: Dep) -> eyre::Result<Manifest> {
+        if let Some(name) = &dep.name {
+            validate_name(name)?;
+        }
+        let deployment = self
+            .get(&dep.module)
+            .ok_or_else(|| eyre::eyre!("Unknown module: {}", dep.module))?;
+
+        let ctx = Context {
+            external_ip: dep
+                .external_ip
+                .as_deref()
+                .map(resolve_external_ip)
+                .transpose()?,
+            versions: dep.versions.clone(),
+        };
+
+        let mut manifest = deployment.apply(&dep, &ctx)?;
+        manifest.apply_patch(&dep.patch)?;
+        manifest.apply_data_dirs(&dep.data_dirs)?;
+        Ok(manifest)
+    }
+
+    /// Capabilities of every module, named as registered
+    pub fn capabilities(&self) -> Vec<ModuleCapabilities> {
+        self.modules
+            .iter()
+            .map(|(name, deployment)| ModuleCapabilities {
+                module: name.clone(),
+                ..deployment.capabilities()
+            })
+            .collect()
+    }
+
+    pub fn schemas(&self) -> BTreeMap<String, serde_json::Value> {
+        self.modules
+            .iter()
+            .map(|(name, deployment)| (name.clone(), deployment.input_schema()))
+            .collect()
+    }
+
+    pub fn list(&self) -> Vec<ModuleInfo> {
+        self.capabilities().iter().map(ModuleInfo::new).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChainCapability;
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+    use spec::{ChainSpec, Pod, Spec};
+
+    #[derive(Default)]
+    struct DevnetDeployment;
+
+    #[derive(Deserialize, JsonSchema)]
+    struct DevnetInput {
+        image: String,
+    }
+
+    #[derive(Default, PartialEq)]
+    struct Local;
+
+    impl std::str::FromStr for Local {
+        type Err = eyre::Report;
+
+        fn from_str(chain: &str) -> eyre::Result<Self> {
+            match chain {
+                "local" => Ok(Local),
+                chain => eyre::bail!("Unknown devnet chain: {}", chain),
+            }
+        }
+    }
+
+    fn chain_name(_: &Local) -> &str {
+        "local"
+    }
+
+    impl Deployment for DevnetDeployment {
+        type Input = DevnetInput;
+        type Chains = Local;
+
+        fn capabilities(&self) -> Vec<ChainSpec<Local>> {
+            vec![ChainSpec {
+                chain: Local,
+                min_version: "0.1.0".to_string(),
+                recommended_version: "0.1.0".to_string(),
+            }]
+        }
+
+        fn manifest(&self, _: Local, input: DevnetInput, _: &Context) -> eyre::Result<Manifest> {
+            let mut manifest = Manifest::new("devnet".to_string());
+            manifest.add_spec(
+                "node".to_string(),
+                Pod::default().with_spec("node", Spec::builder().image(input.image)),
+            );
+            Ok(manifest)
+        }
+    }
+
+    impl ErasedDeployment for DevnetDeployment {
+        fn apply(&self, dep: &Dep, ctx: &Context) -> eyre::Result<Manifest> {
+            Deployment::apply(self, dep, ctx)
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            Deployment::input_schema(self)
+        }
+
+        fn capabilities(&self) -> ModuleCapabilities {
+            ModuleCapabilities {
+                module: "devnet".to_string(),
+                network: "devnet".to_string(),
+                chains: ChainCapability::from_deployment(self, chain_name),
+                components: vec![],
+            }
+        }
+    }
+
+    #[test]
+    fn test_registered_module_is_deployed() -> eyre::Result<()> {
+        let mut registry = DeploymentRegistry::default();
+        registry.register("devnet", Box::new(DevnetDeployment))?;
+        assert!(
+            registry
+                .register("ethereum", Box::new(DevnetDeployment))
+                .is_err()
+        );
+
+        let dep: Dep = serde_json::from_value(serde_json::json!({
+            "module": "devnet",
+            "chain": "local",
+            "args": {"image": "devnet-node"},
+        }))?;
+        let manifest = registry.apply(dep)?;
+        assert_eq!(manifest.module, "devnet");
+        assert_eq!(manifest.pods["node"].specs["node"].image, "devnet-node");
+
+        let modules: Vec<String> = registry
+            .list()
+            .into_iter()
+            .map(|module| module.name)
+            .collect();
+        assert_eq!(modules.last().map(String::as_str), Some("devnet"));
+        assert!(registry.schemas().contains_key("devnet"));
+        Ok(())
+    }
+}
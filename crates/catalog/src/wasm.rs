@@ -0,0 +1,395 @@
+//! Deployment modules compiled to WebAssembly, so that chain teams can ship
+//! their recipes without a bbuilder release. The plugin runs without imports
+//! (no filesystem, network or clock) and exchanges JSON through its memory:
+//!
+//! - `alloc(len: i32) -> i32` reserves the bytes the host writes the input to
+//! - `apply(ptr: i32, len: i32) -> i64` builds the manifest of the `PluginInput`
+//!   written at `ptr` and returns its `PluginOutput` as `ptr << 32 | len`
+//! - `capabilities() -> i64` and `input_schema() -> i64` (optional) return the
+//!   `ModuleCapabilities` and the JSON Schema of the args the same way
+//!
+//! The manifest of a plugin can not escalate out of its containers, specs
+//! that are privileged, add capabilities, map devices or bind host paths
+//! outside of the deployment directory are rejected.
+
+use serde::{Deserialize, Serialize};
+use spec::{Context, Dep, Manifest, Spec, VolumeKind};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use wasmtime::{Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::{ErasedDeployment, ModuleCapabilities};
+
+// instructions a single call can run before it is aborted
+const FUEL: u64 = 10_000_000_000;
+
+// linear memory a single call can grow to
+const MAX_MEMORY: usize = 256 * 1024 * 1024;
+
+/// Input of `apply`, the Dep with the deployment wide settings resolved
+#[derive(Serialize)]
+struct PluginInput<'a> {
+    name: Option<&'a str>,
+    chain: &'a str,
+    args: &'a serde_json::Value,
+    external_ip: Option<IpAddr>,
+    versions: &'a HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PluginOutput {
+    Manifest(Box<Manifest>),
+    Error(String),
+}
+
+/// Deployment module loaded from a WebAssembly plugin
+pub struct WasmDeployment {
+    path: PathBuf,
+    // compiled on the first call, most commands never run the plugins
+    compiled: OnceLock<Result<Compiled, String>>,
+}
+
+struct Compiled {
+    engine: Engine,
+    module: Module,
+}
+
+fn wasm_error(err: wasmtime::Error) -> eyre::Report {
+    eyre::eyre!("{:#}", err)
+}
+
+impl WasmDeployment {
+    /// Plugin of the file, it is read and compiled the first time it is called
+    pub fn load(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            compiled: OnceLock::new(),
+        }
+    }
+
+    /// Compiles the plugin from its binary or text format
+    pub fn new(wasm: &[u8]) -> eyre::Result<Self> {
+        let compiled = Compiled::new(wasm)?;
+        Ok(Self {
+            path: PathBuf::new(),
+            compiled: OnceLock::from(Ok(compiled)),
+        })
+    }
+
+    fn compiled(&self) -> eyre::Result<&Compiled> {
+        self.compiled
+            .get_or_init(|| {
+                let wasm = std::fs::read(&self.path).map_err(|err| {
+                    format!("Failed to read plugin {}: {}", self.path.display(), err)
+                })?;
+                Compiled::new(&wasm).map_err(|err| {
+                    format!("Failed to compile plugin {}: {}", self.path.display(), err)
+                })
+            })
+            .as_ref()
+            .map_err(|err| eyre::eyre!("{}", err))
+    }
+
+    /// Calls the export on a fresh instance and returns the bytes it points
+    /// to, none if the plugin does not export it
+    fn call(&self, export: &str, input: Option<&[u8]>) -> eyre::Result<Option<Vec<u8>>> {
+        let compiled = self.compiled()?;
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&compiled.engine, limits);
+        store.limiter(|limits: &mut StoreLimits| limits);
+        store.set_fuel(FUEL).map_err(wasm_error)?;
+        let instance = Instance::new(&mut store, &compiled.module, &[]).map_err(wasm_error)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| eyre::eyre!("WASM plugin does not export its memory"))?;
+        let Some(func) = instance.get_func(&mut store, export) else {
+            return Ok(None);
+        };
+
+        let packed = match input {
+            None => func
+                .typed::<(), i64>(&store)
+                .and_then(|func| func.call(&mut store, ())),
+            Some(input) => {
+                let len = i32::try_from(input.len())?;
+                let alloc = instance
+                    .get_typed_func::<i32, i32>(&mut store, "alloc")
+                    .map_err(wasm_error)?;
+                let ptr = alloc.call(&mut store, len).map_err(wasm_error)?;
+                memory.write(&mut store, ptr as u32 as usize, input)?;
+                func.typed::<(i32, i32), i64>(&store)
+                    .and_then(|func| func.call(&mut store, (ptr, len)))
+            }
+        }
+        .map_err(|err| eyre::eyre!("WASM plugin {} failed: {:#}", export, err))?;
+
+        // the range is checked before anything the size of the plugin's
+        // length is allocated
+        let (ptr, len) = ((packed as u64 >> 32) as usize, packed as u32 as usize);
+        let output = ptr
+            .checked_add(len)
+            .and_then(|end| memory.data(&store).get(ptr..end))
+            .ok_or_else(|| eyre::eyre!("WASM plugin {} output is out of its memory", export))?;
+        Ok(Some(output.to_vec()))
+    }
+}
+
+impl Compiled {
+    fn new(wasm: &[u8]) -> eyre::Result<Self> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(wasm_error)?;
+        let module = Module::new(&engine, wasm).map_err(wasm_error)?;
+        if module.imports().next().is_some() {
+            eyre::bail!("WASM plugins can not import host functions");
+        }
+        Ok(Self { engine, module })
+    }
+}
+
+/// Fails on the first spec of the plugin manifest that reaches out of its
+/// container into the host, the init and pre start specs are run like the
+/// others and follow the same rules
+fn check_confined(manifest: &Manifest) -> eyre::Result<()> {
+    for (pod_name, pod) in &manifest.pods {
+        let specs = pod
+            .specs
+            .iter()
+            .map(|(name, spec)| (name.clone(), spec.clone()))
+            .chain(pod.specs.iter().filter_map(|(name, spec)| {
+                Some((format!("{}-pre-start", name), spec.pre_start_spec()?))
+            }))
+            .chain(
+                pod.init_specs
+                    .iter()
+                    .map(|init| (init.name.clone(), init.spec.clone())),
+            );
+        for (spec_name, spec) in specs {
+            if let Some(denied) = host_access(&spec) {
+                eyre::bail!(
+                    "WASM plugin spec {}-{} {}, plugins can not reach the host",
+                    pod_name,
+                    spec_name,
+                    denied
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How the spec reaches the host, if it does
+fn host_access(spec: &Spec) -> Option<String> {
+    if spec.privileged {
+        return Some("is privileged".to_string());
+    }
+    if !spec.cap_add.is_empty() {
+        return Some(format!("adds the capabilities {}", spec.cap_add.join(", ")));
+    }
+    if !spec.devices.is_empty() {
+        return Some(format!("maps the devices {}", spec.devices.join(", ")));
+    }
+    if spec.gpu.is_some() {
+        return Some("reserves gpus".to_string());
+    }
+    if !spec.sysctls.is_empty() {
+        let keys: Vec<&str> = spec.sysctls.keys().map(String::as_str).collect();
+        return Some(format!("sets the sysctls {}", keys.join(", ")));
+    }
+    spec.volumes.values().find_map(|volume| match &volume.kind {
+        VolumeKind::Bind { host_path } if !is_confined_path(host_path) => {
+            Some(format!("binds the host path {}", host_path))
+        }
+        // e.g. the data of another deployment
+        VolumeKind::External { source } => Some(format!("mounts the external volume {}", source)),
+        _ => None,
+    })
+}
+
+/// Relative paths stay under the deployment directory unless they go up
+fn is_confined_path(host_path: &str) -> bool {
+    Path::new(host_path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+impl ErasedDeployment for WasmDeployment {
+    fn apply(&self, dep: &Dep, ctx: &Context) -> eyre::Result<Manifest> {
+        let input = serde_json::to_vec(&PluginInput {
+            name: dep.name.as_deref(),
+            chain: &dep.chain,
+            args: &dep.args,
+            external_ip: ctx.external_ip,
+            versions: &ctx.versions,
+        })?;
+        let output = self
+            .call("apply", Some(&input))?
+            .ok_or_else(|| eyre::eyre!("WASM plugin does not export apply"))?;
+
+        let mut manifest = match serde_json::from_slice(&output)? {
+            PluginOutput::Manifest(manifest) => *manifest,
+            PluginOutput::Error(err) => eyre::bail!("{}", err),
+        };
+        check_confined(&manifest)?;
+        if let Some(name) = &dep.name {
+            manifest.name = name.clone();
+        }
+        manifest.module = dep.module.clone();
+        manifest.chain = dep.chain.clone();
+        manifest.input = dep.args.clone();
+        Ok(manifest)
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        match self.call("input_schema", None) {
+            Ok(Some(schema)) => serde_json::from_slice(&schema).unwrap_or_else(|err| {
+                tracing::warn!("Invalid input schema of the WASM plugin: {}", err);
+                serde_json::Value::Bool(true)
+            }),
+            Ok(None) => serde_json::Value::Bool(true),
+            Err(err) => {
+                tracing::warn!("{}", err);
+                serde_json::Value::Bool(true)
+            }
+        }
+    }
+
+    fn capabilities(&self) -> ModuleCapabilities {
+        let capabilities = match self.call("capabilities", None) {
+            Ok(Some(capabilities)) => serde_json::from_slice(&capabilities)
+                .map_err(|err| tracing::warn!("Invalid capabilities of the WASM plugin: {}", err))
+                .ok(),
+            Ok(None) => None,
+            Err(err) => {
+                tracing::warn!("{}", err);
+                None
+            }
+        };
+        capabilities.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DeploymentRegistry;
+    use spec::{Gpu, Pod, Volume};
+
+    // plugin that returns the same manifest for any input
+    fn plugin(manifest: &Manifest) -> String {
+        let output = serde_json::json!({ "manifest": manifest }).to_string();
+        format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "{data}")
+                (func (export "alloc") (param i32) (result i32) (i32.const 4096))
+                (func (export "apply") (param i32 i32) (result i64) (i64.const {len})))"#,
+            data = output.replace('\\', "\\\\").replace('"', "\\\""),
+            len = output.len(),
+        )
+    }
+
+    #[test]
+    fn test_wasm_plugin_is_deployed() -> eyre::Result<()> {
+        let mut manifest = Manifest::new("devnet".to_string());
+        manifest.add_spec(
+            "node".to_string(),
+            Pod::default().with_spec("node", Spec::builder().image("devnet-node")),
+        );
+
+        let mut registry = DeploymentRegistry::default();
+        registry.register(
+            "devnet",
+            Box::new(WasmDeployment::new(plugin(&manifest).as_bytes())?),
+        )?;
+
+        let dep: Dep = serde_json::from_value(serde_json::json!({
+            "name": "devnet-1",
+            "module": "devnet",
+            "chain": "local",
+            "args": {},
+        }))?;
+        let manifest = registry.apply(dep)?;
+        assert_eq!(manifest.name, "devnet-1");
+        assert_eq!(manifest.module, "devnet");
+        assert_eq!(manifest.pods["node"].specs["node"].image, "devnet-node");
+
+        // the capabilities are optional
+        let devnet = registry.capabilities().pop().unwrap();
+        assert_eq!(devnet.module, "devnet");
+        assert!(devnet.chains.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_wasm_plugin_can_not_reach_the_host() -> eyre::Result<()> {
+        let dep: Dep = serde_json::from_value(serde_json::json!({
+            "module": "devnet",
+            "chain": "local",
+            "args": {},
+        }))?;
+        let specs = [
+            Spec::builder().image("devnet-node").privileged(),
+            Spec::builder().image("devnet-node").cap_add("SYS_ADMIN"),
+            Spec::builder()
+                .image("devnet-node")
+                .volume(Volume::bind("root", "/", "/host")),
+            Spec::builder()
+                .image("devnet-node")
+                .volume(Volume::bind("up", "../..", "/host")),
+            Spec::builder().image("devnet-node").gpu(Gpu::default()),
+            Spec::builder()
+                .image("devnet-node")
+                .sysctl("net.core.rmem_max", "8388608"),
+            Spec::builder()
+                .image("devnet-node")
+                .volume(Volume::external("data", "eth_el-node-data", "/data")),
+        ];
+        for spec in specs {
+            let mut manifest = Manifest::new("devnet".to_string());
+            manifest.add_spec("node".to_string(), Pod::default().with_spec("node", spec));
+            let plugin = WasmDeployment::new(plugin(&manifest).as_bytes())?;
+            assert!(plugin.apply(&dep, &Context::default()).is_err());
+        }
+
+        // the init specs run on the same runtime as the specs
+        let mut manifest = Manifest::new("devnet".to_string());
+        manifest.add_spec(
+            "node".to_string(),
+            Pod::default()
+                .with_init_spec("setup", Spec::builder().image("devnet-node").privileged())
+                .with_spec("node", Spec::builder().image("devnet-node")),
+        );
+        let plugin = WasmDeployment::new(plugin(&manifest).as_bytes())?;
+        let err = plugin.apply(&dep, &Context::default()).unwrap_err();
+        assert!(err.to_string().contains("node-setup is privileged"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wasm_plugin_output_is_read_from_its_memory() -> eyre::Result<()> {
+        // 4 GiB of output from a single page of memory
+        let plugin = WasmDeployment::new(
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "capabilities") (result i64) (i64.const 4294967295)))"#
+                .as_bytes(),
+        )?;
+        let err = plugin.call("capabilities", None).unwrap_err();
+        assert!(err.to_string().contains("out of its memory"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wasm_plugin_is_compiled_on_first_call() {
+        // loading does not read the file, only the commands that run it fail
+        let plugin = WasmDeployment::load(Path::new("/nonexistent/plugin.wasm"));
+        assert!(plugin.call("apply", None).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use clap::Parser;
+use logging::{LevelFilter, LogFormat};
 use std::path::PathBuf;
 use std::process;
 
@@ -6,30 +7,56 @@ use std::process;
 #[command(name = "fetcher")]
 #[command(about = "A go-getter-like tool to fetch files from various sources")]
 struct Args {
-    /// Source URL to fetch from
+    /// Source URL to fetch from (http, s3, gs or file), the local file to
+    /// upload with `--upload`
     source: String,
 
-    /// Destination path to save the file
+    /// Destination path to save the file, the storage URL (s3, gs or file)
+    /// to upload to with `--upload`
     destination: PathBuf,
+
+    /// Upload the local source to the storage URL of the destination
+    #[arg(long, conflicts_with = "checksums")]
+    upload: bool,
+
+    /// The source is a sha256sum file, the files it lists are fetched from its
+    /// directory into the destination directory and verified
+    #[arg(long)]
+    checksums: bool,
+
+    /// Format of the log output
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Minimum log level (trace, debug, info, warn, error)
+    #[arg(long, default_value_t = LevelFilter::INFO)]
+    log_level: LevelFilter,
 }
 
 fn main() {
     let args = Args::parse();
 
-    let mut progress = fetcher::ConsoleProgressTracker::new();
+    logging::init(args.log_format, args.log_level);
 
-    if let Err(e) = fetcher::fetch_with_progress(&args.source, &args.destination, &mut progress) {
-        eprintln!("Error: {}", e);
+    let mut progress = fetcher::ConsoleProgressTracker::new();
 
-        // Print the error chain
-        let mut source = e.source();
-        while let Some(err) = source {
-            eprintln!("  Caused by: {}", err);
-            source = err.source();
-        }
+    let result = if args.upload {
+        fetcher::upload(
+            std::path::Path::new(&args.source),
+            &args.destination.to_string_lossy(),
+        )
+    } else if args.checksums {
+        fetcher::fetch_checksummed(&args.source, &args.destination, &mut progress)
+    } else {
+        fetcher::fetch_with_progress(&args.source, &args.destination, &mut progress)
+    };
+    if let Err(e) = result {
+        // Report the error together with its chain
+        let causes: Vec<String> = e.chain().skip(1).map(|err| err.to_string()).collect();
+        tracing::error!(error = %e, causes = ?causes, "Fetch failed");
 
         process::exit(1);
     }
 
-    println!("Successfully downloaded to: {}", args.destination.display());
+    tracing::info!(destination = %args.destination.display(), "Successfully downloaded");
 }
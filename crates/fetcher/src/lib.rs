@@ -1,11 +1,14 @@
 use anyhow::{Context, Result};
 use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use tar::Archive;
 use url::Url;
 
+pub mod storage;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum ArchiveFormat {
     TarGz,
@@ -59,13 +62,19 @@ impl ConsoleProgressTracker {
     }
 }
 
+impl Default for ConsoleProgressTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ProgressTracker for ConsoleProgressTracker {
     fn set_total(&mut self, total: u64) {
         self.total = Some(total);
-        println!(
-            "Total size: {} bytes ({:.2} MB)",
-            total,
-            total as f64 / 1024.0 / 1024.0
+        tracing::info!(
+            total_bytes = total,
+            total_mb = format!("{:.2}", total as f64 / 1024.0 / 1024.0),
+            "Download size"
         );
     }
 
@@ -73,17 +82,19 @@ impl ProgressTracker for ConsoleProgressTracker {
         self.downloaded = downloaded;
         if let Some(total) = self.total {
             let percentage = (downloaded as f64 / total as f64) * 100.0;
-            println!(
-                "Downloaded: {} / {} bytes ({:.2}%)",
-                downloaded, total, percentage
+            tracing::info!(
+                downloaded_bytes = downloaded,
+                total_bytes = total,
+                percentage = format!("{:.2}", percentage),
+                "Download progress"
             );
         } else {
-            println!("Downloaded: {} bytes", downloaded);
+            tracing::info!(downloaded_bytes = downloaded, "Download progress");
         }
     }
 
     fn finish(&mut self) {
-        println!("Download complete!");
+        tracing::info!("Download complete");
     }
 }
 
@@ -102,25 +113,140 @@ pub fn fetch_with_progress<T: ProgressTracker>(
 
     match url.scheme() {
         "http" | "https" => fetch_http(&url, destination, progress),
+        "s3" | "gs" | "file" => fetch_storage(&url, destination, progress),
         scheme => anyhow::bail!("Unsupported URL scheme: {}", scheme),
     }
 }
 
-fn fetch_http<T: ProgressTracker>(
+/// Uploads the local file to the storage location (`s3://`, `gs://` or
+/// `file://`), see `storage::provider_for`
+pub fn upload(source: &Path, destination: &str) -> Result<()> {
+    let (provider, key) = storage::provider_for(destination)?;
+    tracing::info!(destination = %destination, "Uploading");
+    provider.upload(source, &key)
+}
+
+fn fetch_storage<T: ProgressTracker>(
     url: &Url,
     destination: &PathBuf,
     progress: &mut T,
 ) -> Result<()> {
-    println!("Fetching from: {}", url);
+    tracing::info!(url = %url, "Fetching");
+    let (provider, key) = storage::provider_for(url.as_str())?;
+    let object = provider.download(&key)?;
+    write_download(object.reader, object.size, url, destination, progress)
+}
 
-    // Detect if the URL points to an archive
-    let archive_format = ArchiveFormat::detect(url);
+/// Fetches the files listed in a sha256sum file (`<sha256>  <name>` lines)
+/// from the directory of its url into the destination directory. Every file
+/// is verified against its checksum, the files already fetched are skipped so
+/// an interrupted fetch resumes where it stopped.
+pub fn fetch_checksummed<T: ProgressTracker>(
+    checksums: &str,
+    destination: &Path,
+    progress: &mut T,
+) -> Result<()> {
+    let checksums_url = Url::parse(checksums)
+        .with_context(|| format!("Failed to parse source as URL: {}", checksums))?;
+    let response = reqwest::blocking::get(checksums_url.as_str())
+        .with_context(|| format!("Failed to download from: {}", checksums_url))?;
+    if !response.status().is_success() {
+        anyhow::bail!("HTTP request failed with status: {}", response.status());
+    }
+    let files = parse_checksums(&response.text()?)?;
+
+    std::fs::create_dir_all(destination)
+        .with_context(|| format!("Failed to create directory: {}", destination.display()))?;
+    for (sha256, name) in files {
+        let path = destination.join(&name);
+        if path.exists() && file_sha256(&path)? == sha256 {
+            tracing::debug!(file = %name, "Already fetched");
+            continue;
+        }
 
-    // Create the parent directory if it doesn't exist
-    if let Some(parent) = destination.parent() {
-        std::fs::create_dir_all(parent)
-            .with_context(|| format!("Failed to create parent directory: {}", parent.display()))?;
+        let url = checksums_url
+            .join(&name)
+            .with_context(|| format!("Invalid file name: {}", name))?;
+        tracing::info!(url = %url, "Fetching");
+        let response = reqwest::blocking::get(url.as_str())
+            .with_context(|| format!("Failed to download from: {}", url))?;
+        if !response.status().is_success() {
+            anyhow::bail!("HTTP request failed with status: {}", response.status());
+        }
+        if let Some(total) = response.content_length() {
+            progress.set_total(total);
+        }
+
+        // written next to the destination and renamed once verified
+        let partial = destination.join(format!("{}.part", name));
+        let mut file = File::create(&partial)
+            .with_context(|| format!("Failed to create file: {}", partial.display()))?;
+        let mut reader = ProgressReader::new(response, progress);
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let read = reader.read(&mut buf).context("Failed to download")?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+            file.write_all(&buf[..read])
+                .context("Failed to write file")?;
+        }
+        reader.finish();
+
+        let actual = hex::encode(hasher.finalize());
+        if actual != sha256 {
+            let _ = std::fs::remove_file(&partial);
+            anyhow::bail!(
+                "Checksum mismatch for {}: expected {}, got {}",
+                name,
+                sha256,
+                actual
+            );
+        }
+        std::fs::rename(&partial, &path)
+            .with_context(|| format!("Failed to write file: {}", path.display()))?;
     }
+    Ok(())
+}
+
+/// Parses the `<sha256>  <name>` lines of a sha256sum file, the names must be
+/// plain file names
+fn parse_checksums(contents: &str) -> Result<Vec<(String, String)>> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (sha256, name) = line
+                .split_once(char::is_whitespace)
+                .with_context(|| format!("Invalid checksum line: {}", line))?;
+            // sha256sum marks the files hashed in binary mode with a `*`
+            let name = name.trim().trim_start_matches('*');
+            let valid_sha256 = sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit());
+            let valid_name = !name.is_empty() && !name.contains('/') && name != "..";
+            if !valid_sha256 || !valid_name {
+                anyhow::bail!("Invalid checksum line: {}", line);
+            }
+            Ok((sha256.to_lowercase(), name.to_string()))
+        })
+        .collect()
+}
+
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn fetch_http<T: ProgressTracker>(
+    url: &Url,
+    destination: &PathBuf,
+    progress: &mut T,
+) -> Result<()> {
+    tracing::info!(url = %url, "Fetching");
 
     // Download the file
     let response = reqwest::blocking::get(url.as_str())
@@ -130,17 +256,39 @@ fn fetch_http<T: ProgressTracker>(
         anyhow::bail!("HTTP request failed with status: {}", response.status());
     }
 
+    let total = response.content_length();
+    write_download(response, total, url, destination, progress)
+}
+
+/// Writes the downloaded object to the destination, archives are extracted
+/// into it
+fn write_download<R: Read, T: ProgressTracker>(
+    reader: R,
+    total: Option<u64>,
+    url: &Url,
+    destination: &PathBuf,
+    progress: &mut T,
+) -> Result<()> {
+    // Detect if the URL points to an archive
+    let archive_format = ArchiveFormat::detect(url);
+
+    // Create the parent directory if it doesn't exist
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create parent directory: {}", parent.display()))?;
+    }
+
     // Get the content length if available
-    if let Some(total) = response.content_length() {
+    if let Some(total) = total {
         progress.set_total(total);
     }
 
     // Create a progress reader wrapper
-    let mut progress_reader = ProgressReader::new(response, progress);
+    let mut progress_reader = ProgressReader::new(reader, progress);
 
     match archive_format {
         ArchiveFormat::TarGz => {
-            println!("Detected tar.gz archive, streaming decompression...");
+            tracing::info!("Detected tar.gz archive, streaming decompression");
             extract_tar_gz(&mut progress_reader, destination)?;
         }
         ArchiveFormat::None => {
@@ -205,6 +353,20 @@ mod tests {
     use super::*;
     use std::fs;
 
+    #[test]
+    fn test_parse_checksums() {
+        let sha256 = "ab".repeat(32);
+        let files = parse_checksums(&format!(
+            "{sha256}  mainnet-00000-5ec1ffb8.era1\n{sha256} *mainnet-00001-a5364e9a.era1\n"
+        ))
+        .unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].1, "mainnet-00001-a5364e9a.era1");
+
+        assert!(parse_checksums(&format!("{sha256}  ../etc/passwd")).is_err());
+        assert!(parse_checksums("abc  mainnet.era1").is_err());
+    }
+
     #[test]
     fn test_download_readme() {
         let source =
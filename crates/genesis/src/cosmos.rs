@@ -0,0 +1,138 @@
+use crate::GenesisFile;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Parameters of a Cosmos SDK devnet
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CosmosParams {
+    pub chain_id: String,
+    // unix time of the genesis, now if not set
+    #[serde(default)]
+    pub genesis_time: Option<u64>,
+    // staking and fee denom
+    pub denom: String,
+    pub validators: Vec<CosmosValidator>,
+    // bech32 address to balance in `denom`, as a decimal string
+    #[serde(default)]
+    pub prefunded_accounts: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CosmosValidator {
+    pub name: String,
+    // base64 ed25519 consensus public key (pub_key.value of priv_validator_key.json)
+    pub pub_key: String,
+    pub power: u64,
+}
+
+/// CometBFT genesis of the devnet with the validators, the balances of the
+/// prefunded accounts and the denom of the staking, mint and bank modules.
+/// The staking state of the validators is not generated, a Cosmos SDK chain
+/// adds their gentxs to it with `<daemon> genesis collect-gentxs`.
+#[derive(Debug, Clone)]
+pub struct CosmosGenesis {
+    pub genesis: serde_json::Value,
+}
+
+impl CosmosGenesis {
+    pub fn generate(params: &CosmosParams) -> eyre::Result<Self> {
+        if params.validators.is_empty() {
+            eyre::bail!("The genesis requires at least one validator");
+        }
+
+        let genesis_time = params.genesis_time.unwrap_or_else(crate::now);
+        let genesis_time =
+            humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(genesis_time));
+
+        let validators: Vec<_> = params
+            .validators
+            .iter()
+            .map(|validator| {
+                json!({
+                    "name": validator.name,
+                    "pub_key": {
+                        "type": "tendermint/PubKeyEd25519",
+                        "value": validator.pub_key,
+                    },
+                    "power": validator.power.to_string(),
+                })
+            })
+            .collect();
+
+        let coins = |amount: u128| json!([{ "denom": params.denom, "amount": amount.to_string() }]);
+        let mut balances = vec![];
+        let mut supply: u128 = 0;
+        for (address, amount) in &params.prefunded_accounts {
+            let amount = crate::parse_amount(amount)?;
+            supply = supply
+                .checked_add(amount)
+                .ok_or_else(|| eyre::eyre!("The supply of {} overflows", params.denom))?;
+            balances.push(json!({ "address": address, "coins": coins(amount) }));
+        }
+
+        let genesis = json!({
+            "genesis_time": genesis_time.to_string(),
+            "chain_id": params.chain_id,
+            "initial_height": "1",
+            "validators": validators,
+            "app_hash": "",
+            "app_state": {
+                "bank": {
+                    "balances": balances,
+                    "supply": if supply > 0 { coins(supply) } else { json!([]) },
+                },
+                "staking": {
+                    "params": { "bond_denom": params.denom },
+                },
+                "mint": {
+                    "params": { "mint_denom": params.denom },
+                },
+            },
+        });
+        Ok(Self { genesis })
+    }
+
+    pub fn files(&self) -> eyre::Result<Vec<GenesisFile>> {
+        Ok(vec![GenesisFile {
+            name: "genesis.json".to_string(),
+            contents: serde_json::to_string_pretty(&self.genesis)?,
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefunded_accounts_are_in_the_bank_supply() -> eyre::Result<()> {
+        let params: CosmosParams = serde_json::from_value(json!({
+            "chain_id": "devnet-1",
+            "genesis_time": 1700000000,
+            "denom": "stake",
+            "validators": [{
+                "name": "val-0",
+                "pub_key": "u8VIYUcBhlzYs6rT3DCh8+zMhdn8sJwRg2w6i7xQ0tI=",
+                "power": 10
+            }],
+            "prefunded_accounts": {
+                "cosmos1a": "100",
+                "cosmos1b": "50"
+            }
+        }))?;
+        let genesis = CosmosGenesis::generate(&params)?.genesis;
+
+        assert_eq!(genesis["genesis_time"], "2023-11-14T22:13:20Z");
+        assert_eq!(genesis["validators"][0]["power"], "10");
+        assert_eq!(genesis["app_state"]["bank"]["supply"][0]["amount"], "150");
+        assert_eq!(
+            genesis["app_state"]["staking"]["params"]["bond_denom"],
+            "stake"
+        );
+        Ok(())
+    }
+}
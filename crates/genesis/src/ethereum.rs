@@ -0,0 +1,349 @@
+use crate::GenesisFile;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+// epoch of the forks that are not scheduled
+const FAR_FUTURE_EPOCH: u64 = u64::MAX;
+
+const DEFAULT_DEPOSIT_CONTRACT: &str = "0x4242424242424242424242424242424242424242";
+
+/// Parameters of an Ethereum devnet, the execution and consensus genesis are
+/// generated from the same values so they agree on the fork schedule
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EthereumParams {
+    pub chain_id: u64,
+    // unix time of the genesis, now plus the delay if not set
+    #[serde(default)]
+    pub genesis_time: Option<u64>,
+    #[serde(default = "default_genesis_delay")]
+    pub genesis_delay: u64,
+    // number of validators active at genesis
+    pub validators: u64,
+    // address to balance in wei, as a decimal string
+    #[serde(default)]
+    pub prefunded_accounts: BTreeMap<String, String>,
+    #[serde(default)]
+    pub forks: Forks,
+    #[serde(default = "default_seconds_per_slot")]
+    pub seconds_per_slot: u64,
+    #[serde(default)]
+    pub preset: Preset,
+    #[serde(default = "default_genesis_fork_version")]
+    pub genesis_fork_version: String,
+    #[serde(default = "default_deposit_contract")]
+    pub deposit_contract_address: String,
+    #[serde(default = "default_gas_limit")]
+    pub gas_limit: u64,
+}
+
+fn default_genesis_delay() -> u64 {
+    60
+}
+
+fn default_seconds_per_slot() -> u64 {
+    12
+}
+
+fn default_genesis_fork_version() -> String {
+    "0x10000000".to_string()
+}
+
+fn default_deposit_contract() -> String {
+    DEFAULT_DEPOSIT_CONTRACT.to_string()
+}
+
+fn default_gas_limit() -> u64 {
+    30_000_000
+}
+
+/// Epoch of the consensus forks, unset forks are not scheduled
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct Forks {
+    pub altair: Option<u64>,
+    pub bellatrix: Option<u64>,
+    pub capella: Option<u64>,
+    pub deneb: Option<u64>,
+    pub electra: Option<u64>,
+}
+
+impl Default for Forks {
+    fn default() -> Self {
+        Self {
+            altair: Some(0),
+            bellatrix: Some(0),
+            capella: Some(0),
+            deneb: Some(0),
+            electra: None,
+        }
+    }
+}
+
+impl Forks {
+    fn epochs(&self) -> [(&'static str, Option<u64>); 5] {
+        [
+            ("altair", self.altair),
+            ("bellatrix", self.bellatrix),
+            ("capella", self.capella),
+            ("deneb", self.deneb),
+            ("electra", self.electra),
+        ]
+    }
+
+    /// Every fork requires the previous one at the same or an earlier epoch
+    fn validate(&self) -> eyre::Result<()> {
+        let epochs = self.epochs();
+        for window in epochs.windows(2) {
+            let [(prev_name, prev), (name, epoch)] = window else {
+                unreachable!()
+            };
+            match (prev, epoch) {
+                (None, Some(_)) => {
+                    eyre::bail!("Fork {} is scheduled but {} is not", name, prev_name)
+                }
+                (Some(prev), Some(epoch)) if epoch < prev => {
+                    eyre::bail!("Fork {} is scheduled before {}", name, prev_name)
+                }
+                _ => {}
+            }
+        }
+        if self.bellatrix != Some(0) {
+            eyre::bail!("The devnet genesis is post merge, bellatrix must be at epoch 0");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    #[default]
+    Mainnet,
+    Minimal,
+}
+
+impl Preset {
+    fn slots_per_epoch(self) -> u64 {
+        match self {
+            Preset::Mainnet => 32,
+            Preset::Minimal => 8,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Preset::Mainnet => "mainnet",
+            Preset::Minimal => "minimal",
+        }
+    }
+}
+
+/// Execution genesis (`genesis.json`) and consensus network config
+/// (`config.yaml`) of the devnet. The consensus genesis state (`genesis.ssz`)
+/// is not generated, it is built from them by the genesis tooling of the
+/// consensus clients.
+#[derive(Debug, Clone)]
+pub struct EthereumGenesis {
+    pub el_genesis: serde_json::Value,
+    pub cl_config: String,
+}
+
+impl EthereumGenesis {
+    pub fn generate(params: &EthereumParams) -> eyre::Result<Self> {
+        params.forks.validate()?;
+        let fork_version = parse_fork_version(&params.genesis_fork_version)?;
+        validate_address(&params.deposit_contract_address)?;
+
+        let genesis_time = params
+            .genesis_time
+            .unwrap_or_else(|| crate::now() + params.genesis_delay);
+        let epoch_duration = params.preset.slots_per_epoch() * params.seconds_per_slot;
+        let fork_time =
+            |epoch: Option<u64>| epoch.map(|epoch| genesis_time + epoch * epoch_duration);
+
+        let mut alloc = serde_json::Map::new();
+        for (address, balance) in &params.prefunded_accounts {
+            validate_address(address)?;
+            let balance = crate::parse_amount(balance)?;
+            alloc.insert(
+                address.to_lowercase(),
+                json!({ "balance": format!("{:#x}", balance) }),
+            );
+        }
+
+        let mut config = json!({
+            "chainId": params.chain_id,
+            "homesteadBlock": 0,
+            "eip150Block": 0,
+            "eip155Block": 0,
+            "eip158Block": 0,
+            "byzantiumBlock": 0,
+            "constantinopleBlock": 0,
+            "petersburgBlock": 0,
+            "istanbulBlock": 0,
+            "berlinBlock": 0,
+            "londonBlock": 0,
+            "mergeNetsplitBlock": 0,
+            "terminalTotalDifficulty": 0,
+            "terminalTotalDifficultyPassed": true,
+            "depositContractAddress": params.deposit_contract_address.to_lowercase(),
+        });
+        let mut blob_schedule = serde_json::Map::new();
+        for (key, time) in [
+            ("shanghaiTime", fork_time(params.forks.capella)),
+            ("cancunTime", fork_time(params.forks.deneb)),
+            ("pragueTime", fork_time(params.forks.electra)),
+        ] {
+            if let Some(time) = time {
+                config[key] = json!(time);
+            }
+        }
+        if params.forks.deneb.is_some() {
+            blob_schedule.insert(
+                "cancun".to_string(),
+                json!({ "target": 3, "max": 6, "baseFeeUpdateFraction": 3338477 }),
+            );
+        }
+        if params.forks.electra.is_some() {
+            blob_schedule.insert(
+                "prague".to_string(),
+                json!({ "target": 6, "max": 9, "baseFeeUpdateFraction": 5007716 }),
+            );
+        }
+        if !blob_schedule.is_empty() {
+            config["blobSchedule"] = blob_schedule.into();
+        }
+
+        let el_genesis = json!({
+            "config": config,
+            "alloc": alloc,
+            "coinbase": format!("0x{}", "0".repeat(40)),
+            "difficulty": "0x0",
+            "extraData": "0x",
+            "gasLimit": format!("{:#x}", params.gas_limit),
+            "nonce": "0x0",
+            "mixhash": format!("0x{}", "0".repeat(64)),
+            "parentHash": format!("0x{}", "0".repeat(64)),
+            "timestamp": format!("{:#x}", genesis_time),
+        });
+
+        let mut lines = vec![
+            format!("PRESET_BASE: '{}'", params.preset.name()),
+            format!("CONFIG_NAME: 'devnet-{}'", params.chain_id),
+            format!("MIN_GENESIS_ACTIVE_VALIDATOR_COUNT: {}", params.validators),
+            format!("MIN_GENESIS_TIME: {}", genesis_time),
+            "GENESIS_DELAY: 0".to_string(),
+            format!("GENESIS_FORK_VERSION: 0x{}", hex::encode(fork_version)),
+        ];
+        for (i, (name, epoch)) in params.forks.epochs().into_iter().enumerate() {
+            let mut version = fork_version;
+            version[0] = (i as u8 + 2) << 4;
+            lines.push(format!(
+                "{}_FORK_VERSION: 0x{}",
+                name.to_uppercase(),
+                hex::encode(version)
+            ));
+            lines.push(format!(
+                "{}_FORK_EPOCH: {}",
+                name.to_uppercase(),
+                epoch.unwrap_or(FAR_FUTURE_EPOCH)
+            ));
+        }
+        lines.extend([
+            "TERMINAL_TOTAL_DIFFICULTY: 0".to_string(),
+            format!("SECONDS_PER_SLOT: {}", params.seconds_per_slot),
+            format!("DEPOSIT_CHAIN_ID: {}", params.chain_id),
+            format!("DEPOSIT_NETWORK_ID: {}", params.chain_id),
+            format!(
+                "DEPOSIT_CONTRACT_ADDRESS: {}",
+                params.deposit_contract_address.to_lowercase()
+            ),
+        ]);
+
+        Ok(Self {
+            el_genesis,
+            cl_config: lines.join("\n") + "\n",
+        })
+    }
+
+    pub fn files(&self) -> eyre::Result<Vec<GenesisFile>> {
+        Ok(vec![
+            GenesisFile {
+                name: "genesis.json".to_string(),
+                contents: serde_json::to_string_pretty(&self.el_genesis)?,
+            },
+            GenesisFile {
+                name: "config.yaml".to_string(),
+                contents: self.cl_config.clone(),
+            },
+        ])
+    }
+}
+
+fn parse_fork_version(version: &str) -> eyre::Result<[u8; 4]> {
+    let bytes = hex::decode(version.trim_start_matches("0x"))
+        .map_err(|err| eyre::eyre!("Invalid fork version {}: {}", version, err))?;
+    bytes
+        .try_into()
+        .map_err(|_| eyre::eyre!("Invalid fork version {}: expected 4 bytes", version))
+}
+
+fn validate_address(address: &str) -> eyre::Result<()> {
+    let valid = address
+        .strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+    if !valid {
+        eyre::bail!("Invalid address {}", address);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fork_schedule_is_shared_by_el_and_cl() -> eyre::Result<()> {
+        let params: EthereumParams = serde_json::from_value(json!({
+            "chain_id": 1337,
+            "genesis_time": 1700000000,
+            "validators": 64,
+            "prefunded_accounts": {
+                "0x8943545177806ED17B9F23F0a21ee5948eCaa776": "1000000000000000000000"
+            },
+            "forks": { "electra": 2 }
+        }))?;
+        let genesis = EthereumGenesis::generate(&params)?;
+
+        let config = &genesis.el_genesis["config"];
+        assert_eq!(config["chainId"], 1337);
+        assert_eq!(config["cancunTime"], 1700000000);
+        // 2 epochs of 32 slots of 12 seconds
+        assert_eq!(config["pragueTime"], 1700000000 + 768);
+        assert_eq!(
+            genesis.el_genesis["alloc"]["0x8943545177806ed17b9f23f0a21ee5948ecaa776"]["balance"],
+            "0x3635c9adc5dea00000"
+        );
+
+        assert!(genesis.cl_config.contains("MIN_GENESIS_TIME: 1700000000\n"));
+        assert!(
+            genesis
+                .cl_config
+                .contains("ELECTRA_FORK_VERSION: 0x60000000\n")
+        );
+        assert!(genesis.cl_config.contains("ELECTRA_FORK_EPOCH: 2\n"));
+
+        let params = EthereumParams {
+            forks: Forks {
+                capella: None,
+                ..Default::default()
+            },
+            ..params
+        };
+        assert!(EthereumGenesis::generate(&params).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+//! Genesis files of devnets generated from high level parameters. The
+//! Ethereum genesis is generated by the `devnet` input of the generic-evm
+//! module of the catalog too.
+
+pub mod cosmos;
+pub mod ethereum;
+
+pub use cosmos::{CosmosGenesis, CosmosParams};
+pub use ethereum::{EthereumGenesis, EthereumParams};
+
+/// File generated for a genesis, `name` is relative to the output directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenesisFile {
+    pub name: String,
+    pub contents: String,
+}
+
+fn parse_amount(amount: &str) -> eyre::Result<u128> {
+    amount
+        .parse()
+        .map_err(|err| eyre::eyre!("Invalid amount {}: {}", amount, err))
+}
+
+/// Genesis time used when the parameters do not set one
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,28 @@
+//! Log output of the bbuilder, babel and fetcher binaries
+
+use tracing_subscriber::EnvFilter;
+pub use tracing_subscriber::filter::LevelFilter;
+
+/// Format of the log lines
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Filter of the log level, `RUST_LOG` takes precedence over it
+pub fn env_filter(level: LevelFilter) -> EnvFilter {
+    EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env_lossy()
+}
+
+/// Installs the global subscriber that writes the logs to stdout
+pub fn init(format: LogFormat, level: LevelFilter) {
+    let subscriber = tracing_subscriber::fmt().with_env_filter(env_filter(level));
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
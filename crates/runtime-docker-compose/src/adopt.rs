@@ -0,0 +1,404 @@
+//! Parsing of the compose projects that were not deployed by bbuilder into
+//! specs, so that they can be adopted as deployments.
+
+use serde::Deserialize;
+use spec::{Arg, Port, Protocol, Spec, Volume};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::registry::Image;
+
+/// Services of a compose project
+#[derive(Debug)]
+pub struct ComposeProject {
+    // top level `name` of the compose file
+    pub name: Option<String>,
+    pub services: BTreeMap<String, ComposeService>,
+}
+
+#[derive(Debug)]
+pub struct ComposeService {
+    pub spec: Spec,
+    // host port published for each port of the spec, by port name
+    pub host_ports: BTreeMap<String, u16>,
+    // `x-bbuilder` block of the services written by bbuilder
+    pub metadata: Option<ComposeMetadata>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ComposeMetadata {
+    pub deployment: String,
+    #[serde(default)]
+    pub module: String,
+    #[serde(default)]
+    pub chain: String,
+    pub pod: String,
+    pub spec: String,
+}
+
+#[derive(Deserialize)]
+struct ComposeFile {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    services: BTreeMap<String, Service>,
+    #[serde(default)]
+    volumes: BTreeMap<String, Option<TopLevelVolume>>,
+}
+
+#[derive(Deserialize, Default)]
+struct TopLevelVolume {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    external: bool,
+}
+
+#[derive(Deserialize)]
+struct Service {
+    image: Option<String>,
+    #[serde(default)]
+    command: Command,
+    #[serde(default)]
+    entrypoint: Command,
+    #[serde(default)]
+    environment: Environment,
+    #[serde(default)]
+    ports: Vec<serde_yaml::Value>,
+    #[serde(default)]
+    volumes: Vec<serde_yaml::Value>,
+    #[serde(default, rename = "x-bbuilder")]
+    x_bbuilder: Option<ComposeMetadata>,
+}
+
+// compose accepts both the shell form and the exec form
+#[derive(Deserialize, Default)]
+#[serde(untagged)]
+enum Command {
+    #[default]
+    None,
+    Shell(String),
+    Exec(Vec<String>),
+}
+
+impl Command {
+    /// Arguments of the command, the shell form is split the way compose
+    /// does with the quotes of the shell
+    fn into_vec(self) -> eyre::Result<Vec<String>> {
+        match self {
+            Command::None => Ok(vec![]),
+            Command::Shell(command) => shell_words::split(&command)
+                .map_err(|err| eyre::eyre!("Invalid command '{}': {}", command, err)),
+            Command::Exec(command) => Ok(command),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(untagged)]
+enum Environment {
+    #[default]
+    None,
+    List(Vec<String>),
+    Map(HashMap<String, serde_yaml::Value>),
+}
+
+impl Environment {
+    fn into_map(self) -> HashMap<String, String> {
+        match self {
+            Environment::None => HashMap::new(),
+            Environment::List(vars) => vars
+                .into_iter()
+                .filter_map(|var| {
+                    let (key, value) = var.split_once('=')?;
+                    Some((key.to_string(), value.to_string()))
+                })
+                .collect(),
+            Environment::Map(vars) => vars
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let value = match value {
+                        serde_yaml::Value::String(value) => value,
+                        serde_yaml::Value::Number(value) => value.to_string(),
+                        serde_yaml::Value::Bool(value) => value.to_string(),
+                        _ => return None,
+                    };
+                    Some((key, value))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Parses the services of a compose file. The relative host paths of the bind
+/// mounts are resolved against `compose_dir` so that the adopted specs keep
+/// using the same data.
+pub fn parse_compose(contents: &str, compose_dir: &Path) -> eyre::Result<ComposeProject> {
+    let file: ComposeFile = serde_yaml::from_str(contents)
+        .map_err(|err| eyre::eyre!("Invalid compose file: {}", err))?;
+
+    // compose names the volumes of a project `<project>_<volume>`, the project
+    // is named after the directory of the file unless the file names it
+    let project = file
+        .name
+        .clone()
+        .or_else(|| {
+            compose_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().to_lowercase())
+        })
+        .unwrap_or_default();
+
+    let mut services = BTreeMap::new();
+    for (name, service) in file.services {
+        let image = service
+            .image
+            .ok_or_else(|| eyre::eyre!("Service {} does not set an image", name))?;
+        let image = Image::parse(&image);
+
+        let mut spec = Spec::builder()
+            .image(image.repo())
+            .entrypoint(
+                service
+                    .entrypoint
+                    .into_vec()
+                    .map_err(|err| eyre::eyre!("Service {}: {}", name, err))?,
+            )
+            .envs(&service.environment.into_map());
+        spec = match image.reference.split_at(1) {
+            ("@", digest) => spec.digest(digest),
+            (_, tag) => spec.tag(tag),
+        };
+        let command = service
+            .command
+            .into_vec()
+            .map_err(|err| eyre::eyre!("Service {}: {}", name, err))?;
+        for arg in command {
+            spec = spec.arg(Arg::Value(arg));
+        }
+
+        let mut host_ports = BTreeMap::new();
+        for port in &service.ports {
+            let Some((host, container, protocol)) = parse_port(port) else {
+                eyre::bail!("Service {} has an invalid port: {:?}", name, port);
+            };
+            // the ports that are not published are reachable in the network
+            let Some((host_ip, host)) = host else {
+                continue;
+            };
+            let port_name = format!("{}-{}", protocol_name(protocol), container);
+            let port = match protocol {
+                Protocol::Tcp => Port::tcp(&port_name, container),
+                Protocol::Udp => Port::udp(&port_name, container),
+            };
+            // only the ports published on every address are public, the
+            // others (e.g. on 127.0.0.1) keep their address
+            let port = match host_ip {
+                Some(ip) if !ip.is_unspecified() => port.host_ip(ip),
+                _ => port.public(),
+            };
+            spec = spec.port(port);
+            host_ports.insert(port_name, host);
+        }
+
+        for volume in &service.volumes {
+            let Some((source, target)) = parse_volume(volume) else {
+                eyre::bail!("Service {} has an invalid volume: {:?}", name, volume);
+            };
+            let volume_name = target.trim_matches('/').replace('/', "-");
+            let volume = match source {
+                Some(source) if source.starts_with(['/', '.']) => {
+                    let host_path = compose_dir.join(source.trim_start_matches("./"));
+                    Volume::bind(volume_name, host_path.to_string_lossy(), target)
+                }
+                // the named volumes of the project are mounted as external
+                // volumes, so the adopted spec keeps their data
+                Some(source) => {
+                    let external = match file.volumes.get(&source) {
+                        Some(Some(TopLevelVolume {
+                            name: Some(name), ..
+                        })) => name.clone(),
+                        Some(Some(TopLevelVolume { external: true, .. })) => source.clone(),
+                        _ => format!("{}_{}", project, source),
+                    };
+                    Volume::external(source, external, target)
+                }
+                None => Volume::named(volume_name, target),
+            };
+            spec = spec.volume(volume);
+        }
+
+        services.insert(
+            name,
+            ComposeService {
+                spec: spec.build(),
+                host_ports,
+                metadata: service.x_bbuilder,
+            },
+        );
+    }
+
+    Ok(ComposeProject {
+        name: file.name,
+        services,
+    })
+}
+
+fn protocol_name(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Tcp => "tcp",
+        Protocol::Udp => "udp",
+    }
+}
+
+// host address and port a port is published on
+type Published = Option<(Option<IpAddr>, u16)>;
+
+/// Published address, container port and protocol of the short
+/// (`[ip:]host:container[/udp]`) and long syntax of a port
+fn parse_port(port: &serde_yaml::Value) -> Option<(Published, u16, Protocol)> {
+    let protocol = |protocol: Option<&str>| match protocol {
+        None | Some("tcp") => Some(Protocol::Tcp),
+        Some("udp") => Some(Protocol::Udp),
+        _ => None,
+    };
+
+    match port {
+        serde_yaml::Value::Number(port) => {
+            Some((None, port.as_u64()?.try_into().ok()?, Protocol::Tcp))
+        }
+        serde_yaml::Value::String(port) => {
+            let (mapping, proto) = match port.split_once('/') {
+                Some((mapping, proto)) => (mapping, Some(proto)),
+                None => (port.as_str(), None),
+            };
+            let parts: Vec<&str> = mapping.rsplitn(3, ':').collect();
+            let container = parts[0].parse().ok()?;
+            let host_ip = match parts.get(2) {
+                Some(ip) => Some(ip.trim_matches(['[', ']']).parse().ok()?),
+                None => None,
+            };
+            let host = match parts.get(1) {
+                Some(host) => Some((host_ip, host.parse().ok()?)),
+                None => None,
+            };
+            Some((host, container, protocol(proto)?))
+        }
+        serde_yaml::Value::Mapping(port) => {
+            let container = port.get("target")?.as_u64()?.try_into().ok()?;
+            let host_ip = match port.get("host_ip").and_then(serde_yaml::Value::as_str) {
+                Some(ip) => Some(ip.parse().ok()?),
+                None => None,
+            };
+            let host = match port.get("published") {
+                Some(serde_yaml::Value::Number(host)) => {
+                    Some((host_ip, host.as_u64()?.try_into().ok()?))
+                }
+                Some(serde_yaml::Value::String(host)) => Some((host_ip, host.parse().ok()?)),
+                _ => None,
+            };
+            let proto = port.get("protocol").and_then(serde_yaml::Value::as_str);
+            Some((host, container, protocol(proto)?))
+        }
+        _ => None,
+    }
+}
+
+/// Source and target of the short (`source:target[:mode]`) and long syntax of
+/// a volume, the anonymous volumes have no source
+fn parse_volume(volume: &serde_yaml::Value) -> Option<(Option<String>, String)> {
+    match volume {
+        serde_yaml::Value::String(volume) => {
+            let parts: Vec<&str> = volume.split(':').collect();
+            match parts.as_slice() {
+                [target] => Some((None, target.to_string())),
+                [source, target] | [source, target, _] => {
+                    Some((Some(source.to_string()), target.to_string()))
+                }
+                _ => None,
+            }
+        }
+        serde_yaml::Value::Mapping(volume) => {
+            let target = volume.get("target")?.as_str()?.to_string();
+            let source = volume
+                .get("source")
+                .and_then(serde_yaml::Value::as_str)
+                .map(String::from);
+            Some((source, target))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::VolumeKind;
+
+    #[test]
+    fn test_compose_services_are_parsed_into_specs() -> eyre::Result<()> {
+        let compose = r#"
+name: mainnet
+services:
+  reth:
+    image: ghcr.io/paradigmxyz/reth:v1.4.8
+    command: node --chain mainnet --datadir /data --log.file.filter "info,net=debug"
+    environment:
+      RUST_LOG: info
+    ports:
+      - "30303:30303"
+      - "30303:30303/udp"
+      - "127.0.0.1:18545:8545"
+      - "9001"
+    volumes:
+      - ./reth:/data
+      - reth-logs:/logs
+volumes:
+  reth-logs:
+"#;
+        let project = parse_compose(compose, Path::new("/srv/mainnet"))?;
+        assert_eq!(project.name.as_deref(), Some("mainnet"));
+
+        let reth = &project.services["reth"];
+        assert!(reth.metadata.is_none());
+        assert_eq!(reth.spec.image, "ghcr.io/paradigmxyz/reth");
+        assert_eq!(reth.spec.tag.as_deref(), Some("v1.4.8"));
+        assert_eq!(reth.spec.args.len(), 7);
+        assert!(matches!(
+            reth.spec.args.last(),
+            Some(Arg::Value(filter)) if filter == "info,net=debug"
+        ));
+        assert_eq!(reth.spec.env["RUST_LOG"], "info");
+        assert_eq!(reth.spec.ports.len(), 3);
+        assert_eq!(reth.host_ports["tcp-8545"], 18545);
+        assert_eq!(reth.host_ports["udp-30303"], 30303);
+        let rpc = reth
+            .spec
+            .ports
+            .iter()
+            .find(|port| port.port == 8545)
+            .unwrap();
+        assert!(!rpc.expose_publicly);
+        assert_eq!(rpc.host_ip, Some("127.0.0.1".parse()?));
+        assert!(
+            reth.spec
+                .ports
+                .iter()
+                .any(|port| port.port == 30303 && port.expose_publicly)
+        );
+        assert_eq!(
+            reth.spec.volumes["data"].kind,
+            VolumeKind::Bind {
+                host_path: "/srv/mainnet/reth".to_string()
+            }
+        );
+        assert_eq!(
+            reth.spec.volumes["reth-logs"].kind,
+            VolumeKind::External {
+                source: "mainnet_reth-logs".to_string()
+            }
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Number of exits within the window after which a container is considered
+/// to be crash looping and it is not restarted anymore
+#[derive(Debug, Clone, Copy)]
+pub struct CrashLoopPolicy {
+    pub max_restarts: usize,
+    pub window: Duration,
+}
+
+impl Default for CrashLoopPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            window: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Tracks the exits of every container reported by the docker events
+#[derive(Debug, Default)]
+pub(crate) struct CrashLoopDetector {
+    policy: CrashLoopPolicy,
+    exits: HashMap<String, VecDeque<Instant>>,
+}
+
+impl CrashLoopDetector {
+    pub(crate) fn new(policy: CrashLoopPolicy) -> Self {
+        Self {
+            policy,
+            exits: HashMap::new(),
+        }
+    }
+
+    /// Records an exit of the container, returns true once it exceeds the
+    /// restarts allowed by the policy
+    pub(crate) fn record_exit(&mut self, container_id: &str, now: Instant) -> bool {
+        let exits = self.exits.entry(container_id.to_string()).or_default();
+        exits.push_back(now);
+        while exits
+            .front()
+            .is_some_and(|exit| now.duration_since(*exit) > self.policy.window)
+        {
+            exits.pop_front();
+        }
+
+        if exits.len() > self.policy.max_restarts {
+            self.exits.remove(container_id);
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn forget(&mut self, container_id: &str) {
+        self.exits.remove(container_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crash_loop_is_detected_within_the_window() {
+        let mut detector = CrashLoopDetector::new(CrashLoopPolicy {
+            max_restarts: 2,
+            window: Duration::from_secs(60),
+        });
+        let start = Instant::now();
+
+        // exits spread out over more than the window are not a crash loop
+        assert!(!detector.record_exit("a", start));
+        assert!(!detector.record_exit("a", start + Duration::from_secs(50)));
+        assert!(!detector.record_exit("a", start + Duration::from_secs(120)));
+
+        assert!(!detector.record_exit("b", start));
+        assert!(!detector.record_exit("b", start + Duration::from_secs(1)));
+        assert!(detector.record_exit("b", start + Duration::from_secs(2)));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::registry::Image;
+use spec::HostPort;
+use std::collections::BTreeMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+// lowest port a user can bind without privileges when the kernel does not say
+const DEFAULT_UNPRIVILEGED_PORT_START: u16 = 1024;
+
+/// Container engine the compose runtime talks to, podman is reached through
+/// its docker compatible API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    #[default]
+    Docker,
+    // rootless when podman runs as the user instead of root, its containers
+    // run in a user namespace with the uids mapped to the subuids of the user
+    Podman {
+        rootless: bool,
+    },
+}
+
+impl Engine {
+    /// Podman of the current user, rootless unless bbuilder runs as root
+    pub fn podman() -> Self {
+        Engine::Podman {
+            rootless: euid() != Some(0),
+        }
+    }
+
+    /// Default API socket of the engine, none for docker which uses the
+    /// local defaults of its client
+    pub fn socket(&self) -> Option<String> {
+        match self {
+            Engine::Docker => None,
+            Engine::Podman { rootless: false } => {
+                Some("unix:///run/podman/podman.sock".to_string())
+            }
+            Engine::Podman { rootless: true } => {
+                let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
+                    .unwrap_or_else(|_| format!("/run/user/{}", euid().unwrap_or_default()));
+                Some(format!("unix://{}/podman/podman.sock", runtime_dir))
+            }
+        }
+    }
+
+    /// Command that runs the compose file on the engine
+    pub(crate) fn compose_command(&self) -> Vec<String> {
+        let program = match self {
+            Engine::Docker => "docker",
+            Engine::Podman { .. } => "podman",
+        };
+        vec![program.to_string(), "compose".to_string()]
+    }
+
+    pub fn is_rootless(&self) -> bool {
+        matches!(self, Engine::Podman { rootless: true })
+    }
+
+    /// Image reference written to the compose file, podman does not resolve
+    /// the short names without a prompt so they are qualified with docker hub
+    pub(crate) fn image(&self, image: &str) -> String {
+        match self {
+            Engine::Docker => image.to_string(),
+            Engine::Podman { .. } => Image::parse(image).qualified(),
+        }
+    }
+
+    /// Chowns the paths to their uid inside the user namespace of rootless
+    /// podman, the uids of the host they map to can not be set by the user
+    pub(crate) async fn chown(&self, podman: &str, paths: &[(PathBuf, u32)]) -> eyre::Result<()> {
+        let mut by_uid: BTreeMap<u32, Vec<&PathBuf>> = BTreeMap::new();
+        for (path, uid) in paths {
+            by_uid.entry(*uid).or_default().push(path);
+        }
+        for (uid, paths) in by_uid {
+            unshare_chown(podman, &[format!("{}:{}", uid, uid)], &paths).await?;
+        }
+        Ok(())
+    }
+
+    /// Gives the paths chowned by a previous deploy back to the user, the
+    /// root of the user namespace of rootless podman, so that bbuilder can
+    /// read, rewrite and remove them again
+    pub(crate) async fn chown_back(&self, podman: &str, paths: &[PathBuf]) -> eyre::Result<()> {
+        let paths: Vec<&PathBuf> = paths.iter().filter(|path| path.exists()).collect();
+        if !self.is_rootless() || paths.is_empty() {
+            return Ok(());
+        }
+        unshare_chown(podman, &["-R".to_string(), "0:0".to_string()], &paths).await
+    }
+
+    /// Fails listing the host ports a rootless engine can not publish
+    pub(crate) fn check_host_ports(&self, host_ports: &[HostPort]) -> eyre::Result<()> {
+        if !self.is_rootless() {
+            return Ok(());
+        }
+        let start = unprivileged_port_start();
+        let privileged: Vec<String> = host_ports
+            .iter()
+            .filter(|host_port| host_port.host_port < start)
+            .map(|host_port| {
+                format!(
+                    "{}-{} {} ({})",
+                    host_port.pod, host_port.spec, host_port.name, host_port.host_port
+                )
+            })
+            .collect();
+        if !privileged.is_empty() {
+            eyre::bail!(
+                "Rootless podman can not publish the host ports below {}: {} \
+                 (lower net.ipv4.ip_unprivileged_port_start to allow them)",
+                start,
+                privileged.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Runs `chown` with the args on the paths in the user namespace of podman
+async fn unshare_chown(podman: &str, args: &[String], paths: &[&PathBuf]) -> eyre::Result<()> {
+    let output = tokio::process::Command::new(podman)
+        .args(["unshare", "chown"])
+        .args(args)
+        .args(paths)
+        .output()
+        .await
+        .map_err(|e| eyre::eyre!("Failed to run podman unshare: {}", e))?;
+    if !output.status.success() {
+        eyre::bail!(
+            "podman unshare chown {} exited with {}: {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Effective uid of the process, the owner of its `/proc/self`
+fn euid() -> Option<u32> {
+    std::fs::metadata("/proc/self")
+        .ok()
+        .map(|metadata| metadata.uid())
+}
+
+fn unprivileged_port_start() -> u16 {
+    let path = Path::new("/proc/sys/net/ipv4/ip_unprivileged_port_start");
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|start| start.trim().parse().ok())
+        .unwrap_or(DEFAULT_UNPRIVILEGED_PORT_START)
+}
@@ -0,0 +1,109 @@
+use serde::Serialize;
+use spec::Manifest;
+use std::path::Path;
+
+// path where grafana expects the dashboards to be mounted
+const GRAFANA_DASHBOARDS_PATH: &str = "/var/lib/grafana/dashboards";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DashboardProvisioning {
+    api_version: u32,
+    providers: Vec<DashboardProvider>,
+}
+
+#[derive(Serialize)]
+struct DashboardProvider {
+    name: String,
+    folder: String,
+    #[serde(rename = "type")]
+    provider_type: String,
+    options: DashboardProviderOptions,
+}
+
+#[derive(Serialize)]
+struct DashboardProviderOptions {
+    path: String,
+}
+
+/// Writes the dashboards of the manifest as a grafana provisioning directory:
+///
+/// grafana/
+///   dashboards/<name>.json
+///   provisioning/dashboards/bbuilder.yaml
+pub(crate) fn write_dashboards(dir: &Path, manifest: &Manifest) -> eyre::Result<()> {
+    if manifest.dashboards.is_empty() {
+        return Ok(());
+    }
+
+    let grafana_dir = dir.join("grafana");
+    let dashboards_dir = grafana_dir.join("dashboards");
+    let provisioning_dir = grafana_dir.join("provisioning").join("dashboards");
+    std::fs::create_dir_all(&dashboards_dir)?;
+    std::fs::create_dir_all(&provisioning_dir)?;
+
+    // The same client can be deployed in several pods, the dashboard
+    // is written only once since it is keyed by name.
+    for dashboard in &manifest.dashboards {
+        std::fs::write(
+            dashboards_dir.join(format!("{}.json", dashboard.name)),
+            &dashboard.content,
+        )?;
+    }
+
+    let provisioning = DashboardProvisioning {
+        api_version: 1,
+        providers: vec![DashboardProvider {
+            name: "bbuilder".to_string(),
+            folder: manifest.name.clone(),
+            provider_type: "file".to_string(),
+            options: DashboardProviderOptions {
+                path: GRAFANA_DASHBOARDS_PATH.to_string(),
+            },
+        }],
+    };
+    std::fs::write(
+        provisioning_dir.join("bbuilder.yaml"),
+        serde_yaml::to_string(&provisioning)?,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::Dashboard;
+
+    #[test]
+    fn test_dashboards_are_provisioned_in_the_folder_of_the_deployment() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut manifest = Manifest::new("eth".to_string());
+        write_dashboards(dir.path(), &manifest)?;
+        assert!(!dir.path().join("grafana").exists());
+
+        let dashboard = |name: &str| Dashboard {
+            name: name.to_string(),
+            content: format!("{{\"title\": \"{}\"}}", name),
+        };
+        manifest.add_dashboards([dashboard("reth"), dashboard("lighthouse")]);
+        write_dashboards(dir.path(), &manifest)?;
+
+        let grafana = dir.path().join("grafana");
+        assert_eq!(
+            std::fs::read_to_string(grafana.join("dashboards/reth.json"))?,
+            "{\"title\": \"reth\"}"
+        );
+        assert!(grafana.join("dashboards/lighthouse.json").exists());
+
+        let provisioning: serde_yaml::Value = serde_yaml::from_str(&std::fs::read_to_string(
+            grafana.join("provisioning/dashboards/bbuilder.yaml"),
+        )?)?;
+        assert_eq!(provisioning["apiVersion"], 1);
+        let provider = &provisioning["providers"][0];
+        assert_eq!(provider["folder"], "eth");
+        assert_eq!(provider["type"], "file");
+        assert_eq!(provider["options"]["path"], GRAFANA_DASHBOARDS_PATH);
+        Ok(())
+    }
+}
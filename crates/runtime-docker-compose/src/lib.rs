@@ -1,2 +1,11 @@
+mod adopt;
+mod crash_loop;
+mod engine;
+mod grafana;
+mod prometheus;
+mod registry;
 mod runtime;
-pub use runtime::DockerRuntime;
+pub use adopt::{ComposeMetadata, ComposeProject, ComposeService, parse_compose};
+pub use engine::Engine;
+pub use registry::{RegistryAuth, RegistryConfig};
+pub use runtime::{CRASH_LOOP, DockerRuntime, PAUSED};
@@ -0,0 +1,91 @@
+use serde::Serialize;
+use spec::Manifest;
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+pub(crate) struct PrometheusConfig {
+    scrape_configs: Vec<ScrapeConfig>,
+}
+
+#[derive(Serialize)]
+struct ScrapeConfig {
+    job_name: String,
+    static_configs: Vec<StaticConfig>,
+}
+
+#[derive(Serialize)]
+struct StaticConfig {
+    targets: Vec<String>,
+    labels: BTreeMap<String, String>,
+}
+
+/// Builds a prometheus scrape config with one job per spec that declares a
+/// metrics port. Targets point to the published host ports so that a
+/// prometheus instance running outside of the deployment can scrape them.
+pub(crate) fn scrape_config(manifest: &Manifest) -> Option<PrometheusConfig> {
+    let scrape_configs: Vec<ScrapeConfig> = manifest
+        .metrics_ports()
+        .into_iter()
+        .map(|(pod_name, spec_name, port)| {
+            let labels = BTreeMap::from([
+                ("deployment".to_string(), manifest.name.clone()),
+                ("pod".to_string(), pod_name.to_string()),
+                ("spec".to_string(), spec_name.to_string()),
+            ]);
+
+            ScrapeConfig {
+                job_name: format!("{}-{}-{}", manifest.name, pod_name, spec_name),
+                static_configs: vec![StaticConfig {
+                    targets: vec![format!("localhost:{}", port)],
+                    labels,
+                }],
+            }
+        })
+        .collect();
+
+    if scrape_configs.is_empty() {
+        return None;
+    }
+    Some(PrometheusConfig { scrape_configs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::{Pod, Spec};
+
+    #[test]
+    fn test_scrape_config_only_includes_metrics_ports() {
+        let mut manifest = Manifest::new("eth".to_string());
+
+        let node = Spec::builder()
+            .image("test-image")
+            .arg(spec::port!("http", 8545))
+            .arg(spec::port!(spec::METRICS_PORT, 9001));
+        let sidecar = Spec::builder()
+            .image("sidecar")
+            .arg(spec::port!("http", 3000));
+
+        let pod = Pod::default()
+            .with_spec("node", node)
+            .with_spec("sidecar", sidecar);
+        manifest.add_spec("el".to_string(), pod);
+
+        let config = scrape_config(&manifest).unwrap();
+        assert_eq!(config.scrape_configs.len(), 1);
+
+        let job = &config.scrape_configs[0];
+        assert_eq!(job.job_name, "eth-el-node");
+        assert_eq!(job.static_configs[0].targets, ["localhost:9001"]);
+        assert_eq!(job.static_configs[0].labels["pod"], "el");
+    }
+
+    #[test]
+    fn test_scrape_config_without_metrics_ports() {
+        let mut manifest = Manifest::new("eth".to_string());
+        let pod = Pod::default().with_spec("node", Spec::builder().image("test-image"));
+        manifest.add_spec("el".to_string(), pod);
+
+        assert!(scrape_config(&manifest).is_none());
+    }
+}
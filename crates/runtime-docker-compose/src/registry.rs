@@ -0,0 +1,156 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// registry of the images without a registry host (e.g. `sigp/lighthouse`)
+const DOCKER_HUB: &str = "docker.io";
+
+/// Registries the images are pulled from
+#[derive(Debug, Clone, Default)]
+pub struct RegistryConfig {
+    // mirrors of docker hub (e.g. https://mirror.gcr.io), tried in order
+    // before docker hub itself
+    pub mirrors: Vec<String>,
+    // credentials of the private registries by host (e.g. ghcr.io)
+    pub auths: HashMap<String, RegistryAuth>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegistryAuth {
+    pub username: String,
+    // secret with the password or token, resolved by the secret providers
+    pub password_secret: String,
+}
+
+impl RegistryConfig {
+    /// Whether the image is not pulled by compose itself, either because it
+    /// goes through a mirror or because its registry needs credentials
+    pub(crate) fn pulls(&self, image: &Image) -> bool {
+        (image.is_docker_hub() && !self.mirrors.is_empty()) || self.auths.contains_key(&image.host)
+    }
+
+    /// References the image is pulled from in order, the docker hub images go
+    /// through the mirrors first. The images pinned by digest are pulled from
+    /// their registry since a mirrored digest can not be tagged locally.
+    pub(crate) fn pull_refs(&self, image: &Image) -> Vec<String> {
+        let mut refs = vec![];
+        if image.is_docker_hub() && !image.reference.starts_with('@') {
+            for mirror in &self.mirrors {
+                let host = mirror
+                    .trim_start_matches("https://")
+                    .trim_start_matches("http://")
+                    .trim_end_matches('/');
+                refs.push(format!("{}/{}{}", host, image.path, image.reference));
+            }
+        }
+        refs.push(image.to_string());
+        refs
+    }
+}
+
+/// Image reference split into its registry, repository path and tag or digest
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Image {
+    pub host: String,
+    pub path: String,
+    // `:<tag>` or `@<digest>`
+    pub reference: String,
+}
+
+impl Image {
+    pub(crate) fn parse(image: &str) -> Self {
+        let (name, reference) = match image.find('@') {
+            Some(at) => (&image[..at], image[at..].to_string()),
+            None => match image.rfind(':') {
+                Some(colon) if !image[colon..].contains('/') => {
+                    (&image[..colon], image[colon..].to_string())
+                }
+                _ => (image, ":latest".to_string()),
+            },
+        };
+
+        // the first component is a registry when it looks like a host
+        let (host, path) = match name.split_once('/') {
+            Some((host, path))
+                if host.contains('.') || host.contains(':') || host == "localhost" =>
+            {
+                (host.to_string(), path.to_string())
+            }
+            Some(_) => (DOCKER_HUB.to_string(), name.to_string()),
+            None => (DOCKER_HUB.to_string(), format!("library/{}", name)),
+        };
+        Self {
+            host,
+            path,
+            reference,
+        }
+    }
+
+    pub(crate) fn is_docker_hub(&self) -> bool {
+        self.host == DOCKER_HUB
+    }
+
+    /// Repository and tag the pulled image is tagged as, so that compose
+    /// finds it under the reference of the spec
+    pub(crate) fn repo(&self) -> String {
+        if self.is_docker_hub() {
+            self.path.trim_start_matches("library/").to_string()
+        } else {
+            format!("{}/{}", self.host, self.path)
+        }
+    }
+
+    /// Fully qualified reference, with the registry host and the `library/`
+    /// path of the official images
+    pub(crate) fn qualified(&self) -> String {
+        format!("{}/{}{}", self.host, self.path, self.reference)
+    }
+}
+
+impl std::fmt::Display for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.repo(), self.reference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_docker_hub_images_go_through_the_mirrors() {
+        let config = RegistryConfig {
+            mirrors: vec!["https://mirror.gcr.io".to_string()],
+            auths: HashMap::from([(
+                "ghcr.io".to_string(),
+                RegistryAuth {
+                    username: "bbuilder".to_string(),
+                    password_secret: "ghcr_token".to_string(),
+                },
+            )]),
+        };
+
+        let image = Image::parse("sigp/lighthouse:v8.0.0");
+        assert!(config.pulls(&image));
+        assert_eq!(
+            config.pull_refs(&image),
+            vec![
+                "mirror.gcr.io/sigp/lighthouse:v8.0.0",
+                "sigp/lighthouse:v8.0.0"
+            ]
+        );
+        assert_eq!(
+            config.pull_refs(&Image::parse("alpine")),
+            vec!["mirror.gcr.io/library/alpine:latest", "alpine:latest"]
+        );
+
+        let image = Image::parse("ghcr.io/paradigmxyz/reth:v1.4.8");
+        assert!(config.pulls(&image));
+        assert_eq!(
+            config.pull_refs(&image),
+            vec!["ghcr.io/paradigmxyz/reth:v1.4.8"]
+        );
+
+        assert!(!config.pulls(&Image::parse("localhost:5000/reth:v1")));
+    }
+}
@@ -1,35 +1,80 @@
 use bollard::Docker;
-use bollard::query_parameters::EventsOptionsBuilder;
+use bollard::auth::DockerCredentials;
+use bollard::exec::StartExecResults;
+use bollard::models::{ContainerCpuStats, ContainerStatsResponse, ExecConfig};
+use bollard::query_parameters::{
+    CreateImageOptionsBuilder, EventsOptionsBuilder, ListContainersOptionsBuilder,
+    RemoveContainerOptionsBuilder, RestartContainerOptions, StartContainerOptions,
+    StatsOptionsBuilder, StopContainerOptionsBuilder, TagImageOptionsBuilder,
+};
 use futures_util::stream::StreamExt;
 use serde::ser::SerializeMap;
 use serde::{Serialize, Serializer};
-use std::collections::HashMap;
-
-use runtime_trait::Runtime;
-use spec::{File, Manifest};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+use base64::Engine as _;
+use base64::prelude::BASE64_STANDARD;
+
+use crate::crash_loop::{CrashLoopDetector, CrashLoopPolicy};
+use crate::engine::Engine;
+use crate::registry::{Image, RegistryConfig};
+use crate::{grafana, prometheus};
+use runtime_trait::{Event, Health, IdentityKey, ResourceStats, Runtime, SpecStatus, Subscription};
+use spec::{
+    Arch, DATA_VOLUME, DefaultSecretProvider, EnvSecretProvider, File, HostPort, Manifest,
+    Metadata, RefTarget, SecretProvider, VolumeKind,
+};
 
 #[derive(Serialize)]
 struct DockerComposeSpec {
-    services: HashMap<String, DockerComposeService>,
+    services: BTreeMap<String, DockerComposeService>,
+
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    networks: BTreeMap<String, Option<Network>>,
+
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    volumes: BTreeMap<String, Option<NamedVolume>>,
+
+    // artifact dirs written by the conversion, the rest of `_config` is
+    // pruned once the services are up
+    #[serde(skip)]
+    artifact_dirs: HashSet<PathBuf>,
 
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    networks: HashMap<String, Option<Network>>,
+    // paths created for a uid of the user namespace of rootless podman, they
+    // are chowned in it before the services start
+    #[serde(skip)]
+    owned_paths: Vec<(PathBuf, u32)>,
 }
 
 #[derive(Serialize, Default)]
 struct DockerComposeService {
     image: String,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pull_policy: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+
     command: Vec<String>,
 
     #[serde(skip_serializing_if = "Vec::is_empty")]
     entrypoint: Vec<String>,
 
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    labels: HashMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    labels: BTreeMap<String, String>,
 
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    environment: HashMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    environment: BTreeMap<String, String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    env_file: Vec<String>,
 
     #[serde(skip_serializing_if = "Vec::is_empty")]
     ports: Vec<Port>,
@@ -40,22 +85,214 @@ struct DockerComposeService {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     volumes: Vec<String>,
 
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tmpfs: Vec<String>,
+
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     #[serde(serialize_with = "serialize_depends_on")]
-    depends_on: HashMap<String, Option<DependsOnCondition>>,
+    depends_on: BTreeMap<String, Option<DependsOnCondition>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    healthcheck: Option<Healthcheck>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    restart: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    working_dir: Option<String>,
+
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    read_only: bool,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cap_add: Vec<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cap_drop: Vec<String>,
+
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    privileged: bool,
+
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    ulimits: BTreeMap<String, Ulimit>,
+
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    sysctls: BTreeMap<String, String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    oom_score_adj: Option<i32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mem_limit: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memswap_limit: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shm_size: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_signal: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_grace_period: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logging: Option<Logging>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    devices: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deploy: Option<Deploy>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    post_start: Vec<LifecycleHook>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pre_stop: Vec<LifecycleHook>,
+
+    #[serde(rename = "x-bbuilder", skip_serializing_if = "Option::is_none")]
+    x_bbuilder: Option<ServiceMetadata>,
+}
+
+/// Command that compose runs inside the container of the service
+#[derive(Serialize)]
+struct LifecycleHook {
+    command: Vec<String>,
+}
+
+impl LifecycleHook {
+    fn new(commands: Vec<String>) -> Vec<Self> {
+        commands
+            .into_iter()
+            .map(|command| LifecycleHook {
+                command: escape_interpolation(vec!["sh".to_string(), "-c".to_string(), command]),
+            })
+            .collect()
+    }
+}
+
+/// Extension block of the services that identifies the deployment they
+/// belong to, for the tools (and later bbuilder versions) that adopt them
+#[derive(Serialize)]
+struct ServiceMetadata {
+    deployment: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    module: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    chain: String,
+    pod: String,
+    spec: String,
+    manifest_hash: String,
+    version: String,
+}
+
+#[derive(Serialize)]
+struct Deploy {
+    resources: Resources,
+}
+
+#[derive(Serialize)]
+struct Resources {
+    reservations: Reservations,
+}
+
+#[derive(Serialize)]
+struct Reservations {
+    devices: Vec<DeviceRequest>,
+}
+
+#[derive(Serialize)]
+struct DeviceRequest {
+    driver: String,
+    // a number of devices or `all`
+    count: serde_yaml::Value,
+    capabilities: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Logging {
+    driver: String,
+
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    options: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct Ulimit {
+    soft: u64,
+    hard: u64,
+}
+
+#[derive(Serialize)]
+struct Healthcheck {
+    test: Vec<String>,
+    interval: String,
+    timeout: String,
+    retries: u32,
+    start_period: String,
+}
+
+impl Healthcheck {
+    fn new(healthcheck: &spec::HealthCheck, spec: &spec::Spec) -> eyre::Result<Self> {
+        let test = match &healthcheck.probe {
+            spec::Probe::Command(command) => std::iter::once("CMD".to_string())
+                .chain(command.clone())
+                .collect(),
+            spec::Probe::Http { port, path } => {
+                let port = spec
+                    .port(port)
+                    .ok_or_else(|| eyre::eyre!("Health check port {} does not exist", port))?;
+                // the images ship curl or the wget of busybox, if any
+                let url = format!("http://localhost:{}{}", port, path);
+                vec![
+                    "CMD-SHELL".to_string(),
+                    format!(
+                        "curl -fsS {url} > /dev/null || wget -q -O /dev/null {url} || exit 1",
+                        url = url
+                    ),
+                ]
+            }
+        };
+
+        Ok(Self {
+            test,
+            interval: format!("{}s", healthcheck.interval),
+            timeout: format!("{}s", healthcheck.timeout),
+            retries: healthcheck.retries,
+            start_period: format!("{}s", healthcheck.start_period),
+        })
+    }
 }
 
 #[derive(Serialize, Default)]
 struct Network {}
 
-#[derive(Serialize)]
+#[derive(Serialize, Default)]
+struct NamedVolume {
+    // created outside of the project, compose neither creates nor removes it
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    external: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+// the variants are named after the compose conditions
+#[allow(clippy::enum_variant_names)]
+#[derive(Serialize, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 enum DependsOnCondition {
     ServiceCompletedSuccessfully,
+    ServiceHealthy,
+    ServiceStarted,
 }
 
 fn serialize_depends_on<S>(
-    map: &HashMap<String, Option<DependsOnCondition>>,
+    map: &BTreeMap<String, Option<DependsOnCondition>>,
     serializer: S,
 ) -> Result<S::Ok, S::Error>
 where
@@ -83,8 +320,11 @@ where
 }
 
 struct Port {
+    // address of the host, every address if not set
+    host_ip: Option<std::net::IpAddr>,
     host: u16,
     container: u16,
+    protocol: spec::Protocol,
 }
 
 impl Serialize for Port {
@@ -93,21 +333,291 @@ impl Serialize for Port {
         S: Serializer,
     {
         // Docker Compose ports format: "host:container" or extended format
-        let port_mapping = format!("{}:{}", self.host, self.container);
+        let mut port_mapping = format!("{}:{}", self.host, self.container);
+        match self.host_ip {
+            Some(std::net::IpAddr::V6(ip)) => port_mapping = format!("[{}]:{}", ip, port_mapping),
+            Some(ip) => port_mapping = format!("{}:{}", ip, port_mapping),
+            None => {}
+        }
+        if self.protocol == spec::Protocol::Udp {
+            port_mapping.push_str("/udp");
+        }
 
         // For simple format, just serialize as string
         port_mapping.serialize(serializer)
     }
 }
 
+// labels that identify the deployment and pod of a container
+const DEPLOYMENT_LABEL: &str = "bbuilder.deployment";
+const POD_LABEL: &str = "bbuilder.pod";
+const SPEC_LABEL: &str = "bbuilder.spec";
+const CHAIN_LABEL: &str = "bbuilder.chain";
+// set on the init containers, they are not part of the status of the deployment
+const INIT_LABEL: &str = "bbuilder.init";
+// comma separated identity key files of the spec, see `Runtime::rotate_keys`
+const IDENTITY_KEYS_LABEL: &str = "bbuilder.identity-keys";
+
+// hex chars of the content hash in the path of the artifact dirs
+const ARTIFACT_HASH_LEN: usize = 16;
+
+// secrets mounted by the specs and chowned to their uid
+const SECRETS_DIR: &str = "_secrets";
+// generated secrets of the deployment, kept out of the chowned ones
+const GENERATED_SECRETS_DIR: &str = "_generated";
+
+// events buffered per subscriber before the slow ones start missing events
+const EVENTS_CAPACITY: usize = 256;
+
+/// Values of the manifest shared by the conversion of all its specs
+struct ConvertContext {
+    deployment: String,
+    module: String,
+    chain: String,
+    manifest_hash: String,
+    compose_dir: std::path::PathBuf,
+    // absolute path of the `_config` dir with the artifacts of the specs
+    config_dir: PathBuf,
+    refs: HashMap<(String, String), RefTarget>,
+    host_ports: Vec<HostPort>,
+    // artifact dirs written by the conversion
+    artifact_dirs: Mutex<HashSet<PathBuf>>,
+    // paths to chown in the user namespace of rootless podman
+    owned_paths: Mutex<Vec<(PathBuf, u32)>>,
+    // warns once that the paths can not be chowned by an unprivileged bbuilder
+    chown_denied: std::sync::Once,
+    engine: Engine,
+}
+
+impl ConvertContext {
+    /// Gives the host path to the uid of the spec. Rootless podman maps the
+    /// uids of its containers, the path is chowned in its user namespace
+    /// before the services start instead.
+    fn chown(&self, path: &Path, uid: Option<u32>) -> std::io::Result<()> {
+        match uid {
+            Some(uid) if self.engine.is_rootless() => {
+                self.owned_paths
+                    .lock()
+                    .unwrap()
+                    .push((path.to_path_buf(), uid));
+                Ok(())
+            }
+            Some(uid) => match std::os::unix::fs::chown(path, Some(uid), Some(uid)) {
+                // only root gives files away, the spec runs with the paths of
+                // the user instead of failing the deploy
+                Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+                    self.chown_denied.call_once(|| {
+                        tracing::warn!(
+                            path = %path.display(),
+                            uid,
+                            "bbuilder is not root and can not chown the paths of the spec, \
+                             it may not be able to write them (run bbuilder as root or use \
+                             rootless podman)"
+                        );
+                    });
+                    Ok(())
+                }
+                result => result,
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Bind mount of the host path
+    fn bind(&self, host_path: &Path, target: &str, read_only: bool) -> String {
+        let mapping = format!("{}:{}", host_path.display(), target);
+        if read_only {
+            format!("{}:ro", mapping)
+        } else {
+            mapping
+        }
+    }
+
+    /// Host port the port is published on, the allocated one if the preferred port was in use
+    fn host_port(
+        &self,
+        pod: &str,
+        spec: &str,
+        name: &str,
+        protocol: spec::Protocol,
+        port: u16,
+    ) -> u16 {
+        self.host_ports
+            .iter()
+            .find(|host_port| {
+                host_port.pod == pod
+                    && host_port.spec == spec
+                    && host_port.name == name
+                    && host_port.protocol == protocol
+            })
+            .map(|host_port| host_port.host_port)
+            .unwrap_or(port)
+    }
+
+    /// Dir of an artifact of the spec, `_config/<pod>-<spec>/<content hash>`. Specs
+    /// with artifacts of the same name do not collide and a changed artifact is
+    /// written to a new dir.
+    fn artifact_dir(&self, pod: &str, spec: &str, content: &[u8]) -> eyre::Result<PathBuf> {
+        let hash = hex::encode(Sha256::digest(content));
+        let dir = self
+            .config_dir
+            .join(format!("{}-{}", pod, spec))
+            .join(&hash[..ARTIFACT_HASH_LEN]);
+        std::fs::create_dir_all(&dir)?;
+        self.artifact_dirs.lock().unwrap().insert(dir.clone());
+        Ok(dir)
+    }
+}
+
+/// Keeps the first generated value of the secret in `_generated` of the
+/// deployment, out of the secrets chowned to the uids of the specs. The
+/// deployments of previous versions only have the mounted secret.
+fn keep_generated_secret(compose_dir: &Path, name: &str, secret: String) -> eyre::Result<String> {
+    let generated_dir = compose_dir.join(GENERATED_SECRETS_DIR);
+    let path = generated_dir.join(name);
+    if path.exists() {
+        return Ok(std::fs::read_to_string(&path)?);
+    }
+    let mounted = compose_dir.join(SECRETS_DIR).join(name);
+    let secret = if mounted.exists() {
+        std::fs::read_to_string(&mounted)?
+    } else {
+        secret
+    };
+    std::fs::create_dir_all(&generated_dir)?;
+    std::fs::write(&path, &secret)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(secret)
+}
+
+/// Removes the files of `_config` that the last conversion did not write, the
+/// artifacts of specs and contents that are no longer in the manifest. The
+/// artifacts mounted by a container (e.g. one compose did not recreate) are
+/// kept until the container is gone.
+fn prune_artifacts(
+    config_dir: &Path,
+    artifact_dirs: &HashSet<PathBuf>,
+    mounted: &[PathBuf],
+) -> eyre::Result<()> {
+    for entry in std::fs::read_dir(config_dir)? {
+        let spec_dir = entry?.path();
+        if !spec_dir.is_dir() {
+            std::fs::remove_file(&spec_dir)?;
+            continue;
+        }
+        for entry in std::fs::read_dir(&spec_dir)? {
+            let path = entry?.path();
+            if artifact_dirs.contains(&path) || mounted.iter().any(|m| m.starts_with(&path)) {
+                continue;
+            }
+            tracing::debug!(path = %path.display(), "pruning stale artifact");
+            if path.is_dir() {
+                std::fs::remove_dir_all(&path)?;
+            } else {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        if std::fs::read_dir(&spec_dir)?.next().is_none() {
+            std::fs::remove_dir(&spec_dir)?;
+        }
+    }
+    Ok(())
+}
+
 pub struct DockerRuntime {
     dir_path: String,
+    docker_host: Option<String>,
+    events: broadcast::Sender<Event>,
+    // queried in order until one of them has the secret
+    secret_providers: Vec<Box<dyn SecretProvider>>,
+    // deployments whose specs are being stopped by pause, their exits are not crashes
+    pausing: Arc<Mutex<HashSet<String>>>,
+    registry: RegistryConfig,
+    engine: Engine,
+    // runs the compose file instead of the compose command of the engine
+    compose: Option<Vec<String>>,
+    // program of the `podman unshare` commands of rootless podman
+    podman: String,
+    // runs the babel sidecars, whose image is built locally
+    babel: bool,
+}
+
+// state and event action reported for the containers stopped after crash looping
+pub const CRASH_LOOP: &str = "crash_loop";
+
+// state reported for the stopped specs of a paused deployment
+pub const PAUSED: &str = "paused";
+
+// marker in the folder of a deployment while it is paused, it outlives the
+// runtime so a restarted supervisor still ignores the stopped containers
+const PAUSED_FILE: &str = "paused";
+
+fn is_paused(dir_path: &str, deployment: &str) -> bool {
+    Path::new(dir_path)
+        .join(deployment)
+        .join(PAUSED_FILE)
+        .exists()
+}
+
+// folder of a deployment with a marker per container stopped after crash
+// looping, like the paused marker it outlives the runtime so the containers
+// are still reported as crash looping after a restart
+const CRASH_LOOP_DIR: &str = "crash_loop";
+
+fn crash_loop_marker(dir_path: &str, deployment: &str, container_id: &str) -> PathBuf {
+    Path::new(dir_path)
+        .join(deployment)
+        .join(CRASH_LOOP_DIR)
+        .join(container_id)
+}
+
+fn mark_crash_looping(dir_path: &str, deployment: &str, container_id: &str) -> eyre::Result<()> {
+    let marker = crash_loop_marker(dir_path, deployment, container_id);
+    if let Some(folder) = marker.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    std::fs::write(marker, "")?;
+    Ok(())
+}
+
+fn is_crash_looping(dir_path: &str, deployment: &str, container_id: &str) -> bool {
+    crash_loop_marker(dir_path, deployment, container_id).exists()
+}
+
+fn clear_crash_looping(dir_path: &str, deployment: &str, container_id: &str) {
+    let _ = std::fs::remove_file(crash_loop_marker(dir_path, deployment, container_id));
+}
+
+// removes the paused marker of the deployment if it has one
+fn clear_paused(dir_path: &str, deployment: &str) -> eyre::Result<()> {
+    match std::fs::remove_file(Path::new(dir_path).join(deployment).join(PAUSED_FILE)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
 }
 
 impl DockerRuntime {
     pub fn new(dir_path: String) -> Self {
+        Self::with_docker_host(dir_path, None)
+    }
+
+    /// Creates the runtime against the given docker daemon (unix://, tcp:// or
+    /// http:// address) instead of the local default one
+    pub fn with_docker_host(dir_path: String, docker_host: Option<String>) -> Self {
+        let listener_host = docker_host.clone();
+        let listener_dir = dir_path.clone();
+        let (events_tx, _) = broadcast::channel(EVENTS_CAPACITY);
+        let sender = events_tx.clone();
+        let pausing = Arc::new(Mutex::new(HashSet::new()));
+        let listener_pausing = pausing.clone();
         tokio::spawn(async move {
-            let docker = Docker::connect_with_local_defaults().unwrap();
+            let docker = match connect_docker(listener_host.as_deref()) {
+                Ok(docker) => docker,
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to connect to docker, not listening for events");
+                    return;
+                }
+            };
 
             // Filter for container events only
             let filters = HashMap::from([
@@ -117,282 +627,1600 @@ impl DockerRuntime {
             let options = EventsOptionsBuilder::new().filters(&filters).build();
 
             let mut events = docker.events(Some(options));
-            println!("Listening for container events...");
+            let mut detector = CrashLoopDetector::new(CrashLoopPolicy::default());
+            tracing::info!("Listening for container events");
 
             while let Some(event_result) = events.next().await {
                 match event_result {
                     Ok(event) => {
-                        println!("Event: {:?}", event.action);
-                        if let Some(actor) = event.actor {
-                            println!("  Container ID: {:?}", actor.id);
-                            if let Some(attrs) = actor.attributes {
-                                if let Some(name) = attrs.get("name") {
-                                    println!("  Container Name: {}", name);
+                        let actor = event.actor.unwrap_or_default();
+                        let attributes = actor.attributes.unwrap_or_default();
+
+                        // the container labels are part of the event attributes
+                        let Some(deployment) = attributes.get(DEPLOYMENT_LABEL) else {
+                            continue;
+                        };
+
+                        tracing::info!(
+                            action = ?event.action,
+                            container_id = ?actor.id,
+                            container_name = ?attributes.get("name"),
+                            deployment = %deployment,
+                            "Container event"
+                        );
+
+                        let action = event.action.unwrap_or_default();
+                        let mut runtime_event = Event {
+                            deployment: deployment.clone(),
+                            pod: attributes.get(POD_LABEL).cloned().unwrap_or_default(),
+                            spec: attributes.get(SPEC_LABEL).cloned().unwrap_or_default(),
+                            action: action.clone(),
+                            container_id: actor.id.clone(),
+                        };
+                        // sending only fails when there are no subscribers
+                        let _ = sender.send(runtime_event.clone());
+
+                        let Some(container_id) = actor.id else {
+                            continue;
+                        };
+                        match action.as_str() {
+                            // the containers of a paused deployment are stopped on purpose
+                            "die"
+                                if is_paused(&listener_dir, deployment)
+                                    || listener_pausing.lock().unwrap().contains(deployment) => {}
+                            "die" if detector.record_exit(&container_id, Instant::now()) => {
+                                tracing::warn!(
+                                    container_id = %container_id,
+                                    deployment = %deployment,
+                                    "Container is crash looping, stopping it"
+                                );
+                                if let Err(e) =
+                                    mark_crash_looping(&listener_dir, deployment, &container_id)
+                                {
+                                    tracing::error!(error = %e, "Failed to mark crash looping container");
+                                }
+
+                                // a stopped container is not restarted by its restart policy
+                                let options = StopContainerOptionsBuilder::new().build();
+                                if let Err(e) =
+                                    docker.stop_container(&container_id, Some(options)).await
+                                {
+                                    tracing::error!(error = %e, "Failed to stop crash looping container");
                                 }
+
+                                runtime_event.action = CRASH_LOOP.to_string();
+                                let _ = sender.send(runtime_event);
                             }
+                            "destroy" => {
+                                detector.forget(&container_id);
+                                clear_crash_looping(&listener_dir, deployment, &container_id);
+                            }
+                            _ => {}
                         }
-                        println!();
                     }
-                    Err(e) => eprintln!("Error: {}", e),
+                    Err(e) => tracing::error!(error = %e, "Failed to receive container event"),
                 }
             }
         });
 
-        Self { dir_path }
+        Self {
+            dir_path,
+            docker_host,
+            events: events_tx,
+            secret_providers: vec![Box::new(EnvSecretProvider), Box::new(DefaultSecretProvider)],
+            pausing,
+            registry: RegistryConfig::default(),
+            engine: Engine::Docker,
+            compose: None,
+            podman: "podman".to_string(),
+            babel: false,
+        }
+    }
+
+    /// Adapts the compose file to the engine behind the docker host, e.g.
+    /// rootless podman
+    pub fn with_engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Pulls the images through the mirrors and with the credentials of the
+    /// registry config instead of leaving the pulls to compose
+    pub fn with_registry(mut self, registry: RegistryConfig) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Runs the compose file with the command (e.g. `docker-compose` for the
+    /// standalone compose) instead of the compose command of the engine
+    pub fn with_compose_command(mut self, command: Vec<String>) -> Self {
+        self.compose = Some(command);
+        self
     }
 
+    /// Runs the `podman unshare` commands of rootless podman with the program
+    /// instead of the `podman` of the PATH
+    pub fn with_podman_command(mut self, program: impl Into<String>) -> Self {
+        self.podman = program.into();
+        self
+    }
+
+    /// Runs the babel sidecars of the nodes. Their image is built locally
+    /// (`just build babel`) so they are skipped unless enabled and the
+    /// dependent pods wait for the node containers to start instead.
+    pub fn with_babel(mut self, enabled: bool) -> Self {
+        self.babel = enabled;
+        self
+    }
+
+    /// Replaces the providers used to resolve the secret args
+    pub fn with_secret_providers(mut self, providers: Vec<Box<dyn SecretProvider>>) -> Self {
+        self.secret_providers = providers;
+        self
+    }
+
+    /// Value of the secret, a generated one is only generated once for the
+    /// deployment of `compose_dir` and kept afterwards
+    fn resolve_secret(&self, name: &str, compose_dir: Option<&Path>) -> eyre::Result<String> {
+        for provider in &self.secret_providers {
+            if let Some(secret) = provider.secret(name)? {
+                if let Some(compose_dir) = compose_dir.filter(|_| provider.generated()) {
+                    return keep_generated_secret(compose_dir, name, secret);
+                }
+                return Ok(secret);
+            }
+        }
+        Err(eyre::eyre!(
+            "Secret {} not found (set {})",
+            name,
+            EnvSecretProvider::var_name(name)
+        ))
+    }
+
+    #[tracing::instrument(skip_all, fields(manifest = %manifest.name))]
     fn convert_to_docker_compose_spec(
         &self,
-        manifest: Manifest,
+        mut manifest: Manifest,
     ) -> eyre::Result<DockerComposeSpec> {
-        let mut services = HashMap::new();
+        if !self.babel {
+            for pod in manifest.pods.values_mut() {
+                pod.specs.retain(|_, spec| spec.image != "babel");
+            }
+        }
+
+        let mut services = BTreeMap::new();
         let compose_dir = std::path::Path::new(&self.dir_path).join(&manifest.name);
+        let config_dir = compose_dir.join("_config");
+        std::fs::create_dir_all(&config_dir)?;
+        // First pass: resolve the refs between specs before converting them
+        let ctx = ConvertContext {
+            deployment: manifest.name.clone(),
+            module: manifest.module.clone(),
+            chain: manifest.chain.clone(),
+            manifest_hash: manifest.content_hash()?,
+            config_dir: config_dir.canonicalize()?,
+            compose_dir,
+            refs: manifest.resolve_refs()?,
+            host_ports: manifest.host_ports(),
+            artifact_dirs: Mutex::new(HashSet::new()),
+            owned_paths: Mutex::new(Vec::new()),
+            chown_denied: std::sync::Once::new(),
+            engine: self.engine,
+        };
 
-        // First pass: collect all ports from all pods/specs
-        let mut port_registry: HashMap<String, HashMap<String, u16>> = HashMap::new();
-        for (_, pod) in &manifest.pods {
+        // The specs of a pod wait for the specs of the pods it depends on, to
+        // be healthy if they have a health check or started otherwise. The
+        // babel of a node, when enabled, is healthy once the node serves its
+        // rpc, so the dependent pods wait for the readiness of the node.
+        manifest.startup_order()?;
+        let mut pod_services: HashMap<String, Vec<(String, DependsOnCondition)>> = HashMap::new();
+        for (pod_name, pod) in &manifest.pods {
             for (spec_name, spec) in &pod.specs {
-                let service_name = spec_name.clone();
-                let mut service_ports = HashMap::new();
+                let condition = if spec.healthcheck.is_some() {
+                    DependsOnCondition::ServiceHealthy
+                } else {
+                    DependsOnCondition::ServiceStarted
+                };
+                pod_services
+                    .entry(pod_name.clone())
+                    .or_default()
+                    .push((format!("{}-{}", pod_name, spec_name), condition));
+            }
+        }
 
-                for arg in &spec.args {
-                    if let spec::Arg::Port { name, preferred } = arg {
-                        service_ports.insert(name.clone(), *preferred);
+        // Named volumes are scoped to the pod that declares them
+        let mut volumes = BTreeMap::new();
+        for (pod_name, pod) in &manifest.pods {
+            let specs = pod
+                .specs
+                .values()
+                .chain(pod.init_specs.iter().map(|init| &init.spec));
+            for volume in specs.flat_map(|spec| spec.volumes.values()) {
+                match &volume.kind {
+                    VolumeKind::Named => {
+                        volumes.insert(format!("{}-{}", pod_name, volume.name), None);
                     }
+                    VolumeKind::External { source } => {
+                        let external = NamedVolume {
+                            external: true,
+                            name: Some(source.clone()),
+                        };
+                        volumes.insert(format!("{}-{}", pod_name, volume.name), Some(external));
+                    }
+                    _ => {}
                 }
-
-                port_registry.insert(service_name, service_ports);
             }
         }
 
         for (pod_name, pod) in manifest.pods {
-            for (spec_name, spec) in pod.specs {
-                if spec.image == "babel" {
-                    // TODO: Babel skipped for now until the image is published
-                    continue;
+            // Init specs prepare the data of the pod so they mount the volumes
+            // of its specs besides their own, keyed by the spec that declares
+            // them since every spec has its own data dir. The host paths are
+            // rendered for that spec and a target path declared by several
+            // specs is mounted from the first one.
+            let mut pod_volumes: Vec<(String, spec::Volume)> = vec![];
+            for (spec_name, spec) in &pod.specs {
+                let metadata = Metadata {
+                    deployment: &ctx.deployment,
+                    pod: &pod_name,
+                    spec: spec_name,
+                    chain: &ctx.chain,
+                };
+                for volume in spec.volumes.values() {
+                    let mut volume = volume.clone();
+                    if let VolumeKind::Bind { host_path } = &mut volume.kind {
+                        *host_path = metadata.render(host_path);
+                    }
+                    pod_volumes.push((format!("{}-{}", spec_name, volume.name), volume));
                 }
+            }
 
-                let image = format!(
-                    "{}:{}",
-                    spec.image,
-                    spec.tag.unwrap_or("latest".to_string())
-                );
-
-                let mut ports = vec![];
-                let mut command = vec![];
-                let mut volumes = vec![];
-                let mut init_services = HashMap::new();
-                let mut artifacts_to_process = vec![];
-                let mut environment = HashMap::new();
-
-                // Track volume mounts by target directory to reuse volumes
-                // let mut volume_mounts: HashMap<String, String> = HashMap::new();
-                let data_path = compose_dir.join("data");
-                std::fs::create_dir_all(&data_path)?;
-                let absolute_data_path = data_path.canonicalize()?;
-
-                {
-                    let volume_mapping = format!("{}:{}", absolute_data_path.display(), "/data");
-                    volumes.push(volume_mapping);
+            // Init specs run in order and the specs of the pod wait for all of them.
+            // They write the data of the pod as the uid of its specs unless they
+            // set their own.
+            let pod_uid = pod.specs.values().find_map(|spec| spec.uid);
+            let mut init_services = BTreeMap::new();
+            let mut previous_init: Option<String> = None;
+            for mut init in pod.init_specs {
+                init.spec.uid = init.spec.uid.or(pod_uid);
+                for (key, volume) in &pod_volumes {
+                    let mounted = init
+                        .spec
+                        .volumes
+                        .values()
+                        .any(|mounted| mounted.target == volume.target);
+                    if !mounted {
+                        init.spec.volumes.insert(key.clone(), volume.clone());
+                    }
                 }
-
-                for (key, value) in spec.env {
-                    environment.insert(key, value);
+                let mut service = self.convert_spec(&ctx, &pod_name, &init.name, init.spec)?;
+                service
+                    .labels
+                    .insert(INIT_LABEL.to_string(), "true".to_string());
+                if let Some(previous) = previous_init.take() {
+                    service.depends_on.insert(
+                        previous,
+                        Some(DependsOnCondition::ServiceCompletedSuccessfully),
+                    );
                 }
 
-                for arg in spec.args {
-                    let cleaned_arg = match arg {
-                        spec::Arg::Value(value) => Ok(Some(value)),
-                        spec::Arg::Dir { path, .. } => Ok(Some(path)),
-                        spec::Arg::Port { preferred, .. } => {
-                            ports.push(Port {
-                                host: preferred,
-                                container: preferred,
-                            });
-                            Ok(Some(format!("{}", preferred)))
-                        }
-                        spec::Arg::File(file) => {
-                            artifacts_to_process.push(spec::Artifacts::File(file));
-                            Ok(None)
-                        }
-                        spec::Arg::Ref { name, port } => {
-                            if let Some(service_ports) = port_registry.get(&name) {
-                                if let Some(port_num) = service_ports.get(&port) {
-                                    Ok(Some(format!("http://{}:{}", name, port_num)))
-                                } else {
-                                    Err(eyre::eyre!("Ref port {} does not exists", port))
-                                }
-                            } else {
-                                Err(eyre::eyre!("Ref name {} does not exists", name))
-                            }
-                        }
-                    }?;
-                    if let Some(cleaned_arg) = cleaned_arg {
-                        command.push(cleaned_arg);
-                    }
-                }
+                let service_name = format!("{}-init-{}", pod_name, init.name);
+                init_services.insert(
+                    service_name.clone(),
+                    Some(DependsOnCondition::ServiceCompletedSuccessfully),
+                );
+                previous_init = Some(service_name.clone());
+                services.insert(service_name, service);
+            }
 
-                // Add artifacts from spec.artifacts
-                artifacts_to_process.extend(spec.artifacts);
-
-                let config_path = compose_dir.join("_config");
-                std::fs::create_dir_all(&config_path)?;
-                let absolute_config_path = config_path.canonicalize()?;
-
-                // Process all artifacts after args have been hydrated
-                for artifact in artifacts_to_process {
-                    match artifact {
-                        spec::Artifacts::File(File {
-                            name,
-                            target_path,
-                            content,
-                        }) => {
-                            // Check if the file is a URL
-                            if content.starts_with("https://") {
-                                // For URLs, create an init container to download the file
-                                let init_service_name =
-                                    format!("{}-{}-init-{}", pod_name, spec_name, name);
-
-                                // Resolve the target path within the mounted volume
-                                // target_path might be something like "/data/heimdall/genesis.json"
-                                // We need to strip the volume mount prefix and get the path relative to /data
-                                let container_target = std::path::Path::new(&target_path);
-                                let volume_mount = std::path::Path::new("/data");
-
-                                let relative_target = container_target
-                                    .strip_prefix(volume_mount)
-                                    .unwrap_or(container_target);
-
-                                // The path inside the container after mounting absolute_data_path to /data
-                                let download_path = format!("/data/{}", relative_target.display());
-
-                                // Create init container service
-                                let init_service = DockerComposeService {
-                                    image: "curlimages/curl:latest".to_string(),
-                                    command: vec![
-                                        "sh".to_string(),
-                                        "-c".to_string(),
-                                        format!(
-                                            "mkdir -p $(dirname {}) && curl -L -o {} {}",
-                                            download_path, download_path, content
-                                        ),
-                                    ],
-
-                                    volumes: vec![format!(
-                                        "{}:{}",
-                                        absolute_data_path.display(),
-                                        "/data"
-                                    )],
-                                    ..Default::default()
-                                };
-
-                                services.insert(init_service_name.clone(), init_service);
-                                init_services.insert(
-                                    init_service_name,
-                                    Some(DependsOnCondition::ServiceCompletedSuccessfully),
-                                );
-                            } else {
-                                let target_host_path = absolute_config_path.join(name);
-                                if let Some(parent) = target_host_path.parent() {
-                                    std::fs::create_dir_all(parent)?;
-                                }
-                                std::fs::write(&target_host_path, content)?;
+            for (spec_name, spec) in pod.specs {
+                // the pre start commands run to completion in a helper service
+                // of the spec, after the init specs of the pod
+                let pre_start = spec
+                    .pre_start_spec()
+                    .map(|pre_start| self.convert_spec(&ctx, &pod_name, &spec_name, pre_start))
+                    .transpose()?;
+                let mut service = self.convert_spec(&ctx, &pod_name, &spec_name, spec)?;
+                service.depends_on.extend(init_services.keys().map(|name| {
+                    (
+                        name.clone(),
+                        Some(DependsOnCondition::ServiceCompletedSuccessfully),
+                    )
+                }));
+                if let Some(mut pre_start) = pre_start {
+                    pre_start
+                        .labels
+                        .insert(INIT_LABEL.to_string(), "true".to_string());
+                    pre_start.depends_on.extend(init_services.clone());
+                    let pre_start_name = format!("{}-{}-pre-start", pod_name, spec_name);
+                    service.depends_on.insert(
+                        pre_start_name.clone(),
+                        Some(DependsOnCondition::ServiceCompletedSuccessfully),
+                    );
+                    services.insert(pre_start_name, pre_start);
+                }
 
-                                volumes.push(format!(
-                                    "{}:{}",
-                                    target_host_path.display(),
-                                    target_path
-                                ));
-                            }
-                        }
+                for dependency in manifest.dependencies.get(&pod_name).into_iter().flatten() {
+                    for (service_name, condition) in
+                        pod_services.get(dependency).into_iter().flatten()
+                    {
+                        service
+                            .depends_on
+                            .insert(service_name.clone(), Some(*condition));
                     }
                 }
 
-                let mut labels = spec.labels;
-                labels.insert("bbuilder".to_string(), "true".to_string());
-
-                let service = DockerComposeService {
-                    command,
-                    entrypoint: spec.entrypoint,
-                    environment,
-                    image,
-                    labels,
-                    ports,
-                    volumes,
-                    networks: vec!["test".to_string()],
-                    depends_on: init_services,
-                };
+                // the supervisor stops the containers that crash loop
+                service.restart = Some("unless-stopped".to_string());
 
                 let service_name = format!("{}-{}", pod_name, spec_name);
-                services.insert(service_name.to_string(), service);
+                services.insert(service_name, service);
             }
         }
 
-        let mut networks = HashMap::new();
+        let mut networks = BTreeMap::new();
         networks.insert("test".to_string(), None);
 
-        Ok(DockerComposeSpec { services, networks })
+        Ok(DockerComposeSpec {
+            services,
+            networks,
+            volumes,
+            artifact_dirs: ctx.artifact_dirs.into_inner().unwrap(),
+            owned_paths: ctx.owned_paths.into_inner().unwrap(),
+        })
     }
-}
-
-#[async_trait::async_trait]
-impl Runtime for DockerRuntime {
-    async fn run(&self, manifest: Manifest) -> eyre::Result<()> {
-        let name = manifest.name.clone();
 
-        // Create the parent folder path
-        let parent_folder = std::path::Path::new(&self.dir_path).join(&name);
-        std::fs::create_dir_all(&parent_folder)?;
+    /// Converts a spec of the pod into its compose service
+    fn convert_spec(
+        &self,
+        ctx: &ConvertContext,
+        pod_name: &str,
+        spec_name: &str,
+        spec: spec::Spec,
+    ) -> eyre::Result<DockerComposeService> {
+        let healthcheck = spec
+            .healthcheck
+            .as_ref()
+            .map(|healthcheck| Healthcheck::new(healthcheck, &spec))
+            .transpose()?;
+
+        let image = ctx.engine.image(&spec.image_ref());
+        let pull_policy = spec.pull_policy.map(|policy| {
+            match policy {
+                spec::PullPolicy::Always => "always",
+                spec::PullPolicy::IfNotPresent => "missing",
+                spec::PullPolicy::Never => "never",
+            }
+            .to_string()
+        });
 
-        let docker_compose_spec = self.convert_to_docker_compose_spec(manifest)?;
+        let mut ports = vec![];
+        let mut command = vec![];
+        let mut volumes = vec![];
+        let mut tmpfs = vec![];
+        let mut artifacts_to_process = vec![];
+        let mut secrets = vec![];
+        let mut environment = BTreeMap::new();
+
+        let metadata = Metadata {
+            deployment: &ctx.deployment,
+            pod: pod_name,
+            spec: spec_name,
+            chain: &ctx.chain,
+        };
 
-        // Write the compose file in the parent folder
-        let compose_file_path = parent_folder.join("docker-compose.yaml");
-        std::fs::write(
-            compose_file_path.clone(),
-            serde_yaml::to_string(&docker_compose_spec)?,
-        )?;
+        let owner_uid = spec.uid;
+
+        // nvidia gpus are reserved through the container toolkit, the amd ones
+        // are mapped as rocm devices
+        let mut devices = spec.devices.clone();
+        let mut deploy = None;
+        match &spec.gpu {
+            Some(spec::Gpu {
+                vendor: spec::GpuVendor::Nvidia,
+                count,
+            }) => {
+                deploy = Some(Deploy {
+                    resources: Resources {
+                        reservations: Reservations {
+                            devices: vec![DeviceRequest {
+                                driver: "nvidia".to_string(),
+                                count: match count {
+                                    Some(count) => (*count).into(),
+                                    None => "all".into(),
+                                },
+                                capabilities: vec!["gpu".to_string()],
+                            }],
+                        },
+                    },
+                })
+            }
+            Some(spec::Gpu {
+                vendor: spec::GpuVendor::Amd,
+                ..
+            }) => devices.extend(["/dev/kfd".to_string(), "/dev/dri".to_string()]),
+            None => {}
+        }
 
-        /*
-        // Run docker-compose up in detached mode
-        Command::new("docker-compose")
-            .arg("-f")
-            .arg(&compose_file_path)
-            .arg("up")
-            .arg("-d")
-            .status()?;
-        */
+        let logging = spec.logging.as_ref().map(|logging| {
+            let mut options = BTreeMap::new();
+            if let Some(max_size) = &logging.max_size {
+                options.insert("max-size".to_string(), max_size.clone());
+            }
+            if let Some(max_file) = logging.max_file {
+                options.insert("max-file".to_string(), max_file.to_string());
+            }
+            if let Some(tag) = &logging.tag {
+                options.insert("tag".to_string(), metadata.render(tag));
+            }
+            Logging {
+                driver: logging.driver.clone(),
+                options,
+            }
+        });
 
-        Ok(())
-    }
-}
+        let mut declared_volumes: Vec<&spec::Volume> = spec.volumes.values().collect();
+        declared_volumes.sort_by(|a, b| a.name.cmp(&b.name));
+        for volume in declared_volumes {
+            match &volume.kind {
+                VolumeKind::Named | VolumeKind::External { .. } => {
+                    let mut mount = format!("{}-{}:{}", pod_name, volume.name, volume.target);
+                    if volume.read_only {
+                        mount.push_str(":ro");
+                    }
+                    volumes.push(mount);
+                }
+                VolumeKind::Bind { host_path } => {
+                    let host_path = metadata.render(host_path);
+                    let host_path = std::path::Path::new(&host_path);
+                    let host_path = if host_path.is_absolute() {
+                        // the data dirs placed on other disks are created for the
+                        // spec too, the other host paths must exist already
+                        if volume.name == DATA_VOLUME && !host_path.exists() {
+                            std::fs::create_dir_all(host_path)?;
+                            ctx.chown(host_path, owner_uid)?;
+                        }
+                        host_path.to_path_buf()
+                    } else {
+                        // host dirs under the deployment are created for the
+                        // spec, only chowned once since they hold its data
+                        let path = ctx.compose_dir.join(host_path);
+                        let created = !path.exists();
+                        std::fs::create_dir_all(&path)?;
+                        let path = path.canonicalize()?;
+                        if created {
+                            ctx.chown(&path, owner_uid)?;
+                        }
+                        path
+                    };
+                    volumes.push(ctx.bind(&host_path, &volume.target, volume.read_only));
+                }
+                VolumeKind::Tmpfs { size } => match size {
+                    Some(size) => tmpfs.push(format!("{}:size={}", volume.target, size)),
+                    None => tmpfs.push(volume.target.clone()),
+                },
+            }
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use spec::{Artifacts, File, Manifest, Pod, Spec};
+        // Declared ports are only reachable inside the compose network
+        // unless they are exposed publicly or bound to an address of the host
+        for port in spec.ports.iter().filter(|port| port.is_published()) {
+            ports.push(Port {
+                host_ip: port.host_ip,
+                host: ctx.host_port(pod_name, spec_name, &port.name, port.protocol, port.port),
+                container: port.port,
+                protocol: port.protocol,
+            });
+        }
 
-    #[tokio::test]
-    async fn test_artifact_files_are_mounted_in_volumes() -> eyre::Result<()> {
-        let temp_dir = std::env::temp_dir().join("test-runtime");
-        let _ = std::fs::remove_dir_all(&temp_dir);
-        std::fs::create_dir_all(&temp_dir).unwrap();
-        let runtime = DockerRuntime::new(temp_dir.to_str().unwrap().to_string());
+        for (key, value) in spec.env {
+            environment.insert(key, metadata.render(&value));
+        }
 
-        let mut manifest = Manifest::new("test-manifest".to_string());
+        // ports of the spec referenced by its files as `{{port:<name>}}`
+        let spec_ports: HashMap<String, u16> = spec
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                spec::Arg::Port { name, preferred } => Some((name.clone(), *preferred)),
+                _ => None,
+            })
+            .chain(spec.ports.iter().map(|port| (port.name.clone(), port.port)))
+            .collect();
+
+        for arg in spec.args {
+            let cleaned_arg = match arg {
+                spec::Arg::Value(value) => Ok(Some(value)),
+                spec::Arg::Dir { path, .. } => Ok(Some(path)),
+                spec::Arg::Port { name, preferred } => {
+                    ports.push(Port {
+                        host_ip: None,
+                        host: ctx.host_port(
+                            pod_name,
+                            spec_name,
+                            &name,
+                            spec::Protocol::Tcp,
+                            preferred,
+                        ),
+                        container: preferred,
+                        protocol: spec::Protocol::Tcp,
+                    });
+                    Ok(Some(format!("{}", preferred)))
+                }
+                spec::Arg::File(file) => {
+                    artifacts_to_process.push(spec::Artifacts::File(file));
+                    Ok(None)
+                }
+                spec::Arg::Secret { name } => {
+                    let path = spec::secret_path(&name);
+                    if !secrets.contains(&name) {
+                        secrets.push(name);
+                    }
+                    Ok(Some(path))
+                }
+                spec::Arg::Ref { name, port } => ctx
+                    .refs
+                    .get(&(name.clone(), port.clone()))
+                    .map(|target| Some(target.url()))
+                    .ok_or_else(|| eyre::eyre!("Ref {} with port {} does not exist", name, port)),
+                spec::Arg::Refs { names, port } => names
+                    .iter()
+                    .map(|name| {
+                        ctx.refs
+                            .get(&(name.clone(), port.clone()))
+                            .map(|target| target.url())
+                            .ok_or_else(|| {
+                                eyre::eyre!("Ref {} with port {} does not exist", name, port)
+                            })
+                    })
+                    .collect::<eyre::Result<Vec<_>>>()
+                    .map(|urls| Some(urls.join(","))),
+            }?;
+            if let Some(cleaned_arg) = cleaned_arg {
+                command.push(cleaned_arg);
+            }
+        }
 
-        let file_artifact = File {
-            name: "config.json".to_string(),
-            target_path: "/app/config.json".to_string(),
-            content: r#"{"key": "value"}"#.to_string(),
-        };
+        // Add artifacts from spec.artifacts
+        artifacts_to_process.extend(spec.artifacts);
+
+        // Process all artifacts after args have been hydrated
+        for artifact in artifacts_to_process {
+            match artifact {
+                spec::Artifacts::File(file) => {
+                    file.verify_checksum()?;
+                    for name in file.secrets() {
+                        if !secrets.contains(&name) {
+                            secrets.push(name);
+                        }
+                    }
+                    let content = file.render(&metadata, &ctx.refs, &spec_ports)?;
+                    let File {
+                        name,
+                        target_path,
+                        mode,
+                        read_only,
+                        owner,
+                        ..
+                    } = file;
+
+                    let _span = tracing::debug_span!(
+                        "artifact",
+                        service = %format!("{}-{}", pod_name, spec_name),
+                        name = %name,
+                        target_path = %target_path,
+                    )
+                    .entered();
+
+                    let target_host_path = ctx
+                        .artifact_dir(pod_name, spec_name, content.as_bytes())?
+                        .join(name);
+                    if let Some(parent) = target_host_path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&target_host_path, content)?;
 
-        let spec = Spec::builder()
-            .image("test-image")
-            .artifact(Artifacts::File(file_artifact))
-            .build();
+                    if let Some(mode) = mode {
+                        std::fs::set_permissions(
+                            &target_host_path,
+                            std::fs::Permissions::from_mode(mode),
+                        )?;
+                    }
 
-        let pod = Pod::default().with_spec("test-service", spec);
+                    let owner = owner.or(owner_uid);
+                    ctx.chown(&target_host_path, owner)?;
+                    volumes.push(ctx.bind(&target_host_path, &target_path, read_only));
+                }
+                spec::Artifacts::Dir(dir) => {
+                    let _span = tracing::debug_span!(
+                        "artifact",
+                        service = %format!("{}-{}", pod_name, spec_name),
+                        name = %dir.name,
+                        target_path = %dir.target_path,
+                    )
+                    .entered();
+
+                    let mut hasher = Sha256::new();
+                    for file in &dir.files {
+                        hasher.update(file.path.as_bytes());
+                        hasher.update([0]);
+                        hasher.update(file.content.as_bytes());
+                        hasher.update([0]);
+                    }
+                    let dir_host_path = ctx
+                        .artifact_dir(pod_name, spec_name, &hasher.finalize())?
+                        .join(&dir.name);
+                    // the tree is written from scratch so that removed files do not linger
+                    if dir_host_path.exists() {
+                        std::fs::remove_dir_all(&dir_host_path)?;
+                    }
+                    std::fs::create_dir_all(&dir_host_path)?;
+
+                    for file in &dir.files {
+                        let relative = std::path::Path::new(&file.path);
+                        let is_relative = relative
+                            .components()
+                            .all(|component| matches!(component, std::path::Component::Normal(_)));
+                        if !is_relative {
+                            eyre::bail!("File {} of dir {} is outside of it", file.path, dir.name);
+                        }
+
+                        let file_host_path = dir_host_path.join(relative);
+                        if let Some(parent) = file_host_path.parent() {
+                            std::fs::create_dir_all(parent)?;
+                        }
+                        std::fs::write(&file_host_path, &file.content)?;
+                    }
+
+                    ctx.chown(&dir_host_path, owner_uid)?;
+                    volumes.push(ctx.bind(&dir_host_path, &dir.target_path, dir.read_only));
+                }
+            }
+        }
+
+        let mut env_file = vec![];
+        if !spec.env_file.is_empty() {
+            let mut vars: Vec<_> = spec.env_file.into_iter().collect();
+            vars.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut content = String::new();
+            for (key, value) in vars {
+                let value = value
+                    .inline()
+                    .ok_or_else(|| eyre::eyre!("Env file var {} is not resolved", key))?;
+                // fetched lists end with a new line
+                let value = env_file_value(&metadata.render(value.trim()));
+                content.push_str(&format!("{}={}\n", key, value));
+            }
+            let env_file_path = ctx
+                .artifact_dir(pod_name, spec_name, content.as_bytes())?
+                .join(format!("{}-{}.env", pod_name, spec_name));
+            std::fs::write(&env_file_path, content)?;
+            env_file.push(env_file_path.display().to_string());
+        }
+
+        // Secrets are only written to the host when deploying, with owner only permissions
+        let secrets_path = ctx.compose_dir.join(SECRETS_DIR);
+        for name in secrets {
+            std::fs::create_dir_all(&secrets_path)?;
+            let secret_host_path = secrets_path.canonicalize()?.join(&name);
+            let secret = self.resolve_secret(&name, Some(&ctx.compose_dir))?;
+            std::fs::write(&secret_host_path, secret)?;
+            std::fs::set_permissions(&secret_host_path, std::fs::Permissions::from_mode(0o600))?;
+            ctx.chown(&secret_host_path, owner_uid)?;
+            volumes.push(ctx.bind(&secret_host_path, &spec::secret_path(&name), true));
+        }
+
+        let mut labels: BTreeMap<String, String> = spec
+            .labels
+            .into_iter()
+            .map(|(key, value)| (key, metadata.render(&value)))
+            .collect();
+        labels.insert("bbuilder".to_string(), "true".to_string());
+        labels.insert(DEPLOYMENT_LABEL.to_string(), ctx.deployment.clone());
+        labels.insert(POD_LABEL.to_string(), pod_name.to_string());
+        labels.insert(SPEC_LABEL.to_string(), spec_name.to_string());
+        if !ctx.chain.is_empty() {
+            labels.insert(CHAIN_LABEL.to_string(), ctx.chain.clone());
+        }
+        if !spec.identity_keys.is_empty() {
+            labels.insert(
+                IDENTITY_KEYS_LABEL.to_string(),
+                spec.identity_keys.join(","),
+            );
+        }
+
+        Ok(DockerComposeService {
+            command: escape_interpolation(command),
+            entrypoint: escape_interpolation(spec.entrypoint),
+            environment,
+            env_file,
+            image,
+            pull_policy,
+            platform: spec.platform.map(platform),
+            labels,
+            ports,
+            volumes,
+            tmpfs,
+            networks: vec!["test".to_string()],
+            healthcheck,
+            user: spec.uid.map(|uid| format!("{}:{}", uid, uid)),
+            working_dir: spec.workdir,
+            read_only: spec.read_only_rootfs,
+            cap_add: spec.cap_add,
+            cap_drop: spec.cap_drop,
+            privileged: spec.privileged,
+            ulimits: spec
+                .ulimits
+                .into_iter()
+                .map(|(name, (soft, hard))| (name, Ulimit { soft, hard }))
+                .collect(),
+            sysctls: spec.sysctls,
+            oom_score_adj: spec.oom_score_adj,
+            mem_limit: spec.mem_limit,
+            memswap_limit: spec.memswap_limit,
+            shm_size: spec.shm_size,
+            stop_signal: spec.stop_signal,
+            stop_grace_period: spec
+                .stop_grace_period
+                .map(|seconds| format!("{}s", seconds)),
+            logging,
+            devices,
+            deploy,
+            post_start: LifecycleHook::new(spec.post_start),
+            pre_stop: LifecycleHook::new(spec.pre_stop),
+            x_bbuilder: Some(ServiceMetadata {
+                deployment: ctx.deployment.clone(),
+                module: ctx.module.clone(),
+                chain: ctx.chain.clone(),
+                pod: pod_name.to_string(),
+                spec: spec_name.to_string(),
+                manifest_hash: ctx.manifest_hash.clone(),
+                // the crates of the workspace are released together
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+/// Platform of the images of the arch
+fn platform(arch: Arch) -> String {
+    format!("linux/{}", arch.name())
+}
+
+/// Image pulled before compose looks for it
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct PrePull {
+    image: String,
+    platform: Option<String>,
+    // only pulled when the image is not in the local store yet
+    if_missing: bool,
+}
+
+/// Images of the specs pulled before compose starts them, minus the ones
+/// their spec never pulls (e.g. the locally built babel image)
+fn pre_pulls(manifest: &Manifest) -> BTreeSet<PrePull> {
+    manifest
+        .pods
+        .values()
+        .flat_map(|pod| {
+            pod.specs
+                .values()
+                .chain(pod.init_specs.iter().map(|init| &init.spec))
+        })
+        .filter(|spec| spec.pull_policy != Some(spec::PullPolicy::Never))
+        .map(|spec| PrePull {
+            image: spec.image_ref(),
+            platform: spec.platform.map(platform),
+            if_missing: spec.pull_policy == Some(spec::PullPolicy::IfNotPresent),
+        })
+        .collect()
+}
+
+/// Escapes the `$` of the args so compose passes them as written instead of
+/// interpolating its variables (e.g. in the shell scripts of init specs)
+fn escape_interpolation(args: Vec<String>) -> Vec<String> {
+    args.into_iter().map(|arg| arg.replace('$', "$$")).collect()
+}
+
+/// Quotes the value of an env file, compose expands the escaped newlines and
+/// interpolates the variables of the double quoted values
+fn env_file_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('$', "$$");
+    format!("\"{}\"", escaped)
+}
+
+impl DockerRuntime {
+    /// Pulls the image for the platform from the first of its references
+    /// that succeeds and tags it with the reference of the spec
+    async fn pull_image(&self, image: &Image, platform: Option<&str>) -> eyre::Result<()> {
+        let docker = connect_docker(self.docker_host.as_deref())?;
+        let credentials = match self.registry.auths.get(&image.host) {
+            Some(auth) => Some(DockerCredentials {
+                username: Some(auth.username.clone()),
+                password: Some(self.resolve_secret(&auth.password_secret, None)?),
+                serveraddress: Some(image.host.clone()),
+                ..Default::default()
+            }),
+            None => None,
+        };
+
+        let mut last_err = None;
+        for pull_ref in self.registry.pull_refs(image) {
+            tracing::info!(image = %pull_ref, "Pulling image");
+            let mut options = CreateImageOptionsBuilder::new().from_image(&pull_ref);
+            if let Some(platform) = platform {
+                options = options.platform(platform);
+            }
+            let options = options.build();
+            // the credentials are for the registry of the image, not its mirrors
+            let credentials = if pull_ref == image.to_string() {
+                credentials.clone()
+            } else {
+                None
+            };
+            let mut pull = docker.create_image(Some(options), None, credentials);
+            let mut result = Ok(());
+            while let Some(progress) = pull.next().await {
+                if let Err(err) = progress {
+                    result = Err(err);
+                    break;
+                }
+            }
+            match result {
+                Ok(()) => {
+                    if pull_ref != image.to_string() {
+                        let options = TagImageOptionsBuilder::new()
+                            .repo(&image.repo())
+                            .tag(image.reference.trim_start_matches(':'))
+                            .build();
+                        docker.tag_image(&pull_ref, Some(options)).await?;
+                    }
+                    return Ok(());
+                }
+                Err(err) => {
+                    tracing::warn!(image = %pull_ref, error = %err, "Failed to pull image");
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(eyre::eyre!(
+            "Failed to pull image {}: {}",
+            image,
+            last_err.map(|err| err.to_string()).unwrap_or_default()
+        ))
+    }
+
+    /// Whether the image is in the local store of the engine
+    async fn has_image(&self, image: &str) -> eyre::Result<bool> {
+        let docker = connect_docker(self.docker_host.as_deref())?;
+        match docker.inspect_image(image).await {
+            Ok(_) => Ok(true),
+            Err(bollard::errors::Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Docker config of the user with the credentials of the registries added,
+    /// compose pulls with them the images it pulls itself (e.g. `always`)
+    fn registry_config(&self, mut config: serde_json::Value) -> eyre::Result<serde_json::Value> {
+        if !config.is_object() {
+            config = serde_json::json!({});
+        }
+        // a credentials store of the user would shadow the auths written here
+        if let Some(config) = config.as_object_mut() {
+            config.remove("credsStore");
+        }
+        for (host, auth) in &self.registry.auths {
+            let password = self.resolve_secret(&auth.password_secret, None)?;
+            let token = BASE64_STANDARD.encode(format!("{}:{}", auth.username, password));
+            if let Some(helpers) = config["credHelpers"].as_object_mut() {
+                helpers.remove(host);
+            }
+            config["auths"][host] = serde_json::json!({ "auth": token });
+        }
+        Ok(config)
+    }
+
+    /// Writes the docker config with the registry credentials to the secrets
+    /// of the deployment, none without credentials
+    fn write_registry_config(&self, deployment_dir: &Path) -> eyre::Result<Option<PathBuf>> {
+        if self.registry.auths.is_empty() {
+            return Ok(None);
+        }
+        let user_config = std::env::var_os("DOCKER_CONFIG")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".docker")))
+            .and_then(|dir| std::fs::read_to_string(dir.join("config.json")).ok())
+            .map(|content| serde_json::from_str(&content))
+            .transpose()?
+            .unwrap_or_default();
+        let config = self.registry_config(user_config)?;
+
+        let config_dir = deployment_dir.join(SECRETS_DIR).join("docker");
+        std::fs::create_dir_all(&config_dir)?;
+        let config_path = config_dir.join("config.json");
+        std::fs::write(&config_path, serde_json::to_string_pretty(&config)?)?;
+        std::fs::set_permissions(&config_path, std::fs::Permissions::from_mode(0o600))?;
+        Ok(Some(config_dir.canonicalize()?))
+    }
+
+    /// Host paths mounted by the containers of the deployment
+    async fn mounted_paths(&self, deployment: &str) -> eyre::Result<Vec<PathBuf>> {
+        let docker = connect_docker(self.docker_host.as_deref())?;
+
+        let label = format!("{}={}", DEPLOYMENT_LABEL, deployment);
+        let filters = HashMap::from([("label", vec![label.as_str()])]);
+        let options = ListContainersOptionsBuilder::new()
+            .all(true)
+            .filters(&filters)
+            .build();
+        let containers = docker.list_containers(Some(options)).await?;
+        Ok(containers
+            .into_iter()
+            .flat_map(|container| container.mounts.unwrap_or_default())
+            .filter_map(|mount| mount.source.map(PathBuf::from))
+            .collect())
+    }
+
+    /// Creates and starts the services of the compose file on the daemon of
+    /// the docker host, the services of a previous deploy that the file no
+    /// longer has are removed. Compose pulls with the registry credentials of
+    /// the docker config dir if given.
+    async fn compose_up(
+        &self,
+        project: &str,
+        compose_file: &Path,
+        registry_config: Option<&Path>,
+    ) -> eyre::Result<()> {
+        let command = self
+            .compose
+            .clone()
+            .unwrap_or_else(|| self.engine.compose_command());
+        let Some((program, args)) = command.split_first() else {
+            eyre::bail!("The compose command is empty");
+        };
+
+        let mut up = tokio::process::Command::new(program);
+        up.args(args)
+            .arg("-f")
+            .arg(compose_file)
+            .arg("-p")
+            .arg(project)
+            .args(["up", "-d", "--remove-orphans"]);
+        if let Some(host) = &self.docker_host {
+            up.env("DOCKER_HOST", host);
+        }
+        if let Some(config_dir) = registry_config {
+            // podman reads the same format from its auth file
+            up.env("DOCKER_CONFIG", config_dir)
+                .env("REGISTRY_AUTH_FILE", config_dir.join("config.json"));
+        }
+        tracing::info!(command = %command.join(" "), "Starting the compose services");
+        let output = up
+            .output()
+            .await
+            .map_err(|e| eyre::eyre!("Failed to run {}: {}", command.join(" "), e))?;
+        if !output.status.success() {
+            eyre::bail!(
+                "{} up exited with {}: {}",
+                command.join(" "),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Lists the specs of the deployment, the stats of the running ones are
+    /// sampled concurrently
+    async fn list_specs(
+        &self,
+        deployment: &str,
+        with_stats: bool,
+    ) -> eyre::Result<Vec<SpecStatus>> {
+        let docker = connect_docker(self.docker_host.as_deref())?;
+
+        let label = format!("{}={}", DEPLOYMENT_LABEL, deployment);
+        let filters = HashMap::from([("label", vec![label.as_str()])]);
+        let options = ListContainersOptionsBuilder::new()
+            .all(true)
+            .filters(&filters)
+            .build();
+
+        let containers = docker.list_containers(Some(options)).await?;
+        let paused = is_paused(&self.dir_path, deployment);
+
+        let specs = containers.into_iter().filter_map(|container| {
+            let labels = container.labels.unwrap_or_default();
+            if labels.contains_key(INIT_LABEL) {
+                return None;
+            }
+            let id = container.id.unwrap_or_default();
+            let crash_looping = is_crash_looping(&self.dir_path, deployment, &id);
+            let status = SpecStatus {
+                pod: labels.get(POD_LABEL).cloned().unwrap_or_default(),
+                spec: labels.get(SPEC_LABEL).cloned().unwrap_or_default(),
+                state: match container.state.map(|state| state.to_string()) {
+                    _ if crash_looping => CRASH_LOOP.to_string(),
+                    Some(state) if paused && state != "running" => PAUSED.to_string(),
+                    state => state.unwrap_or_default(),
+                },
+                health: parse_health(container.status.as_deref().unwrap_or_default()),
+                stats: None,
+            };
+            Some((id, status))
+        });
+
+        let mut statuses = futures_util::future::try_join_all(specs.map(|(id, mut status)| {
+            let docker = &docker;
+            async move {
+                if with_stats && status.state == "running" {
+                    // without one-shot the daemon takes two samples to compute the cpu usage
+                    let options = StatsOptionsBuilder::new()
+                        .stream(false)
+                        .one_shot(false)
+                        .build();
+                    let mut stats = docker.stats(&id, Some(options));
+                    if let Some(stats) = stats.next().await {
+                        status.stats = Some(resource_stats(&stats?));
+                    }
+                }
+                Ok::<_, eyre::Report>(status)
+            }
+        }))
+        .await?;
+        statuses.sort_by(|a, b| (&a.pod, &a.spec).cmp(&(&b.pod, &b.spec)));
+
+        Ok(statuses)
+    }
+}
+
+/// Converts a stats sample of the docker daemon, the memory excludes the page
+/// cache like `docker stats` does
+fn resource_stats(stats: &ContainerStatsResponse) -> ResourceStats {
+    let total_usage = |cpu: &Option<ContainerCpuStats>| {
+        cpu.as_ref()
+            .and_then(|cpu| cpu.cpu_usage.as_ref())
+            .and_then(|usage| usage.total_usage)
+            .unwrap_or_default()
+    };
+    let system_usage = |cpu: &Option<ContainerCpuStats>| {
+        cpu.as_ref()
+            .and_then(|cpu| cpu.system_cpu_usage)
+            .unwrap_or_default()
+    };
+
+    let cpu_delta = total_usage(&stats.cpu_stats).saturating_sub(total_usage(&stats.precpu_stats));
+    let system_delta =
+        system_usage(&stats.cpu_stats).saturating_sub(system_usage(&stats.precpu_stats));
+    let online_cpus = stats
+        .cpu_stats
+        .as_ref()
+        .and_then(|cpu| cpu.online_cpus)
+        .unwrap_or(1);
+    let cpu_percent = if system_delta > 0 {
+        cpu_delta as f64 / system_delta as f64 * online_cpus as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    let memory = stats.memory_stats.clone().unwrap_or_default();
+    let cache = memory
+        .stats
+        .as_ref()
+        .and_then(|stats| stats.get("inactive_file"))
+        .copied()
+        .unwrap_or_default();
+
+    let networks = stats.networks.clone().unwrap_or_default();
+    let disk_bytes = |op: &str| {
+        stats
+            .blkio_stats
+            .as_ref()
+            .and_then(|blkio| blkio.io_service_bytes_recursive.as_ref())
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter(|entry| {
+                        entry
+                            .op
+                            .as_deref()
+                            .is_some_and(|entry_op| entry_op.eq_ignore_ascii_case(op))
+                    })
+                    .filter_map(|entry| entry.value)
+                    .sum()
+            })
+            .unwrap_or_default()
+    };
+
+    ResourceStats {
+        cpu_percent,
+        memory_bytes: memory.usage.unwrap_or_default().saturating_sub(cache),
+        memory_limit_bytes: memory.limit.unwrap_or_default(),
+        network_rx_bytes: networks.values().filter_map(|net| net.rx_bytes).sum(),
+        network_tx_bytes: networks.values().filter_map(|net| net.tx_bytes).sum(),
+        disk_read_bytes: disk_bytes("read"),
+        disk_write_bytes: disk_bytes("write"),
+    }
+}
+
+// seconds before a request to the docker daemon times out
+const DOCKER_TIMEOUT: u64 = 120;
+
+fn connect_docker(host: Option<&str>) -> Result<Docker, bollard::errors::Error> {
+    match host {
+        Some(host) if host.starts_with("unix://") => {
+            Docker::connect_with_unix(host, DOCKER_TIMEOUT, bollard::API_DEFAULT_VERSION)
+        }
+        Some(host) => Docker::connect_with_http(host, DOCKER_TIMEOUT, bollard::API_DEFAULT_VERSION),
+        None => Docker::connect_with_local_defaults(),
+    }
+}
+
+/// Parses the health from the container status (e.g. "Up 2 minutes (healthy)")
+fn parse_health(status: &str) -> Health {
+    if status.contains("(healthy)") {
+        Health::Healthy
+    } else if status.contains("(unhealthy)") {
+        Health::Unhealthy
+    } else if status.contains("(health: starting)") {
+        Health::Starting
+    } else {
+        Health::Unknown
+    }
+}
+
+impl DockerRuntime {
+    /// Id, spec and identity key files of the running containers of the pod
+    /// that declare identity keys
+    async fn identity_key_containers(
+        &self,
+        deployment: &str,
+        pod: &str,
+    ) -> eyre::Result<Vec<(String, String, Vec<String>)>> {
+        let docker = connect_docker(self.docker_host.as_deref())?;
+
+        let labels = [
+            format!("{}={}", DEPLOYMENT_LABEL, deployment),
+            format!("{}={}", POD_LABEL, pod),
+            IDENTITY_KEYS_LABEL.to_string(),
+        ];
+        let filters = HashMap::from([
+            ("label", labels.iter().map(String::as_str).collect()),
+            ("status", vec!["running"]),
+        ]);
+        let options = ListContainersOptionsBuilder::new()
+            .filters(&filters)
+            .build();
+
+        let mut containers = vec![];
+        for container in docker.list_containers(Some(options)).await? {
+            let (Some(id), Some(labels)) = (container.id, container.labels) else {
+                continue;
+            };
+            if labels.contains_key(INIT_LABEL) {
+                continue;
+            }
+            let spec = labels.get(SPEC_LABEL).cloned().unwrap_or_default();
+            let paths = labels[IDENTITY_KEYS_LABEL]
+                .split(',')
+                .map(str::to_string)
+                .collect();
+            containers.push((id, spec, paths));
+        }
+        Ok(containers)
+    }
+}
+
+#[async_trait::async_trait]
+impl Runtime for DockerRuntime {
+    #[tracing::instrument(skip_all, fields(manifest = %manifest.name))]
+    async fn run(&self, mut manifest: Manifest) -> eyre::Result<()> {
+        manifest.validate()?;
+        self.engine.check_host_ports(&manifest.host_ports())?;
+        let name = manifest.name.clone();
+
+        // the lazy artifact contents are only resolved when deploying
+        let mut fetched = HashMap::new();
+        for url in manifest.artifact_urls() {
+            tracing::info!(url = %url, "Fetching artifact content");
+            let content = reqwest::get(&url).await?.error_for_status()?.text().await?;
+            fetched.insert(url, content);
+        }
+        manifest.resolve_artifacts(&fetched)?;
+
+        // Create the parent folder path
+        let parent_folder = std::path::Path::new(&self.dir_path).join(&name);
+        std::fs::create_dir_all(&parent_folder)?;
+        // the deployment runs again, a paused marker of a previous run would
+        // hide the exits of its specs
+        clear_paused(&self.dir_path, &name)?;
+        // the artifacts and secrets of the previous deploy are owned by the
+        // uids of its specs until they are given back to be written again
+        self.engine
+            .chown_back(
+                &self.podman,
+                &[
+                    parent_folder.join("_config"),
+                    parent_folder.join(SECRETS_DIR),
+                ],
+            )
+            .await?;
+
+        let prometheus_config = prometheus::scrape_config(&manifest);
+        grafana::write_dashboards(&parent_folder, &manifest)?;
+
+        let pulls = pre_pulls(&manifest);
+
+        let docker_compose_spec = self.convert_to_docker_compose_spec(manifest)?;
+        let config_dir = parent_folder.join("_config").canonicalize()?;
+
+        // Write the compose file in the parent folder
+        let compose_file_path = parent_folder.join("docker-compose.yaml");
+        tracing::info!(path = %compose_file_path.display(), "Writing docker compose file");
+        std::fs::write(
+            compose_file_path.clone(),
+            serde_yaml::to_string(&docker_compose_spec)?,
+        )?;
+
+        // Write the prometheus scrape config for the specs that expose metrics
+        if let Some(prometheus_config) = prometheus_config {
+            std::fs::write(
+                parent_folder.join("prometheus.yml"),
+                serde_yaml::to_string(&prometheus_config)?,
+            )?;
+        }
+
+        // the images are in the local store before compose looks for them
+        for pull in &pulls {
+            let image = Image::parse(&pull.image);
+            if !self.registry.pulls(&image) {
+                continue;
+            }
+            if pull.if_missing && self.has_image(&pull.image).await? {
+                continue;
+            }
+            self.pull_image(&image, pull.platform.as_deref()).await?;
+        }
+
+        let registry_config = self.write_registry_config(&parent_folder)?;
+        self.engine
+            .chown(&self.podman, &docker_compose_spec.owned_paths)
+            .await?;
+        self.compose_up(&name, &compose_file_path, registry_config.as_deref())
+            .await?;
+
+        // the artifacts of the previous deploy are only removed once the new
+        // services are up, minus the ones its remaining containers mount
+        match self.mounted_paths(&name).await {
+            Ok(mounted) => {
+                prune_artifacts(&config_dir, &docker_compose_spec.artifact_dirs, &mounted)
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to list the mounts, the stale artifacts are kept");
+                Ok(())
+            }
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn status(&self, deployment: &str) -> eyre::Result<Vec<SpecStatus>> {
+        self.list_specs(deployment, false).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn status_with_stats(&self, deployment: &str) -> eyre::Result<Vec<SpecStatus>> {
+        self.list_specs(deployment, true).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn exec(
+        &self,
+        deployment: &str,
+        pod: &str,
+        spec: &str,
+        command: Vec<String>,
+    ) -> eyre::Result<String> {
+        let docker = connect_docker(self.docker_host.as_deref())?;
+
+        let labels = [
+            format!("{}={}", DEPLOYMENT_LABEL, deployment),
+            format!("{}={}", POD_LABEL, pod),
+            format!("{}={}", SPEC_LABEL, spec),
+        ];
+        let filters = HashMap::from([
+            ("label", labels.iter().map(String::as_str).collect()),
+            ("status", vec!["running"]),
+        ]);
+        let options = ListContainersOptionsBuilder::new()
+            .filters(&filters)
+            .build();
+        let container = docker
+            .list_containers(Some(options))
+            .await?
+            .into_iter()
+            .find(|container| {
+                !container
+                    .labels
+                    .as_ref()
+                    .is_some_and(|labels| labels.contains_key(INIT_LABEL))
+            })
+            .and_then(|container| container.id)
+            .ok_or_else(|| eyre::eyre!("Spec {}/{} is not running", pod, spec))?;
+
+        let exec = docker
+            .create_exec(
+                &container,
+                ExecConfig {
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    cmd: Some(command),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        if let StartExecResults::Attached { mut output, .. } =
+            docker.start_exec(&exec.id, None).await?
+        {
+            while let Some(message) = output.next().await {
+                match message? {
+                    bollard::container::LogOutput::StdErr { message } => {
+                        stderr.push_str(&String::from_utf8_lossy(&message))
+                    }
+                    message => stdout.push_str(&message.to_string()),
+                }
+            }
+        }
+
+        let exit_code = docker.inspect_exec(&exec.id).await?.exit_code;
+        if exit_code != Some(0) {
+            eyre::bail!(
+                "Command in {}/{} exited with {:?}: {}",
+                pod,
+                spec,
+                exit_code,
+                stderr.trim()
+            );
+        }
+        Ok(stdout)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn pause(&self, deployment: &str) -> eyre::Result<()> {
+        let docker = connect_docker(self.docker_host.as_deref())?;
+
+        let label = format!("{}={}", DEPLOYMENT_LABEL, deployment);
+        let filters = HashMap::from([("label", vec![label.as_str()]), ("status", vec!["running"])]);
+        let options = ListContainersOptionsBuilder::new()
+            .filters(&filters)
+            .build();
+        let containers = docker.list_containers(Some(options)).await?;
+        if containers.is_empty() {
+            eyre::bail!("Deployment {} has no running specs", deployment);
+        }
+
+        // the exits are not counted as crashes while stopping, the deployment
+        // is only marked as paused once all its specs are stopped
+        self.pausing.lock().unwrap().insert(deployment.to_string());
+        let stopped = async {
+            // without a timeout the daemon waits for the stop grace period of
+            // the container before killing it
+            for container in containers {
+                let Some(id) = container.id else {
+                    continue;
+                };
+                tracing::info!(container = %id, "Stopping container");
+                docker
+                    .stop_container(&id, Some(StopContainerOptionsBuilder::new().build()))
+                    .await?;
+            }
+            let folder = Path::new(&self.dir_path).join(deployment);
+            std::fs::create_dir_all(&folder)?;
+            std::fs::write(folder.join(PAUSED_FILE), "")?;
+            eyre::Ok(())
+        }
+        .await;
+        self.pausing.lock().unwrap().remove(deployment);
+        stopped
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn resume(&self, deployment: &str) -> eyre::Result<()> {
+        if !is_paused(&self.dir_path, deployment) {
+            eyre::bail!("Deployment {} is not paused", deployment);
+        }
+        let docker = connect_docker(self.docker_host.as_deref())?;
+
+        let label = format!("{}={}", DEPLOYMENT_LABEL, deployment);
+        let filters = HashMap::from([("label", vec![label.as_str()])]);
+        let options = ListContainersOptionsBuilder::new()
+            .all(true)
+            .filters(&filters)
+            .build();
+        let mut containers = docker.list_containers(Some(options)).await?;
+        // compose creates the dependencies of a spec first, the init specs
+        // already ran and their data is kept
+        containers.retain(|container| {
+            !container
+                .labels
+                .as_ref()
+                .is_some_and(|labels| labels.contains_key(INIT_LABEL))
+        });
+        containers.sort_by_key(|container| container.created);
+
+        for container in containers {
+            let Some(id) = container.id else {
+                continue;
+            };
+            tracing::info!(container = %id, "Starting container");
+            docker
+                .start_container(&id, None::<StartContainerOptions>)
+                .await?;
+        }
+
+        clear_paused(&self.dir_path, deployment)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn identity_keys(&self, deployment: &str, pod: &str) -> eyre::Result<Vec<IdentityKey>> {
+        let mut keys = vec![];
+        for (_, spec, paths) in self.identity_key_containers(deployment, pod).await? {
+            // read through the container, the volumes may not be in this host
+            for path in paths {
+                let content = self
+                    .exec(
+                        deployment,
+                        pod,
+                        &spec,
+                        vec!["base64".to_string(), path.clone()],
+                    )
+                    .await?;
+                keys.push(IdentityKey {
+                    spec: spec.clone(),
+                    path,
+                    content: content.split_whitespace().collect(),
+                });
+            }
+        }
+
+        if keys.is_empty() {
+            eyre::bail!(
+                "Pod {}/{} has no running specs with identity keys",
+                deployment,
+                pod
+            );
+        }
+        Ok(keys)
+    }
+
+    #[tracing::instrument(skip(self, keys))]
+    async fn rotate_keys(
+        &self,
+        deployment: &str,
+        pod: &str,
+        keys: &[IdentityKey],
+    ) -> eyre::Result<()> {
+        let docker = connect_docker(self.docker_host.as_deref())?;
+
+        for (id, spec, _) in self.identity_key_containers(deployment, pod).await? {
+            let paths: Vec<String> = keys
+                .iter()
+                .filter(|key| key.spec == spec)
+                .map(|key| key.path.clone())
+                .collect();
+            if paths.is_empty() {
+                continue;
+            }
+
+            let mut remove = vec!["rm".to_string(), "-f".to_string()];
+            remove.extend(paths);
+            self.exec(deployment, pod, &spec, remove).await?;
+
+            tracing::info!(container = %id, "Restarting container");
+            docker
+                .restart_container(&id, None::<RestartContainerOptions>)
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn destroy(&self, deployment: &str) -> eyre::Result<()> {
+        let docker = connect_docker(self.docker_host.as_deref())?;
+
+        let label = format!("{}={}", DEPLOYMENT_LABEL, deployment);
+        let filters = HashMap::from([("label", vec![label.as_str()])]);
+        let options = ListContainersOptionsBuilder::new()
+            .all(true)
+            .filters(&filters)
+            .build();
+
+        for container in docker.list_containers(Some(options)).await? {
+            let Some(id) = container.id else {
+                continue;
+            };
+            tracing::info!(container = %id, "Removing container");
+            docker
+                .remove_container(
+                    &id,
+                    Some(RemoveContainerOptionsBuilder::new().force(true).build()),
+                )
+                .await?;
+            clear_crash_looping(&self.dir_path, deployment, &id);
+        }
+        clear_paused(&self.dir_path, deployment)
+    }
+
+    fn subscribe(&self, deployment: &str) -> Subscription {
+        Subscription::new(deployment, self.events.subscribe())
+    }
+
+    /// Architecture of the docker daemon, the host one for the local daemon
+    async fn arch(&self) -> eyre::Result<Arch> {
+        if self.docker_host.is_none() {
+            return Arch::host();
+        }
+        let docker = connect_docker(self.docker_host.as_deref())?;
+        let info = docker.info().await?;
+        info.architecture
+            .ok_or_else(|| eyre::eyre!("Docker daemon did not report its architecture"))?
+            .parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::{Artifacts, File, LogConfig, Manifest, Pod, Spec, Volume};
+
+    // runtime writing its deployments in a temp dir removed on drop
+    fn test_runtime() -> (tempfile::TempDir, DockerRuntime) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let runtime = DockerRuntime::new(temp_dir.path().to_str().unwrap().to_string());
+        (temp_dir, runtime)
+    }
+
+    // host path mounted on the target path of the service
+    fn mounted_host_path(service: &DockerComposeService, target_path: &str) -> PathBuf {
+        service
+            .volumes
+            .iter()
+            .find_map(|volume| {
+                let (host_path, target) = volume.split_once(':')?;
+                (target.trim_end_matches(":ro") == target_path).then(|| PathBuf::from(host_path))
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_artifact_files_are_mounted_in_volumes() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("test-manifest".to_string());
+
+        let file_artifact = File {
+            name: "config.json".to_string(),
+            target_path: "/app/config.json".to_string(),
+            content: r#"{"key": "value"}"#.into(),
+            ..Default::default()
+        };
+
+        let spec = Spec::builder()
+            .image("test-image")
+            .artifact(Artifacts::File(file_artifact))
+            .build();
+
+        let pod = Pod::default().with_spec("test-service", spec);
         manifest.add_spec("test-pod".to_string(), pod);
 
         let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
@@ -411,40 +2239,1401 @@ mod tests {
             "Artifact file should be mounted in volumes"
         );
 
-        let _ = std::fs::remove_dir_all(&temp_dir);
-
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_port_arg_uses_preferred_port() {
-        let temp_dir = std::env::temp_dir().join("test-runtime-port");
-        let _ = std::fs::remove_dir_all(&temp_dir);
-        std::fs::create_dir_all(&temp_dir).unwrap();
-        let runtime = DockerRuntime::new(temp_dir.to_str().unwrap().to_string());
-
-        let mut manifest = Manifest::new("port-test".to_string());
+    async fn test_pinned_image_and_pull_policy() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
 
-        let spec = Spec::builder()
+        let digest = format!("sha256:{}", "ab".repeat(32));
+        let mut manifest = Manifest::new("digest-test".to_string());
+        let pinned = Spec::builder()
             .image("test-image")
-            .arg(spec::Arg::Port {
-                name: "rpc-port".to_string(),
-                preferred: 8545,
-            })
+            .tag("v1")
+            .digest(digest.clone())
+            .pull_policy(spec::PullPolicy::IfNotPresent)
             .build();
+        let tagged = Spec::builder().image("test-image").tag("v1").build();
+        manifest.add_spec(
+            "test-pod".to_string(),
+            Pod::default()
+                .with_spec("pinned", pinned)
+                .with_spec("tagged", tagged),
+        );
 
-        let pod = Pod::default().with_spec("service", spec);
-        manifest.add_spec("pod".to_string(), pod);
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let pinned = &docker_compose.services["test-pod-pinned"];
+        assert_eq!(pinned.image, format!("test-image@{}", digest));
+        assert_eq!(pinned.pull_policy.as_deref(), Some("missing"));
+        let tagged = &docker_compose.services["test-pod-tagged"];
+        assert_eq!(tagged.image, "test-image:v1");
+        assert_eq!(tagged.pull_policy, None);
+        Ok(())
+    }
 
-        let docker_compose = runtime.convert_to_docker_compose_spec(manifest).unwrap();
-        let service = docker_compose.services.get("pod-service").unwrap();
+    #[test]
+    fn test_pre_pulls_follow_the_pull_policy_and_platform_of_the_spec() {
+        let mut manifest = Manifest::new("pull-test".to_string());
+        let always = Spec::builder()
+            .image("test-image")
+            .tag("v1")
+            .platform(Arch::Arm64)
+            .build();
+        let missing = Spec::builder()
+            .image("other-image")
+            .tag("v2")
+            .pull_policy(spec::PullPolicy::IfNotPresent)
+            .build();
+        let never = Spec::builder()
+            .image("local-image")
+            .pull_policy(spec::PullPolicy::Never)
+            .build();
+        manifest.add_spec(
+            "test-pod".to_string(),
+            Pod::default()
+                .with_spec("always", always)
+                .with_spec("missing", missing)
+                .with_spec("never", never),
+        );
 
+        let pulls: Vec<PrePull> = pre_pulls(&manifest).into_iter().collect();
         assert_eq!(
-            service.command,
-            ["8545"],
-            "Command should contain port arg with preferred port"
+            pulls,
+            vec![
+                PrePull {
+                    image: "other-image:v2".to_string(),
+                    platform: None,
+                    if_missing: true,
+                },
+                PrePull {
+                    image: "test-image:v1".to_string(),
+                    platform: Some("linux/arm64".to_string()),
+                    if_missing: false,
+                },
+            ]
         );
+    }
 
-        let _ = std::fs::remove_dir_all(&temp_dir);
+    #[tokio::test]
+    async fn test_registry_config_adds_the_registry_credentials() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+        let runtime = runtime
+            .with_registry(RegistryConfig {
+                mirrors: vec![],
+                auths: HashMap::from([(
+                    "ghcr.io".to_string(),
+                    crate::registry::RegistryAuth {
+                        username: "user".to_string(),
+                        password_secret: "ghcr_token".to_string(),
+                    },
+                )]),
+            })
+            .with_secret_providers(vec![Box::new(HashMap::from([(
+                "ghcr_token".to_string(),
+                "token".to_string(),
+            )]))]);
+
+        let user_config = serde_json::json!({
+            "auths": {"quay.io": {"auth": "cXVheTpxdWF5"}},
+            "credsStore": "desktop",
+            "credHelpers": {"ghcr.io": "gh"},
+        });
+        let config = runtime.registry_config(user_config)?;
+        // the credentials of the user are kept, the ones of bbuilder win
+        assert_eq!(
+            config,
+            serde_json::json!({
+                "auths": {
+                    "quay.io": {"auth": "cXVheTpxdWF5"},
+                    "ghcr.io": {"auth": BASE64_STANDARD.encode("user:token")},
+                },
+                "credHelpers": {},
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_security_context_and_limits() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("security-test".to_string());
+        let spec = Spec::builder()
+            .image("test-image")
+            .uid(1000)
+            .workdir("/data")
+            .read_only_rootfs()
+            .cap_drop("ALL")
+            .cap_add("NET_BIND_SERVICE")
+            .ulimit("nofile", 65536, 1048576)
+            .sysctl("net.core.somaxconn", "4096")
+            .oom_score_adj(-500)
+            .mem_limit(8 << 30)
+            .memswap_limit(8 << 30)
+            .shm_size(1 << 30)
+            .stop_signal("SIGINT")
+            .stop_grace_period(300)
+            .logging(LogConfig::rotate("100m", 5).tag("{{pod}}-{{spec}}"))
+            .device("/dev/bus/usb")
+            .gpu(spec::Gpu {
+                count: Some(1),
+                ..Default::default()
+            })
+            .build();
+        manifest.add_spec(
+            "test-pod".to_string(),
+            Pod::default().with_spec("node", spec),
+        );
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let yaml = serde_yaml::to_string(&docker_compose.services["test-pod-node"])?;
+        assert!(yaml.contains("user: 1000:1000"));
+        assert!(yaml.contains("working_dir: /data"));
+        assert!(yaml.contains("read_only: true"));
+        assert!(yaml.contains("cap_drop:\n- ALL"));
+        assert!(yaml.contains("cap_add:\n- NET_BIND_SERVICE"));
+        assert!(!yaml.contains("privileged"));
+        assert!(yaml.contains("nofile:\n    soft: 65536\n    hard: 1048576"));
+        assert!(yaml.contains("net.core.somaxconn: '4096'"));
+        assert!(yaml.contains("oom_score_adj: -500"));
+        assert!(yaml.contains("mem_limit: 8589934592"));
+        assert!(yaml.contains("memswap_limit: 8589934592"));
+        assert!(yaml.contains("shm_size: 1073741824"));
+        assert!(yaml.contains("stop_signal: SIGINT"));
+        assert!(yaml.contains("stop_grace_period: 300s"));
+        assert!(yaml.contains("driver: json-file"));
+        assert!(yaml.contains("max-size: 100m"));
+        assert!(yaml.contains("max-file: '5'"));
+        assert!(yaml.contains("tag: test-pod-node"));
+        assert!(yaml.contains("devices:\n- /dev/bus/usb"));
+        assert!(
+            yaml.contains(
+                "- driver: nvidia\n        count: 1\n        capabilities:\n        - gpu"
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_artifact_file_mode_and_read_only_mount() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("mode-test".to_string());
+
+        let spec = Spec::builder()
+            .image("test-image")
+            .artifact(Artifacts::File(File {
+                name: "key.json".to_string(),
+                target_path: "/app/key.json".to_string(),
+                content: "secret".into(),
+                mode: Some(0o600),
+                read_only: true,
+                ..Default::default()
+            }))
+            .build();
+
+        let pod = Pod::default().with_spec("service", spec);
+        manifest.add_spec("pod".to_string(), pod);
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let service = docker_compose.services.get("pod-service").unwrap();
+
+        assert!(
+            service
+                .volumes
+                .iter()
+                .any(|v| v.ends_with("/app/key.json:ro")),
+            "Read-only artifact should be mounted with :ro"
+        );
+
+        let host_path = mounted_host_path(service, "/app/key.json");
+        let mode = std::fs::metadata(host_path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_env_file_is_written_to_the_config() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("env-test".to_string());
+        let spec = Spec::builder()
+            .image("test-image")
+            .env_file("PEERS", "enode://a@1.2.3.4:30303\nenode://b@\"$HOST\"")
+            .build();
+        manifest.add_spec("pod".to_string(), Pod::default().with_spec("node", spec));
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let service = docker_compose.services.get("pod-node").unwrap();
+        assert!(service.environment.is_empty());
+        assert_eq!(service.env_file.len(), 1);
+        assert!(service.env_file[0].contains("env-test/_config/pod-node/"));
+        assert!(service.env_file[0].ends_with("/pod-node.env"));
+        assert_eq!(
+            std::fs::read_to_string(&service.env_file[0])?,
+            "PEERS=\"enode://a@1.2.3.4:30303\\nenode://b@\\\"$$HOST\\\"\"\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_artifact_placeholders_are_rendered() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let el = Spec::builder().image("reth").arg(spec::Arg::Port {
+            name: "authrpc".to_string(),
+            preferred: 8552,
+        });
+        let cl = Spec::builder()
+            .image("beacon-kit")
+            .arg(spec::Arg::Port {
+                name: "http".to_string(),
+                preferred: 1317,
+            })
+            .artifact(Artifacts::File(File {
+                name: "app.toml".to_string(),
+                target_path: "/data/app.toml".to_string(),
+                content: "rpc = \"{{ref:el:authrpc}}\"\nws = \"ws://{{addr:el:authrpc}}\"\napi = \"0.0.0.0:{{port:http}}\"\nname = \"{{pod}}\"\njwt = \"{{secret:jwt}}\"\nraw = \"{{other}}\"".into(),
+                ..Default::default()
+            }));
+        let mut manifest = Manifest::new("render-test".to_string());
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", el));
+        manifest.add_spec("cl".to_string(), Pod::default().with_spec("node", cl));
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let service = docker_compose.services.get("cl-node").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(mounted_host_path(service, "/data/app.toml"))?,
+            "rpc = \"http://el-node:8552\"\nws = \"ws://el-node:8552\"\napi = \"0.0.0.0:1317\"\nname = \"cl\"\njwt = \"/run/secrets/jwt\"\nraw = \"{{other}}\""
+        );
+        // the secrets of the placeholders are mounted like the secret args
+        assert!(
+            service
+                .volumes
+                .iter()
+                .any(|volume| volume.ends_with(":/run/secrets/jwt:ro"))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_artifact_dirs_are_materialized() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("dir-test".to_string());
+        let dir = spec::Dir {
+            name: "heimdall-config".to_string(),
+            target_path: "/data/config".to_string(),
+            files: vec![
+                spec::DirFile {
+                    path: "app.toml".to_string(),
+                    content: "app".to_string(),
+                },
+                spec::DirFile {
+                    path: "nested/client.toml".to_string(),
+                    content: "client".to_string(),
+                },
+            ],
+            read_only: true,
+        };
+        let spec = Spec::builder()
+            .image("test-image")
+            .artifact(Artifacts::Dir(dir.clone()))
+            .build();
+        manifest.add_spec("pod".to_string(), Pod::default().with_spec("service", spec));
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let service = docker_compose.services.get("pod-service").unwrap();
+        assert!(
+            service
+                .volumes
+                .iter()
+                .any(|volume| volume.ends_with("/heimdall-config:/data/config:ro"))
+        );
+
+        let host_path = mounted_host_path(service, "/data/config");
+        assert_eq!(
+            std::fs::read_to_string(host_path.join("nested/client.toml"))?,
+            "client"
+        );
+
+        // files cannot escape the directory
+        let mut escaping = dir;
+        escaping.files[0].path = "../escaped.toml".to_string();
+        let mut manifest = Manifest::new("dir-test".to_string());
+        let spec = Spec::builder()
+            .image("test-image")
+            .artifact(Artifacts::Dir(escaping))
+            .build();
+        manifest.add_spec("pod".to_string(), Pod::default().with_spec("service", spec));
+        assert!(runtime.convert_to_docker_compose_spec(manifest).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stale_artifacts_are_pruned() -> eyre::Result<()> {
+        let (temp_dir, runtime) = test_runtime();
+
+        let manifest = |el_config: &str| {
+            let artifact = |content: &str| {
+                Artifacts::File(File {
+                    name: "config.toml".to_string(),
+                    target_path: "/data/config.toml".to_string(),
+                    content: content.into(),
+                    ..Default::default()
+                })
+            };
+            let mut manifest = Manifest::new("prune-test".to_string());
+            let el = Spec::builder().image("reth").artifact(artifact(el_config));
+            let cl = Spec::builder().image("lighthouse").artifact(artifact("cl"));
+            manifest.add_spec("el".to_string(), Pod::default().with_spec("node", el));
+            manifest.add_spec("cl".to_string(), Pod::default().with_spec("node", cl));
+            manifest
+        };
+        let config_dir = temp_dir.path().join("prune-test/_config");
+        std::fs::create_dir_all(&config_dir)?;
+        std::fs::write(config_dir.join("config.toml"), "stale")?;
+
+        let config_dir = config_dir.canonicalize()?;
+
+        // artifacts with the same name do not collide
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest("v1"))?;
+        prune_artifacts(&config_dir, &docker_compose.artifact_dirs, &[])?;
+        let el_v1 = mounted_host_path(&docker_compose.services["el-node"], "/data/config.toml");
+        let cl = mounted_host_path(&docker_compose.services["cl-node"], "/data/config.toml");
+        assert_eq!(std::fs::read_to_string(&el_v1)?, "v1");
+        assert_eq!(std::fs::read_to_string(&cl)?, "cl");
+        assert!(!config_dir.join("config.toml").exists());
+
+        // the artifact mounted by the running container is kept
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest("v2"))?;
+        prune_artifacts(
+            &config_dir,
+            &docker_compose.artifact_dirs,
+            std::slice::from_ref(&el_v1),
+        )?;
+        let el_v2 = mounted_host_path(&docker_compose.services["el-node"], "/data/config.toml");
+        assert_ne!(el_v1, el_v2);
+        assert!(el_v1.exists());
+
+        prune_artifacts(&config_dir, &docker_compose.artifact_dirs, &[])?;
+        assert!(!el_v1.exists());
+        assert_eq!(std::fs::read_to_string(&el_v2)?, "v2");
+        assert!(cl.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_port_arg_uses_preferred_port() {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("port-test".to_string());
+
+        let spec = Spec::builder()
+            .image("test-image")
+            .arg(spec::Arg::Port {
+                name: "rpc-port".to_string(),
+                preferred: 8545,
+            })
+            .build();
+
+        let pod = Pod::default().with_spec("service", spec);
+        manifest.add_spec("pod".to_string(), pod);
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest).unwrap();
+        let service = docker_compose.services.get("pod-service").unwrap();
+
+        assert_eq!(
+            service.command,
+            ["8545"],
+            "Command should contain port arg with preferred port"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_healthcheck_uses_port_arg() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("healthcheck-test".to_string());
+        let spec = Spec::builder()
+            .image("test-image")
+            .arg(spec::Arg::Port {
+                name: "http".to_string(),
+                preferred: 5052,
+            })
+            .healthcheck(spec::HealthCheck::http("http", "/eth/v1/node/health").start_period(30))
+            .build();
+        manifest.add_spec("cl".to_string(), Pod::default().with_spec("node", spec));
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let healthcheck = docker_compose.services["cl-node"]
+            .healthcheck
+            .as_ref()
+            .unwrap();
+
+        assert_eq!(
+            healthcheck.test,
+            [
+                "CMD-SHELL",
+                "curl -fsS http://localhost:5052/eth/v1/node/health > /dev/null || wget -q -O /dev/null http://localhost:5052/eth/v1/node/health || exit 1"
+            ]
+        );
+        assert_eq!(healthcheck.start_period, "30s");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_public_udp_ports_are_published() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("udp-test".to_string());
+        let spec = Spec::builder()
+            .image("test-image")
+            .port(spec::Port::tcp("p2p", 30303).public())
+            .port(spec::Port::udp("discovery", 30303).public())
+            .port(spec::Port::tcp("internal", 8000))
+            .build();
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", spec));
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let yaml = serde_yaml::to_string(&docker_compose.services["el-node"].ports)?;
+
+        assert_eq!(yaml, "- 30303:30303\n- 30303:30303/udp\n");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_services_are_labeled_with_deployment() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("eth-2".to_string());
+        manifest.module = "ethereum".to_string();
+        manifest.chain = "sepolia".to_string();
+        let spec = Spec::builder()
+            .image("test-image")
+            .label("logging.job", "{{deployment}}/{{pod}}-{{spec}}")
+            .env("CHAIN", "{{chain}}")
+            .identity_key("/data/geth/nodekey")
+            .identity_key("/data/discovery-secret");
+        let pod = Pod::default().with_spec("node", spec);
+        manifest.add_spec("el".to_string(), pod);
+        let manifest_hash = manifest.content_hash()?;
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let service = docker_compose.services.get("el-node").unwrap();
+
+        assert_eq!(service.labels.get(DEPLOYMENT_LABEL).unwrap(), "eth-2");
+        assert_eq!(service.labels.get(POD_LABEL).unwrap(), "el");
+        assert_eq!(service.labels.get(CHAIN_LABEL).unwrap(), "sepolia");
+        assert_eq!(service.labels.get("logging.job").unwrap(), "eth-2/el-node");
+        assert_eq!(
+            service.labels.get(IDENTITY_KEYS_LABEL).unwrap(),
+            "/data/geth/nodekey,/data/discovery-secret"
+        );
+        assert_eq!(service.environment.get("CHAIN").unwrap(), "sepolia");
+
+        let yaml = serde_yaml::to_string(&docker_compose)?;
+        assert!(yaml.contains(&format!(
+            "x-bbuilder:\n      deployment: eth-2\n      module: ethereum\n      chain: sepolia\n      \
+             pod: el\n      spec: node\n      manifest_hash: {}",
+            manifest_hash
+        )));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compose_file_is_written_in_a_stable_order() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let manifest = || {
+            let mut manifest = Manifest::new("order-test".to_string());
+            for pod in ["vc", "el", "cl"] {
+                let spec = Spec::builder()
+                    .image("test-image")
+                    .env("B", "2")
+                    .env("A", "1")
+                    .label("z", "z")
+                    .label("a", "a");
+                manifest.add_spec(pod.to_string(), Pod::default().with_spec("node", spec));
+            }
+            manifest.add_dependency("vc", "cl");
+            manifest.add_dependency("vc", "el");
+            manifest
+        };
+
+        let yaml = serde_yaml::to_string(&runtime.convert_to_docker_compose_spec(manifest())?)?;
+        for _ in 0..5 {
+            let again =
+                serde_yaml::to_string(&runtime.convert_to_docker_compose_spec(manifest())?)?;
+            assert_eq!(yaml, again);
+        }
+        let position = |text: &str| yaml.find(text).unwrap();
+        assert!(position("  cl-node:") < position("  el-node:"));
+        assert!(position("  el-node:") < position("  vc-node:"));
+        assert!(position("      A: '1'") < position("      B: '2'"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_init_specs_run_in_order_before_the_pod() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        let pod = Pod::default()
+            .with_init_spec(
+                "genesis",
+                Spec::download(
+                    "https://example.com/genesis.json",
+                    "/data/genesis.json",
+                    Some(&"ab".repeat(32)),
+                ),
+            )
+            .with_init_spec("keys", Spec::builder().image("keys-image").build())
+            .with_spec(
+                "node",
+                Spec::builder()
+                    .image("test-image")
+                    .volume(Volume::data())
+                    .build(),
+            );
+        manifest.add_spec("el".to_string(), pod);
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+
+        let genesis = docker_compose.services.get("el-init-genesis").unwrap();
+        assert!(genesis.depends_on.is_empty());
+        assert!(genesis.command[0].contains("| sha256sum -c -"));
+        // the shell substitutions are not interpolated by compose
+        assert!(genesis.command[0].starts_with("mkdir -p \"$$(dirname"));
+        // the init spec writes into the data dir of the spec of the pod
+        assert!(
+            genesis
+                .volumes
+                .iter()
+                .any(|volume| volume.ends_with("/data/el/node:/data"))
+        );
+
+        let keys = docker_compose.services.get("el-init-keys").unwrap();
+        assert!(keys.depends_on.contains_key("el-init-genesis"));
+        assert_eq!(keys.labels.get(INIT_LABEL).unwrap(), "true");
+
+        let node = docker_compose.services.get("el-node").unwrap();
+        assert_eq!(node.depends_on.len(), 2);
+        assert!(matches!(
+            node.depends_on.get("el-init-keys"),
+            Some(Some(DependsOnCondition::ServiceCompletedSuccessfully))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_init_specs_run_as_the_uid_of_the_pod() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        let pod = Pod::default()
+            .with_init_spec("fetch", Spec::builder().image("fetch-image").build())
+            .with_init_spec("keys", Spec::builder().image("keys-image").uid(0).build())
+            .with_spec(
+                "node",
+                Spec::builder()
+                    .image("test-image")
+                    .uid(1000)
+                    .volume(Volume::data())
+                    .build(),
+            );
+        manifest.add_spec("cl".to_string(), pod);
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let user = |service: &str| docker_compose.services[service].user.clone();
+        assert_eq!(user("cl-init-fetch").as_deref(), Some("1000:1000"));
+        // an init spec keeps its own uid
+        assert_eq!(user("cl-init-keys").as_deref(), Some("0:0"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_hooks_of_a_spec() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        let pod = Pod::default()
+            .with_init_spec("keys", Spec::builder().image("keys-image").build())
+            .with_spec(
+                "node",
+                Spec::builder()
+                    .image("test-image")
+                    .arg("--http")
+                    .arg(spec::Arg::Port {
+                        name: "http".to_string(),
+                        preferred: 8545,
+                    })
+                    .volume(Volume::data())
+                    .pre_start("node migrate --datadir /data")
+                    .pre_start("node import-keys")
+                    .post_start("echo started")
+                    .pre_stop("kill -INT $(pidof node)")
+                    .build(),
+            );
+        manifest.add_spec("el".to_string(), pod);
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+
+        let pre_start = docker_compose.services.get("el-node-pre-start").unwrap();
+        assert_eq!(pre_start.entrypoint, vec!["sh", "-c"]);
+        assert_eq!(
+            pre_start.command,
+            vec!["node migrate --datadir /data && node import-keys"]
+        );
+        assert!(pre_start.ports.is_empty());
+        assert!(pre_start.depends_on.contains_key("el-init-keys"));
+        assert_eq!(pre_start.labels.get(INIT_LABEL).unwrap(), "true");
+        assert!(
+            pre_start
+                .volumes
+                .iter()
+                .any(|volume| volume.ends_with("/data/el/node:/data"))
+        );
+
+        let node = docker_compose.services.get("el-node").unwrap();
+        assert!(matches!(
+            node.depends_on.get("el-node-pre-start"),
+            Some(Some(DependsOnCondition::ServiceCompletedSuccessfully))
+        ));
+        assert_eq!(node.post_start[0].command, vec!["sh", "-c", "echo started"]);
+        assert_eq!(
+            node.pre_stop[0].command,
+            vec!["sh", "-c", "kill -INT $$(pidof node)"]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_declared_volumes_are_mounted() -> eyre::Result<()> {
+        let (temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        let spec = Spec::builder()
+            .image("test-image")
+            .volume(Volume::named("db", "/data/db"))
+            .volume(Volume::bind("logs", "logs", "/var/log"))
+            .volume(Volume::bind("host", "/mnt/nvme", "/data/static").read_only())
+            .volume(Volume::tmpfs("tmp", "/tmp", Some(64 * 1024 * 1024)))
+            .build();
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", spec));
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        assert!(docker_compose.volumes.contains_key("el-db"));
+
+        let service = docker_compose.services.get("el-node").unwrap();
+        let logs_path = temp_dir.path().join("test-manifest/logs").canonicalize()?;
+        assert_eq!(
+            service.volumes,
+            vec![
+                "el-db:/data/db".to_string(),
+                "/mnt/nvme:/data/static:ro".to_string(),
+                format!("{}:/var/log", logs_path.display()),
+            ]
+        );
+        assert_eq!(service.tmpfs, vec!["/tmp:size=67108864"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_secret_args_are_resolved_into_files() -> eyre::Result<()> {
+        let (temp_dir, runtime) = test_runtime();
+        let runtime = runtime.with_secret_providers(vec![Box::new(HashMap::from([(
+            "jwt".to_string(),
+            "0xdeadbeef".to_string(),
+        )]))]);
+
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        let spec = Spec::builder()
+            .image("test-image")
+            .arg2("--jwt", spec::Arg::secret("jwt"))
+            .build();
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", spec));
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let service = docker_compose.services.get("el-node").unwrap();
+        assert_eq!(service.command, vec!["--jwt", "/run/secrets/jwt"]);
+        assert!(
+            service
+                .volumes
+                .iter()
+                .any(|volume| volume.ends_with(":/run/secrets/jwt:ro"))
+        );
+
+        let secret_path = temp_dir.path().join("test-manifest/_secrets/jwt");
+        assert_eq!(std::fs::read_to_string(&secret_path)?, "0xdeadbeef");
+        let mode = std::fs::metadata(&secret_path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        let spec = Spec::builder()
+            .image("test-image")
+            .arg(spec::Arg::secret("missing"))
+            .build();
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", spec));
+        assert!(runtime.convert_to_docker_compose_spec(manifest).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_default_jwt_is_generated_once_per_deployment() -> eyre::Result<()> {
+        let (temp_dir, runtime) = test_runtime();
+        let manifest = |name: &str| {
+            let mut manifest = Manifest::new(name.to_string());
+            for pod in ["el", "cl"] {
+                let spec = Spec::builder()
+                    .image("test-image")
+                    .arg2("--jwt", spec::Arg::secret(spec::JWT_SECRET));
+                manifest.add_spec(pod.to_string(), Pod::default().with_spec("node", spec));
+            }
+            manifest
+        };
+        let jwt =
+            |name: &str| std::fs::read_to_string(temp_dir.path().join(name).join("_secrets/jwt"));
+
+        // the specs of the deployment share it and it survives a redeploy
+        runtime.convert_to_docker_compose_spec(manifest("eth"))?;
+        let first = jwt("eth")?;
+        assert_eq!(hex::decode(&first)?.len(), 32);
+        runtime.convert_to_docker_compose_spec(manifest("eth"))?;
+        assert_eq!(jwt("eth")?, first);
+
+        runtime.convert_to_docker_compose_spec(manifest("eth-2"))?;
+        assert_ne!(jwt("eth-2")?, first);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resource_stats_from_docker_sample() {
+        use bollard::models::{
+            ContainerBlkioStatEntry, ContainerBlkioStats, ContainerCpuUsage, ContainerMemoryStats,
+            ContainerNetworkStats,
+        };
+
+        let cpu = |total_usage, system_cpu_usage| ContainerCpuStats {
+            cpu_usage: Some(ContainerCpuUsage {
+                total_usage: Some(total_usage),
+                ..Default::default()
+            }),
+            system_cpu_usage: Some(system_cpu_usage),
+            online_cpus: Some(4),
+            ..Default::default()
+        };
+        let blkio = |op: &str, value| ContainerBlkioStatEntry {
+            op: Some(op.to_string()),
+            value: Some(value),
+            ..Default::default()
+        };
+
+        let sample = ContainerStatsResponse {
+            cpu_stats: Some(cpu(300, 2000)),
+            precpu_stats: Some(cpu(100, 1000)),
+            memory_stats: Some(ContainerMemoryStats {
+                usage: Some(1000),
+                limit: Some(4000),
+                stats: Some(HashMap::from([("inactive_file".to_string(), 200)])),
+                ..Default::default()
+            }),
+            networks: Some(HashMap::from([(
+                "eth0".to_string(),
+                ContainerNetworkStats {
+                    rx_bytes: Some(10),
+                    tx_bytes: Some(20),
+                    ..Default::default()
+                },
+            )])),
+            blkio_stats: Some(ContainerBlkioStats {
+                io_service_bytes_recursive: Some(vec![blkio("Read", 30), blkio("write", 40)]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            resource_stats(&sample),
+            ResourceStats {
+                cpu_percent: 80.0,
+                memory_bytes: 800,
+                memory_limit_bytes: 4000,
+                network_rx_bytes: 10,
+                network_tx_bytes: 20,
+                disk_read_bytes: 30,
+                disk_write_bytes: 40,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pod_dependencies_are_compose_depends_on() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        let el = Spec::builder()
+            .image("el-image")
+            .healthcheck(spec::HealthCheck::command(["true"]))
+            .build();
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", el));
+        let cl = Spec::builder().image("cl-image").build();
+        manifest.add_spec("cl".to_string(), Pod::default().with_spec("node", cl));
+        let vc = Spec::builder().image("vc-image").build();
+        manifest.add_spec("vc".to_string(), Pod::default().with_spec("node", vc));
+        manifest.add_dependency("cl", "el");
+        manifest.add_dependency("vc", "cl");
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+
+        let cl = docker_compose.services.get("cl-node").unwrap();
+        assert!(matches!(
+            cl.depends_on.get("el-node"),
+            Some(Some(DependsOnCondition::ServiceHealthy))
+        ));
+        let vc = docker_compose.services.get("vc-node").unwrap();
+        assert!(matches!(
+            vc.depends_on.get("cl-node"),
+            Some(Some(DependsOnCondition::ServiceStarted))
+        ));
+        assert!(docker_compose.services["el-node"].depends_on.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dependent_pods_wait_for_the_babel_of_the_node() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        let el = Spec::builder()
+            .image("el-image")
+            .arg2("--http.port", spec::port!("http", 8545))
+            .build();
+        manifest.add_spec(
+            "el".to_string(),
+            Pod::default()
+                .with_spec("node", el)
+                .with_babel(spec::Babel::new("ethereum", spec::ref_!("el", "http"))),
+        );
+        let cl = Spec::builder().image("cl-image").build();
+        manifest.add_spec("cl".to_string(), Pod::default().with_spec("node", cl));
+        manifest.add_dependency("cl", "el");
+
+        // babel is skipped unless enabled, its image is built locally
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest.clone())?;
+        assert!(!docker_compose.services.contains_key("el-babel"));
+        assert!(matches!(
+            docker_compose.services["cl-node"].depends_on.get("el-node"),
+            Some(Some(DependsOnCondition::ServiceStarted))
+        ));
+
+        let runtime = runtime.with_babel(true);
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+
+        let babel = &docker_compose.services["el-babel"];
+        assert_eq!(
+            babel.healthcheck.as_ref().unwrap().test,
+            vec!["CMD", "babel", "probe", "http://127.0.0.1:3000/health"]
+        );
+        assert_eq!(babel.pull_policy.as_deref(), Some("never"));
+        let cl = &docker_compose.services["cl-node"];
+        assert!(matches!(
+            cl.depends_on.get("el-babel"),
+            Some(Some(DependsOnCondition::ServiceHealthy))
+        ));
+        assert!(matches!(
+            cl.depends_on.get("el-node"),
+            Some(Some(DependsOnCondition::ServiceStarted))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_paths_of_the_spec_are_chowned_to_its_uid() -> eyre::Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let (temp_dir, runtime) = test_runtime();
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        let spec = Spec::builder()
+            .image("test-image")
+            .uid(1234)
+            .volume(Volume::data())
+            .artifact(Artifacts::File(File {
+                name: "config.toml".to_string(),
+                target_path: "/config/config.toml".to_string(),
+                content: "key = 1".into(),
+                ..Default::default()
+            }))
+            .arg(spec::Arg::secret("jwt"))
+            .build();
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", spec));
+
+        // an unprivileged bbuilder can not give the paths away, it does not fail
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let service = &docker_compose.services["el-node"];
+        assert_eq!(service.user.as_deref(), Some("1234:1234"));
+
+        let (owner, group) = if euid() == 0 {
+            (1234, Some(1234))
+        } else {
+            (euid(), None)
+        };
+        let paths = [
+            temp_dir.path().join("test-manifest/data/el/node"),
+            mounted_host_path(service, "/config/config.toml"),
+            mounted_host_path(service, &spec::secret_path("jwt")),
+        ];
+        for path in paths {
+            let metadata = std::fs::metadata(&path)?;
+            assert_eq!(metadata.uid(), owner, "{}", path.display());
+            if let Some(group) = group {
+                assert_eq!(metadata.gid(), group, "{}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    // effective uid of the test process
+    fn euid() -> u32 {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::metadata("/proc/self").unwrap().uid()
+    }
+
+    #[tokio::test]
+    async fn test_rootless_podman_chowns_the_new_paths_of_the_spec() -> eyre::Result<()> {
+        let (temp_dir, runtime) = test_runtime();
+        let runtime = runtime.with_engine(Engine::Podman { rootless: true });
+
+        let manifest = || {
+            let mut manifest = Manifest::new("test-manifest".to_string());
+            let spec = Spec::builder()
+                .image("sigp/lighthouse")
+                .tag("v5.3.0")
+                .uid(1000)
+                .volume(Volume::data())
+                .volume(Volume::bind("host", "/mnt/nvme", "/data/static"))
+                .arg(spec::Arg::secret("jwt"))
+                .build();
+            manifest.add_spec("cl".to_string(), Pod::default().with_spec("node", spec));
+            manifest
+        };
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest())?;
+        let service = &docker_compose.services["cl-node"];
+        // podman does not resolve the short names of docker hub
+        assert_eq!(service.image, "docker.io/sigp/lighthouse:v5.3.0");
+
+        // the uids are mapped by podman, the paths are chowned in its user
+        // namespace instead of on every mount
+        let data_path = temp_dir
+            .path()
+            .join("test-manifest/data/cl/node")
+            .canonicalize()?;
+        assert!(
+            service
+                .volumes
+                .contains(&format!("{}:/data", data_path.display()))
+        );
+        assert!(
+            service
+                .volumes
+                .iter()
+                .any(|volume| volume.ends_with("/run/secrets/jwt:ro"))
+        );
+        assert!(
+            docker_compose
+                .owned_paths
+                .contains(&(data_path.clone(), 1000))
+        );
+        assert!(
+            docker_compose
+                .owned_paths
+                .iter()
+                .any(|(path, uid)| path.ends_with("_secrets/jwt") && *uid == 1000)
+        );
+        // the host paths are not owned by the spec
+        assert!(
+            !docker_compose
+                .owned_paths
+                .iter()
+                .any(|(path, _)| path.starts_with("/mnt/nvme"))
+        );
+
+        // the data dir already exists on the next deploy, its data is not chowned again
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest())?;
+        assert!(
+            !docker_compose
+                .owned_paths
+                .iter()
+                .any(|(path, _)| path == &data_path)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rootless_podman_deploys_again_over_the_chowned_paths() -> eyre::Result<()> {
+        let (temp_dir, runtime) = test_runtime();
+        // podman that records its unshare commands
+        let log = temp_dir.path().join("podman.log");
+        let podman = temp_dir.path().join("podman");
+        std::fs::write(
+            &podman,
+            format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display()),
+        )?;
+        std::fs::set_permissions(&podman, std::fs::Permissions::from_mode(0o755))?;
+        let runtime = runtime
+            .with_engine(Engine::Podman { rootless: true })
+            .with_compose_command(vec!["true".to_string()])
+            .with_podman_command(podman.display().to_string());
+
+        let manifest = || {
+            let mut manifest = Manifest::new("test-manifest".to_string());
+            let spec = Spec::builder()
+                .image("test-image")
+                .unprivileged(1000)
+                .artifact(Artifacts::Dir(spec::Dir {
+                    name: "keys".to_string(),
+                    target_path: "/keys".to_string(),
+                    files: vec![spec::DirFile {
+                        path: "key.json".to_string(),
+                        content: "{}".to_string(),
+                    }],
+                    ..Default::default()
+                }))
+                .arg2("--jwt", spec::Arg::secret(spec::JWT_SECRET))
+                .build();
+            manifest.add_spec("cl".to_string(), Pod::default().with_spec("node", spec));
+            manifest
+        };
+        let deployment_dir = temp_dir.path().join("test-manifest");
+        let jwt = || std::fs::read_to_string(deployment_dir.join("_generated/jwt"));
+
+        runtime.run(manifest()).await?;
+        let first = jwt()?;
+        let commands = std::fs::read_to_string(&log)?;
+        assert_eq!(commands.lines().count(), 1);
+        assert!(commands.starts_with("unshare chown 1000:1000 "));
+
+        // the next deploy gives the paths back before it writes them and
+        // keeps the generated jwt out of them
+        std::fs::remove_file(&log)?;
+        runtime.run(manifest()).await?;
+        let commands = std::fs::read_to_string(&log)?;
+        let commands: Vec<&str> = commands.lines().collect();
+        assert_eq!(
+            commands[0],
+            format!(
+                "unshare chown -R 0:0 {} {}",
+                deployment_dir.join("_config").display(),
+                deployment_dir.join("_secrets").display()
+            )
+        );
+        assert!(commands[1].starts_with("unshare chown 1000:1000 "));
+        assert_eq!(jwt()?, first);
+        assert_eq!(
+            std::fs::read_to_string(deployment_dir.join("_secrets/jwt"))?,
+            first
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_allocated_host_ports_are_published() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let node = || {
+            Spec::builder().image("test-image").arg(spec::Arg::Port {
+                name: "authrpc".to_string(),
+                preferred: 8551,
+            })
+        };
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        manifest.add_spec("a".to_string(), Pod::default().with_spec("node", node()));
+        manifest.add_spec("b".to_string(), Pod::default().with_spec("node", node()));
+        manifest.allocate_host_ports(&Default::default());
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let port = |service: &str| {
+            let port = &docker_compose.services[service].ports[0];
+            (port.host, port.container)
+        };
+        assert_eq!(port("a-node"), (8551, 8551));
+        assert_eq!(port("b-node"), (8552, 8551));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_health_from_container_status() {
+        assert_eq!(parse_health("Up 2 minutes (healthy)"), Health::Healthy);
+        assert_eq!(
+            parse_health("Up 5 seconds (health: starting)"),
+            Health::Starting
+        );
+        assert_eq!(parse_health("Up 1 hour (unhealthy)"), Health::Unhealthy);
+        assert_eq!(parse_health("Exited (1) 3 seconds ago"), Health::Unknown);
+    }
+
+    // docker api on a local port that lists the containers of a deployment
+    // and accepts the requests on them, the failing paths reply with an error.
+    // The requests are recorded as "<method> <path>" without the api version.
+    async fn mock_docker(
+        containers: &str,
+        failing: &'static [&'static str],
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = format!("tcp://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let containers = containers.to_string();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let requests = recorded.clone();
+                let containers = containers.clone();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 4096];
+                    loop {
+                        let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+                            match stream.read(&mut chunk).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                            }
+                            continue;
+                        };
+                        let head = String::from_utf8_lossy(&buf[..end]).to_string();
+                        buf.drain(..end + 4);
+
+                        let mut line = head.split_whitespace();
+                        let method = line.next().unwrap_or_default();
+                        let path = line.next().unwrap_or_default();
+                        let path = path.split('?').next().unwrap_or_default();
+                        let path = &path[path.find("/containers").unwrap_or(0)..];
+                        let request = format!("{} {}", method, path);
+                        requests.lock().unwrap().push(request.clone());
+
+                        let (status, body) = if failing.contains(&request.as_str()) {
+                            (
+                                "500 Internal Server Error",
+                                r#"{"message":"failed"}"#.to_string(),
+                            )
+                        } else if path == "/containers/json" {
+                            ("200 OK", containers.clone())
+                        } else if path.starts_with("/containers/") {
+                            ("204 No Content", String::new())
+                        } else {
+                            ("404 Not Found", r#"{"message":"not found"}"#.to_string())
+                        };
+                        let response = format!(
+                            "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                            status,
+                            body.len(),
+                            body
+                        );
+                        if stream.write_all(response.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        (host, requests)
+    }
+
+    const PAUSED_CONTAINERS: &str = r#"[
+        {"Id": "node-1", "Created": 1, "State": "running", "Labels": {"bbuilder.deployment": "test"}},
+        {"Id": "node-2", "Created": 2, "State": "running", "Labels": {"bbuilder.deployment": "test"}}
+    ]"#;
+
+    #[tokio::test]
+    async fn test_pause_and_resume_the_specs_of_a_deployment() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir_path = temp_dir.path().to_str().unwrap().to_string();
+        let (host, requests) = mock_docker(PAUSED_CONTAINERS, &[]).await;
+        let runtime = DockerRuntime::with_docker_host(dir_path.clone(), Some(host));
+
+        assert!(runtime.resume("test").await.is_err());
+
+        runtime.pause("test").await?;
+        assert!(is_paused(&dir_path, "test"));
+        assert!(runtime.pausing.lock().unwrap().is_empty());
+
+        runtime.resume("test").await?;
+        assert!(!is_paused(&dir_path, "test"));
+
+        let requests: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.starts_with("POST"))
+            .cloned()
+            .collect();
+        assert_eq!(
+            requests,
+            vec![
+                "POST /containers/node-1/stop",
+                "POST /containers/node-2/stop",
+                "POST /containers/node-1/start",
+                "POST /containers/node-2/start",
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deployment_is_not_paused_when_a_stop_fails() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir_path = temp_dir.path().to_str().unwrap().to_string();
+        let (host, _) = mock_docker(PAUSED_CONTAINERS, &["POST /containers/node-2/stop"]).await;
+        let runtime = DockerRuntime::with_docker_host(dir_path.clone(), Some(host));
+
+        assert!(runtime.pause("test").await.is_err());
+        assert!(!is_paused(&dir_path, "test"));
+        assert!(runtime.pausing.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_destroy_removes_the_paused_marker() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir_path = temp_dir.path().to_str().unwrap().to_string();
+        let (host, requests) = mock_docker(PAUSED_CONTAINERS, &[]).await;
+        let runtime = DockerRuntime::with_docker_host(dir_path.clone(), Some(host));
+
+        runtime.pause("test").await?;
+        runtime.destroy("test").await?;
+        assert!(!is_paused(&dir_path, "test"));
+        assert!(
+            requests
+                .lock()
+                .unwrap()
+                .contains(&"DELETE /containers/node-2".to_string())
+        );
+
+        // destroying a deployment that is not paused is fine
+        runtime.destroy("test").await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_crash_looping_containers_are_reported_after_a_restart() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir_path = temp_dir.path().to_str().unwrap().to_string();
+        let containers = r#"[
+            {"Id": "node-1", "State": "exited", "Labels": {"bbuilder.deployment": "test"}},
+            {"Id": "node-2", "State": "running", "Labels": {"bbuilder.deployment": "test"}}
+        ]"#;
+        let (host, _) = mock_docker(containers, &[]).await;
+
+        // marked by the supervisor of a previous runtime
+        mark_crash_looping(&dir_path, "test", "node-1")?;
+        let runtime = DockerRuntime::with_docker_host(dir_path.clone(), Some(host));
+        let states: Vec<String> = runtime
+            .status("test")
+            .await?
+            .into_iter()
+            .map(|status| status.state)
+            .collect();
+        assert_eq!(states, vec![CRASH_LOOP, "running"]);
+
+        runtime.destroy("test").await?;
+        assert!(!is_crash_looping(&dir_path, "test", "node-1"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_artifacts_are_pruned_once_the_services_are_up() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir_path = temp_dir.path().to_str().unwrap().to_string();
+        let (host, _) = mock_docker("[]", &[]).await;
+        let runtime = |compose: &str| {
+            DockerRuntime::with_docker_host(dir_path.clone(), Some(host.clone()))
+                .with_compose_command(vec![compose.to_string()])
+        };
+        let manifest = |content: &str| {
+            let config = Artifacts::File(File {
+                name: "config.toml".to_string(),
+                target_path: "/data/config.toml".to_string(),
+                content: content.into(),
+                ..Default::default()
+            });
+            let mut manifest = Manifest::new("test".to_string());
+            let node = Spec::builder().image("reth").artifact(config);
+            manifest.add_spec("el".to_string(), Pod::default().with_spec("node", node));
+            manifest
+        };
+        let artifacts = || {
+            std::fs::read_dir(temp_dir.path().join("test/_config/el-node"))
+                .unwrap()
+                .count()
+        };
+
+        runtime("true").run(manifest("v1")).await?;
+        assert_eq!(artifacts(), 1);
+
+        // the services of the previous deploy still run with their artifacts
+        assert!(runtime("false").run(manifest("v2")).await.is_err());
+        assert_eq!(artifacts(), 2);
+
+        runtime("true").run(manifest("v2")).await?;
+        assert_eq!(artifacts(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_init_specs_mount_the_volumes_of_every_spec() -> eyre::Result<()> {
+        let (_temp_dir, runtime) = test_runtime();
+
+        let mut manifest = Manifest::new("test-manifest".to_string());
+        let pod = Pod::default()
+            .with_init_spec("keys", Spec::builder().image("keys-image").build())
+            .with_spec(
+                "node",
+                Spec::builder()
+                    .image("node-image")
+                    .volume(Volume::data())
+                    .build(),
+            )
+            .with_spec(
+                "signer",
+                Spec::builder()
+                    .image("signer-image")
+                    .volume(Volume::bind("data", "data/{{pod}}/{{spec}}", "/signer"))
+                    .build(),
+            )
+            .with_spec(
+                "validator",
+                Spec::builder()
+                    .image("validator-image")
+                    .volume(Volume::data())
+                    .build(),
+            );
+        manifest.add_spec("el".to_string(), pod);
+
+        let docker_compose = runtime.convert_to_docker_compose_spec(manifest)?;
+        let keys = &docker_compose.services["el-init-keys"];
+        let mounts: Vec<&str> = keys
+            .volumes
+            .iter()
+            .filter_map(|volume| volume.split_once("/data/el/").map(|(_, mount)| mount))
+            .collect();
+        // the data dir of the second spec on the same target is not mounted
+        assert_eq!(mounts, vec!["node:/data", "signer:/signer"]);
+        Ok(())
     }
 }
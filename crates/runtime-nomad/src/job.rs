@@ -0,0 +1,869 @@
+//! Nomad job spec of a manifest, in the JSON format of the Nomad HTTP API.
+//! Every pod is a task group and every spec a docker task of it, the init
+//! specs run as prestart tasks.
+
+use serde::Serialize;
+use spec::{Arg, Artifacts, HealthCheck, Manifest, Metadata, Probe, RefTarget, Spec, VolumeKind};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// prefix of the env vars with the address of the node that runs a ref of
+// another pod, rendered from the Nomad service of its port
+const ADDRESS_VAR_PREFIX: &str = "BBUILDER_ADDR";
+
+// delimiters of the templates with a literal content, consul-template finds
+// no action in them and the `{{` of the configs is left as is
+const LITERAL_LEFT_DELIM: &str = "[[bbuilder-literal";
+const LITERAL_RIGHT_DELIM: &str = "bbuilder-literal]]";
+
+// markers of the init tasks that completed, in the alloc dir shared by the
+// tasks of a group
+const INIT_MARKERS_DIR: &str = "/alloc/bbuilder";
+
+// prefix of the Nomad variables with the secrets of a deployment
+pub const SECRETS_VARIABLE_PREFIX: &str = "bbuilder";
+
+// the secrets are rendered into the secrets dir of the task
+const SECRETS_TASK_DIR: &str = "secrets/";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Job {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "Type")]
+    pub kind: String,
+    pub datacenters: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    pub meta: BTreeMap<String, String>,
+    pub task_groups: Vec<TaskGroup>,
+}
+
+impl Job {
+    /// Secrets the tasks read from the variable of the deployment
+    pub fn secrets(&self) -> BTreeSet<String> {
+        self.task_groups
+            .iter()
+            .flat_map(|group| &group.tasks)
+            .flat_map(|task| &task.templates)
+            .filter_map(|template| template.dest_path.strip_prefix(SECRETS_TASK_DIR))
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TaskGroup {
+    pub name: String,
+    pub count: u32,
+    pub networks: Vec<Network>,
+    pub tasks: Vec<Task>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Network {
+    pub mode: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reserved_ports: Vec<NetworkPort>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dynamic_ports: Vec<NetworkPort>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct NetworkPort {
+    pub label: String,
+    // host port, 0 for the dynamic ports
+    pub value: u16,
+    // port of the container
+    pub to: u16,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Task {
+    pub name: String,
+    pub driver: String,
+    // config of the docker driver, its keys are snake case
+    pub config: serde_json::Value,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<Template>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<TaskArtifact>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lifecycle: Option<Lifecycle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Resources>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kill_signal: Option<String>,
+    // nanoseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kill_timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<Service>,
+}
+
+/// File rendered by Nomad into the task dir
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Template {
+    pub embedded_tmpl: String,
+    // relative to the task dir
+    pub dest_path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub perms: Option<String>,
+    // the rendered file is read back as env vars of the task
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub envvars: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left_delim: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub right_delim: Option<String>,
+}
+
+/// File downloaded by Nomad into the task dir before the task starts
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TaskArtifact {
+    pub getter_source: String,
+    pub getter_mode: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub getter_options: BTreeMap<String, String>,
+    pub relative_dest: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Lifecycle {
+    pub hook: String,
+    pub sidecar: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Resources {
+    #[serde(rename = "MemoryMB")]
+    pub memory_mb: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Service {
+    pub name: String,
+    pub provider: String,
+    pub port_label: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<Check>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Check {
+    pub name: String,
+    #[serde(rename = "Type")]
+    pub kind: String,
+    pub path: String,
+    // nanoseconds
+    pub interval: u64,
+    pub timeout: u64,
+}
+
+/// Where the job runs, see `NomadRuntime`
+pub struct JobOptions<'a> {
+    pub datacenters: &'a [String],
+    pub namespace: Option<&'a str>,
+    // directory of the Nomad clients the relative bind mounts are placed under
+    pub data_dir: &'a str,
+}
+
+/// Name of the task of an init spec, the other tasks are named after their spec
+pub fn init_task_name(name: &str) -> String {
+    format!("init-{}", name)
+}
+
+/// Name of the prestart task with the pre start commands of the spec
+pub fn pre_start_task_name(spec: &str) -> String {
+    format!("{}-pre-start", spec)
+}
+
+// labels of the ports are also the names of the `NOMAD_PORT_<label>` vars
+fn port_label(spec: &str, port: &str) -> String {
+    format!("{}_{}", spec, port).replace('-', "_")
+}
+
+/// Nomad service of a published port, the other pods find the node that
+/// runs the spec through it
+pub fn port_service_name(deployment: &str, pod: &str, spec: &str, port: &str) -> String {
+    format!("{}-{}-{}-{}", deployment, pod, spec, port).replace('_', "-")
+}
+
+// env var with the address of the node of the port, set by a template
+fn address_var(pod: &str, spec: &str, port: &str) -> String {
+    format!("{}_{}_{}_{}", ADDRESS_VAR_PREFIX, pod, spec, port)
+        .replace('-', "_")
+        .to_uppercase()
+}
+
+fn nanoseconds(seconds: u64) -> u64 {
+    seconds * 1_000_000_000
+}
+
+/// Renders the job of the manifest, the url and generated contents of its
+/// env files must be resolved already
+pub fn render(manifest: &Manifest, options: &JobOptions) -> eyre::Result<Job> {
+    let refs = manifest.resolve_refs()?;
+    let host_ports = manifest.host_ports();
+    let manifest_hash = manifest.content_hash()?;
+
+    let mut task_groups = vec![];
+    for (pod_name, pod) in &manifest.pods {
+        // The tasks of a group share its network namespace, the refs to the
+        // specs of the pod are reached on localhost and the ones to other
+        // pods on the host ports of the node that runs them, which can be
+        // another node of the cluster
+        let mut pod_refs = HashMap::new();
+        let mut extra_hosts = BTreeSet::new();
+        let mut addresses = BTreeMap::new();
+        for (key, target) in &refs {
+            let target = if &target.pod == pod_name {
+                extra_hosts.insert(format!("{}:127.0.0.1", target.host()));
+                target.clone()
+            } else {
+                let host_port = host_ports
+                    .iter()
+                    .find(|host_port| {
+                        host_port.pod == target.pod
+                            && host_port.spec == target.spec
+                            && host_port.port == target.port
+                    })
+                    .ok_or_else(|| {
+                        eyre::eyre!(
+                            "Ref {}:{} of pod {} is to a port that is not published on the host",
+                            key.0,
+                            key.1,
+                            pod_name
+                        )
+                    })?;
+                let var = address_var(&target.pod, &target.spec, &host_port.name);
+                extra_hosts.insert(format!("{}:${{{}}}", target.host(), var));
+                addresses.insert(
+                    var,
+                    port_service_name(&manifest.name, &target.pod, &target.spec, &host_port.name),
+                );
+                RefTarget {
+                    port: host_port.host_port,
+                    ..target.clone()
+                }
+            };
+            pod_refs.insert(key.clone(), target);
+        }
+        let converter = TaskConverter {
+            manifest,
+            options,
+            pod: pod_name,
+            refs: &pod_refs,
+            extra_hosts: extra_hosts.into_iter().collect(),
+            addresses: &addresses,
+        };
+
+        let mut network = Network {
+            mode: "bridge".to_string(),
+            reserved_ports: vec![],
+            dynamic_ports: vec![],
+        };
+        let mut tasks = vec![];
+        // the init specs write the data of the pod as the uid of its specs
+        let pod_uid = pod.specs.values().find_map(|spec| spec.uid);
+        let mut init_tasks: Vec<(String, Spec)> = pod
+            .init_specs
+            .iter()
+            .map(|init| {
+                let mut spec = init.spec.clone();
+                spec.uid = spec.uid.or(pod_uid);
+                (init_task_name(&init.name), spec)
+            })
+            .collect();
+        for (spec_name, spec) in &pod.specs {
+            if spec.image == "babel" {
+                // the image is built locally and never pulled, the Nomad
+                // clients can not run it until it is published
+                continue;
+            }
+            if let Some(pre_start) = spec.pre_start_spec() {
+                init_tasks.push((pre_start_task_name(spec_name), pre_start));
+            }
+
+            let mut task = converter.task(spec_name, spec_name, spec)?;
+            for (name, port) in spec_ports(spec) {
+                let host_port = host_ports.iter().find(|host_port| {
+                    &host_port.pod == pod_name
+                        && &host_port.spec == spec_name
+                        && host_port.name == name
+                });
+                let label = port_label(spec_name, &name);
+                match host_port {
+                    Some(host_port) => {
+                        task.services.push(Service {
+                            name: port_service_name(&manifest.name, pod_name, spec_name, &name),
+                            provider: "nomad".to_string(),
+                            port_label: label.clone(),
+                            checks: vec![],
+                        });
+                        network.reserved_ports.push(NetworkPort {
+                            label,
+                            value: host_port.host_port,
+                            to: port,
+                        });
+                    }
+                    None => network.dynamic_ports.push(NetworkPort {
+                        label,
+                        value: 0,
+                        to: port,
+                    }),
+                }
+            }
+            tasks.push(task);
+        }
+
+        // Nomad starts the prestart tasks together, each init task waits for
+        // the marker of the previous one in the alloc dir
+        let mut previous: Option<String> = None;
+        for (name, spec) in init_tasks {
+            let mut task = converter.task(&name, &name, &spec)?;
+            task.lifecycle = Some(Lifecycle {
+                hook: "prestart".to_string(),
+                sidecar: false,
+            });
+            if spec.entrypoint.is_empty() {
+                eyre::bail!(
+                    "Init task {} of pod {} needs an entrypoint to be ordered",
+                    name,
+                    pod_name
+                );
+            }
+            let wait = match &previous {
+                Some(previous) => format!(
+                    "until [ -f {dir}/{previous}.done ]; do sleep 1; done; ",
+                    dir = INIT_MARKERS_DIR,
+                ),
+                None => String::new(),
+            };
+            let mut entrypoint = vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!(
+                    "{wait}\"$@\" && mkdir -p {dir} && touch {dir}/{name}.done",
+                    dir = INIT_MARKERS_DIR,
+                ),
+                "sh".to_string(),
+            ];
+            entrypoint.extend(spec.entrypoint.iter().cloned());
+            task.config["entrypoint"] = entrypoint.into();
+            tasks.push(task);
+            previous = Some(name);
+        }
+
+        task_groups.push(TaskGroup {
+            name: pod_name.clone(),
+            count: 1,
+            networks: vec![network],
+            tasks,
+        });
+    }
+
+    Ok(Job {
+        id: manifest.name.clone(),
+        name: manifest.name.clone(),
+        kind: "service".to_string(),
+        datacenters: options.datacenters.to_vec(),
+        namespace: options.namespace.map(str::to_string),
+        meta: BTreeMap::from([
+            ("bbuilder.deployment".to_string(), manifest.name.clone()),
+            ("bbuilder.module".to_string(), manifest.module.clone()),
+            ("bbuilder.chain".to_string(), manifest.chain.clone()),
+            ("bbuilder.manifest-hash".to_string(), manifest_hash),
+        ]),
+        task_groups,
+    })
+}
+
+/// Port args and declared ports of the spec
+fn spec_ports(spec: &Spec) -> BTreeMap<String, u16> {
+    spec.args
+        .iter()
+        .filter_map(|arg| match arg {
+            Arg::Port { name, preferred } => Some((name.clone(), *preferred)),
+            _ => None,
+        })
+        .chain(spec.ports.iter().map(|port| (port.name.clone(), port.port)))
+        .collect()
+}
+
+struct TaskConverter<'a> {
+    manifest: &'a Manifest,
+    options: &'a JobOptions<'a>,
+    pod: &'a str,
+    refs: &'a HashMap<(String, String), RefTarget>,
+    extra_hosts: Vec<String>,
+    // env vars of the node addresses of the extra hosts by Nomad service
+    addresses: &'a BTreeMap<String, String>,
+}
+
+impl TaskConverter<'_> {
+    fn task(&self, name: &str, metadata_spec: &str, spec: &Spec) -> eyre::Result<Task> {
+        let metadata = Metadata {
+            deployment: &self.manifest.name,
+            pod: self.pod,
+            spec: metadata_spec,
+            chain: &self.manifest.chain,
+        };
+        let ports: HashMap<String, u16> = spec_ports(spec).into_iter().collect();
+        let port_labels: BTreeSet<String> = ports
+            .keys()
+            .map(|port| port_label(metadata_spec, port))
+            .collect();
+
+        let mut args = vec![];
+        let mut files = spec.artifacts.clone();
+        let mut secrets = BTreeSet::new();
+        for arg in &spec.args {
+            match arg {
+                Arg::Value(value) => args.push(value.clone()),
+                Arg::Dir { path, .. } => args.push(path.clone()),
+                Arg::Port { preferred, .. } => args.push(preferred.to_string()),
+                Arg::File(file) => files.push(Artifacts::File(file.clone())),
+                Arg::Secret { name } => {
+                    secrets.insert(name.clone());
+                    args.push(spec::secret_path(name));
+                }
+                Arg::Ref { .. } | Arg::Refs { .. } => {
+                    let urls = arg
+                        .refs()
+                        .into_iter()
+                        .map(|(name, port)| {
+                            self.refs
+                                .get(&(name.clone(), port.clone()))
+                                .map(RefTarget::url)
+                                .ok_or_else(|| {
+                                    eyre::eyre!("Ref {} with port {} does not exist", name, port)
+                                })
+                        })
+                        .collect::<eyre::Result<Vec<_>>>()?;
+                    args.push(urls.join(","));
+                }
+            }
+        }
+
+        let mut mounts = vec![];
+        let mut templates = vec![];
+        let mut artifacts = vec![];
+        for volume in spec.volumes.values() {
+            let mut mount = match &volume.kind {
+                VolumeKind::Named => serde_json::json!({
+                    "type": "volume",
+                    "source": format!("{}-{}-{}", self.manifest.name, self.pod, volume.name),
+                    "target": volume.target,
+                }),
+                VolumeKind::External { source } => serde_json::json!({
+                    "type": "volume",
+                    "source": source,
+                    "target": volume.target,
+                }),
+                VolumeKind::Bind { host_path } => {
+                    let host_path = metadata.render(host_path);
+                    let source = if host_path.starts_with('/') {
+                        host_path
+                    } else {
+                        format!(
+                            "{}/{}/{}",
+                            self.options.data_dir.trim_end_matches('/'),
+                            self.manifest.name,
+                            host_path
+                        )
+                    };
+                    serde_json::json!({"type": "bind", "source": source, "target": volume.target})
+                }
+                VolumeKind::Tmpfs { size } => {
+                    let mut mount = serde_json::json!({"type": "tmpfs", "target": volume.target});
+                    if let Some(size) = size {
+                        mount["tmpfs_options"] = serde_json::json!({"size": size});
+                    }
+                    mount
+                }
+            };
+            if volume.read_only {
+                mount["readonly"] = serde_json::json!(true);
+            }
+            mounts.push(mount);
+        }
+
+        for artifact in files {
+            match artifact {
+                Artifacts::File(mut file) => {
+                    let dest_path = format!("local/{}", file.name);
+                    if let spec::ArtifactSource::Url { url } = &file.content {
+                        // Nomad downloads it on the client, it is not rendered
+                        let mut getter_options = BTreeMap::new();
+                        if let Some(sha256) = &file.sha256 {
+                            getter_options
+                                .insert("checksum".to_string(), format!("sha256:{}", sha256));
+                        }
+                        artifacts.push(TaskArtifact {
+                            getter_source: url.clone(),
+                            getter_mode: "file".to_string(),
+                            getter_options,
+                            relative_dest: dest_path.clone(),
+                        });
+                    } else {
+                        file.content.resolve(&HashMap::new())?;
+                        file.verify_checksum()?;
+                        secrets.extend(file.secrets());
+                        templates.push(Template {
+                            perms: file.mode.map(|mode| format!("{:o}", mode)),
+                            ..literal(file.render(&metadata, self.refs, &ports)?, &dest_path)
+                        });
+                    }
+                    mounts.push(serde_json::json!({
+                        "type": "bind",
+                        "source": dest_path,
+                        "target": file.target_path,
+                        "readonly": file.read_only,
+                    }));
+                }
+                Artifacts::Dir(dir) => {
+                    let dest_dir = format!("local/{}", dir.name);
+                    for file in &dir.files {
+                        let relative = std::path::Path::new(&file.path);
+                        let is_relative = relative
+                            .components()
+                            .all(|component| matches!(component, std::path::Component::Normal(_)));
+                        if !is_relative {
+                            eyre::bail!("File {} of dir {} is outside of it", file.path, dir.name);
+                        }
+                        templates.push(literal(
+                            file.content.clone(),
+                            &format!("{}/{}", dest_dir, file.path),
+                        ));
+                    }
+                    mounts.push(serde_json::json!({
+                        "type": "bind",
+                        "source": dest_dir,
+                        "target": dir.target_path,
+                        "readonly": dir.read_only,
+                    }));
+                }
+            }
+        }
+
+        // The secrets are read by Nomad from a variable of the deployment so
+        // that they are not part of the job
+        for name in &secrets {
+            templates.push(Template {
+                embedded_tmpl: format!(
+                    "{{{{ with nomadVar \"{}/{}\" }}}}{{{{ index . \"{}\" }}}}{{{{ end }}}}",
+                    SECRETS_VARIABLE_PREFIX, self.manifest.name, name
+                ),
+                dest_path: format!("{}{}", SECRETS_TASK_DIR, name),
+                perms: Some("600".to_string()),
+                envvars: false,
+                left_delim: None,
+                right_delim: None,
+            });
+            mounts.push(serde_json::json!({
+                "type": "bind",
+                "source": format!("{}{}", SECRETS_TASK_DIR, name),
+                "target": spec::secret_path(name),
+                "readonly": true,
+            }));
+        }
+
+        // Nomad interpolates the addresses into the extra hosts, the task is
+        // restarted when the other pod moves to another node
+        if !self.addresses.is_empty() {
+            let content = self
+                .addresses
+                .iter()
+                .map(|(var, service)| {
+                    format!(
+                        "{{{{ range nomadService \"{}\" }}}}{}={{{{ .Address }}}}\n{{{{ end }}}}",
+                        service, var
+                    )
+                })
+                .collect();
+            templates.push(Template {
+                embedded_tmpl: content,
+                dest_path: "local/addresses.env".to_string(),
+                perms: None,
+                envvars: true,
+                left_delim: None,
+                right_delim: None,
+            });
+        }
+
+        if !spec.env_file.is_empty() {
+            let mut content = String::new();
+            for (key, value) in &spec.env_file {
+                let value = value
+                    .inline()
+                    .ok_or_else(|| eyre::eyre!("Env file var {} is not resolved", key))?;
+                content.push_str(&format!(
+                    "{}={}\n",
+                    key,
+                    serde_json::to_string(&metadata.render(value.trim()))?
+                ));
+            }
+            templates.push(Template {
+                envvars: true,
+                ..literal(content, "local/env")
+            });
+        }
+
+        let mut labels: BTreeMap<String, String> = spec
+            .labels
+            .iter()
+            .map(|(key, value)| (key.clone(), metadata.render(value)))
+            .collect();
+        labels.insert(
+            "bbuilder.deployment".to_string(),
+            self.manifest.name.clone(),
+        );
+        labels.insert("bbuilder.pod".to_string(), self.pod.to_string());
+        labels.insert("bbuilder.spec".to_string(), metadata_spec.to_string());
+
+        let mut config = serde_json::json!({
+            "image": spec.image_ref(),
+            "args": args,
+            "labels": labels,
+        });
+        if !port_labels.is_empty() {
+            config["ports"] = serde_json::to_value(port_labels)?;
+        }
+        if !spec.entrypoint.is_empty() {
+            config["entrypoint"] = spec.entrypoint.clone().into();
+        }
+        if !mounts.is_empty() {
+            config["mount"] = mounts.into();
+        }
+        if !self.extra_hosts.is_empty() {
+            config["extra_hosts"] = self.extra_hosts.clone().into();
+        }
+        if let Some(workdir) = &spec.workdir {
+            config["work_dir"] = workdir.clone().into();
+        }
+        if spec.read_only_rootfs {
+            config["readonly_rootfs"] = true.into();
+        }
+        if spec.privileged {
+            config["privileged"] = true.into();
+        }
+        if !spec.cap_add.is_empty() {
+            config["cap_add"] = spec.cap_add.clone().into();
+        }
+        if !spec.cap_drop.is_empty() {
+            config["cap_drop"] = spec.cap_drop.clone().into();
+        }
+        if !spec.ulimits.is_empty() {
+            let ulimits: BTreeMap<&String, String> = spec
+                .ulimits
+                .iter()
+                .map(|(name, (soft, hard))| (name, format!("{}:{}", soft, hard)))
+                .collect();
+            config["ulimit"] = serde_json::to_value(ulimits)?;
+        }
+        if !spec.sysctls.is_empty() {
+            config["sysctl"] = serde_json::to_value(&spec.sysctls)?;
+        }
+        if let Some(shm_size) = spec.shm_size {
+            config["shm_size"] = shm_size.into();
+        }
+        if !spec.devices.is_empty() {
+            let devices: Vec<serde_json::Value> = spec
+                .devices
+                .iter()
+                .map(|device| {
+                    let (host_path, container_path) =
+                        device.split_once(':').unwrap_or((device, device));
+                    serde_json::json!({"host_path": host_path, "container_path": container_path})
+                })
+                .collect();
+            config["devices"] = devices.into();
+        }
+        if spec.pull_policy == Some(spec::PullPolicy::Always) {
+            config["force_pull"] = true.into();
+        }
+
+        Ok(Task {
+            name: name.to_string(),
+            driver: "docker".to_string(),
+            config,
+            env: spec
+                .env
+                .iter()
+                .map(|(key, value)| (key.clone(), metadata.render(value)))
+                .collect(),
+            user: spec.uid.map(|uid| format!("{}:{}", uid, uid)),
+            templates,
+            artifacts,
+            lifecycle: None,
+            resources: spec.mem_limit.map(|mem_limit| Resources {
+                memory_mb: mem_limit.div_ceil(1024 * 1024),
+            }),
+            kill_signal: spec.stop_signal.clone(),
+            kill_timeout: spec.stop_grace_period.map(nanoseconds),
+            services: spec
+                .healthcheck
+                .as_ref()
+                .and_then(|healthcheck| self.service(metadata_spec, healthcheck))
+                .into_iter()
+                .collect(),
+        })
+    }
+
+    /// Nomad service of the spec with its health check, Nomad only runs the
+    /// http probes itself
+    fn service(&self, spec: &str, healthcheck: &HealthCheck) -> Option<Service> {
+        let Probe::Http { port, path } = &healthcheck.probe else {
+            tracing::warn!(
+                pod = self.pod,
+                spec,
+                "Command health checks are not run by Nomad"
+            );
+            return None;
+        };
+        let name = format!("{}-{}-{}", self.manifest.name, self.pod, spec);
+        Some(Service {
+            name: name.clone(),
+            provider: "nomad".to_string(),
+            port_label: port_label(spec, port),
+            checks: vec![Check {
+                name,
+                kind: "http".to_string(),
+                path: path.clone(),
+                interval: nanoseconds(healthcheck.interval),
+                timeout: nanoseconds(healthcheck.timeout),
+            }],
+        })
+    }
+}
+
+/// Template whose content is written as is
+fn literal(content: String, dest_path: &str) -> Template {
+    Template {
+        embedded_tmpl: content,
+        dest_path: dest_path.to_string(),
+        perms: None,
+        envvars: false,
+        left_delim: Some(LITERAL_LEFT_DELIM.to_string()),
+        right_delim: Some(LITERAL_RIGHT_DELIM.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::{File, Pod, Volume};
+
+    #[test]
+    fn test_pods_are_rendered_as_task_groups() -> eyre::Result<()> {
+        let el = Spec::builder()
+            .image("reth")
+            .arg(Arg::Port {
+                name: "http".to_string(),
+                preferred: 8545,
+            })
+            .volume(Volume::data())
+            .artifact(Artifacts::File(File {
+                name: "config.toml".to_string(),
+                target_path: "/data/config.toml".to_string(),
+                content: "port = {{port:http}}\nname = \"{{ .name }}\"".into(),
+                ..Default::default()
+            }))
+            .artifact(Artifacts::File(File {
+                name: "genesis.json".to_string(),
+                target_path: "/data/genesis.json".to_string(),
+                content: spec::ArtifactSource::url("https://example.com/genesis.json"),
+                ..Default::default()
+            }));
+        let cl = Spec::builder().image("lighthouse").arg(Arg::Ref {
+            name: "el".to_string(),
+            port: "http".to_string(),
+        });
+        let init = |script: &str| {
+            Spec::builder()
+                .image("busybox")
+                .entrypoint(["sh", "-c"])
+                .arg(script)
+        };
+
+        let mut manifest = Manifest::new("eth".to_string());
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", el));
+        manifest.add_spec(
+            "cl".to_string(),
+            Pod::default()
+                .with_init_spec("first", init("echo 1"))
+                .with_init_spec("second", init("echo 2"))
+                .with_spec("node", cl),
+        );
+
+        let datacenters = vec!["dc1".to_string()];
+        let job = render(
+            &manifest,
+            &JobOptions {
+                datacenters: &datacenters,
+                namespace: None,
+                data_dir: "/opt/bbuilder",
+            },
+        )?;
+        let groups: Vec<&str> = job
+            .task_groups
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect();
+        assert_eq!(groups, vec!["cl", "el"]);
+
+        let el = &job.task_groups[1];
+        assert_eq!(el.networks[0].reserved_ports[0].label, "node_http");
+        assert_eq!(el.networks[0].reserved_ports[0].value, 8545);
+        let node = &el.tasks[0];
+        assert_eq!(
+            node.templates[0].embedded_tmpl,
+            "port = 8545\nname = \"{{ .name }}\""
+        );
+        assert_eq!(node.templates[0].dest_path, "local/config.toml");
+        assert_eq!(
+            node.artifacts[0].getter_source,
+            "https://example.com/genesis.json"
+        );
+        assert_eq!(
+            node.config["mount"][0]["source"],
+            "/opt/bbuilder/eth/data/el/node"
+        );
+
+        // the refs to other pods go through the host ports of the node
+        let cl = &job.task_groups[0];
+        let names: Vec<&str> = cl.tasks.iter().map(|task| task.name.as_str()).collect();
+        assert_eq!(names, vec!["node", "init-first", "init-second"]);
+        assert_eq!(cl.tasks[0].config["args"][0], "http://el-node:8545");
+        assert_eq!(
+            cl.tasks[0].config["extra_hosts"][0],
+            "el-node:${BBUILDER_ADDR_EL_NODE_HTTP}"
+        );
+        let addresses = &cl.tasks[0].templates[0];
+        assert!(addresses.envvars);
+        assert_eq!(
+            addresses.embedded_tmpl,
+            "{{ range nomadService \"eth-el-node-http\" }}BBUILDER_ADDR_EL_NODE_HTTP={{ .Address }}\n{{ end }}"
+        );
+        assert_eq!(node.services[0].name, "eth-el-node-http");
+        assert_eq!(node.services[0].port_label, "node_http");
+        let second = cl.tasks[2].config["entrypoint"][2].as_str().unwrap();
+        assert!(second.starts_with("until [ -f /alloc/bbuilder/init-first.done ]"));
+        Ok(())
+    }
+}
@@ -0,0 +1,4 @@
+mod job;
+mod runtime;
+pub use job::{Job, JobOptions, SECRETS_VARIABLE_PREFIX, init_task_name, pre_start_task_name};
+pub use runtime::NomadRuntime;
@@ -0,0 +1,974 @@
+use crate::job::{self, JobOptions};
+use runtime_trait::{Event, Health, IdentityKey, ResourceStats, Runtime, SpecStatus, Subscription};
+use serde::Deserialize;
+use spec::{ArtifactSource, DefaultSecretProvider, EnvSecretProvider, Manifest, SecretProvider};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Once;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+const DEFAULT_ADDR: &str = "http://127.0.0.1:4646";
+
+// directory of the Nomad clients with the bind mounts of the deployments
+const DEFAULT_DATA_DIR: &str = "/opt/bbuilder";
+
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Runs the manifests as Nomad jobs through the HTTP API of a Nomad agent
+pub struct NomadRuntime {
+    addr: String,
+    // ACL token sent as `X-Nomad-Token`
+    token: Option<String>,
+    namespace: Option<String>,
+    datacenters: Vec<String>,
+    data_dir: String,
+    client: reqwest::Client,
+    // task events of the allocations, the Nomad event stream is followed
+    // from the first subscription
+    events: broadcast::Sender<Event>,
+    listening: Once,
+    // queried in order until one of them has the secret
+    secret_providers: Vec<Box<dyn SecretProvider>>,
+}
+
+/// Allocation of a task group as listed by `/v1/job/<id>/allocations`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Allocation {
+    #[serde(rename = "ID")]
+    id: String,
+    #[serde(rename = "JobID", default)]
+    job_id: String,
+    task_group: String,
+    #[serde(default)]
+    desired_status: String,
+    #[serde(default)]
+    task_states: Option<BTreeMap<String, TaskState>>,
+    #[serde(default)]
+    deployment_status: Option<DeploymentStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TaskState {
+    // pending, running or dead
+    state: String,
+    #[serde(default)]
+    failed: bool,
+    // the last events of the task, Nomad keeps the 10 most recent ones
+    #[serde(default)]
+    events: Vec<TaskEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TaskEvent {
+    #[serde(rename = "Type")]
+    kind: String,
+    // unix nanoseconds
+    #[serde(default)]
+    time: i64,
+}
+
+/// Frame of `/v1/event/stream`, the heartbeats have no events
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EventFrame {
+    #[serde(default)]
+    events: Vec<StreamEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StreamEvent {
+    #[serde(default)]
+    payload: EventPayload,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EventPayload {
+    #[serde(default)]
+    allocation: Option<Allocation>,
+}
+
+/// Allocation with the resources allocated to its tasks, as returned by
+/// `/v1/allocation/<id>`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AllocationResources {
+    #[serde(default)]
+    allocated_resources: Option<AllocatedResources>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AllocatedResources {
+    #[serde(default)]
+    tasks: HashMap<String, TaskResources>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TaskResources {
+    #[serde(default)]
+    memory: TaskMemory,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TaskMemory {
+    #[serde(rename = "MemoryMB", default)]
+    memory_mb: u64,
+    // hard limit when memory oversubscription is enabled, 0 otherwise
+    #[serde(rename = "MemoryMaxMB", default)]
+    memory_max_mb: u64,
+}
+
+impl TaskMemory {
+    /// Memory the task is killed above
+    fn limit_bytes(&self) -> u64 {
+        self.memory_max_mb.max(self.memory_mb) * 1024 * 1024
+    }
+}
+
+/// Nomad variable with the secrets of a deployment, as returned by
+/// `/v1/var/<path>`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Variable {
+    #[serde(default)]
+    items: BTreeMap<String, String>,
+    // index of the last write, the next one is checked against it
+    #[serde(default)]
+    modify_index: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DeploymentStatus {
+    // none until the checks of the allocation passed or failed
+    #[serde(default)]
+    healthy: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AllocationStats {
+    #[serde(default)]
+    tasks: HashMap<String, TaskStats>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TaskStats {
+    resource_usage: ResourceUsage,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ResourceUsage {
+    memory_stats: MemoryStats,
+    cpu_stats: CpuStats,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MemoryStats {
+    #[serde(rename = "RSS", default)]
+    rss: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CpuStats {
+    #[serde(default)]
+    percent: f64,
+}
+
+impl NomadRuntime {
+    pub fn new(addr: impl Into<String>) -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self {
+            addr: addr.into().trim_end_matches('/').to_string(),
+            token: None,
+            namespace: None,
+            datacenters: vec!["dc1".to_string()],
+            data_dir: DEFAULT_DATA_DIR.to_string(),
+            client: reqwest::Client::new(),
+            events,
+            listening: Once::new(),
+            secret_providers: vec![Box::new(EnvSecretProvider), Box::new(DefaultSecretProvider)],
+        }
+    }
+
+    /// Creates the runtime from the `NOMAD_ADDR`, `NOMAD_TOKEN` and
+    /// `NOMAD_NAMESPACE` vars used by the nomad cli
+    pub fn from_env() -> Self {
+        let runtime = Self::new(std::env::var("NOMAD_ADDR").unwrap_or(DEFAULT_ADDR.to_string()));
+        Self {
+            token: std::env::var("NOMAD_TOKEN").ok(),
+            namespace: std::env::var("NOMAD_NAMESPACE").ok(),
+            ..runtime
+        }
+    }
+
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    pub fn with_datacenters(mut self, datacenters: Vec<String>) -> Self {
+        self.datacenters = datacenters;
+        self
+    }
+
+    /// Directory of the Nomad clients the relative bind volumes are placed
+    /// under, one folder per deployment
+    pub fn with_data_dir(mut self, data_dir: impl Into<String>) -> Self {
+        self.data_dir = data_dir.into();
+        self
+    }
+
+    /// Replaces the providers used to resolve the secret args
+    pub fn with_secret_providers(mut self, providers: Vec<Box<dyn SecretProvider>>) -> Self {
+        self.secret_providers = providers;
+        self
+    }
+
+    /// Job the manifest is submitted as
+    pub fn job(&self, manifest: &Manifest) -> eyre::Result<job::Job> {
+        job::render(
+            manifest,
+            &JobOptions {
+                datacenters: &self.datacenters,
+                namespace: self.namespace.as_deref(),
+                data_dir: &self.data_dir,
+            },
+        )
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", self.addr, path));
+        if let Some(token) = &self.token {
+            request = request.header("X-Nomad-Token", token);
+        }
+        if let Some(namespace) = &self.namespace {
+            request = request.query(&[("namespace", namespace)]);
+        }
+        request
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> eyre::Result<reqwest::Response> {
+        check(request.send().await?).await
+    }
+
+    /// Writes the secrets of the job to the variable of the deployment the
+    /// tasks read them from. The generated secrets already in the variable
+    /// are kept, so they do not change between deploys.
+    async fn write_secrets(&self, deployment: &str, names: &BTreeSet<String>) -> eyre::Result<()> {
+        if names.is_empty() {
+            return Ok(());
+        }
+        let path = format!("/v1/var/{}/{}", job::SECRETS_VARIABLE_PREFIX, deployment);
+        let response = self.request(reqwest::Method::GET, &path).send().await?;
+        let variable: Variable = if response.status() == reqwest::StatusCode::NOT_FOUND {
+            Variable::default()
+        } else {
+            check(response).await?.json().await?
+        };
+
+        let mut items = variable.items.clone();
+        let mut missing = vec![];
+        for name in names {
+            match resolve_secret(&self.secret_providers, name, variable.items.get(name))? {
+                Some(secret) => {
+                    items.insert(name.clone(), secret);
+                }
+                None => missing.push(format!(
+                    "{} (set {})",
+                    name,
+                    EnvSecretProvider::var_name(name)
+                )),
+            }
+        }
+        if !missing.is_empty() {
+            eyre::bail!("Secrets not found: {}", missing.join(", "));
+        }
+        if items == variable.items {
+            return Ok(());
+        }
+
+        // the write fails if the variable changed since it was read
+        let request = self
+            .request(reqwest::Method::PUT, &path)
+            .query(&[("cas", variable.modify_index)])
+            .json(&serde_json::json!({
+                "Path": format!("{}/{}", job::SECRETS_VARIABLE_PREFIX, deployment),
+                "Items": items,
+            }));
+        self.send(request).await?;
+        tracing::info!(deployment = %deployment, "Wrote the secrets of the deployment");
+        Ok(())
+    }
+
+    async fn allocations(&self, deployment: &str) -> eyre::Result<Vec<Allocation>> {
+        let request = self.request(
+            reqwest::Method::GET,
+            &format!("/v1/job/{}/allocations", deployment),
+        );
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    async fn allocation_stats(&self, id: &str) -> eyre::Result<AllocationStats> {
+        let request = self.request(
+            reqwest::Method::GET,
+            &format!("/v1/client/allocation/{}/stats", id),
+        );
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    async fn allocation_resources(&self, id: &str) -> eyre::Result<AllocationResources> {
+        let request = self.request(reqwest::Method::GET, &format!("/v1/allocation/{}", id));
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    /// Prestart tasks of the job by task group, they run the init and pre
+    /// start commands of the specs
+    async fn prestart_tasks(&self, deployment: &str) -> eyre::Result<HashSet<(String, String)>> {
+        let request = self.request(reqwest::Method::GET, &format!("/v1/job/{}", deployment));
+        let job: serde_json::Value = self.send(request).await?.json().await?;
+        Ok(prestart_tasks(&job))
+    }
+
+    /// Sets the count of every task group of the job
+    async fn scale(&self, deployment: &str, count: u32) -> eyre::Result<()> {
+        let request = self.request(reqwest::Method::GET, &format!("/v1/job/{}", deployment));
+        let job: serde_json::Value = self.send(request).await?.json().await?;
+        let groups = job["TaskGroups"].as_array().cloned().unwrap_or_default();
+        for group in groups {
+            let Some(name) = group["Name"].as_str() else {
+                continue;
+            };
+            let request = self
+                .request(
+                    reqwest::Method::POST,
+                    &format!("/v1/job/{}/scale", deployment),
+                )
+                .json(&serde_json::json!({
+                    "Count": count,
+                    "Target": {"Group": name},
+                    "Message": "scaled by bbuilder",
+                }));
+            self.send(request).await?;
+        }
+        Ok(())
+    }
+}
+
+fn prestart_tasks(job: &serde_json::Value) -> HashSet<(String, String)> {
+    let mut tasks = HashSet::new();
+    for group in job["TaskGroups"].as_array().into_iter().flatten() {
+        for task in group["Tasks"].as_array().into_iter().flatten() {
+            if task["Lifecycle"].is_null() {
+                continue;
+            }
+            if let (Some(group), Some(task)) = (group["Name"].as_str(), task["Name"].as_str()) {
+                tasks.insert((group.to_string(), task.to_string()));
+            }
+        }
+    }
+    tasks
+}
+
+/// Status of the specs of the running allocations, the prestart tasks are
+/// not specs of the pod
+fn spec_statuses<'a>(
+    allocations: &'a [Allocation],
+    prestart: &HashSet<(String, String)>,
+) -> Vec<(&'a str, SpecStatus)> {
+    let mut statuses = vec![];
+    for allocation in allocations {
+        if allocation.desired_status != "run" {
+            continue;
+        }
+        let health = match allocation
+            .deployment_status
+            .as_ref()
+            .and_then(|status| status.healthy)
+        {
+            Some(true) => Health::Healthy,
+            Some(false) => Health::Unhealthy,
+            None => Health::Unknown,
+        };
+        for (task, state) in allocation.task_states.iter().flatten() {
+            if prestart.contains(&(allocation.task_group.clone(), task.clone())) {
+                continue;
+            }
+            let state = match state.state.as_str() {
+                "dead" if state.failed => "failed".to_string(),
+                "dead" => "exited".to_string(),
+                state => state.to_string(),
+            };
+            statuses.push((
+                allocation.id.as_str(),
+                SpecStatus {
+                    pod: allocation.task_group.clone(),
+                    spec: task.clone(),
+                    state,
+                    health,
+                    stats: None,
+                },
+            ));
+        }
+    }
+    statuses
+}
+
+/// Fails with the body of the error replies of Nomad
+async fn check(response: reqwest::Response) -> eyre::Result<reqwest::Response> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        eyre::bail!("Nomad request failed with {}: {}", status, body.trim());
+    }
+    Ok(response)
+}
+
+/// Value of the secret, a generated one is only generated once for the
+/// deployment and kept from the variable afterwards
+fn resolve_secret(
+    providers: &[Box<dyn SecretProvider>],
+    name: &str,
+    current: Option<&String>,
+) -> eyre::Result<Option<String>> {
+    for provider in providers {
+        if let Some(secret) = provider.secret(name)? {
+            if let Some(current) = current.filter(|_| provider.generated()) {
+                return Ok(Some(current.clone()));
+            }
+            return Ok(Some(secret));
+        }
+    }
+    Ok(None)
+}
+
+async fn fetch(url: &str) -> eyre::Result<String> {
+    Ok(reqwest::get(url).await?.error_for_status()?.text().await?)
+}
+
+/// Action of a Nomad task event, named like the docker ones
+fn event_action(kind: &str) -> String {
+    match kind {
+        "Started" => "start".to_string(),
+        "Terminated" => "die".to_string(),
+        "Restarting" => "restart".to_string(),
+        "Killed" => "stop".to_string(),
+        kind => kind.to_lowercase().replace(' ', "_"),
+    }
+}
+
+/// Events of the tasks of the allocations of a frame of the event stream
+/// that happened after `since`. The allocations carry the last events of
+/// their tasks, `seen` has the time of the last one sent per task.
+fn allocation_events(
+    frame: &EventFrame,
+    seen: &mut HashMap<(String, String), i64>,
+    since: i64,
+) -> Vec<Event> {
+    let mut events = vec![];
+    for stream_event in &frame.events {
+        let Some(allocation) = &stream_event.payload.allocation else {
+            continue;
+        };
+        for (task, state) in allocation.task_states.iter().flatten() {
+            let last = seen
+                .entry((allocation.id.clone(), task.clone()))
+                .or_insert(since);
+            for task_event in &state.events {
+                if task_event.time <= *last {
+                    continue;
+                }
+                *last = task_event.time;
+                events.push(Event {
+                    deployment: allocation.job_id.clone(),
+                    pod: allocation.task_group.clone(),
+                    spec: task.clone(),
+                    action: event_action(&task_event.kind),
+                    container_id: Some(allocation.id.clone()),
+                });
+            }
+        }
+    }
+    events
+}
+
+/// Follows the allocation events of Nomad until the stream ends, the events
+/// older than the start are skipped
+async fn follow_events(request: reqwest::RequestBuilder, sender: broadcast::Sender<Event>) {
+    let mut response = match request.send().await.and_then(|r| r.error_for_status()) {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to follow the Nomad events");
+            return;
+        }
+    };
+    tracing::info!("Listening for Nomad allocation events");
+
+    let start = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_nanos() as i64)
+        .unwrap_or_default();
+    let mut seen: HashMap<(String, String), i64> = HashMap::new();
+    let mut buffer = vec![];
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => buffer.extend_from_slice(&chunk),
+            Ok(None) => break,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to receive Nomad event");
+                break;
+            }
+        }
+        // the stream is newline delimited json
+        while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let frame: EventFrame = match serde_json::from_slice(&line) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    tracing::error!(error = %e, "Invalid Nomad event");
+                    continue;
+                }
+            };
+            for event in allocation_events(&frame, &mut seen, start) {
+                // sending only fails when there are no subscribers
+                let _ = sender.send(event);
+            }
+        }
+    }
+    tracing::warn!("Nomad event stream ended");
+}
+
+/// Resource usage of a task against the memory Nomad allocated to it
+fn task_stats(
+    usage: &AllocationStats,
+    resources: &AllocationResources,
+    task: &str,
+) -> Option<ResourceStats> {
+    let usage = &usage.tasks.get(task)?.resource_usage;
+    let memory_limit_bytes = resources
+        .allocated_resources
+        .as_ref()
+        .and_then(|allocated| allocated.tasks.get(task))
+        .map(|task| task.memory.limit_bytes())
+        .unwrap_or_default();
+    Some(ResourceStats {
+        cpu_percent: usage.cpu_stats.percent,
+        memory_bytes: usage.memory_stats.rss,
+        memory_limit_bytes,
+        ..Default::default()
+    })
+}
+
+#[async_trait::async_trait]
+impl Runtime for NomadRuntime {
+    #[tracing::instrument(skip_all, fields(manifest = %manifest.name))]
+    async fn run(&self, mut manifest: Manifest) -> eyre::Result<()> {
+        manifest.validate()?;
+
+        // The generators are resolved by the client before deploying. The
+        // file artifacts from urls are downloaded by Nomad, only the contents
+        // of the env files are rendered into the job
+        for pod in manifest.pods.values_mut() {
+            let specs = pod
+                .specs
+                .values_mut()
+                .chain(pod.init_specs.iter_mut().map(|init| &mut init.spec));
+            for spec in specs {
+                for value in spec.env_file.values_mut() {
+                    if let ArtifactSource::Url { url } = value {
+                        tracing::info!(url = %url, "Fetching env file content");
+                        *value = ArtifactSource::Inline(fetch(url).await?);
+                    }
+                    value.resolve(&HashMap::new())?;
+                }
+            }
+        }
+
+        let job = self.job(&manifest)?;
+        self.write_secrets(&manifest.name, &job.secrets()).await?;
+        let request = self
+            .request(reqwest::Method::POST, "/v1/jobs")
+            .json(&serde_json::json!({ "Job": job }));
+        self.send(request).await?;
+        tracing::info!(job = %manifest.name, "Registered Nomad job");
+        Ok(())
+    }
+
+    async fn status(&self, deployment: &str) -> eyre::Result<Vec<SpecStatus>> {
+        let allocations = self.allocations(deployment).await?;
+        let prestart = self.prestart_tasks(deployment).await?;
+        Ok(spec_statuses(&allocations, &prestart)
+            .into_iter()
+            .map(|(_, status)| status)
+            .collect())
+    }
+
+    async fn status_with_stats(&self, deployment: &str) -> eyre::Result<Vec<SpecStatus>> {
+        let allocations = self.allocations(deployment).await?;
+        let prestart = self.prestart_tasks(deployment).await?;
+        let mut stats: HashMap<&str, (AllocationStats, AllocationResources)> = HashMap::new();
+        let mut statuses = vec![];
+        for (allocation, mut status) in spec_statuses(&allocations, &prestart) {
+            if status.state == "running" {
+                if !stats.contains_key(allocation) {
+                    let usage = self.allocation_stats(allocation).await?;
+                    let resources = self.allocation_resources(allocation).await?;
+                    stats.insert(allocation, (usage, resources));
+                }
+                let (usage, resources) = &stats[allocation];
+                status.stats = task_stats(usage, resources, &status.spec);
+            }
+            statuses.push(status);
+        }
+        Ok(statuses)
+    }
+
+    async fn exec(
+        &self,
+        _deployment: &str,
+        _pod: &str,
+        _spec: &str,
+        _command: Vec<String>,
+    ) -> eyre::Result<String> {
+        eyre::bail!("exec is not supported by the Nomad runtime, use `nomad alloc exec`")
+    }
+
+    async fn pause(&self, deployment: &str) -> eyre::Result<()> {
+        self.scale(deployment, 0).await
+    }
+
+    async fn resume(&self, deployment: &str) -> eyre::Result<()> {
+        self.scale(deployment, 1).await
+    }
+
+    async fn identity_keys(&self, _deployment: &str, _pod: &str) -> eyre::Result<Vec<IdentityKey>> {
+        eyre::bail!("Key rotation is not supported by the Nomad runtime")
+    }
+
+    async fn rotate_keys(
+        &self,
+        _deployment: &str,
+        _pod: &str,
+        _keys: &[IdentityKey],
+    ) -> eyre::Result<()> {
+        eyre::bail!("Key rotation is not supported by the Nomad runtime")
+    }
+
+    async fn destroy(&self, deployment: &str) -> eyre::Result<()> {
+        let request = self.request(reqwest::Method::DELETE, &format!("/v1/job/{}", deployment));
+        self.send(request).await?;
+        Ok(())
+    }
+
+    fn subscribe(&self, deployment: &str) -> Subscription {
+        // followed once the token and namespace of the runtime are set
+        self.listening.call_once(|| {
+            let request = self
+                .request(reqwest::Method::GET, "/v1/event/stream")
+                .query(&[("topic", "Allocation")]);
+            tokio::spawn(follow_events(request, self.events.clone()));
+        });
+        Subscription::new(deployment, self.events.subscribe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_prestart_tasks_are_not_specs() -> eyre::Result<()> {
+        let allocations: Vec<Allocation> = serde_json::from_value(serde_json::json!([
+            {
+                "ID": "a1",
+                "TaskGroup": "el",
+                "DesiredStatus": "run",
+                "TaskStates": {
+                    "init-genesis": {"State": "dead", "Failed": false},
+                    "node-pre-start": {"State": "dead", "Failed": false},
+                    "node": {"State": "running", "Failed": false},
+                    "init-db": {"State": "running", "Failed": false},
+                },
+                "DeploymentStatus": {"Healthy": true},
+            },
+            {
+                "ID": "a0",
+                "TaskGroup": "el",
+                "DesiredStatus": "stop",
+                "TaskStates": {"node": {"State": "dead", "Failed": true}},
+            },
+        ]))?;
+
+        // a spec can be named like an init task, only the prestart tasks
+        // of the job are skipped
+        let prestart = prestart_tasks(&serde_json::json!({
+            "TaskGroups": [{
+                "Name": "el",
+                "Tasks": [
+                    {"Name": "node", "Lifecycle": null},
+                    {"Name": "init-db"},
+                    {"Name": "init-genesis", "Lifecycle": {"Hook": "prestart"}},
+                    {"Name": "node-pre-start", "Lifecycle": {"Hook": "prestart"}},
+                ],
+            }],
+        }));
+
+        let statuses = spec_statuses(&allocations, &prestart);
+        let specs: Vec<&str> = statuses
+            .iter()
+            .map(|(_, status)| status.spec.as_str())
+            .collect();
+        assert_eq!(specs, vec!["init-db", "node"]);
+        let (allocation, status) = &statuses[1];
+        assert_eq!(*allocation, "a1");
+        assert_eq!(status.pod, "el");
+        assert!(status.is_ready());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_limit_is_the_allocated_memory() -> eyre::Result<()> {
+        let usage: AllocationStats = serde_json::from_value(serde_json::json!({
+            "Tasks": {"node": {"ResourceUsage": {
+                "MemoryStats": {"RSS": 1024, "MaxUsage": 4096},
+                "CpuStats": {"Percent": 12.5},
+            }}},
+        }))?;
+        let resources = |memory: serde_json::Value| -> eyre::Result<AllocationResources> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "AllocatedResources": {"Tasks": {"node": {"Memory": memory}}},
+            }))?)
+        };
+
+        let stats = task_stats(
+            &usage,
+            &resources(serde_json::json!({"MemoryMB": 512}))?,
+            "node",
+        )
+        .unwrap();
+        assert_eq!(stats.memory_bytes, 1024);
+        assert_eq!(stats.memory_limit_bytes, 512 * 1024 * 1024);
+        assert_eq!(stats.cpu_percent, 12.5);
+
+        // with oversubscription the task can use up to the max
+        let oversubscribed = resources(serde_json::json!({"MemoryMB": 512, "MemoryMaxMB": 1024}))?;
+        let stats = task_stats(&usage, &oversubscribed, "node").unwrap();
+        assert_eq!(stats.memory_limit_bytes, 1024 * 1024 * 1024);
+
+        assert!(task_stats(&usage, &oversubscribed, "other").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_events_of_the_allocations() -> eyre::Result<()> {
+        let frame = |events: serde_json::Value| -> eyre::Result<EventFrame> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "Index": 10,
+                "Events": [{
+                    "Topic": "Allocation",
+                    "Type": "AllocationUpdated",
+                    "Payload": {"Allocation": {
+                        "ID": "a1",
+                        "JobID": "eth",
+                        "TaskGroup": "el",
+                        "TaskStates": {"node": {"State": "running", "Events": events}},
+                    }},
+                }],
+            }))?)
+        };
+        let mut seen = HashMap::new();
+
+        // the events before the stream was followed are skipped
+        let events = allocation_events(
+            &frame(serde_json::json!([
+                {"Type": "Received", "Time": 5},
+                {"Type": "Started", "Time": 20},
+            ]))?,
+            &mut seen,
+            10,
+        );
+        let actions: Vec<&str> = events.iter().map(|event| event.action.as_str()).collect();
+        assert_eq!(actions, vec!["start"]);
+        assert_eq!(
+            (
+                events[0].deployment.as_str(),
+                events[0].pod.as_str(),
+                events[0].spec.as_str()
+            ),
+            ("eth", "el", "node")
+        );
+
+        // the next updates repeat the last events of the task
+        let events = allocation_events(
+            &frame(serde_json::json!([
+                {"Type": "Started", "Time": 20},
+                {"Type": "Terminated", "Time": 30},
+                {"Type": "Restarting", "Time": 31},
+            ]))?,
+            &mut seen,
+            10,
+        );
+        let actions: Vec<&str> = events.iter().map(|event| event.action.as_str()).collect();
+        assert_eq!(actions, vec!["die", "restart"]);
+
+        // heartbeats have no events
+        let heartbeat: EventFrame = serde_json::from_str("{}")?;
+        assert!(allocation_events(&heartbeat, &mut seen, 10).is_empty());
+        Ok(())
+    }
+
+    // variables of the mock Nomad by path, with the index of their last write
+    type Variables = Arc<Mutex<HashMap<String, (u64, serde_json::Value)>>>;
+
+    // Nomad api on a local port that keeps the variables written with the
+    // index they were read at and accepts the jobs, recorded by id
+    async fn mock_nomad() -> (String, Variables, Arc<Mutex<Vec<String>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let variables = Variables::default();
+        let jobs = Arc::new(Mutex::new(Vec::new()));
+        let (state, registered) = (variables.clone(), jobs.clone());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                let end = loop {
+                    if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break end;
+                    }
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                };
+                let head = String::from_utf8_lossy(&buf[..end]).to_string();
+                let length = head
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length:")?
+                            .trim()
+                            .parse()
+                            .ok()
+                    })
+                    .unwrap_or(0);
+                while buf.len() < end + 4 + length {
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+                let body: serde_json::Value =
+                    serde_json::from_slice(&buf[end + 4..]).unwrap_or_default();
+
+                let mut line = head.split_whitespace();
+                let method = line.next().unwrap_or_default();
+                let target = line.next().unwrap_or_default();
+                let (path, query) = target.split_once('?').unwrap_or((target, ""));
+                let (status, reply) = match (method, path) {
+                    ("GET", path) if path.starts_with("/v1/var/") => {
+                        match state.lock().unwrap().get(path) {
+                            Some((index, items)) => (
+                                "200 OK",
+                                serde_json::json!({"Items": items, "ModifyIndex": index}),
+                            ),
+                            None => ("404 Not Found", serde_json::Value::Null),
+                        }
+                    }
+                    ("PUT", path) if path.starts_with("/v1/var/") => {
+                        let mut variables = state.lock().unwrap();
+                        let index = variables.get(path).map(|(index, _)| *index).unwrap_or(0);
+                        if query != format!("cas={}", index) {
+                            ("409 Conflict", serde_json::Value::Null)
+                        } else {
+                            variables.insert(path.to_string(), (index + 1, body["Items"].clone()));
+                            ("200 OK", serde_json::Value::Null)
+                        }
+                    }
+                    ("POST", "/v1/jobs") => {
+                        registered
+                            .lock()
+                            .unwrap()
+                            .push(body["Job"]["ID"].as_str().unwrap_or_default().to_string());
+                        ("200 OK", serde_json::json!({}))
+                    }
+                    _ => ("404 Not Found", serde_json::Value::Null),
+                };
+                let reply = reply.to_string();
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (addr, variables, jobs)
+    }
+
+    #[tokio::test]
+    async fn test_secrets_are_written_to_the_variable_of_the_deployment() -> eyre::Result<()> {
+        let (addr, variables, jobs) = mock_nomad().await;
+        let manifest = |name: &str| {
+            let mut manifest = Manifest::new(name.to_string());
+            let node = spec::Spec::builder()
+                .image("reth")
+                .arg2("--authrpc.jwtsecret", spec::Arg::secret("jwt"));
+            manifest.add_spec(
+                "el".to_string(),
+                spec::Pod::default().with_spec("node", node),
+            );
+            manifest
+        };
+        let jwt = |deployment: &str| {
+            variables.lock().unwrap()[&format!("/v1/var/bbuilder/{}", deployment)].1["jwt"]
+                .as_str()
+                .map(str::to_string)
+        };
+
+        // the generated jwt is kept by the next deploys
+        let runtime = NomadRuntime::new(addr.clone());
+        runtime.run(manifest("eth")).await?;
+        let generated = jwt("eth").unwrap();
+        assert_eq!(generated.len(), 64);
+        runtime.run(manifest("eth")).await?;
+        assert_eq!(jwt("eth"), Some(generated));
+        assert_eq!(*jobs.lock().unwrap(), vec!["eth", "eth"]);
+
+        // a secret set by the user replaces it
+        let provided = HashMap::from([("jwt".to_string(), "0xabc".to_string())]);
+        let runtime =
+            NomadRuntime::new(addr.clone()).with_secret_providers(vec![Box::new(provided)]);
+        runtime.run(manifest("eth")).await?;
+        assert_eq!(jwt("eth").as_deref(), Some("0xabc"));
+
+        // the job is not registered without its secrets
+        let runtime = NomadRuntime::new(addr).with_secret_providers(vec![]);
+        let err = runtime.run(manifest("other")).await.unwrap_err();
+        assert!(
+            err.to_string().contains("jwt (set BBUILDER_SECRET_JWT)"),
+            "{}",
+            err
+        );
+        assert_eq!(jobs.lock().unwrap().len(), 3);
+        Ok(())
+    }
+}
@@ -1,6 +1,179 @@
-use spec::Manifest;
+use serde::{Deserialize, Serialize};
+use spec::{Arch, Manifest};
+use tokio::sync::broadcast;
 
 #[async_trait::async_trait]
 pub trait Runtime {
     async fn run(&self, manifest: Manifest) -> eyre::Result<()>;
+
+    /// Returns the status of every running spec of the deployment
+    async fn status(&self, deployment: &str) -> eyre::Result<Vec<SpecStatus>>;
+
+    /// Same as `status` with a sample of the resource usage of the running specs
+    async fn status_with_stats(&self, deployment: &str) -> eyre::Result<Vec<SpecStatus>>;
+
+    /// Runs the command in the running container of the spec and returns its
+    /// stdout, it fails if the command exits with a non zero code
+    async fn exec(
+        &self,
+        deployment: &str,
+        pod: &str,
+        spec: &str,
+        command: Vec<String>,
+    ) -> eyre::Result<String>;
+
+    /// Stops the specs of the deployment and marks it as paused, the specs
+    /// are kept with their ports and volumes until it is resumed
+    async fn pause(&self, deployment: &str) -> eyre::Result<()>;
+
+    /// Starts again the specs of a paused deployment
+    async fn resume(&self, deployment: &str) -> eyre::Result<()>;
+
+    /// Reads the identity keys of the running specs of the pod
+    async fn identity_keys(&self, deployment: &str, pod: &str) -> eyre::Result<Vec<IdentityKey>>;
+
+    /// Removes the identity key files of the running specs of the pod and
+    /// restarts them so that they generate new ones. The keys are gone once
+    /// it returns, they are archived from `identity_keys` before.
+    async fn rotate_keys(
+        &self,
+        deployment: &str,
+        pod: &str,
+        keys: &[IdentityKey],
+    ) -> eyre::Result<()>;
+
+    /// Removes the specs of the deployment, its data is kept
+    async fn destroy(&self, deployment: &str) -> eyre::Result<()>;
+
+    /// Subscribes to the events of the specs of the deployment
+    fn subscribe(&self, deployment: &str) -> Subscription;
+
+    /// Architecture of the hosts that run the specs, the local one by default
+    async fn arch(&self) -> eyre::Result<Arch> {
+        Arch::host()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpecStatus {
+    pub pod: String,
+    pub spec: String,
+    // state reported by the runtime (e.g. running, exited)
+    pub state: String,
+    pub health: Health,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ResourceStats>,
+}
+
+/// Identity key file of a spec as it was before the rotation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdentityKey {
+    pub spec: String,
+    // path of the file inside the container
+    pub path: String,
+    // base64 of the content of the file
+    pub content: String,
+}
+
+/// Resource usage of a spec, the network and disk counters are totals since it started
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ResourceStats {
+    pub cpu_percent: f64,
+    pub memory_bytes: u64,
+    pub memory_limit_bytes: u64,
+    pub network_rx_bytes: u64,
+    pub network_tx_bytes: u64,
+    pub disk_read_bytes: u64,
+    pub disk_write_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    Healthy,
+    Unhealthy,
+    Starting,
+    // the spec does not declare a health check
+    Unknown,
+}
+
+impl SpecStatus {
+    /// The spec is running and healthy, specs without health check are ready once running
+    pub fn is_ready(&self) -> bool {
+        self.state == "running" && matches!(self.health, Health::Healthy | Health::Unknown)
+    }
+}
+
+/// Lifecycle event of a spec (e.g. start, die, health_status) reported by the runtime
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub deployment: String,
+    pub pod: String,
+    pub spec: String,
+    pub action: String,
+    pub container_id: Option<String>,
+}
+
+/// Stream of the events of a single deployment
+pub struct Subscription {
+    deployment: String,
+    receiver: broadcast::Receiver<Event>,
+}
+
+impl Subscription {
+    pub fn new(deployment: impl Into<String>, receiver: broadcast::Receiver<Event>) -> Self {
+        Self {
+            deployment: deployment.into(),
+            receiver,
+        }
+    }
+
+    pub fn deployment(&self) -> &str {
+        &self.deployment
+    }
+
+    /// Waits for the next event of the deployment, returns None once the
+    /// runtime stops publishing events. Events missed by a slow subscriber
+    /// are skipped.
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) if event.deployment == self.deployment => return Some(event),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(deployment: &str, action: &str) -> Event {
+        Event {
+            deployment: deployment.to_string(),
+            pod: "el".to_string(),
+            spec: "node".to_string(),
+            action: action.to_string(),
+            container_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscription_only_receives_its_deployment() {
+        let (sender, receiver) = broadcast::channel(16);
+        let mut subscription = Subscription::new("eth-1", receiver);
+
+        sender.send(event("eth-2", "start")).unwrap();
+        sender.send(event("eth-1", "start")).unwrap();
+        sender.send(event("eth-2", "die")).unwrap();
+        drop(sender);
+
+        let received = subscription.recv().await.unwrap();
+        assert_eq!(received.deployment, "eth-1");
+        assert_eq!(received.action, "start");
+        assert!(subscription.recv().await.is_none());
+    }
 }
@@ -1,49 +1,388 @@
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    net::IpAddr,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-pub const DEFAULT_JWT_TOKEN: &str =
-    "04592280e1778419b7aa954d43871cb2cfb2ebda754fb735e8adeb293a88f9bf";
+// name of the secret shared by the execution and consensus clients
+pub const JWT_SECRET: &str = "jwt";
+
+// directory where the runtimes mount the resolved secrets
+pub const SECRETS_DIR: &str = "/run/secrets";
+
+// name of the port that exposes prometheus metrics for a spec
+pub const METRICS_PORT: &str = "metrics";
+
+// name of the data volume of the specs, see `Volume::data`
+pub const DATA_VOLUME: &str = "data";
+
+// volume the specs of a pod share their IPC sockets in
+pub const IPC_VOLUME: &str = "ipc";
 
 #[derive(Debug, Deserialize)]
 pub struct Dep {
+    // name of the deployment, defaults to the module name. Distinct names let
+    // several instances of the same module run side by side
+    #[serde(default)]
+    pub name: Option<String>,
     pub module: String,
+    // chain of the module, the cli fills it from its config when empty
+    #[serde(default)]
     pub chain: String,
     pub args: serde_json::Value,
+    // public ip advertised to the p2p networks, either an ip or "auto"
+    #[serde(default)]
+    pub external_ip: Option<String>,
+    // image tag of a client (e.g. "reth") used instead of the recommended one of the catalog
+    #[serde(default)]
+    pub versions: HashMap<String, String>,
+    // overrides applied to the manifest generated by the module
+    #[serde(default)]
+    pub patch: ManifestPatch,
+    // host directories of the data of the pods (e.g. a NVMe disk for the EL)
+    #[serde(default)]
+    pub data_dirs: DataDirs,
+}
+
+/// Host directories the data volumes of the pods are placed in instead of
+/// the `data` folder of the deployment
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DataDirs {
+    // data of every pod under `<root>/<deployment>/<pod>/<spec>`
+    #[serde(default)]
+    pub root: Option<String>,
+    // data of the pod under `<dir>/<spec>`, it takes precedence over the root
+    #[serde(default)]
+    pub pods: HashMap<String, String>,
+}
+
+/// Overrides of the specs of a generated manifest, keyed by pod and spec name,
+/// to tweak a flag of a client without changing its catalog module
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ManifestPatch {
+    #[serde(default)]
+    pub pods: HashMap<String, HashMap<String, SpecPatch>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpecPatch {
+    // image tag used instead of the one of the catalog
+    #[serde(default)]
+    pub tag: Option<String>,
+    // appended to the args of the spec
+    #[serde(default)]
+    pub args: Vec<String>,
+    // set over the env vars of the spec
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+/// Signal as docker takes it, its name (`SIGINT`) or its number (`2`)
+fn is_signal(signal: &str) -> bool {
+    match signal.strip_prefix("SIG") {
+        Some(name) => !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase()),
+        None => signal.parse::<u8>().is_ok(),
+    }
+}
+
+/// Deployment wide settings resolved from the Dep and shared by every component
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    pub external_ip: Option<IpAddr>,
+    pub versions: HashMap<String, String>,
+}
+
+impl Context {
+    /// Value of the geth style `--nat` flag for the external ip, if any
+    pub fn nat_extip(&self) -> Option<String> {
+        self.external_ip.map(|ip| format!("extip:{}", ip))
+    }
 }
 
 pub trait Deployment {
-    type Input: DeserializeOwned;
-    type Chains: Default;
+    type Input: DeserializeOwned + schemars::JsonSchema;
+    type Chains: Default + std::str::FromStr<Err = eyre::Report>;
 
-    fn apply(&self, dep: &Dep) -> eyre::Result<Manifest> {
-        let input: Self::Input = serde_json::from_value(dep.args.clone())?;
-        let manifest = self.manifest(Default::default(), input)?;
+    /// Builds the manifest for the chain of the Dep, the default chain of the
+    /// module if it does not set one
+    fn apply(&self, dep: &Dep, ctx: &Context) -> eyre::Result<Manifest> {
+        let chain = match dep.chain.as_str() {
+            "" => Self::Chains::default(),
+            chain => chain.parse()?,
+        };
+        let args = merge_input(self.defaults(&chain), dep.args.clone());
+        let input: Self::Input = serde_json::from_value(args.clone())?;
+        let mut manifest = self.manifest(chain, input, ctx)?;
+        if let Some(name) = &dep.name {
+            manifest.name = name.clone();
+        }
+        manifest.module = dep.module.clone();
+        manifest.chain = dep.chain.clone();
+        manifest.input = args;
         Ok(manifest)
     }
 
+    /// Canonical input of the chain, the args of the Dep are merged over it
+    /// so that they only need to set what differs from the defaults
+    fn defaults(&self, _chain: &Self::Chains) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
     fn capabilities(&self) -> Vec<ChainSpec<Self::Chains>>;
-    fn manifest(&self, chain: Self::Chains, input: Self::Input) -> eyre::Result<Manifest>;
+
+    /// JSON Schema of the input, the args of a Dep for the module. The args
+    /// are merged over the defaults of the chain before they are parsed, so
+    /// they can leave out the fields the schema requires.
+    fn input_schema(&self) -> serde_json::Value {
+        schemars::schema_for!(Self::Input).to_value()
+    }
+
+    /// Capability of the deployment for the chain given by name, none if the
+    /// name is not a chain of the module or the module does not support it
+    fn capability(&self, chain: &str) -> Option<ChainSpec<Self::Chains>>
+    where
+        Self::Chains: PartialEq,
+    {
+        let chain: Self::Chains = chain.parse().ok()?;
+        self.capabilities()
+            .into_iter()
+            .find(|spec| spec.chain == chain)
+    }
+
+    fn manifest(
+        &self,
+        chain: Self::Chains,
+        input: Self::Input,
+        ctx: &Context,
+    ) -> eyre::Result<Manifest>;
+}
+
+/// Merges the args over the defaults. Objects are merged key by key except
+/// when both have a single different key with an object value (an enum
+/// variant, e.g. the client of a node), in which case the args replace the
+/// defaults.
+pub fn merge_input(defaults: serde_json::Value, args: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+
+    match (defaults, args) {
+        (defaults, Value::Null) => defaults,
+        (Value::Object(mut defaults), Value::Object(args)) => {
+            let variant = |object: &serde_json::Map<String, Value>| match object.iter().next() {
+                Some((key, value)) if object.len() == 1 && value.is_object() => Some(key.clone()),
+                _ => None,
+            };
+            let other_variant = matches!(
+                (variant(&defaults), variant(&args)),
+                (Some(default), Some(arg)) if default != arg
+            );
+            if other_variant {
+                return Value::Object(args);
+            }
+            for (key, value) in args {
+                let merged = match defaults.remove(&key) {
+                    Some(default) => merge_input(default, value),
+                    None => value,
+                };
+                defaults.insert(key, merged);
+            }
+            Value::Object(defaults)
+        }
+        (_, args) => args,
+    }
 }
 
 pub trait ComputeResource {
     type Chains: Default;
 
-    fn spec(&self, chain: Self::Chains) -> eyre::Result<Pod>;
+    fn spec(&self, chain: Self::Chains, ctx: &Context) -> eyre::Result<Pod>;
     fn capabilities(&self) -> Capabilities<Self::Chains>;
+
+    /// Profiles the component can run with, the first one is its default
+    fn profiles(&self) -> Vec<Profile> {
+        vec![Profile::Full]
+    }
+
+    /// Profile selected by the input of the component, it fails if the
+    /// component does not support it
+    fn profile(&self, selected: Option<Profile>) -> eyre::Result<Profile> {
+        let profiles = self.profiles();
+        match selected {
+            None => Ok(profiles.first().copied().unwrap_or_default()),
+            Some(profile) if profiles.contains(&profile) => Ok(profile),
+            Some(profile) => Err(eyre::eyre!(
+                "Profile {} is not supported, use one of: {}",
+                profile.name(),
+                profiles
+                    .iter()
+                    .map(Profile::name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+/// Flags and env vars of a client input applied over the ones of the catalog,
+/// flattened into the inputs of the clients
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Overrides {
+    // flags appended after the arguments set by the catalog
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    // env vars set over the ones of the catalog
+    #[serde(default)]
+    pub extra_env: HashMap<String, String>,
+}
+
+/// How much of the history of the chain a node keeps
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    // every block and the recent state
+    #[default]
+    Full,
+    // every block and the state at every block
+    Archive,
+    // only the recent blocks and state
+    Pruned,
+}
+
+impl Profile {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Profile::Full => "full",
+            Profile::Archive => "archive",
+            Profile::Pruned => "pruned",
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct Capabilities<Chains: Default> {
     pub chains: Vec<ChainSpec<Chains>>,
     pub volumes: Vec<Volume>,
+    pub dashboards: Vec<Dashboard>,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+/// Volume mounted in the container of a spec, volumes with the same name are
+/// shared by the specs of a pod
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Volume {
     pub name: String,
+    // path inside the container
+    #[serde(default)]
+    pub target: String,
+    #[serde(default)]
+    pub kind: VolumeKind,
+    // mount the volume read-only inside the container
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VolumeKind {
+    // volume managed by the runtime
+    #[default]
+    Named,
+    // host directory, relative paths are resolved under the deployment directory
+    // and the metadata placeholders (e.g. `{{pod}}`) are rendered
+    Bind {
+        host_path: String,
+    },
+    // volume of the runtime created outside of the deployment (e.g. by an
+    // adopted compose project), mounted by its name and never removed
+    External {
+        source: String,
+    },
+    // in memory filesystem, optionally limited to `size` bytes
+    Tmpfs {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
+    },
+}
+
+impl Volume {
+    pub fn named(name: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            target: target.into(),
+            kind: VolumeKind::Named,
+            read_only: false,
+        }
+    }
+
+    pub fn bind(
+        name: impl Into<String>,
+        host_path: impl Into<String>,
+        target: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            target: target.into(),
+            kind: VolumeKind::Bind {
+                host_path: host_path.into(),
+            },
+            read_only: false,
+        }
+    }
+
+    /// Mounts the volume read-only, e.g. the data of another client that the
+    /// spec only reads
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Data dir of a spec at `/data`, bound to `data/<pod>/<spec>` under the
+    /// deployment directory so every spec gets its own
+    pub fn data() -> Self {
+        Self::bind(DATA_VOLUME, "data/{{pod}}/{{spec}}", "/data")
+    }
+
+    /// Volume at `/ipc` shared by the specs of the pod that mount it, for
+    /// the IPC sockets of the nodes
+    pub fn ipc() -> Self {
+        Self::named(IPC_VOLUME, "/ipc")
+    }
+
+    pub fn external(
+        name: impl Into<String>,
+        source: impl Into<String>,
+        target: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            target: target.into(),
+            kind: VolumeKind::External {
+                source: source.into(),
+            },
+            read_only: false,
+        }
+    }
+
+    pub fn tmpfs(name: impl Into<String>, target: impl Into<String>, size: Option<u64>) -> Self {
+        Self {
+            name: name.into(),
+            target: target.into(),
+            kind: VolumeKind::Tmpfs { size },
+            read_only: false,
+        }
+    }
+}
+
+/// Grafana dashboard (JSON model) provided by a compute resource
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Dashboard {
+    pub name: String,
+    pub content: String,
 }
 
 #[derive(Default)]
@@ -52,51 +391,1356 @@ pub struct ChainSpec<Chains: Default> {
     pub chain: Chains,
     // minimum version of the resource that needs to by used for this chain
     pub min_version: String,
+    // version of the resource deployed by default for this chain
+    pub recommended_version: String,
+}
+
+/// Minimum and recommended version of a client for a chain
+pub struct ClientVersion<Chains: 'static> {
+    pub client: &'static str,
+    pub chain: Chains,
+    pub min_version: &'static str,
+    pub recommended_version: &'static str,
 }
 
+impl<Chains> ClientVersion<Chains> {
+    pub const fn new(
+        client: &'static str,
+        chain: Chains,
+        min_version: &'static str,
+        recommended_version: &'static str,
+    ) -> Self {
+        Self {
+            client,
+            chain,
+            min_version,
+            recommended_version,
+        }
+    }
+}
+
+/// Table with the versions of every client of a module
+pub struct Versions<Chains: 'static>(pub &'static [ClientVersion<Chains>]);
+
+impl<Chains: Default + Clone + PartialEq> Versions<Chains> {
+    /// Returns the chains supported by the client with their versions
+    pub fn chains(&self, client: &str) -> Vec<ChainSpec<Chains>> {
+        self.0
+            .iter()
+            .filter(|version| version.client == client)
+            .map(|version| ChainSpec {
+                chain: version.chain.clone(),
+                min_version: version.min_version.to_string(),
+                recommended_version: version.recommended_version.to_string(),
+            })
+            .collect()
+    }
+
+    /// Returns the image tag of the client for the chain, either the override
+    /// of the deployment or the recommended version
+    pub fn tag(&self, client: &str, chain: &Chains, ctx: &Context) -> eyre::Result<String> {
+        if let Some(tag) = ctx.versions.get(client) {
+            return Ok(tag.clone());
+        }
+        self.0
+            .iter()
+            .find(|version| version.client == client && &version.chain == chain)
+            .map(|version| version.recommended_version.to_string())
+            .ok_or_else(|| eyre::eyre!("No version of {} available for the chain", client))
+    }
+
+    /// Returns the image tag of the client, the tag set in the input of the
+    /// client takes precedence over `tag`
+    pub fn resolve_tag(
+        &self,
+        client: &str,
+        chain: &Chains,
+        ctx: &Context,
+        input_tag: Option<&str>,
+    ) -> eyre::Result<String> {
+        match input_tag {
+            Some(tag) => Ok(tag.to_string()),
+            None => self.tag(client, chain, ctx),
+        }
+    }
+}
+
+// layout version of the serialized manifests, a change that older manifests
+// can not be read with bumps it and adds its step to `MANIFEST_MIGRATIONS`
+pub const MANIFEST_VERSION: u32 = 1;
+
+type ManifestMigration = fn(&mut serde_json::Value) -> eyre::Result<()>;
+
+// the step at index `n` upgrades a manifest of version `n` to `n + 1`
+const MANIFEST_MIGRATIONS: [ManifestMigration; MANIFEST_VERSION as usize] = [migrate_unversioned];
+
+/// Manifests saved before they had a version, their layout is the one of
+/// version 1
+fn migrate_unversioned(_: &mut serde_json::Value) -> eyre::Result<()> {
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Manifest {
+    // layout version the manifest was written with, 0 if it has none
+    #[serde(default)]
+    pub version: u32,
     pub name: String,
-    pub pods: HashMap<String, Pod>,
+    // catalog module that generated the manifest
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub module: String,
+    // chain the deployment runs on
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub chain: String,
+    pub pods: BTreeMap<String, Pod>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dashboards: Vec<Dashboard>,
+    // pods that have to be started (and healthy if they have a health check)
+    // before the given pod starts
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, Vec<String>>,
+    // host ports assigned because the preferred port was already in use
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allocated_ports: Vec<HostPort>,
+    // effective input of the module, the Dep args merged over its defaults
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub input: serde_json::Value,
+    // checks run against the deployment once it is healthy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub smoke_tests: Vec<SmokeTest>,
 }
 
 impl Manifest {
     pub fn new(name: String) -> Self {
         Manifest {
+            version: MANIFEST_VERSION,
             name,
-            pods: HashMap::new(),
+            module: String::new(),
+            chain: String::new(),
+            pods: BTreeMap::new(),
+            dashboards: vec![],
+            dependencies: BTreeMap::new(),
+            allocated_ports: vec![],
+            input: serde_json::Value::Null,
+            smoke_tests: vec![],
+        }
+    }
+
+    /// Renders the manifest as yaml, the intermediate format between the
+    /// catalog and the runtimes
+    pub fn to_yaml(&self) -> eyre::Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+
+    pub fn from_yaml(yaml: &str) -> eyre::Result<Self> {
+        Ok(serde_yaml::from_str(yaml)?)
+    }
+
+    /// Sha256 (hex) of the manifest, it is computed over its json with the
+    /// keys sorted so that the same manifest always has the same hash
+    pub fn content_hash(&self) -> eyre::Result<String> {
+        let value = serde_json::to_value(self)?;
+        Ok(hex::encode(Sha256::digest(value.to_string().as_bytes())))
+    }
+
+    /// Reads a saved manifest of any version, the older ones are migrated to
+    /// the current layout first
+    pub fn from_json(mut value: serde_json::Value) -> eyre::Result<Self> {
+        let version = match value.get("version") {
+            None => 0,
+            Some(version) => version
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| eyre::eyre!("Invalid manifest version: {}", version))?,
+        };
+        if version > MANIFEST_VERSION {
+            eyre::bail!(
+                "Manifest version {} is newer than the supported version {}",
+                version,
+                MANIFEST_VERSION
+            );
         }
+        for migration in &MANIFEST_MIGRATIONS[version as usize..] {
+            migration(&mut value)?;
+        }
+
+        let mut manifest: Manifest = serde_json::from_value(value)?;
+        manifest.version = MANIFEST_VERSION;
+        Ok(manifest)
     }
 
     pub fn add_spec(&mut self, name: String, pod: Pod) {
         self.pods.insert(name, pod);
     }
+
+    /// Applies the overrides of the patch, a patch of a pod or spec that is
+    /// not in the manifest is an error so that a typo does not go unnoticed
+    pub fn apply_patch(&mut self, patch: &ManifestPatch) -> eyre::Result<()> {
+        for (pod_name, specs) in &patch.pods {
+            let pod = self
+                .pods
+                .get_mut(pod_name)
+                .ok_or_else(|| eyre::eyre!("Patch of unknown pod {}", pod_name))?;
+            for (spec_name, spec_patch) in specs {
+                let spec = pod.specs.get_mut(spec_name).ok_or_else(|| {
+                    eyre::eyre!("Patch of unknown spec {}/{}", pod_name, spec_name)
+                })?;
+                if let Some(tag) = &spec_patch.tag {
+                    spec.tag = Some(tag.clone());
+                }
+                spec.args
+                    .extend(spec_patch.args.iter().cloned().map(Arg::Value));
+                spec.env.extend(spec_patch.env.clone());
+                spec.labels.extend(spec_patch.labels.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Urls of the artifact contents the runtime fetches before deploying,
+    /// including the ones the generators read
+    pub fn artifact_urls(&self) -> BTreeSet<String> {
+        self.artifact_sources()
+            .flat_map(|source| source.urls())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Urls read by the generators of the artifact contents
+    pub fn generator_urls(&self) -> BTreeSet<String> {
+        self.artifact_sources()
+            .filter(|source| matches!(source, ArtifactSource::Generator(_)))
+            .flat_map(|source| source.urls())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Resolves the lazy artifact contents with the contents of the urls
+    /// fetched by the runtime and the generators
+    pub fn resolve_artifacts(&mut self, fetched: &HashMap<String, String>) -> eyre::Result<()> {
+        for source in self.artifact_sources_mut() {
+            source.resolve(fetched)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the generators of the artifact contents, the url contents are
+    /// left for the runtime
+    pub fn resolve_generators(&mut self, fetched: &HashMap<String, String>) -> eyre::Result<()> {
+        for source in self.artifact_sources_mut() {
+            if matches!(source, ArtifactSource::Generator(_)) {
+                source.resolve(fetched)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn artifact_sources(&self) -> impl Iterator<Item = &ArtifactSource> {
+        self.pods
+            .values()
+            .flat_map(|pod| {
+                pod.specs
+                    .values()
+                    .chain(pod.init_specs.iter().map(|init| &init.spec))
+            })
+            .flat_map(|spec| {
+                let files = spec.artifacts.iter().filter_map(|artifact| match artifact {
+                    Artifacts::File(file) => Some(&file.content),
+                    Artifacts::Dir(_) => None,
+                });
+                files.chain(spec.env_file.values())
+            })
+    }
+
+    fn artifact_sources_mut(&mut self) -> impl Iterator<Item = &mut ArtifactSource> {
+        self.pods
+            .values_mut()
+            .flat_map(|pod| {
+                pod.specs
+                    .values_mut()
+                    .chain(pod.init_specs.iter_mut().map(|init| &mut init.spec))
+            })
+            .flat_map(|spec| {
+                let files = spec
+                    .artifacts
+                    .iter_mut()
+                    .filter_map(|artifact| match artifact {
+                        Artifacts::File(file) => Some(&mut file.content),
+                        Artifacts::Dir(_) => None,
+                    });
+                files.chain(spec.env_file.values_mut())
+            })
+    }
+
+    /// Moves the data volumes of the pods to the host directories of
+    /// `data_dirs`, each spec keeps its own directory under the one of its pod.
+    /// The root is shared by the deployments, their pods are placed under a
+    /// directory of the deployment.
+    pub fn apply_data_dirs(&mut self, data_dirs: &DataDirs) -> eyre::Result<()> {
+        if let Some(pod) = data_dirs
+            .pods
+            .keys()
+            .find(|pod| !self.pods.contains_key(*pod))
+        {
+            eyre::bail!("Data dir of unknown pod {}", pod);
+        }
+
+        for (pod_name, pod) in &mut self.pods {
+            let dir = match (data_dirs.pods.get(pod_name), &data_dirs.root) {
+                (Some(dir), _) => dir.trim_end_matches('/').to_string(),
+                (None, Some(root)) => format!(
+                    "{}/{{{{deployment}}}}/{}",
+                    root.trim_end_matches('/'),
+                    pod_name
+                ),
+                (None, None) => continue,
+            };
+            for spec in pod.specs.values_mut() {
+                if let Some(volume) = spec.volumes.get_mut(DATA_VOLUME)
+                    && let VolumeKind::Bind { host_path } = &mut volume.kind
+                {
+                    *host_path = format!("{}/{{{{spec}}}}", dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Host paths of the data volumes of the specs keyed by pod and spec, the
+    /// relative ones are under the folder of the deployment
+    pub fn data_dirs(&self) -> BTreeMap<(String, String), String> {
+        let mut data_dirs = BTreeMap::new();
+        for (pod_name, pod) in &self.pods {
+            for (spec_name, spec) in &pod.specs {
+                if let Some(volume) = spec.volumes.get(DATA_VOLUME)
+                    && let VolumeKind::Bind { host_path } = &volume.kind
+                {
+                    let metadata = Metadata {
+                        deployment: &self.name,
+                        pod: pod_name,
+                        spec: spec_name,
+                        chain: &self.chain,
+                    };
+                    data_dirs.insert(
+                        (pod_name.clone(), spec_name.clone()),
+                        metadata.render(host_path),
+                    );
+                }
+            }
+        }
+        data_dirs
+    }
+
+    pub fn add_dashboards(&mut self, dashboards: impl IntoIterator<Item = Dashboard>) {
+        self.dashboards.extend(dashboards);
+    }
+
+    pub fn add_smoke_test(&mut self, test: SmokeTest) {
+        self.smoke_tests.push(test);
+    }
+
+    /// Declares that `pod` starts after `depends_on`
+    pub fn add_dependency(&mut self, pod: &str, depends_on: &str) {
+        let dependencies = self.dependencies.entry(pod.to_string()).or_default();
+        if !dependencies.iter().any(|dep| dep == depends_on) {
+            dependencies.push(depends_on.to_string());
+        }
+    }
+
+    /// Returns the pods in the order they have to be started, it fails if a
+    /// dependency does not exist or there is a cycle
+    pub fn startup_order(&self) -> eyre::Result<Vec<String>> {
+        for (pod, dependencies) in &self.dependencies {
+            for dep in std::iter::once(pod).chain(dependencies) {
+                if !self.pods.contains_key(dep) {
+                    eyre::bail!("Dependency on unknown pod {}", dep);
+                }
+            }
+        }
+
+        let mut pods: Vec<&String> = self.pods.keys().collect();
+        pods.sort();
+
+        let mut order: Vec<String> = vec![];
+        while order.len() < pods.len() {
+            let ready: Vec<&String> = pods
+                .iter()
+                .copied()
+                .filter(|pod| !order.contains(pod))
+                .filter(|pod| {
+                    self.dependencies
+                        .get(*pod)
+                        .is_none_or(|deps| deps.iter().all(|dep| order.contains(dep)))
+                })
+                .collect();
+            if ready.is_empty() {
+                let pending: Vec<&str> = pods
+                    .iter()
+                    .filter(|pod| !order.contains(pod))
+                    .map(|pod| pod.as_str())
+                    .collect();
+                eyre::bail!("Dependency cycle between pods {}", pending.join(", "));
+            }
+            order.extend(ready.into_iter().cloned());
+        }
+        Ok(order)
+    }
+
+    /// Resolves the target of a ref. The name is either a pod, in which case
+    /// the target is the only spec of the pod with the port, or `<pod>/<spec>`.
+    pub fn resolve_ref(&self, name: &str, port: &str) -> eyre::Result<RefTarget> {
+        let (pod_name, spec_name) = match name.split_once('/') {
+            Some((pod_name, spec_name)) => (pod_name, Some(spec_name)),
+            None => (name, None),
+        };
+        let pod = self
+            .pods
+            .get(pod_name)
+            .ok_or_else(|| eyre::eyre!("Ref {} points to an unknown pod", name))?;
+
+        let mut targets: Vec<RefTarget> = pod
+            .specs
+            .iter()
+            .filter(|(name, _)| spec_name.is_none_or(|spec_name| spec_name == name.as_str()))
+            .filter_map(|(name, spec)| {
+                spec.port(port).map(|port| RefTarget {
+                    pod: pod_name.to_string(),
+                    spec: name.clone(),
+                    port,
+                })
+            })
+            .collect();
+
+        match targets.len() {
+            0 => Err(eyre::eyre!("Ref {} does not have a port {}", name, port)),
+            1 => Ok(targets.remove(0)),
+            _ => Err(eyre::eyre!(
+                "Ref {} is ambiguous, more than one spec has a port {}",
+                name,
+                port
+            )),
+        }
+    }
+
+    /// Resolves every ref of the manifest keyed by (name, port), it fails on
+    /// the first dangling ref
+    pub fn resolve_refs(&self) -> eyre::Result<HashMap<(String, String), RefTarget>> {
+        let mut targets = HashMap::new();
+        for pod in self.pods.values() {
+            let specs = pod
+                .specs
+                .values()
+                .chain(pod.init_specs.iter().map(|init| &init.spec));
+            for spec in specs {
+                for (name, port) in spec.args.iter().flat_map(Arg::refs) {
+                    let target = self.resolve_ref(name, port)?;
+                    targets.insert((name.clone(), port.clone()), target);
+                }
+                let files = spec
+                    .args
+                    .iter()
+                    .filter_map(|arg| match arg {
+                        Arg::File(file) => Some(file),
+                        _ => None,
+                    })
+                    .chain(spec.artifacts.iter().filter_map(|artifact| match artifact {
+                        Artifacts::File(file) => Some(file),
+                        Artifacts::Dir(_) => None,
+                    }));
+                for file in files {
+                    for (name, port) in file.refs() {
+                        let target = self.resolve_ref(&name, &port)?;
+                        targets.insert((name, port), target);
+                    }
+                }
+            }
+        }
+        Ok(targets)
+    }
+
+    /// Checks the manifest before anything is written for it, it returns every
+    /// problem found instead of stopping at the first one
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = vec![];
+        let mut services: HashSet<String> = HashSet::new();
+
+        let mut pod_names: Vec<&String> = self.pods.keys().collect();
+        pod_names.sort();
+        for pod_name in pod_names {
+            let pod = &self.pods[pod_name];
+            let mut specs: Vec<(String, &Spec)> = pod
+                .specs
+                .iter()
+                .map(|(name, spec)| (format!("{}-{}", pod_name, name), spec))
+                .collect();
+            specs.sort_by(|a, b| a.0.cmp(&b.0));
+            specs.extend(
+                pod.init_specs
+                    .iter()
+                    .map(|init| (format!("{}-init-{}", pod_name, init.name), &init.spec)),
+            );
+
+            for (service, spec) in specs {
+                if !services.insert(service.clone()) {
+                    errors.push(ValidationError::DuplicateService {
+                        service: service.clone(),
+                    });
+                }
+                if spec.image.trim().is_empty() {
+                    errors.push(ValidationError::EmptyImage {
+                        service: service.clone(),
+                    });
+                }
+                for device in &spec.devices {
+                    if !device.starts_with('/') {
+                        errors.push(ValidationError::InvalidDevice {
+                            service: service.clone(),
+                            device: device.clone(),
+                        });
+                    }
+                }
+                let mut ulimits: Vec<_> = spec.ulimits.iter().collect();
+                ulimits.sort();
+                for (name, (soft, hard)) in ulimits {
+                    if soft > hard {
+                        errors.push(ValidationError::InvalidUlimit {
+                            service: service.clone(),
+                            name: name.clone(),
+                        });
+                    }
+                }
+                if spec
+                    .oom_score_adj
+                    .is_some_and(|score| !(-1000..=1000).contains(&score))
+                {
+                    errors.push(ValidationError::InvalidOomScoreAdj {
+                        service: service.clone(),
+                    });
+                }
+                if let Some(memswap_limit) = spec.memswap_limit
+                    && spec
+                        .mem_limit
+                        .is_none_or(|mem_limit| memswap_limit < mem_limit)
+                {
+                    errors.push(ValidationError::InvalidMemswapLimit {
+                        service: service.clone(),
+                    });
+                }
+                if let Some(signal) = &spec.stop_signal
+                    && !is_signal(signal)
+                {
+                    errors.push(ValidationError::InvalidStopSignal {
+                        service: service.clone(),
+                        signal: signal.clone(),
+                    });
+                }
+                if let Some(readiness) = &spec.readiness
+                    && !pod
+                        .specs
+                        .values()
+                        .any(|babel| readiness.is_served_by(babel))
+                {
+                    errors.push(ValidationError::MissingBabel {
+                        service: service.clone(),
+                        node_type: readiness.node_type.clone(),
+                    });
+                }
+                for capability in spec.cap_add.iter().chain(&spec.cap_drop) {
+                    let valid = !capability.is_empty()
+                        && capability
+                            .chars()
+                            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+                    if !valid {
+                        errors.push(ValidationError::InvalidCapability {
+                            service: service.clone(),
+                            capability: capability.clone(),
+                        });
+                    }
+                }
+                if spec
+                    .digest
+                    .as_deref()
+                    .is_some_and(|digest| !digest.strip_prefix("sha256:").is_some_and(is_sha256))
+                {
+                    errors.push(ValidationError::InvalidDigest {
+                        service: service.clone(),
+                    });
+                }
+
+                let mut target_paths = HashSet::new();
+                let files = spec
+                    .args
+                    .iter()
+                    .filter_map(|arg| match arg {
+                        Arg::File(file) => Some(file),
+                        _ => None,
+                    })
+                    .chain(spec.artifacts.iter().filter_map(|artifact| match artifact {
+                        Artifacts::File(file) => Some(file),
+                        Artifacts::Dir(_) => None,
+                    }));
+                for file in files {
+                    if file
+                        .sha256
+                        .as_deref()
+                        .is_some_and(|sha256| !is_sha256(sha256))
+                    {
+                        errors.push(ValidationError::InvalidChecksum {
+                            service: service.clone(),
+                            name: file.name.clone(),
+                        });
+                    }
+                    if !target_paths.insert(file.target_path.as_str()) {
+                        errors.push(ValidationError::ConflictingTargetPath {
+                            service: service.clone(),
+                            target_path: file.target_path.clone(),
+                        });
+                    }
+                }
+                for artifact in &spec.artifacts {
+                    if let Artifacts::Dir(dir) = artifact
+                        && !target_paths.insert(dir.target_path.as_str())
+                    {
+                        errors.push(ValidationError::ConflictingTargetPath {
+                            service: service.clone(),
+                            target_path: dir.target_path.clone(),
+                        });
+                    }
+                }
+
+                let mut port_names = HashSet::new();
+                let port_args = spec.args.iter().filter_map(|arg| match arg {
+                    Arg::Port { name, .. } => Some(name),
+                    _ => None,
+                });
+                for name in port_args.chain(spec.ports.iter().map(|port| &port.name)) {
+                    if !port_names.insert(name.as_str()) {
+                        errors.push(ValidationError::DuplicatePortName {
+                            service: service.clone(),
+                            port: name.clone(),
+                        });
+                    }
+                }
+
+                for (name, port) in spec.args.iter().flat_map(Arg::refs) {
+                    if let Err(err) = self.resolve_ref(name, port) {
+                        errors.push(ValidationError::UnresolvedRef {
+                            service: service.clone(),
+                            reason: err.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        if let Err(err) = self.startup_order() {
+            errors.push(ValidationError::InvalidDependencies {
+                reason: err.to_string(),
+            });
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(errors))
+        }
+    }
+
+    /// Returns the ports published on the host, the port args and the ports
+    /// exposed publicly
+    pub fn host_ports(&self) -> Vec<HostPort> {
+        let mut ports = vec![];
+        for (pod_name, pod) in &self.pods {
+            for (spec_name, spec) in &pod.specs {
+                let host_port = |name: &str, port: u16, protocol: Protocol, public: bool| {
+                    let mut host_port = HostPort {
+                        pod: pod_name.clone(),
+                        spec: spec_name.clone(),
+                        name: name.to_string(),
+                        port,
+                        host_port: port,
+                        protocol,
+                        public,
+                    };
+                    if let Some(allocated) = self
+                        .allocated_ports
+                        .iter()
+                        .find(|allocated| allocated.same_port(&host_port))
+                    {
+                        host_port.host_port = allocated.host_port;
+                    }
+                    host_port
+                };
+                for arg in &spec.args {
+                    if let Arg::Port { name, preferred } = arg {
+                        ports.push(host_port(name, *preferred, Protocol::Tcp, false));
+                    }
+                }
+                for port in spec.ports.iter().filter(|port| port.is_published()) {
+                    ports.push(host_port(
+                        &port.name,
+                        port.port,
+                        port.protocol,
+                        port.expose_publicly,
+                    ));
+                }
+            }
+        }
+        ports.sort_by(|a, b| {
+            (a.port, &a.pod, &a.spec, &a.name).cmp(&(b.port, &b.pod, &b.spec, &b.name))
+        });
+        ports
+    }
+
+    /// nftables ruleset that drops the traffic from other hosts to the
+    /// internal host ports of the manifest, only the ports exposed publicly
+    /// stay reachable. The rule matches the forwarded traffic that the
+    /// runtime translated to a container, the host itself is not filtered.
+    pub fn nftables_rules(&self) -> String {
+        let mut internal: BTreeMap<&str, BTreeSet<u16>> = BTreeMap::new();
+        for port in self.host_ports().iter().filter(|port| !port.public) {
+            let protocol = match port.protocol {
+                Protocol::Tcp => "tcp",
+                Protocol::Udp => "udp",
+            };
+            internal.entry(protocol).or_default().insert(port.host_port);
+        }
+
+        // the table is declared and deleted first so that the rules replace
+        // the ones loaded for a previous version of the deployment
+        let table = format!("inet bbuilder_{}", self.name.replace('-', "_"));
+        let mut rules = format!(
+            "# internal host ports of deployment {}\n\
+             table {table}\n\
+             delete table {table}\n\
+             table {table} {{\n\
+             \tchain forward {{\n\
+             \t\ttype filter hook forward priority filter - 1; policy accept;\n",
+            self.name,
+        );
+        for (protocol, ports) in internal {
+            let ports: Vec<String> = ports.iter().map(u16::to_string).collect();
+            rules.push_str(&format!(
+                "\t\tct status dnat meta l4proto {} ct original proto-dst {{ {} }} drop\n",
+                protocol,
+                ports.join(", ")
+            ));
+        }
+        rules.push_str("\t}\n}\n");
+        rules
+    }
+
+    /// Assigns another host port to the published ports whose preferred port
+    /// is already used by another spec of the manifest or by the `used` host
+    /// ports. The new ports are recorded in `allocated_ports`, the port inside
+    /// the container does not change.
+    pub fn allocate_host_ports(&mut self, used: &HashSet<(u16, Protocol)>) -> Vec<HostPort> {
+        let mut taken = used.clone();
+        let mut allocated = vec![];
+
+        for mut port in self.host_ports() {
+            if taken.contains(&(port.host_port, port.protocol)) {
+                let Some(free) = (port.port..=u16::MAX)
+                    .chain(1024..port.port)
+                    .find(|candidate| !taken.contains(&(*candidate, port.protocol)))
+                else {
+                    continue;
+                };
+                port.host_port = free;
+                allocated.push(port.clone());
+            }
+            taken.insert((port.host_port, port.protocol));
+        }
+
+        for port in &allocated {
+            self.allocated_ports
+                .retain(|existing| !existing.same_port(port));
+            self.allocated_ports.push(port.clone());
+        }
+        allocated
+    }
+
+    /// Returns the (pod, spec, port) of every spec that declares a metrics port
+    pub fn metrics_ports(&self) -> Vec<(&str, &str, u16)> {
+        let mut targets = vec![];
+        for (pod_name, pod) in &self.pods {
+            for (spec_name, spec) in &pod.specs {
+                if let Some(port) = spec.port(METRICS_PORT) {
+                    targets.push((pod_name.as_str(), spec_name.as_str(), port));
+                }
+            }
+        }
+        targets.sort();
+        targets
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Metadata of a spec that labels and env values reference as `{{deployment}}`,
+/// `{{pod}}`, `{{spec}}` and `{{chain}}`, the runtimes render them when deploying
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata<'a> {
+    pub deployment: &'a str,
+    pub pod: &'a str,
+    pub spec: &'a str,
+    pub chain: &'a str,
+}
+
+impl Metadata<'_> {
+    /// Replaces the metadata placeholders, any other `{{...}}` is left as is
+    pub fn render(&self, value: &str) -> String {
+        if !value.contains("{{") {
+            return value.to_string();
+        }
+        value
+            .replace("{{deployment}}", self.deployment)
+            .replace("{{pod}}", self.pod)
+            .replace("{{spec}}", self.spec)
+            .replace("{{chain}}", self.chain)
+    }
+}
+
+/// Problem found by `Manifest::validate`, specs are identified by their
+/// service name (`<pod>-<spec>` or `<pod>-init-<name>`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    DuplicateService {
+        service: String,
+    },
+    EmptyImage {
+        service: String,
+    },
+    InvalidDigest {
+        service: String,
+    },
+    InvalidCapability {
+        service: String,
+        capability: String,
+    },
+    InvalidUlimit {
+        service: String,
+        name: String,
+    },
+    InvalidOomScoreAdj {
+        service: String,
+    },
+    InvalidMemswapLimit {
+        service: String,
+    },
+    InvalidStopSignal {
+        service: String,
+        signal: String,
+    },
+    InvalidDevice {
+        service: String,
+        device: String,
+    },
+    MissingBabel {
+        service: String,
+        node_type: String,
+    },
+    ConflictingTargetPath {
+        service: String,
+        target_path: String,
+    },
+    DuplicatePortName {
+        service: String,
+        port: String,
+    },
+    InvalidChecksum {
+        service: String,
+        name: String,
+    },
+    UnresolvedRef {
+        service: String,
+        reason: String,
+    },
+    InvalidDependencies {
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::DuplicateService { service } => {
+                write!(f, "{}: more than one spec has this name", service)
+            }
+            ValidationError::EmptyImage { service } => write!(f, "{}: the image is empty", service),
+            ValidationError::InvalidDigest { service } => {
+                write!(f, "{}: the image digest is not a sha256 digest", service)
+            }
+            ValidationError::InvalidCapability {
+                service,
+                capability,
+            } => write!(f, "{}: {} is not a linux capability", service, capability),
+            ValidationError::InvalidDevice { service, device } => {
+                write!(f, "{}: device {} is not an absolute path", service, device)
+            }
+            ValidationError::InvalidUlimit { service, name } => write!(
+                f,
+                "{}: the soft limit of ulimit {} is above the hard limit",
+                service, name
+            ),
+            ValidationError::InvalidOomScoreAdj { service } => {
+                write!(
+                    f,
+                    "{}: oom_score_adj is not between -1000 and 1000",
+                    service
+                )
+            }
+            ValidationError::InvalidMemswapLimit { service } => write!(
+                f,
+                "{}: memswap_limit requires a mem_limit that is not above it",
+                service
+            ),
+            ValidationError::InvalidStopSignal { service, signal } => {
+                write!(f, "{}: {} is not a signal name or number", service, signal)
+            }
+            ValidationError::MissingBabel { service, node_type } => write!(
+                f,
+                "{}: no {} babel of the pod serves its readiness probe",
+                service, node_type
+            ),
+            ValidationError::ConflictingTargetPath {
+                service,
+                target_path,
+            } => write!(f, "{}: more than one file targets {}", service, target_path),
+            ValidationError::DuplicatePortName { service, port } => {
+                write!(f, "{}: port {} is declared more than once", service, port)
+            }
+            ValidationError::InvalidChecksum { service, name } => {
+                write!(f, "{}: the sha256 of file {} is not valid", service, name)
+            }
+            ValidationError::UnresolvedRef { service, reason } => {
+                write!(f, "{}: {}", service, reason)
+            }
+            ValidationError::InvalidDependencies { reason } => write!(f, "{}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid manifest")?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Spec and port an `Arg::Ref` points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefTarget {
+    pub pod: String,
+    pub spec: String,
+    pub port: u16,
+}
+
+impl RefTarget {
+    /// Name of the spec inside the deployment network
+    pub fn host(&self) -> String {
+        format!("{}-{}", self.pod, self.spec)
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}:{}", self.host(), self.port)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Artifacts {
     File(File),
+    Dir(Dir),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Arg {
     Port { name: String, preferred: u16 },
     Dir { name: String, path: String },
+    // url of a port of another spec, `name` is a pod or `<pod>/<spec>`
     Ref { name: String, port: String },
+    // comma separated urls of the same port of several specs, in order (e.g.
+    // the beacon nodes of a validator client with its fallbacks)
+    Refs { names: Vec<String>, port: String },
     File(File),
     Value(String),
+    // resolved by the runtime at deploy time into the path of a file with the secret
+    Secret { name: String },
 }
 
-#[derive(Debug, Clone)]
+impl Arg {
+    pub fn secret(name: impl Into<String>) -> Self {
+        Arg::Secret { name: name.into() }
+    }
+
+    /// Specs and ports the arg points to
+    pub fn refs(&self) -> Vec<(&String, &String)> {
+        match self {
+            Arg::Ref { name, port } => vec![(name, port)],
+            Arg::Refs { names, port } => names.iter().map(|name| (name, port)).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Path inside the container of the file with the given secret
+pub fn secret_path(name: &str) -> String {
+    format!("{}/{}", SECRETS_DIR, name)
+}
+
+/// Resolves the value of the secrets at deploy time so that they are not
+/// embedded in the manifest
+pub trait SecretProvider: Send + Sync {
+    fn secret(&self, name: &str) -> eyre::Result<Option<String>>;
+
+    /// The secrets are generated on every call, the runtime keeps the one it
+    /// already wrote for the deployment
+    fn generated(&self) -> bool {
+        false
+    }
+}
+
+/// Reads the secrets from `BBUILDER_SECRET_<NAME>` environment variables
+#[derive(Debug, Default, Clone)]
+pub struct EnvSecretProvider;
+
+impl EnvSecretProvider {
+    pub fn var_name(name: &str) -> String {
+        format!("BBUILDER_SECRET_{}", name.to_uppercase().replace('-', "_"))
+    }
+}
+
+impl SecretProvider for EnvSecretProvider {
+    fn secret(&self, name: &str) -> eyre::Result<Option<String>> {
+        match std::env::var(Self::var_name(name)) {
+            Ok(value) => Ok(Some(value)),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(err) => Err(eyre::eyre!("Invalid secret {}: {}", name, err)),
+        }
+    }
+}
+
+/// Random values for the secrets that are only shared by the specs of a
+/// deployment (e.g. the jwt between the execution and consensus clients)
+#[derive(Debug, Default, Clone)]
+pub struct DefaultSecretProvider;
+
+impl SecretProvider for DefaultSecretProvider {
+    fn secret(&self, name: &str) -> eyre::Result<Option<String>> {
+        Ok((name == JWT_SECRET).then(|| hex::encode(rand::random::<[u8; 32]>())))
+    }
+
+    fn generated(&self) -> bool {
+        true
+    }
+}
+
+impl SecretProvider for HashMap<String, String> {
+    fn secret(&self, name: &str) -> eyre::Result<Option<String>> {
+        Ok(self.get(name).cloned())
+    }
+}
+
+/// Directory tree materialized under the target path, the files keep their
+/// path relative to the root of the tree
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Dir {
+    pub name: String,
+    pub target_path: String,
+    pub files: Vec<DirFile>,
+    // mount the directory read-only inside the container
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirFile {
+    // path relative to the root of the directory
     pub path: String,
-    pub dir: include_dir::Dir<'static>,
+    pub content: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Dir {
+    /// Directory with the files of a tree embedded with `include_dir!`
+    pub fn embedded(
+        name: impl Into<String>,
+        dir: &include_dir::Dir<'static>,
+        target_path: impl Into<String>,
+    ) -> eyre::Result<Self> {
+        // the paths of the embedded files are relative to the embedded root,
+        // which is not this dir if it is a subdirectory of the tree
+        let root = dir.path();
+        let mut files = vec![];
+        let mut pending = vec![dir];
+        while let Some(dir) = pending.pop() {
+            for file in dir.files() {
+                let content = file.contents_utf8().ok_or_else(|| {
+                    eyre::eyre!("Embedded file {} is not utf-8", file.path().display())
+                })?;
+                let path = file.path().strip_prefix(root).unwrap_or(file.path());
+                files.push(DirFile {
+                    path: path.to_string_lossy().to_string(),
+                    content: content.to_string(),
+                });
+            }
+            pending.extend(dir.dirs());
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(Self {
+            name: name.into(),
+            target_path: target_path.into(),
+            files,
+            read_only: false,
+        })
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct File {
     pub name: String,
     pub target_path: String,
-    pub content: String,
+    pub content: ArtifactSource,
+    // unix permission bits applied to the materialized file (e.g. 0o600 for keys)
+    #[serde(default)]
+    pub mode: Option<u32>,
+    // mount the file read-only inside the container
+    #[serde(default)]
+    pub read_only: bool,
+    // uid that owns the materialized file, overrides the spec uid
+    #[serde(default)]
+    pub owner: Option<u32>,
+    // expected sha256 (hex) of the content, checked before the file is materialized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// Content of a file artifact (or of an env file var). The url and generated
+/// contents are resolved by the runtime when it deploys the manifest, so that
+/// building the manifest does not block on them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ArtifactSource {
+    Inline(String),
+    Url {
+        url: String,
+    },
+    #[serde(skip_deserializing)]
+    Generator(ContentGenerator),
+}
+
+/// Callback that generates the content of an artifact from the contents of
+/// its urls, which the runtime fetches beforehand. The urls that could not be
+/// fetched are missing from the contents, the generator falls back or fails.
+#[derive(Clone)]
+pub struct ContentGenerator {
+    urls: Vec<String>,
+    generate: Arc<GenerateFn>,
+}
+
+type GenerateFn = dyn Fn(&HashMap<String, String>) -> eyre::Result<String> + Send + Sync;
+
+impl std::fmt::Debug for ContentGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ContentGenerator")
+    }
+}
+
+// the generated content is not known until the manifest is deployed
+impl Serialize for ContentGenerator {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("<generated when deployed>")
+    }
+}
+
+impl Default for ArtifactSource {
+    fn default() -> Self {
+        Self::Inline(String::new())
+    }
+}
+
+impl From<String> for ArtifactSource {
+    fn from(content: String) -> Self {
+        Self::Inline(content)
+    }
+}
+
+impl From<&str> for ArtifactSource {
+    fn from(content: &str) -> Self {
+        Self::Inline(content.to_string())
+    }
+}
+
+impl ArtifactSource {
+    pub fn url(url: impl Into<String>) -> Self {
+        Self::Url { url: url.into() }
+    }
+
+    /// Content generated when deploying, `generate` receives the fetched
+    /// contents of `urls`
+    pub fn generator(
+        urls: Vec<String>,
+        generate: impl Fn(&HashMap<String, String>) -> eyre::Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        Self::Generator(ContentGenerator {
+            urls,
+            generate: Arc::new(generate),
+        })
+    }
+
+    /// Urls the runtime has to fetch to resolve the content
+    pub fn urls(&self) -> Vec<&str> {
+        match self {
+            Self::Inline(_) => vec![],
+            Self::Url { url } => vec![url.as_str()],
+            Self::Generator(generator) => generator.urls.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// Content if it is known already, none until the runtime resolves it
+    pub fn inline(&self) -> Option<&str> {
+        match self {
+            Self::Inline(content) => Some(content),
+            _ => None,
+        }
+    }
+
+    /// Replaces the url content with the one fetched by the runtime and runs
+    /// the generator
+    pub fn resolve(&mut self, fetched: &HashMap<String, String>) -> eyre::Result<()> {
+        let content = match self {
+            Self::Inline(_) => return Ok(()),
+            Self::Url { url } => fetched
+                .get(url)
+                .cloned()
+                .ok_or_else(|| eyre::eyre!("Content of {} was not fetched", url))?,
+            // the urls that were not fetched are handled by the generator
+            Self::Generator(generator) => (generator.generate)(fetched)?,
+        };
+        *self = Self::Inline(content);
+        Ok(())
+    }
+}
+
+impl File {
+    fn content(&self) -> eyre::Result<&str> {
+        self.content
+            .inline()
+            .ok_or_else(|| eyre::eyre!("Content of file {} is not resolved", self.name))
+    }
+
+    /// Checks the content against the expected sha256, if any
+    pub fn verify_checksum(&self) -> eyre::Result<()> {
+        let Some(expected) = &self.sha256 else {
+            return Ok(());
+        };
+        let actual = hex::encode(Sha256::digest(self.content()?.as_bytes()));
+        if !actual.eq_ignore_ascii_case(expected) {
+            eyre::bail!(
+                "Checksum mismatch for {}: expected sha256 {}, got {}",
+                self.name,
+                expected,
+                actual
+            );
+        }
+        Ok(())
+    }
+
+    /// Refs of the `{{ref:<name>:<port>}}` and `{{addr:<name>:<port>}}`
+    /// placeholders of the content, the contents resolved when deploying are
+    /// not known yet
+    pub fn refs(&self) -> Vec<(String, String)> {
+        placeholders(self.content.inline().unwrap_or_default())
+            .filter_map(|placeholder| {
+                let reference = placeholder
+                    .strip_prefix("ref:")
+                    .or_else(|| placeholder.strip_prefix("addr:"))?;
+                let (name, port) = reference.split_once(':')?;
+                Some((name.to_string(), port.to_string()))
+            })
+            .collect()
+    }
+
+    /// Secrets of the `{{secret:<name>}}` placeholders of the content, the
+    /// runtime mounts them like the secret args
+    pub fn secrets(&self) -> Vec<String> {
+        placeholders(self.content.inline().unwrap_or_default())
+            .filter_map(|placeholder| placeholder.strip_prefix("secret:"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Renders the placeholders that are only known when deploying: the
+    /// metadata ones, `{{ref:<name>:<port>}}` with the url of the ref,
+    /// `{{addr:<name>:<port>}}` with its host and port (for the urls of other
+    /// schemes), `{{port:<name>}}` with the port of the spec and
+    /// `{{secret:<name>}}` with the path of the secret. Any other `{{...}}`
+    /// is left as is.
+    pub fn render(
+        &self,
+        metadata: &Metadata,
+        refs: &HashMap<(String, String), RefTarget>,
+        ports: &HashMap<String, u16>,
+    ) -> eyre::Result<String> {
+        let mut rest = self.content()?;
+        let mut rendered = String::with_capacity(rest.len());
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start..].find("}}") else {
+                break;
+            };
+            let placeholder = &rest[start + 2..start + len];
+            rendered.push_str(&rest[..start]);
+
+            let reference = placeholder
+                .strip_prefix("ref:")
+                .map(|reference| (reference, true))
+                .or_else(|| Some((placeholder.strip_prefix("addr:")?, false)));
+            if let Some((reference, url)) = reference {
+                let target = reference
+                    .split_once(':')
+                    .and_then(|(name, port)| refs.get(&(name.to_string(), port.to_string())))
+                    .ok_or_else(|| {
+                        eyre::eyre!("File {} has an unknown ref {}", self.name, reference)
+                    })?;
+                match url {
+                    true => rendered.push_str(&target.url()),
+                    false => rendered.push_str(&format!("{}:{}", target.host(), target.port)),
+                }
+            } else if let Some(name) = placeholder.strip_prefix("secret:") {
+                rendered.push_str(&secret_path(name));
+            } else if let Some(name) = placeholder.strip_prefix("port:") {
+                let port = ports.get(name).ok_or_else(|| {
+                    eyre::eyre!("File {} references an unknown port {}", self.name, name)
+                })?;
+                rendered.push_str(&port.to_string());
+            } else {
+                rendered.push_str(&metadata.render(&rest[start..start + len + 2]));
+            }
+            rest = &rest[start + len + 2..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+}
+
+/// Contents of the `{{...}}` placeholders of the value
+fn placeholders(value: &str) -> impl Iterator<Item = &str> {
+    value.split("{{").skip(1).filter_map(|part| {
+        let (placeholder, _) = part.split_once("}}")?;
+        Some(placeholder)
+    })
+}
+
+fn is_sha256(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
 }
 
 #[macro_export]
@@ -109,6 +1753,49 @@ macro_rules! port {
     };
 }
 
+#[macro_export]
+macro_rules! dir {
+    ($name:expr, $path:expr) => {
+        spec::Arg::Dir {
+            name: $name.to_string(),
+            path: $path.to_string(),
+        }
+    };
+}
+
+// `ref` is a keyword
+#[macro_export]
+macro_rules! ref_ {
+    ($name:expr, $port:expr) => {
+        spec::Arg::Ref {
+            name: $name.to_string(),
+            port: $port.to_string(),
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! file_arg {
+    ($name:expr, $target_path:expr, $content:expr) => {
+        spec::Arg::File(spec::file($name, $target_path, $content))
+    };
+}
+
+/// File with the content mounted at the target path, the other settings of
+/// the file are the defaults
+pub fn file(
+    name: impl Into<String>,
+    target_path: impl Into<String>,
+    content: impl Into<ArtifactSource>,
+) -> File {
+    File {
+        name: name.into(),
+        target_path: target_path.into(),
+        content: content.into(),
+        ..Default::default()
+    }
+}
+
 impl From<String> for Arg {
     fn from(s: String) -> Self {
         Arg::Value(s)
@@ -157,9 +1844,86 @@ impl From<&PathBuf> for Arg {
     }
 }
 
-#[derive(Default, Debug, Clone)]
+/// JSON-RPC call sent to a port of a spec after the deployment, the deploy
+/// fails if its result does not match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmokeTest {
+    pub name: String,
+    pub pod: String,
+    pub spec: String,
+    // name of the port of the spec the call is sent to
+    pub port: String,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<serde_json::Value>,
+    pub expect: Expect,
+}
+
+impl SmokeTest {
+    /// Call without params to the `node` spec of the pod
+    pub fn rpc(
+        name: impl Into<String>,
+        pod: impl Into<String>,
+        port: impl Into<String>,
+        method: impl Into<String>,
+        expect: Expect,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            pod: pod.into(),
+            spec: "node".to_string(),
+            port: port.into(),
+            method: method.into(),
+            params: vec![],
+            expect,
+        }
+    }
+}
+
+/// Expected result of a smoke test
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Expect {
+    // the result is the value
+    Equals(serde_json::Value),
+    // the result has the fields of the value with the same values, the other
+    // fields of the result are ignored
+    Contains(serde_json::Value),
+}
+
+fn contains(result: &serde_json::Value, expected: &serde_json::Value) -> bool {
+    match (result, expected) {
+        (serde_json::Value::Object(result), serde_json::Value::Object(expected)) => {
+            expected.iter().all(|(key, expected)| {
+                result
+                    .get(key)
+                    .is_some_and(|result| contains(result, expected))
+            })
+        }
+        (result, expected) => result == expected,
+    }
+}
+
+impl Expect {
+    pub fn check(&self, result: &serde_json::Value) -> eyre::Result<()> {
+        let (matches, expected) = match self {
+            Expect::Equals(expected) => (result == expected, expected),
+            Expect::Contains(expected) => (contains(result, expected), expected),
+        };
+        if !matches {
+            eyre::bail!("expected {} but got {}", expected, result);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Pod {
-    pub specs: HashMap<String, Spec>,
+    pub specs: BTreeMap<String, Spec>,
+    // run in order before the specs of the pod, each one has to exit
+    // successfully before the next one starts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub init_specs: Vec<InitSpec>,
 }
 
 impl Pod {
@@ -167,36 +1931,659 @@ impl Pod {
         self.specs.insert(name.to_string(), spec.into());
         self
     }
+
+    pub fn with_init_spec(mut self, name: &str, spec: impl Into<Spec>) -> Self {
+        self.init_specs.push(InitSpec {
+            name: name.to_string(),
+            spec: spec.into(),
+        });
+        self
+    }
+
+    /// Adds the babel sidecar as the `babel` spec, the specs it follows
+    /// through its rpc url use it as their readiness probe
+    pub fn with_babel(mut self, babel: Babel) -> Self {
+        let probe = babel.probe();
+        for (name, port) in babel.rpc_url.refs() {
+            let spec_name = name.split_once('/').map(|(_, spec_name)| spec_name);
+            for (_, spec) in self.specs.iter_mut().filter(|(name, spec)| {
+                spec_name.is_none_or(|spec_name| spec_name == name.as_str())
+                    && spec.port(port).is_some()
+            }) {
+                spec.readiness = Some(probe.clone());
+            }
+        }
+        self.with_spec("babel", babel.spec())
+    }
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InitSpec {
+    pub name: String,
+    pub spec: Spec,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Spec {
     pub image: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tag: Option<String>,
+    // content digest (`sha256:<hex>`) the image is pinned to, it takes
+    // precedence over the tag so a re-pushed tag does not change the deployment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    // when the runtime pulls the image, the runtime default if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_policy: Option<PullPolicy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub args: Vec<Arg>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub entrypoint: Vec<String>,
-    pub labels: HashMap<String, String>,
-    pub env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    // env vars written to an env file of the spec instead of being inlined by
+    // the runtime, for large values (e.g. lists of peers)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env_file: BTreeMap<String, ArtifactSource>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub artifacts: Vec<Artifacts>,
-    pub volumes: HashMap<String, Volume>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub volumes: BTreeMap<String, Volume>,
+    // uid (and gid) the container process runs as instead of the user of the
+    // image, artifacts and data dirs are chowned to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    // directory the entrypoint runs in instead of the one of the image, some
+    // daemons read their config relative to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workdir: Option<String>,
+    // root filesystem mounted read only, only the volumes are writable
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only_rootfs: bool,
+    // linux capabilities (e.g. `NET_ADMIN`) added to or dropped from the
+    // default set, `ALL` drops every capability
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cap_add: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cap_drop: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub privileged: bool,
+    // resource limits of the container process (e.g. `nofile`) as (soft, hard)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ulimits: BTreeMap<String, (u64, u64)>,
+    // namespaced kernel parameters of the container (e.g. `net.core.somaxconn`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sysctls: BTreeMap<String, String>,
+    // preference of the kernel OOM killer for the container, from -1000
+    // (never killed) to 1000 (killed first)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oom_score_adj: Option<i32>,
+    // memory the container can use in bytes, and memory plus swap, the swap
+    // limit requires the memory one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mem_limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memswap_limit: Option<u64>,
+    // size of `/dev/shm` in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shm_size: Option<u64>,
+    // signal sent to stop the container (e.g. `SIGINT`), SIGTERM if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_signal: Option<String>,
+    // seconds the container has to exit after the stop signal before it is
+    // killed, the databases of the execution clients need minutes to close
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_grace_period: Option<u64>,
+    // where the runtime sends the output of the container, its default if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logging: Option<LogConfig>,
+    // host devices mapped into the container, `<host path>[:<container path>]`
+    // (e.g. `/dev/bus/usb` for the hardware wallets of a signer)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu: Option<Gpu>,
+    // architecture of the image pulled for the spec, the one of the host if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Arch>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub healthcheck: Option<HealthCheck>,
+    // ports the spec listens on besides the port args (e.g. p2p ports)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<Port>,
+    // shell commands run with the image and volumes of the spec before it
+    // starts (e.g. db migrations, key import), a failure stops the deployment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_start: Vec<String>,
+    // shell commands run inside the container once it started
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_start: Vec<String>,
+    // shell commands run inside the container before it is stopped (e.g. a
+    // graceful shutdown of a consensus node)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_stop: Vec<String>,
+    // files of the node identity in its volumes (e.g. the p2p key), the node
+    // generates them again if they are missing so that they can be rotated
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub identity_keys: Vec<String>,
+    // babel of the pod that reports when the node is ready, runtimes gate the
+    // specs that depend on it on this instead of on the container start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readiness: Option<BabelProbe>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PullPolicy {
+    // pull on every deployment
+    Always,
+    // pull only if the image is not in the host
+    IfNotPresent,
+    // never pull, the image must be in the host
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+/// Port of a spec published on the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostPort {
+    pub pod: String,
+    pub spec: String,
+    pub name: String,
+    // port inside the container
+    pub port: u16,
+    // port published on the host, the same as `port` unless it was allocated
+    pub host_port: u16,
+    pub protocol: Protocol,
+    // reachable from outside the host, the other host ports are internal to
+    // the host and the deployment
+    #[serde(default)]
+    pub public: bool,
+}
+
+impl HostPort {
+    /// Both refer to the same port of the same spec
+    pub fn same_port(&self, other: &HostPort) -> bool {
+        self.pod == other.pod
+            && self.spec == other.spec
+            && self.name == other.name
+            && self.protocol == other.protocol
+    }
+}
+
+/// Port declared by a spec, port args are always tcp
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Port {
+    pub name: String,
+    pub port: u16,
+    #[serde(default)]
+    pub protocol: Protocol,
+    // published on the host so that it is reachable from outside (e.g. by peers)
+    #[serde(default)]
+    pub expose_publicly: bool,
+    // address of the host the port is published on (e.g. `127.0.0.1` to keep
+    // it local), a port with an address is published even if it is not public
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_ip: Option<IpAddr>,
+}
+
+impl Port {
+    pub fn tcp(name: impl Into<String>, port: u16) -> Self {
+        Self {
+            name: name.into(),
+            port,
+            protocol: Protocol::Tcp,
+            expose_publicly: false,
+            host_ip: None,
+        }
+    }
+
+    pub fn udp(name: impl Into<String>, port: u16) -> Self {
+        Self {
+            protocol: Protocol::Udp,
+            ..Self::tcp(name, port)
+        }
+    }
+
+    pub fn public(mut self) -> Self {
+        self.expose_publicly = true;
+        self
+    }
+
+    pub fn host_ip(mut self, ip: IpAddr) -> Self {
+        self.host_ip = Some(ip);
+        self
+    }
+
+    /// Published on the host, either publicly or on a given address
+    pub fn is_published(&self) -> bool {
+        self.expose_publicly || self.host_ip.is_some()
+    }
+}
+
+/// Cpu architecture of the host that runs the specs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    #[serde(alias = "x86_64")]
+    Amd64,
+    #[serde(alias = "aarch64")]
+    Arm64,
+}
+
+impl Arch {
+    /// Architecture of the host bbuilder runs on
+    pub fn host() -> eyre::Result<Self> {
+        std::env::consts::ARCH.parse()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Arch::Amd64 => "amd64",
+            Arch::Arm64 => "arm64",
+        }
+    }
+}
+
+impl std::str::FromStr for Arch {
+    type Err = eyre::Report;
+
+    /// Parses both the docker (`amd64`) and the kernel (`x86_64`) names
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "amd64" | "x86_64" => Ok(Arch::Amd64),
+            "arm64" | "aarch64" => Ok(Arch::Arm64),
+            _ => Err(eyre::eyre!("Unsupported architecture: {}", s)),
+        }
+    }
+}
+
+/// GPUs reserved for a spec
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Gpu {
+    #[serde(default)]
+    pub vendor: GpuVendor,
+    // gpus reserved, all the gpus of the host if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuVendor {
+    // reserved through the nvidia container toolkit
+    #[default]
+    Nvidia,
+    // the rocm devices are mapped into the container, `count` does not apply
+    Amd,
+}
+
+/// Log driver of a spec and the rotation of its log files
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogConfig {
+    #[serde(default = "default_log_driver")]
+    pub driver: String,
+    // size a log file grows to before it is rotated (e.g. `100m`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<String>,
+    // rotated log files kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_file: Option<u32>,
+    // tag of the log lines, the metadata placeholders (e.g. `{{pod}}`) are rendered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+fn default_log_driver() -> String {
+    "json-file".to_string()
+}
+
+impl LogConfig {
+    /// Local json log files rotated at `max_size`, keeping `max_file` of them
+    pub fn rotate(max_size: impl Into<String>, max_file: u32) -> Self {
+        Self {
+            driver: default_log_driver(),
+            max_size: Some(max_size.into()),
+            max_file: Some(max_file),
+            tag: None,
+        }
+    }
+
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+}
+
+/// Probe run periodically by the runtime to know if a spec is healthy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheck {
+    pub probe: Probe,
+    // seconds between two probes
+    pub interval: u64,
+    // seconds before a probe is considered failed
+    pub timeout: u64,
+    // consecutive failures before the spec is marked unhealthy
+    pub retries: u32,
+    // seconds after start during which failures are not counted
+    pub start_period: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Probe {
+    // command run inside the container, healthy if it exits with 0
+    Command(Vec<String>),
+    // GET request to the path on the named port arg, healthy on a 2xx status.
+    // Runtimes may run it with curl or wget from inside the container, the
+    // images without either need a command probe or a babel sidecar.
+    Http { port: String, path: String },
+}
+
+impl HealthCheck {
+    pub fn command<I>(command: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self::new(Probe::Command(
+            command.into_iter().map(Into::into).collect(),
+        ))
+    }
+
+    pub fn http(port: impl Into<String>, path: impl Into<String>) -> Self {
+        Self::new(Probe::Http {
+            port: port.into(),
+            path: path.into(),
+        })
+    }
+
+    fn new(probe: Probe) -> Self {
+        Self {
+            probe,
+            interval: 10,
+            timeout: 5,
+            retries: 3,
+            start_period: 0,
+        }
+    }
+
+    pub fn start_period(mut self, seconds: u64) -> Self {
+        self.start_period = seconds;
+        self
+    }
 }
 
 #[derive(Default)]
 pub struct SpecBuilder {
     image: Option<String>,
     tag: Option<String>,
+    digest: Option<String>,
+    pull_policy: Option<PullPolicy>,
     args: Vec<Arg>,
-    env: HashMap<String, String>,
+    env: BTreeMap<String, String>,
+    env_file: BTreeMap<String, ArtifactSource>,
     entrypoint: Vec<String>,
-    labels: HashMap<String, String>,
+    labels: BTreeMap<String, String>,
     artifacts: Vec<Artifacts>,
-    volumes: HashMap<String, Volume>,
+    volumes: BTreeMap<String, Volume>,
+    uid: Option<u32>,
+    workdir: Option<String>,
+    read_only_rootfs: bool,
+    cap_add: Vec<String>,
+    cap_drop: Vec<String>,
+    privileged: bool,
+    ulimits: BTreeMap<String, (u64, u64)>,
+    sysctls: BTreeMap<String, String>,
+    oom_score_adj: Option<i32>,
+    mem_limit: Option<u64>,
+    memswap_limit: Option<u64>,
+    shm_size: Option<u64>,
+    stop_signal: Option<String>,
+    stop_grace_period: Option<u64>,
+    logging: Option<LogConfig>,
+    devices: Vec<String>,
+    gpu: Option<Gpu>,
+    platform: Option<Arch>,
+    healthcheck: Option<HealthCheck>,
+    ports: Vec<Port>,
+    pre_start: Vec<String>,
+    post_start: Vec<String>,
+    pre_stop: Vec<String>,
+    identity_keys: Vec<String>,
+    readiness: Option<BabelProbe>,
+}
+
+/// Download of a file into the data dir by an init spec, curl retries the
+/// transient failures. The file is downloaded to `<target>.part` and moved
+/// to the target once complete, so a file that was already there (e.g. one
+/// generated by the node) is never mixed with the download. If the sha256 is
+/// set a target that already matches is kept, a partial download is resumed
+/// and a mismatch is removed before the init stage fails, so the next deploy
+/// downloads it again. A compressed download is kept next to the target with
+/// its extension (the sha256 is the one of the archive) and unpacked into it.
+#[derive(Debug, Clone)]
+pub struct Download {
+    url: String,
+    target_path: String,
+    sha256: Option<String>,
+    // unix permission bits of the downloaded file
+    mode: Option<u32>,
+    compression: Option<Compression>,
+}
+
+/// Compression of a downloaded file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    // tarball with the file as its only entry
+    TarGzip,
+}
+
+impl Compression {
+    /// Compression of the url from its extension
+    pub fn from_url(url: &str) -> Option<Self> {
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Some(Compression::TarGzip)
+        } else if path.ends_with(".gz") {
+            Some(Compression::Gzip)
+        } else {
+            None
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => ".gz",
+            Compression::TarGzip => ".tar.gz",
+        }
+    }
+
+    // writes the unpacked file to stdout
+    fn unpack(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gunzip -c",
+            Compression::TarGzip => "tar -xzOf",
+        }
+    }
+}
+
+impl Download {
+    pub fn new(url: impl Into<String>, target_path: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            target_path: target_path.into(),
+            sha256: None,
+            mode: None,
+            compression: None,
+        }
+    }
+
+    pub fn sha256(mut self, sha256: impl Into<String>) -> Self {
+        self.sha256 = Some(sha256.into());
+        self
+    }
+
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Unpacks the download into the target
+    pub fn decompress(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Shell script of the download, the url and the paths are quoted
+    fn script(&self) -> String {
+        let curl = "curl -fL --retry 5 --retry-all-errors";
+        let url = shell_words::quote(&self.url);
+        let target = shell_words::quote(&self.target_path);
+        let part_path = format!("{}.part", self.target_path);
+        let part = shell_words::quote(&part_path);
+        // the file the download is moved to once complete
+        let fetched_path = match self.compression {
+            None => self.target_path.clone(),
+            Some(compression) => format!("{}{}", self.target_path, compression.extension()),
+        };
+        let fetched = shell_words::quote(&fetched_path);
+        let unpack = match self.compression {
+            None => String::new(),
+            Some(compression) => {
+                let tmp_path = format!("{}.tmp", self.target_path);
+                let tmp = shell_words::quote(&tmp_path);
+                format!(
+                    " && {} {fetched} > {tmp} && mv {tmp} {target}",
+                    compression.unpack()
+                )
+            }
+        };
+        let mut script = match &self.sha256 {
+            None => format!(
+                "mkdir -p \"$(dirname {target})\" && {curl} -o {part} {url} && \
+                 mv {part} {fetched}{unpack}"
+            ),
+            Some(sha256) => {
+                let check = |path: &str| {
+                    format!(
+                        "echo {} | sha256sum -c -",
+                        shell_words::quote(&format!("{}  {}", sha256, path))
+                    )
+                };
+                // an archive that matches is only kept if it was unpacked
+                let check_fetched = match self.compression {
+                    None => check(&fetched_path),
+                    Some(_) => format!("{{ {} && test -f {target}; }}", check(&fetched_path)),
+                };
+                format!(
+                    "mkdir -p \"$(dirname {target})\" && ({check_fetched} >/dev/null 2>&1 || \
+                     ({curl} -C - -o {part} {url} && \
+                     ({check_part} || (rm -f {part} && exit 1)) && mv {part} {fetched}{unpack}))",
+                    check_part = check(&part_path),
+                )
+            }
+        };
+        if let Some(mode) = self.mode {
+            script.push_str(&format!(" && chmod {:o} {}", mode, target));
+        }
+        script
+    }
+
+    pub fn build(self) -> Spec {
+        Spec::builder()
+            .image("curlimages/curl")
+            .tag("latest")
+            .entrypoint(["sh", "-c"])
+            .arg(self.script())
+            .build()
+    }
 }
 
 impl Spec {
     pub fn builder() -> SpecBuilder {
         SpecBuilder::default()
     }
+
+    /// Init spec that downloads the url to the target path of the data dir,
+    /// see `Download` to set the mode of the file
+    pub fn download(url: &str, target_path: &str, sha256: Option<&str>) -> Spec {
+        let mut download = Download::new(url, target_path);
+        if let Some(sha256) = sha256 {
+            download = download.sha256(sha256);
+        }
+        download.build()
+    }
+
+    /// Spec that runs the pre start commands of the spec before it starts,
+    /// with its image, env, volumes and files but without its ports or
+    /// healthcheck. None if it has no pre start commands.
+    pub fn pre_start_spec(&self) -> Option<Spec> {
+        if self.pre_start.is_empty() {
+            return None;
+        }
+        let mut artifacts: Vec<Artifacts> = self
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                Arg::File(file) => Some(Artifacts::File(file.clone())),
+                _ => None,
+            })
+            .collect();
+        artifacts.extend(self.artifacts.iter().cloned());
+
+        Some(Spec {
+            entrypoint: vec!["sh".to_string(), "-c".to_string()],
+            args: vec![Arg::Value(self.pre_start.join(" && "))],
+            artifacts,
+            ports: vec![],
+            healthcheck: None,
+            pre_start: vec![],
+            post_start: vec![],
+            pre_stop: vec![],
+            identity_keys: vec![],
+            readiness: None,
+            ..self.clone()
+        })
+    }
+
+    /// Reference of the image to run, `image@digest` if the image is pinned
+    /// and `image:tag` otherwise
+    pub fn image_ref(&self) -> String {
+        match &self.digest {
+            Some(digest) => format!("{}@{}", self.image, digest),
+            None => format!("{}:{}", self.image, self.tag.as_deref().unwrap_or("latest")),
+        }
+    }
+
+    /// Returns the preferred value of the port arg with the given name, or
+    /// the declared port if there is no such arg
+    pub fn port(&self, name: &str) -> Option<u16> {
+        let arg_port = self.args.iter().find_map(|arg| match arg {
+            Arg::Port {
+                name: port_name,
+                preferred,
+            } if port_name == name => Some(*preferred),
+            _ => None,
+        });
+
+        arg_port.or_else(|| {
+            self.ports
+                .iter()
+                .find(|port| port.name == name)
+                .map(|port| port.port)
+        })
+    }
 }
 
 impl SpecBuilder {
@@ -210,6 +2597,16 @@ impl SpecBuilder {
         self
     }
 
+    pub fn digest<S: Into<String>>(mut self, digest: S) -> Self {
+        self.digest = Some(digest.into());
+        self
+    }
+
+    pub fn pull_policy(mut self, pull_policy: PullPolicy) -> Self {
+        self.pull_policy = Some(pull_policy);
+        self
+    }
+
     pub fn arg(mut self, arg: impl Into<Arg>) -> Self {
         self.args.push(arg.into());
         self
@@ -239,6 +2636,34 @@ impl SpecBuilder {
         self
     }
 
+    /// Appends the flags and sets the env vars of the input over the ones
+    /// of the spec
+    pub fn overrides(self, overrides: &Overrides) -> Self {
+        self.args(&overrides.extra_args).envs(&overrides.extra_env)
+    }
+
+    pub fn envs<I, K, V>(mut self, envs: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env.extend(
+            envs.into_iter()
+                .map(|(key, value)| (key.into(), value.into())),
+        );
+        self
+    }
+
+    pub fn env_file<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<ArtifactSource>,
+    {
+        self.env_file.insert(key.into(), value.into());
+        self
+    }
+
     pub fn entrypoint<I>(mut self, entrypoint: I) -> Self
     where
         I: IntoIterator,
@@ -267,30 +2692,234 @@ impl SpecBuilder {
         self
     }
 
+    pub fn uid(mut self, uid: u32) -> Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    pub fn workdir(mut self, workdir: impl Into<String>) -> Self {
+        self.workdir = Some(workdir.into());
+        self
+    }
+
+    pub fn read_only_rootfs(mut self) -> Self {
+        self.read_only_rootfs = true;
+        self
+    }
+
+    pub fn cap_add<S: Into<String>>(mut self, capability: S) -> Self {
+        self.cap_add.push(capability.into());
+        self
+    }
+
+    pub fn cap_drop<S: Into<String>>(mut self, capability: S) -> Self {
+        self.cap_drop.push(capability.into());
+        self
+    }
+
+    pub fn privileged(mut self) -> Self {
+        self.privileged = true;
+        self
+    }
+
+    /// Runs the spec as the uid without capabilities on a read only root
+    /// filesystem with a tmpfs `/tmp`, for the nodes that only write to their
+    /// volumes
+    pub fn unprivileged(self, uid: u32) -> Self {
+        self.uid(uid)
+            .cap_drop("ALL")
+            .read_only_rootfs()
+            .volume(Volume::tmpfs("tmp", "/tmp", None))
+    }
+
+    pub fn ulimit(mut self, name: impl Into<String>, soft: u64, hard: u64) -> Self {
+        self.ulimits.insert(name.into(), (soft, hard));
+        self
+    }
+
+    pub fn sysctl<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.sysctls.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn oom_score_adj(mut self, oom_score_adj: i32) -> Self {
+        self.oom_score_adj = Some(oom_score_adj);
+        self
+    }
+
+    pub fn mem_limit(mut self, bytes: u64) -> Self {
+        self.mem_limit = Some(bytes);
+        self
+    }
+
+    pub fn memswap_limit(mut self, bytes: u64) -> Self {
+        self.memswap_limit = Some(bytes);
+        self
+    }
+
+    pub fn shm_size(mut self, bytes: u64) -> Self {
+        self.shm_size = Some(bytes);
+        self
+    }
+
+    pub fn stop_signal(mut self, signal: impl Into<String>) -> Self {
+        self.stop_signal = Some(signal.into());
+        self
+    }
+
+    pub fn stop_grace_period(mut self, seconds: u64) -> Self {
+        self.stop_grace_period = Some(seconds);
+        self
+    }
+
+    pub fn port(mut self, port: Port) -> Self {
+        self.ports.push(port);
+        self
+    }
+
+    pub fn logging(mut self, logging: LogConfig) -> Self {
+        self.logging = Some(logging);
+        self
+    }
+
+    pub fn device(mut self, device: impl Into<String>) -> Self {
+        self.devices.push(device.into());
+        self
+    }
+
+    pub fn gpu(mut self, gpu: Gpu) -> Self {
+        self.gpu = Some(gpu);
+        self
+    }
+
+    pub fn platform(mut self, arch: Arch) -> Self {
+        self.platform = Some(arch);
+        self
+    }
+
+    pub fn healthcheck(mut self, healthcheck: HealthCheck) -> Self {
+        self.healthcheck = Some(healthcheck);
+        self
+    }
+
+    pub fn pre_start(mut self, command: impl Into<String>) -> Self {
+        self.pre_start.push(command.into());
+        self
+    }
+
+    pub fn identity_key(mut self, path: impl Into<String>) -> Self {
+        self.identity_keys.push(path.into());
+        self
+    }
+
+    pub fn readiness(mut self, probe: BabelProbe) -> Self {
+        self.readiness = Some(probe);
+        self
+    }
+
+    pub fn post_start(mut self, command: impl Into<String>) -> Self {
+        self.post_start.push(command.into());
+        self
+    }
+
+    pub fn pre_stop(mut self, command: impl Into<String>) -> Self {
+        self.pre_stop.push(command.into());
+        self
+    }
+
     pub fn build(self) -> Spec {
         Spec {
             image: self.image.unwrap(),
             tag: self.tag,
+            digest: self.digest,
+            pull_policy: self.pull_policy,
             args: self.args,
             entrypoint: self.entrypoint,
             labels: self.labels,
             env: self.env,
+            env_file: self.env_file,
             artifacts: self.artifacts,
             volumes: self.volumes,
+            uid: self.uid,
+            workdir: self.workdir,
+            read_only_rootfs: self.read_only_rootfs,
+            cap_add: self.cap_add,
+            cap_drop: self.cap_drop,
+            privileged: self.privileged,
+            ulimits: self.ulimits,
+            sysctls: self.sysctls,
+            oom_score_adj: self.oom_score_adj,
+            mem_limit: self.mem_limit,
+            memswap_limit: self.memswap_limit,
+            shm_size: self.shm_size,
+            stop_signal: self.stop_signal,
+            stop_grace_period: self.stop_grace_period,
+            logging: self.logging,
+            devices: self.devices,
+            gpu: self.gpu,
+            platform: self.platform,
+            healthcheck: self.healthcheck,
+            ports: self.ports,
+            pre_start: self.pre_start,
+            post_start: self.post_start,
+            pre_stop: self.pre_stop,
+            identity_keys: self.identity_keys,
+            readiness: self.readiness,
         }
     }
 }
 
-impl Into<Spec> for SpecBuilder {
-    fn into(self) -> Spec {
-        self.build()
+impl From<SpecBuilder> for Spec {
+    fn from(builder: SpecBuilder) -> Self {
+        builder.build()
     }
 }
 
+// port the babel server listens on
+pub const BABEL_PORT: u16 = 3000;
+
 #[derive(Debug, Clone)]
 pub struct Babel {
     pub node_type: String,
     pub rpc_url: Arg,
+    // IPC socket in the ipc volume babel reaches the node on instead of the
+    // rpc url, the rpc url still tells the specs it follows
+    pub ipc_socket: Option<String>,
+}
+
+/// Health endpoint of the babel that follows a node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BabelProbe {
+    // node type babel runs with, e.g. `ethereum` or `cosmos`
+    pub node_type: String,
+    // port of the babel server
+    pub port: u16,
+}
+
+impl BabelProbe {
+    /// Url of the health endpoint on the host of the babel spec
+    pub fn url(&self, host: &str) -> String {
+        format!("http://{}:{}/health", host, self.port)
+    }
+
+    /// Whether the spec is a babel server of the node type on the port
+    pub fn is_served_by(&self, spec: &Spec) -> bool {
+        let flag = |flag: &str| {
+            spec.args.windows(2).find_map(|args| match args {
+                [Arg::Value(name), Arg::Value(value)] if name == flag => Some(value.as_str()),
+                _ => None,
+            })
+        };
+        spec.image == "babel"
+            && flag("--node-type") == Some(self.node_type.as_str())
+            && flag("--addr")
+                .and_then(|addr| addr.rsplit_once(':'))
+                .is_some_and(|(_, port)| port == self.port.to_string())
+    }
 }
 
 impl Babel {
@@ -298,16 +2927,624 @@ impl Babel {
         Self {
             node_type: node_type.into(),
             rpc_url: rpc_url.into(),
+            ipc_socket: None,
+        }
+    }
+
+    pub fn with_ipc(mut self, socket: impl Into<String>) -> Self {
+        self.ipc_socket = Some(socket.into());
+        self
+    }
+
+    pub fn probe(&self) -> BabelProbe {
+        BabelProbe {
+            node_type: self.node_type.clone(),
+            port: BABEL_PORT,
         }
     }
 
+    /// Spec of the babel server, it is healthy once its node replies. The
+    /// image is built locally (`just build babel`), it is never pulled.
     pub fn spec(self) -> Spec {
-        Spec::builder()
+        let health = format!("http://127.0.0.1:{}/health", BABEL_PORT);
+        let spec = Spec::builder()
             .image("babel")
             .tag("latest")
-            .arg2("--node-type", self.node_type)
-            .arg2("--rpc-url", self.rpc_url)
-            .arg2("--addr", "0.0.0.0:3000")
+            .pull_policy(PullPolicy::Never)
+            // the node can take a while to serve its rpc after a restart
+            .healthcheck(
+                HealthCheck::command(["babel", "probe", health.as_str()]).start_period(600),
+            );
+        let spec = match self.ipc_socket {
+            Some(socket) => spec
+                .volume(Volume::ipc())
+                .arg2("--node-type", self.node_type)
+                .arg2("--rpc-url", format!("unix://{}", socket)),
+            None => spec
+                .arg2("--node-type", self.node_type)
+                .arg2("--rpc-url", self.rpc_url),
+        };
+        spec.arg2("--addr", format!("0.0.0.0:{}", BABEL_PORT))
             .build()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_startup_order_follows_dependencies() -> eyre::Result<()> {
+        let mut manifest = Manifest::new("eth".to_string());
+        for pod in ["vc", "cl", "el"] {
+            manifest.add_spec(pod.to_string(), Pod::default());
+        }
+        manifest.add_dependency("vc", "cl");
+        manifest.add_dependency("cl", "el");
+        assert_eq!(manifest.startup_order()?, vec!["el", "cl", "vc"]);
+
+        manifest.add_dependency("el", "vc");
+        assert!(manifest.startup_order().is_err());
+
+        let mut manifest = Manifest::new("eth".to_string());
+        manifest.add_spec("cl".to_string(), Pod::default());
+        manifest.add_dependency("cl", "el");
+        assert!(manifest.startup_order().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_refs_resolve_to_the_spec_with_the_port() -> eyre::Result<()> {
+        let node = Spec::builder().image("reth").arg(Arg::Port {
+            name: "authrpc".to_string(),
+            preferred: 8551,
+        });
+        let babel = Spec::builder().image("babel").arg(Arg::Port {
+            name: "http".to_string(),
+            preferred: 3000,
+        });
+        let mut manifest = Manifest::new("eth".to_string());
+        manifest.add_spec(
+            "el".to_string(),
+            Pod::default()
+                .with_spec("node", node)
+                .with_spec("babel", babel),
+        );
+
+        let target = manifest.resolve_ref("el", "authrpc")?;
+        assert_eq!(target.url(), "http://el-node:8551");
+        assert_eq!(manifest.resolve_ref("el/babel", "http")?.host(), "el-babel");
+
+        assert!(manifest.resolve_ref("el", "ws").is_err());
+        assert!(manifest.resolve_ref("el/node", "http").is_err());
+        assert!(manifest.resolve_ref("cl", "http").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_saved_manifests_are_migrated() -> eyre::Result<()> {
+        let mut manifest = Manifest::new("eth".to_string());
+        manifest.add_spec(
+            "el".to_string(),
+            Pod::default().with_spec("node", Spec::builder().image("reth")),
+        );
+        let saved = serde_json::to_value(&manifest)?;
+        assert_eq!(saved["version"], MANIFEST_VERSION);
+        assert_eq!(
+            Manifest::from_json(saved)?.pods["el"].specs["node"].image,
+            "reth"
+        );
+
+        // written before the manifests had a version
+        let unversioned = serde_json::json!({
+            "name": "eth",
+            "pods": {"el": {"specs": {"node": {"image": "reth"}}}},
+        });
+        assert_eq!(Manifest::from_json(unversioned)?.version, MANIFEST_VERSION);
+
+        let newer = serde_json::json!({"version": MANIFEST_VERSION + 1, "name": "eth", "pods": {}});
+        assert!(Manifest::from_json(newer).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_babel_is_the_readiness_probe_of_its_node() -> eyre::Result<()> {
+        let node = Spec::builder().image("reth").arg(Arg::Port {
+            name: "http".to_string(),
+            preferred: 8545,
+        });
+        let exporter = Spec::builder().image("exporter");
+        let pod = Pod::default()
+            .with_spec("node", node)
+            .with_spec("exporter", exporter)
+            .with_babel(Babel::new(
+                "ethereum",
+                Arg::Ref {
+                    name: "el".to_string(),
+                    port: "http".to_string(),
+                },
+            ));
+
+        let probe = pod.specs["node"].readiness.clone().unwrap();
+        assert_eq!(probe.url("el-babel"), "http://el-babel:3000/health");
+        assert!(probe.is_served_by(&pod.specs["babel"]));
+        assert!(pod.specs["exporter"].readiness.is_none());
+
+        let mut manifest = Manifest::new("eth".to_string());
+        manifest.add_spec("el".to_string(), pod);
+        assert!(manifest.validate().is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_overrides_the_specs() -> eyre::Result<()> {
+        let mut manifest = Manifest::new("eth".to_string());
+        manifest.add_spec(
+            "el".to_string(),
+            Pod::default().with_spec("node", Spec::builder().image("reth").tag("v1").arg("node")),
+        );
+
+        let patch: ManifestPatch = serde_json::from_value(serde_json::json!({
+            "pods": {"el": {"node": {
+                "tag": "v2",
+                "args": ["--full"],
+                "env": {"RUST_LOG": "debug"}
+            }}}
+        }))?;
+        manifest.apply_patch(&patch)?;
+
+        let node = &manifest.pods["el"].specs["node"];
+        assert_eq!(node.tag.as_deref(), Some("v2"));
+        assert!(
+            matches!(&node.args[..], [Arg::Value(a), Arg::Value(b)] if a == "node" && b == "--full")
+        );
+        assert_eq!(node.env["RUST_LOG"], "debug");
+
+        let patch: ManifestPatch = serde_json::from_value(serde_json::json!({
+            "pods": {"el": {"babel": {"tag": "v2"}}}
+        }))?;
+        assert!(manifest.apply_patch(&patch).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_dirs_move_the_data_volumes() -> eyre::Result<()> {
+        let mut manifest = Manifest::new("eth".to_string());
+        for pod in ["el", "cl"] {
+            manifest.add_spec(
+                pod.to_string(),
+                Pod::default().with_spec("node", Spec::builder().image(pod).volume(Volume::data())),
+            );
+        }
+
+        let data_dirs: DataDirs = serde_json::from_value(serde_json::json!({
+            "root": "/mnt/hdd/",
+            "pods": {"el": "/mnt/nvme/eth-el"}
+        }))?;
+        manifest.apply_data_dirs(&data_dirs)?;
+
+        let host_path =
+            |pod: &str| match &manifest.pods[pod].specs["node"].volumes[DATA_VOLUME].kind {
+                VolumeKind::Bind { host_path } => host_path.clone(),
+                kind => panic!("unexpected volume {:?}", kind),
+            };
+        assert_eq!(host_path("el"), "/mnt/nvme/eth-el/{{spec}}");
+        assert_eq!(
+            manifest.data_dirs()[&("cl".to_string(), "node".to_string())],
+            "/mnt/hdd/eth/cl/node"
+        );
+        assert_eq!(host_path("cl"), "/mnt/hdd/{{deployment}}/cl/{{spec}}");
+
+        let data_dirs = DataDirs {
+            pods: HashMap::from([("vc".to_string(), "/mnt/nvme".to_string())]),
+            ..Default::default()
+        };
+        assert!(manifest.apply_data_dirs(&data_dirs).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_allocate_host_ports_on_collisions() {
+        let node = |port: u16| {
+            Spec::builder().image("reth").arg(Arg::Port {
+                name: "authrpc".to_string(),
+                preferred: port,
+            })
+        };
+        let mut manifest = Manifest::new("eth".to_string());
+        manifest.add_spec(
+            "a".to_string(),
+            Pod::default().with_spec("node", node(8551)),
+        );
+        manifest.add_spec(
+            "b".to_string(),
+            Pod::default().with_spec("node", node(8551)),
+        );
+        manifest.add_spec(
+            "c".to_string(),
+            Pod::default().with_spec("node", node(9000)),
+        );
+
+        let used = HashSet::from([(8552, Protocol::Tcp), (9000, Protocol::Udp)]);
+        let allocated = manifest.allocate_host_ports(&used);
+        assert_eq!(allocated.len(), 1);
+        assert_eq!(
+            (allocated[0].pod.as_str(), allocated[0].host_port),
+            ("b", 8553)
+        );
+
+        let host_ports: Vec<(u16, u16)> = manifest
+            .host_ports()
+            .iter()
+            .map(|port| (port.port, port.host_port))
+            .collect();
+        assert_eq!(host_ports, vec![(8551, 8551), (8551, 8553), (9000, 9000)]);
+    }
+
+    #[test]
+    fn test_nftables_rules_drop_the_internal_ports() {
+        let node = Spec::builder()
+            .image("reth")
+            .arg(Arg::Port {
+                name: "http".to_string(),
+                preferred: 8545,
+            })
+            .port(Port::tcp("p2p", 30303).public())
+            .port(Port::udp("discovery", 30303).public());
+        let mut manifest = Manifest::new("eth-dev".to_string());
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", node));
+
+        let rules = manifest.nftables_rules();
+        assert!(rules.contains("table inet bbuilder_eth_dev {"));
+        assert!(rules.contains("meta l4proto tcp ct original proto-dst { 8545 } drop"));
+        assert!(!rules.contains("30303"));
+    }
+
+    #[test]
+    fn test_merge_input_over_defaults() {
+        let defaults = serde_json::json!({
+            "el_node": {"reth": {}},
+            "cl_node": {"lighthouse": {"checkpoint_sync_url": "https://sync"}},
+        });
+
+        let merged = merge_input(
+            defaults.clone(),
+            serde_json::json!({"cl_node": {"lighthouse": {"graffiti": "bbuilder"}}}),
+        );
+        assert_eq!(
+            merged,
+            serde_json::json!({
+                "el_node": {"reth": {}},
+                "cl_node": {"lighthouse": {"checkpoint_sync_url": "https://sync", "graffiti": "bbuilder"}},
+            })
+        );
+
+        // another client replaces the default one
+        let merged = merge_input(
+            defaults.clone(),
+            serde_json::json!({"cl_node": {"prysm": {}}}),
+        );
+        assert_eq!(merged["cl_node"], serde_json::json!({"prysm": {}}));
+
+        assert_eq!(
+            merge_input(defaults.clone(), serde_json::Value::Null),
+            defaults
+        );
+    }
+
+    #[test]
+    fn test_validate_reports_every_error() {
+        let file = |name: &str| File {
+            name: name.to_string(),
+            target_path: "/data/config.toml".to_string(),
+            ..Default::default()
+        };
+        let node = Spec::builder()
+            .image("")
+            .oom_score_adj(-1500)
+            .memswap_limit(1 << 30)
+            .stop_signal("INT")
+            .readiness(Babel::new("ethereum", "http://localhost:8545").probe())
+            .arg(Arg::Port {
+                name: "http".to_string(),
+                preferred: 8545,
+            })
+            .port(Port::tcp("http", 8546))
+            .arg(Arg::Ref {
+                name: "cl".to_string(),
+                port: "http".to_string(),
+            })
+            .artifact(Artifacts::File(file("a")))
+            .artifact(Artifacts::File(file("b")));
+
+        let mut manifest = Manifest::new("eth".to_string());
+        manifest.add_spec(
+            "el".to_string(),
+            Pod::default()
+                .with_spec("node", node)
+                .with_init_spec("x", Spec::builder().image("busybox")),
+        );
+        manifest.add_spec(
+            "el-init".to_string(),
+            Pod::default().with_spec("x", Spec::builder().image("busybox")),
+        );
+
+        let errors = manifest.validate().unwrap_err().0;
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::EmptyImage {
+                    service: "el-node".to_string()
+                },
+                ValidationError::InvalidOomScoreAdj {
+                    service: "el-node".to_string()
+                },
+                ValidationError::InvalidMemswapLimit {
+                    service: "el-node".to_string()
+                },
+                ValidationError::InvalidStopSignal {
+                    service: "el-node".to_string(),
+                    signal: "INT".to_string()
+                },
+                ValidationError::MissingBabel {
+                    service: "el-node".to_string(),
+                    node_type: "ethereum".to_string()
+                },
+                ValidationError::ConflictingTargetPath {
+                    service: "el-node".to_string(),
+                    target_path: "/data/config.toml".to_string()
+                },
+                ValidationError::DuplicatePortName {
+                    service: "el-node".to_string(),
+                    port: "http".to_string()
+                },
+                ValidationError::UnresolvedRef {
+                    service: "el-node".to_string(),
+                    reason: "Ref cl points to an unknown pod".to_string()
+                },
+                ValidationError::DuplicateService {
+                    service: "el-init-x".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_checksum_is_verified() {
+        let mut file = File {
+            name: "genesis.json".to_string(),
+            content: "genesis".into(),
+            sha256: Some(
+                "AEEBAD4A796FCC2E15DC4C6061B45ED9B373F26ADFC798CA7D2D8CC58182718E".to_string(),
+            ),
+            ..Default::default()
+        };
+        assert!(file.verify_checksum().is_ok());
+
+        file.content = "genesis\n".into();
+        assert!(file.verify_checksum().is_err());
+    }
+
+    #[test]
+    fn test_lazy_artifacts_are_resolved() -> eyre::Result<()> {
+        let url = "https://example.com/bootnodes.txt";
+        let seeds_url = "https://example.com/seeds.txt";
+        let node = Spec::builder()
+            .image("beacon-kit")
+            .env_file("BOOTNODES", ArtifactSource::url(url))
+            .artifact(Artifacts::File(File {
+                name: "config".to_string(),
+                target_path: "/data/config.toml".to_string(),
+                content: ArtifactSource::generator(vec![seeds_url.to_string()], move |fetched| {
+                    let seeds = fetched
+                        .get(seeds_url)
+                        .ok_or_else(|| eyre::eyre!("{} was not fetched", seeds_url))?;
+                    Ok(format!("seeds = \"{}\"", seeds.trim()))
+                }),
+                ..Default::default()
+            }));
+        let mut manifest = Manifest::new("bera".to_string());
+        manifest.add_spec("cl".to_string(), Pod::default().with_spec("node", node));
+
+        // the lazy contents are kept out of the planned manifest
+        assert!(manifest.to_yaml()?.contains("<generated when deployed>"));
+        assert_eq!(
+            manifest.artifact_urls(),
+            BTreeSet::from([url.to_string(), seeds_url.to_string()])
+        );
+        assert_eq!(
+            manifest.generator_urls(),
+            BTreeSet::from([seeds_url.to_string()])
+        );
+        assert!(manifest.resolve_artifacts(&HashMap::new()).is_err());
+
+        // the generators are resolved on their own, the urls are left as is
+        let fetched = HashMap::from([(seeds_url.to_string(), "a,b\n".to_string())]);
+        manifest.resolve_generators(&fetched)?;
+        assert!(!manifest.to_yaml()?.contains("<generated when deployed>"));
+        assert_eq!(manifest.artifact_urls(), BTreeSet::from([url.to_string()]));
+
+        let fetched = HashMap::from([(url.to_string(), "enode://a\n".to_string())]);
+        manifest.resolve_artifacts(&fetched)?;
+        let node = &manifest.pods["cl"].specs["node"];
+        assert_eq!(node.env_file["BOOTNODES"].inline(), Some("enode://a\n"));
+        let Artifacts::File(config) = &node.artifacts[0] else {
+            panic!("expected a file artifact");
+        };
+        assert_eq!(config.content.inline(), Some("seeds = \"a,b\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_smoke_test_expectations() {
+        let status = serde_json::json!({
+            "node_info": {"network": "osmosis-1", "version": "0.38.0"},
+            "sync_info": {"catching_up": false},
+        });
+        let network = serde_json::json!({"node_info": {"network": "osmosis-1"}});
+        assert!(Expect::Contains(network.clone()).check(&status).is_ok());
+        assert!(Expect::Equals(network).check(&status).is_err());
+
+        let other = serde_json::json!({"node_info": {"network": "cosmoshub-4"}});
+        assert!(Expect::Contains(other).check(&status).is_err());
+
+        let chain_id = Expect::Equals(serde_json::json!("0x1"));
+        assert!(chain_id.check(&serde_json::json!("0x1")).is_ok());
+        assert!(chain_id.check(&serde_json::json!("0xaa36a7")).is_err());
+    }
+
+    #[test]
+    fn test_manifest_yaml_roundtrip() -> eyre::Result<()> {
+        let node = Spec::builder()
+            .image("ghcr.io/paradigmxyz/reth")
+            .tag("v1.4.8")
+            .arg("node")
+            .arg2(
+                "--http.port",
+                Arg::Port {
+                    name: "http".to_string(),
+                    preferred: 8545,
+                },
+            )
+            .arg2(
+                "--authrpc",
+                Arg::Ref {
+                    name: "el".to_string(),
+                    port: "authrpc".to_string(),
+                },
+            )
+            .artifact(Artifacts::File(File {
+                name: "jwt".to_string(),
+                target_path: "/data/jwt_secret".to_string(),
+                content: "04592280e1778419b7aa954d43871cb2cfb2ebda754fb735e8adeb293a88f9bf".into(),
+                mode: Some(0o600),
+                ..Default::default()
+            }));
+
+        let mut manifest = Manifest::new("eth".to_string());
+        manifest.add_spec("el".to_string(), Pod::default().with_spec("node", node));
+
+        let yaml = manifest.to_yaml()?;
+        let decoded = Manifest::from_yaml(&yaml)?;
+        assert_eq!(decoded.to_yaml()?, yaml);
+
+        let spec = &decoded.pods["el"].specs["node"];
+        assert_eq!(spec.port("http"), Some(8545));
+        assert!(matches!(&spec.artifacts[0], Artifacts::File(file) if file.mode == Some(0o600)));
+
+        Ok(())
+    }
+
+    // runs the script of the download in the host, curl reads the file urls
+    fn run_download(download: Download) -> bool {
+        std::process::Command::new("sh")
+            .arg("-c")
+            .arg(download.script())
+            .status()
+            .unwrap()
+            .success()
+    }
+
+    #[test]
+    fn test_download_paths_are_quoted() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join("test-spec-download quoted");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let source = dir.join("genesis $(touch injected).json");
+        std::fs::write(&source, "{}")?;
+
+        let target = dir.join("config dir/genesis.json");
+        let download = Download::new(
+            format!("file://{}", source.display()).replace(' ', "%20"),
+            target.to_string_lossy(),
+        )
+        .mode(0o600);
+        assert!(run_download(download));
+
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::read_to_string(&target)?, "{}");
+        assert_eq!(
+            std::fs::metadata(&target)?.permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(!Path::new("injected").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_download_is_verified_before_it_replaces_the_target() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join("test-spec-download-verified");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let source = dir.join("genesis-source.json");
+        std::fs::write(&source, "{\"chain_id\":\"heimdall-80002\"}")?;
+        let sha256 = hex::encode(Sha256::digest(std::fs::read(&source)?));
+
+        // a genesis generated by the node init is replaced, not resumed into
+        let target = dir.join("genesis.json");
+        std::fs::write(&target, "{\"chain_id\":\"generated-by-the-node-init\"}")?;
+        let download = || {
+            Download::new(
+                format!("file://{}", source.display()),
+                target.to_string_lossy(),
+            )
+        };
+        assert!(run_download(download().sha256(&sha256)));
+        assert_eq!(std::fs::read(&target)?, std::fs::read(&source)?);
+        assert!(!dir.join("genesis.json.part").exists());
+
+        // a mismatch fails and leaves the target untouched
+        assert!(!run_download(download().sha256("00".repeat(32))));
+        assert_eq!(std::fs::read(&target)?, std::fs::read(&source)?);
+        assert!(!dir.join("genesis.json.part").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_download_is_unpacked() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join("test-spec-download-unpacked");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("genesis.json"), "{\"chain_id\":\"osmosis-1\"}")?;
+        let sh = |script: &str| {
+            std::process::Command::new("sh")
+                .arg("-c")
+                .arg(script)
+                .current_dir(&dir)
+                .status()
+                .unwrap()
+                .success()
+        };
+        assert!(sh(
+            "gzip -c genesis.json > genesis.json.gz && tar -czf genesis.tar.gz genesis.json"
+        ));
+
+        for archive in ["genesis.json.gz", "genesis.tar.gz"] {
+            let url = format!("file://{}", dir.join(archive).display());
+            let compression = Compression::from_url(&url).unwrap();
+            let target = dir.join(format!("{}.d", archive)).join("genesis.json");
+            let sha256 = hex::encode(Sha256::digest(std::fs::read(dir.join(archive))?));
+
+            let download = Download::new(url, target.to_string_lossy())
+                .sha256(sha256)
+                .decompress(compression);
+            assert!(run_download(download.clone()));
+            assert_eq!(
+                std::fs::read_to_string(&target)?,
+                "{\"chain_id\":\"osmosis-1\"}"
+            );
+            assert!(
+                target
+                    .with_extension(format!("json{}", compression.extension()))
+                    .exists()
+            );
+
+            // the verified archive is not downloaded again
+            std::fs::remove_file(dir.join(archive))?;
+            assert!(run_download(download));
+        }
+        Ok(())
+    }
+}